@@ -0,0 +1,20 @@
+class FieldBench {
+  int a;
+  int b;
+  int c;
+  int d;
+
+  public static void main(String[] args) {
+    FieldBench f = new FieldBench();
+
+    for (int i = 0; i < 200000; i++) {
+      f.a += 1;
+      f.b += 2;
+      f.c += f.a;
+      f.d = f.b;
+      f.c = f.d;
+    }
+
+    System.out.println(f.c);
+  }
+}
@@ -0,0 +1,78 @@
+import java.util.ArrayDeque;
+import java.util.ArrayList;
+import java.util.Arrays;
+import java.util.Collections;
+import java.util.ConcurrentModificationException;
+import java.util.HashMap;
+import java.util.HashSet;
+import java.util.Iterator;
+import java.util.List;
+import java.util.Map;
+
+class Iteration {
+  public static void main(String[] args) {
+    List<String> list = new ArrayList<>();
+    list.add("pear");
+    list.add("apple");
+    list.add("fig");
+    for (String s : list) {
+      System.out.println(s);
+    }
+    Collections.sort(list);
+    System.out.println(list);
+
+    // Removing through the iterator is allowed, removing through the list isn't
+    Iterator<String> it = list.iterator();
+    while (it.hasNext()) {
+      if (it.next().startsWith("f")) {
+        it.remove();
+      }
+    }
+    System.out.println(list);
+    list.add("plum");
+    try {
+      for (String s : list) {
+        list.remove(s);
+      }
+    } catch (ConcurrentModificationException e) {
+      System.out.println("ConcurrentModificationException from a list");
+    }
+
+    // The iteration order of a HashMap follows the buckets of the hash codes
+    Map<String, Integer> map = new HashMap<>();
+    map.put("one", 1);
+    map.put("two", 2);
+    map.put("three", 3);
+    map.put("four", 4);
+    for (Map.Entry<String, Integer> e : map.entrySet()) {
+      System.out.println(e.getKey() + "=" + e.getValue());
+    }
+    System.out.println(map);
+    try {
+      for (String key : map.keySet()) {
+        map.put(key + "!", 0);
+      }
+    } catch (ConcurrentModificationException e) {
+      System.out.println("ConcurrentModificationException from a map");
+    }
+
+    HashSet<Integer> set = new HashSet<>();
+    for (int i = 20; i > 0; i -= 3) {
+      set.add(i);
+    }
+    System.out.println(set);
+
+    ArrayDeque<String> deque = new ArrayDeque<>();
+    deque.push("first");
+    deque.addLast("last");
+    deque.addFirst("before");
+    while (!deque.isEmpty()) {
+      System.out.println(deque.pop());
+    }
+
+    int[] numbers = new int[4];
+    Arrays.fill(numbers, 7);
+    System.out.println(Arrays.toString(Arrays.copyOf(numbers, 6)));
+    System.out.println(Arrays.asList("x", "y", "z"));
+  }
+}
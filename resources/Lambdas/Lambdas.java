@@ -0,0 +1,43 @@
+import java.util.function.Supplier;
+
+class Lambdas {
+  private String secret = "Private field of the outer class";
+
+  public static void main(String[] args) {
+    // Non-capturing: d8 creates a singleton in the `<clinit>` of the lambda class
+    Runnable hello = () -> System.out.println("Hello from a lambda");
+    hello.run();
+
+    Supplier<String> supplier = () -> "Supplied by a lambda";
+    System.out.println(supplier.get());
+
+    // Capturing: a new instance for each evaluation, holding the captured values
+    String greeting = "Captured by a lambda";
+    Supplier<String> captured = () -> greeting;
+    System.out.println(captured.get());
+
+    // Private members used by an inner class: `-$$Nest$` accessors
+    Lambdas outer = new Lambdas();
+    outer.show();
+  }
+
+  private String name() {
+    return "Private method of the outer class";
+  }
+
+  void show() {
+    Inner inner = new Inner();
+    System.out.println(inner.secret());
+    System.out.println(inner.name());
+  }
+
+  class Inner {
+    String secret() {
+      return secret;
+    }
+
+    String name() {
+      return Lambdas.this.name();
+    }
+  }
+}
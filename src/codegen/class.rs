@@ -0,0 +1,253 @@
+use super::instructions::FieldSlot;
+
+/// A class known to the module, with the slot layout of its instances
+#[derive(Debug)]
+pub struct Class {
+  name: String,
+  is_interface: bool,
+  superclass: Option<String>,
+  interfaces: Vec<String>,
+  /// Instance fields in slot order - inherited fields come first
+  fields: Vec<String>,
+}
+
+impl Class {
+  pub fn new(
+    name: String,
+    is_interface: bool,
+    superclass: Option<String>,
+    interfaces: Vec<String>,
+    fields: Vec<String>,
+  ) -> Self {
+    Self {
+      name,
+      is_interface,
+      superclass,
+      interfaces,
+      fields,
+    }
+  }
+
+  pub fn name(&self) -> &String {
+    &self.name
+  }
+
+  pub fn is_interface(&self) -> bool {
+    self.is_interface
+  }
+
+  pub fn superclass(&self) -> Option<&String> {
+    self.superclass.as_ref()
+  }
+
+  pub fn interfaces(&self) -> &Vec<String> {
+    &self.interfaces
+  }
+
+  pub fn fields(&self) -> &Vec<String> {
+    &self.fields
+  }
+
+  /// Number of slots an instance of this class needs
+  pub fn n_fields(&self) -> usize {
+    self.fields.len()
+  }
+
+  /// Find the slot of a field. A field declared in this class shadows the ones
+  /// with the same name in its superclasses, which is why we search from the end.
+  pub fn field_slot(&self, field: &str) -> Option<FieldSlot> {
+    self.fields.iter().rposition(|f| f == field)
+  }
+}
+
+/// A class defined while running, like the class of a lambda. It implements interfaces with
+/// functions, which get the object as `this`.
+#[derive(Debug, Clone)]
+pub struct ProxyClass {
+  pub interfaces: Vec<String>,
+  /// The methods it implements, as mangled names with their descriptors, and their functions
+  pub methods: Vec<(String, String)>,
+}
+
+/// Turn a type descriptor into the name Java uses for it (`Class.getName()`), e.g.
+/// `Ljava/lang/String;` becomes `java.lang.String` and `[Ljava/lang/String;` becomes `[Ljava.lang.String;`
+pub fn java_name(descriptor: &str) -> String {
+  match descriptor {
+    "V" => return "void".to_owned(),
+    "Z" => return "boolean".to_owned(),
+    "B" => return "byte".to_owned(),
+    "S" => return "short".to_owned(),
+    "C" => return "char".to_owned(),
+    "I" => return "int".to_owned(),
+    "J" => return "long".to_owned(),
+    "F" => return "float".to_owned(),
+    "D" => return "double".to_owned(),
+    _ => {}
+  }
+
+  if descriptor.starts_with('L') && descriptor.ends_with(';') {
+    descriptor[1..descriptor.len() - 1].replace('/', ".")
+  } else {
+    descriptor.replace('/', ".")
+  }
+}
+
+/// Whether a type descriptor describes a reference (class or array) rather than a primitive
+pub fn is_reference(descriptor: &str) -> bool {
+  descriptor.starts_with('L') || descriptor.starts_with('[')
+}
+
+/// The parameter types of a method descriptor, e.g. `(I[JLjava/lang/String;)V` gives `I`, `[J`
+/// and `Ljava/lang/String;`
+pub fn parameter_types(descriptor: &str) -> Vec<&str> {
+  let params = match (descriptor.find('('), descriptor.find(')')) {
+    (Some(start), Some(end)) if start < end => &descriptor[start + 1..end],
+    _ => return Vec::new(),
+  };
+
+  let bytes = params.as_bytes();
+  let mut types = Vec::new();
+  let mut i = 0;
+  while i < bytes.len() {
+    let start = i;
+    while bytes.get(i) == Some(&b'[') {
+      i += 1;
+    }
+    if bytes.get(i) == Some(&b'L') {
+      while i < bytes.len() && bytes[i] != b';' {
+        i += 1;
+      }
+    }
+    i = (i + 1).min(bytes.len());
+    types.push(&params[start..i]);
+  }
+
+  types
+}
+
+pub fn format_classname(classname: &str) -> String {
+  format!(
+    "CLASS_{}",
+    classname[1..]
+      .replace("__", "____")
+      .replace("/", "__")
+      .replace(";", "")
+  )
+}
+
+pub fn format_name(name: &str) -> String {
+  name
+    .replace("__", "____")
+    .replace("<", "__")
+    .replace(">", "__")
+}
+
+/// Mangled name of a method with its descriptor, like `append(I)Ljava/lang/StringBuilder;`.
+/// Overloads differ in their descriptors, so each of them gets its own function.
+pub fn format_method(name: &str, descriptor: &str) -> String {
+  format!("{}{}", format_name(name), descriptor)
+}
+
+/// A mangled method (or function) name without its descriptor, e.g. `append` for
+/// `append(I)Ljava/lang/StringBuilder;`
+pub fn strip_descriptor(method: &str) -> &str {
+  match method.find('(') {
+    None => method,
+    Some(i) => &method[..i],
+  }
+}
+
+/// Best-effort inverse of `format_classname` and `format_method` for a full function name,
+/// giving the class descriptor and the method name. Used to name native frames in stack traces.
+pub fn demangle(fn_name: &str) -> Option<(String, String)> {
+  let name = strip_descriptor(fn_name).strip_prefix("CLASS_")?;
+
+  for special in &["<init>", "<clinit>"] {
+    if let Some(class) = name.strip_suffix(&format!("__{}", format_name(special))) {
+      return Some((demangle_class(class), special.to_string()));
+    }
+  }
+
+  let name = name.replace("____", "\0");
+  let (class, method) = name.rsplit_once("__")?;
+  Some((demangle_class(class), method.replace('\0', "__")))
+}
+
+fn demangle_class(class: &str) -> String {
+  format!(
+    "L{};",
+    class
+      .replace("____", "\0")
+      .replace("__", "/")
+      .replace('\0', "__")
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parameters_of_descriptors() {
+    assert_eq!(parameter_types("()V"), Vec::<&str>::new());
+    assert_eq!(
+      parameter_types("(I[JLjava/lang/String;)V"),
+      vec!["I", "[J", "Ljava/lang/String;"]
+    );
+    assert_eq!(
+      parameter_types("([[Ljava/lang/Object;ZD)[I"),
+      vec!["[[Ljava/lang/Object;", "Z", "D"]
+    );
+    // Class names may have `$` and the method name parentheses aren't confused with them
+    assert_eq!(
+      parameter_types("foo(Ljava/util/Map$Entry;C)Z"),
+      vec!["Ljava/util/Map$Entry;", "C"]
+    );
+    assert_eq!(parameter_types("I"), Vec::<&str>::new());
+    assert_eq!(parameter_types(")("), Vec::<&str>::new());
+  }
+
+  #[test]
+  fn strip_descriptors() {
+    assert_eq!(
+      strip_descriptor("append(I)Ljava/lang/StringBuilder;"),
+      "append"
+    );
+    assert_eq!(
+      strip_descriptor("CLASS_Foo____init__()V"),
+      "CLASS_Foo____init__"
+    );
+    assert_eq!(strip_descriptor("run"), "run");
+  }
+
+  #[test]
+  fn java_names() {
+    assert_eq!(java_name("I"), "int");
+    assert_eq!(java_name("Ljava/lang/String;"), "java.lang.String");
+    assert_eq!(java_name("[Ljava/lang/String;"), "[Ljava.lang.String;");
+    assert_eq!(java_name("[[I"), "[[I");
+  }
+
+  #[test]
+  fn mangle_and_demangle() {
+    let class = "Lcom/foo__bar/Baz;";
+    let method = format_method("<init>", "(I)V");
+    let name = format!("{}__{}", format_classname(class), method);
+    assert_eq!(name, "CLASS_com__foo____bar__Baz____init__(I)V");
+    assert_eq!(
+      demangle(&name),
+      Some((class.to_owned(), "<init>".to_owned()))
+    );
+
+    let name = format!(
+      "{}__{}",
+      format_classname(class),
+      format_method("get__it", "()I")
+    );
+    assert_eq!(
+      demangle(&name),
+      Some((class.to_owned(), "get__it".to_owned()))
+    );
+    assert_eq!(demangle("concat"), None);
+  }
+}
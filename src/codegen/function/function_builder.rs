@@ -1,9 +1,9 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 
 use super::instructions::*;
 use super::interpreted::*;
 use super::runtime;
-use super::Function;
+use super::{Function, Modifiers, SourceInfo};
 
 use log::debug;
 
@@ -13,7 +13,11 @@ pub struct FunctionBuilder {
   return_: bool,
   instructions: Vec<(Instruction, Option<HandlerIdx>)>,
   next_handler: Option<usize>,
-  handlers: Vec<HashMap<String, usize>>,
+  offsets: Vec<u32>,
+  offset: u32,
+  handlers: Vec<Handler>,
+  source: SourceInfo,
+  modifiers: Modifiers,
 }
 
 impl FunctionBuilder {
@@ -24,21 +28,28 @@ impl FunctionBuilder {
       return_: false,
       instructions: Vec::new(),
       next_handler: None,
+      offsets: Vec::new(),
+      offset: 0,
       handlers: Vec::new(),
+      source: SourceInfo::default(),
+      modifiers: Modifiers::default(),
     }
   }
 
   pub fn build(self) -> Function {
-    Function::Interpreted(InterpretedFunction::new(
-      self.n_regs,
-      self.n_params,
-      self.return_,
-      self.instructions,
-      self.handlers,
-    ))
-  }
-
-  pub fn set_handlers(&mut self, new_handlers: Vec<HashMap<String, usize>>) {
+    Function::Interpreted(InterpretedFunction {
+      n_regs: self.n_regs,
+      n_params: self.n_params,
+      return_: self.return_,
+      instructions: self.instructions,
+      offsets: self.offsets,
+      handlers: self.handlers,
+      source: self.source,
+      modifiers: self.modifiers,
+    })
+  }
+
+  pub fn set_handlers(&mut self, new_handlers: Vec<Handler>) {
     self.handlers = new_handlers;
   }
 
@@ -46,6 +57,15 @@ impl FunctionBuilder {
     self.next_handler = handler;
   }
 
+  /// Set the bytecode offset of the instructions pushed from now on
+  pub fn set_offset(&mut self, offset: u32) {
+    self.offset = offset;
+  }
+
+  pub fn set_source(&mut self, source: SourceInfo) {
+    self.source = source;
+  }
+
   pub fn set_n_regs(&mut self, new_n_regs: usize) {
     self.n_regs = new_n_regs;
   }
@@ -58,8 +78,17 @@ impl FunctionBuilder {
     self.return_ = new_return;
   }
 
+  pub fn set_static(&mut self, is_static: bool) {
+    self.modifiers.is_static = is_static;
+  }
+
+  pub fn set_synchronized(&mut self, synchronized: bool) {
+    self.modifiers.synchronized = synchronized;
+  }
+
   fn push_instruction(&mut self, ins: Instruction) {
     self.instructions.push((ins, self.next_handler));
+    self.offsets.push(self.offset);
     self.next_handler = None;
   }
 
@@ -72,8 +101,8 @@ impl FunctionBuilder {
   }
 
   /// Move from one register to another
-  pub fn move_v(&mut self, _kind: MoveKind, _va: u16, _vb: u16) {
-    todo!();
+  pub fn move_v(&mut self, kind: MoveKind, va: u16, vb: u16) {
+    self.push_instruction(Instruction::Move(kind, va as usize, vb as usize));
   }
 
   /// Move the results to a register
@@ -96,104 +125,75 @@ impl FunctionBuilder {
     self.push_instruction(Instruction::ConstSet(v, lit))
   }
 
-  /// Monitor enter and exit?
-  pub fn monitor(&mut self) {
-    todo!();
+  /// Acquire the monitor of the object in the given register
+  pub fn monitor_enter(&mut self, v: u8) {
+    self.push_instruction(Instruction::MonitorEnter(v));
+  }
+
+  /// Release the monitor of the object in the given register
+  pub fn monitor_exit(&mut self, v: u8) {
+    self.push_instruction(Instruction::MonitorExit(v));
   }
 
   /// "Throw a ClassCastException if the reference in the given register cannot be cast to the indicated type."
-  pub fn check_cast(&mut self) {
-    todo!();
+  pub fn check_cast(&mut self, v: u8, type_: String) {
+    self.push_instruction(Instruction::CheckCast(v, type_));
   }
 
   /// Store in the given destination register 1 if the indicated reference is an instance of the given type, or 0 if not.
-  pub fn instance_of(&mut self, _va: u8, _vb: u8, _type_: TypeIndex) {
-    todo!();
+  pub fn instance_of(&mut self, v_dest: u8, v_ref: u8, type_: String) {
+    self.push_instruction(Instruction::InstanceOf(v_dest, v_ref, type_));
   }
 
   /// Store in the given destination register the length of the indicated array, in entries
-  pub fn array_length(&mut self, _v_dest: u8, _v_arr: u8) {
-    todo!();
+  pub fn array_length(&mut self, v_dest: u8, v_arr: u8) {
+    self.push_instruction(Instruction::ArrayLength(v_dest, v_arr));
   }
 
   /// Construct a new instance of the indicated type, storing a reference to it in the destination. The type must refer to a non-array class.
-  pub fn new_instance(&mut self, v_dest: u8, type_: TypeIndex) {
+  pub fn new_instance(&mut self, v_dest: u8, type_: String) {
     self.push_instruction(Instruction::NewInstance(v_dest, type_));
   }
 
   /// Construct a new array of the indicated type and size. The type must be an array type.
-  pub fn new_array(&mut self, v_dest: u8, v_size: usize, type_: TypeIndex) {
+  pub fn new_array(&mut self, v_dest: u8, v_size: u8, type_: String) {
     self.push_instruction(Instruction::NewArray(v_dest, v_size, type_));
   }
 
   /// Filled new array
   pub fn filled_new_array(&mut self) {
-    todo!();
+    self.unsupported(&"filled-new-array");
   }
 
   /// Filled new array (/range)
   pub fn filled_new_array_range(&mut self) {
-    todo!();
+    self.unsupported(&"filled-new-array/range");
   }
 
-  /// Fill array data
+  /// Fill array data: elements of `el_width` bytes, in little-endian order
   pub fn fill_array_data(&mut self, arr_v: usize, el_width: usize, data: Vec<u8>) {
-    if el_width > 4 {
-      unimplemented!("Parsing of raw data with el_width > 4");
-    }
-
     let data = match el_width {
-      0 => panic!("Array data with el_width 0 not possible (?)"),
       1 => data
         .iter()
-        //TODO: test this..
-        .map(|x| runtime::Value::U32(*x as i32))
+        .map(|x| runtime::Value::U32(*x as i8 as i32))
         .collect(),
-      2 => {
-        unimplemented!();
-        // if data.len() % 2 != 0 {
-        //   panic!("Array data is uneven (2)");
-        // }
-
-        // let mut out = Vec::new();
-        // let mut cur: i16 = 0;
-
-        // for i in 0..data.len() {
-        //   if i % 2 == 0 {
-        //     cur = (data[i] as i16) << 8;
-        //     continue;
-        //   }
-
-        //   cur |= data[i] as i16;
-        //   out.push(runtime::Value::U32(cur as i32));
-        // }
-
-        // out
-      }
-      3 => unimplemented!(),
-      4 => {
-        //TODO test this...
-
-        debug!("Data: {:?}", data);
-        if data.len() % 4 != 0 {
-          panic!("Array data is uneven (4)");
-        }
-
-        let mut out = Vec::new();
-
-        for i in 0..data.len() / 4 {
-          //TODO Check the endianness?
-          out.push(runtime::Value::U32(i32::from_le_bytes([
-            data[i * 4],
-            data[i * 4 + 1],
-            data[i * 4 + 2],
-            data[i * 4 + 3],
-          ])));
-        }
-
-        out
-      }
-      _ => unreachable!(),
+      2 => data
+        .chunks_exact(2)
+        .map(|x| runtime::Value::U32(i16::from_le_bytes([x[0], x[1]]) as i32))
+        .collect(),
+      4 => data
+        .chunks_exact(4)
+        .map(|x| runtime::Value::U32(i32::from_le_bytes([x[0], x[1], x[2], x[3]])))
+        .collect(),
+      8 => data
+        .chunks_exact(8)
+        .map(|x| {
+          let mut bytes = [0; 8];
+          bytes.copy_from_slice(x);
+          runtime::Value::U64(i64::from_le_bytes(bytes))
+        })
+        .collect(),
+      _ => return self.unsupported(&format!("fill-array-data with {}-byte elements", el_width)),
     };
     debug!("Data: {:?}", data);
     debug!("El width: {:?}", el_width);
@@ -202,8 +202,8 @@ impl FunctionBuilder {
   }
 
   /// Throw the indicated exception
-  pub fn throw(&mut self, _v_ex: u8) {
-    todo!();
+  pub fn throw(&mut self, v_ex: u8) {
+    self.push_instruction(Instruction::Throw(v_ex));
   }
 
   /// Goto the specified label
@@ -213,12 +213,12 @@ impl FunctionBuilder {
 
   /// Switch statement
   pub fn switch(&mut self) {
-    todo!();
+    self.unsupported(&"switch");
   }
 
   /// Perform the indicated floating point or long comparison
-  pub fn cmp_kind(&mut self, _v_dest: u8, _v_b: u8, _v_c: u8, _kind: CmpKind) {
-    todo!();
+  pub fn cmp_kind(&mut self, _v_dest: u8, _v_b: u8, _v_c: u8, kind: CmpKind) {
+    match kind {}
   }
 
   /// Branch to the given destination if the given two registers' values compare as specified.
@@ -226,9 +226,9 @@ impl FunctionBuilder {
     self.push_instruction(Instruction::If(kind, v_first, v_second, target_label));
   }
 
-  /// Branch to the given destination if the given two registers' values compare as specified.
-  pub fn ifz_test(&mut self, _v_test: u8, _target_label: usize) {
-    todo!();
+  /// Branch to the given destination if the given register's value compares with zero as specified.
+  pub fn ifz_test(&mut self, kind: IfKind, v_test: u8, target_label: usize) {
+    self.push_instruction(Instruction::IfZ(kind, v_test, target_label));
   }
 
   /// Perform the identified array operation at the identified index of the given array, storing into the dest register.
@@ -237,48 +237,50 @@ impl FunctionBuilder {
   }
 
   /// Perform the identified array operation at the identified index of the given array, loading from the src register.
-  pub fn array_put(&mut self, _v_src: u8, _v_arr: u8, _v_idx: u8, _kind: GetPutKind) {
-    todo!();
+  pub fn array_put(&mut self, kind: GetPutKind, v_src: u8, v_arr: u8, v_idx: u8) {
+    self.push_instruction(Instruction::ArrayPut(kind, v_src, v_arr, v_idx));
   }
 
   /// Perform the identified object instance field operation with the identified field, storing into the dest register.
-  pub fn instance_get(&mut self, kind: GetPutKind, v_dest: u8, v_inst: u8, field: String) {
+  pub fn instance_get(&mut self, kind: GetPutKind, v_dest: u8, v_inst: u8, field: FieldSlot) {
     self.push_instruction(Instruction::InstanceGet(kind, v_dest, v_inst, field));
   }
 
   /// Perform the identified object instance field operation with the identified field, loading from the src register.
-  pub fn instance_put(&mut self, kind: GetPutKind, v_src: u8, v_inst: u8, field_ref: String) {
-    self.push_instruction(Instruction::InstancePut(kind, v_src, v_inst, field_ref));
+  pub fn instance_put(&mut self, kind: GetPutKind, v_src: u8, v_inst: u8, field: FieldSlot) {
+    self.push_instruction(Instruction::InstancePut(kind, v_src, v_inst, field));
   }
 
   /// Perform the identified object static field operation with the identified static field, storing into the dest register.
-  pub fn static_get(&mut self, kind: GetPutKind, v_dest: u8, static_name: String) {
-    self.push_instruction(Instruction::StaticGet(kind, v_dest, static_name));
+  pub fn static_get(&mut self, kind: GetPutKind, v_dest: u8, static_ref: StaticRef) {
+    self.push_instruction(Instruction::StaticGet(kind, v_dest, static_ref));
   }
 
   /// Perform the identified object static field operation with the identified static field, loading from the src register.
-  pub fn static_put(&mut self, kind: GetPutKind, v_src: u8, static_idx: String) {
-    self.push_instruction(Instruction::StaticPut(kind, v_src, static_idx));
+  pub fn static_put(&mut self, kind: GetPutKind, v_src: u8, static_ref: StaticRef) {
+    self.push_instruction(Instruction::StaticPut(kind, v_src, static_ref));
   }
 
   /// Call the indicated method
-  pub fn invoke(&mut self, kind: InvokeKind, method: String, argc: u8, args: [u8; 5]) {
-    self.push_instruction(Instruction::Invoke(kind, method, argc, args));
+  pub fn invoke(&mut self, kind: InvokeKind, method: MethodRef, argc: u8, args: [u8; 5]) {
+    let args = args.iter().take(argc as usize).map(|a| *a as u16).collect();
+    self.push_instruction(Instruction::Invoke(kind, method, args));
   }
 
-  /// Call the indicated method
-  pub fn invoke_range(&mut self, _kind: InvokeKind, _method: u16, _argc: u8, _v_arg1: u16) {
-    todo!();
+  /// Call the indicated method, with the arguments in `argc` registers starting at `v_arg1`
+  pub fn invoke_range(&mut self, kind: InvokeKind, method: MethodRef, argc: u8, v_arg1: u16) {
+    let args = (v_arg1..v_arg1 + argc as u16).collect();
+    self.push_instruction(Instruction::Invoke(kind, method, args));
   }
 
   /// Perform the identified unary operation on the source register, storing the result in the destination register.
-  pub fn un_op(&mut self, _kind: UnOpKind, _v_dest: u8, _v_src: u8) {
-    todo!();
+  pub fn un_op(&mut self, kind: UnOpKind, _v_dest: u8, _v_src: u8) {
+    self.unsupported(&kind);
   }
 
   /// Perform the identified binary operation on the two source registers, storing the result in the destination register.
-  pub fn bin_op(&mut self, _kind: BinOpKind, _v_dest: u8, _v_src_a: u8, _v_src_b: u8) {
-    todo!();
+  pub fn bin_op(&mut self, kind: BinOpKind, _v_dest: u8, _v_src_a: u8, _v_src_b: u8) {
+    self.unsupported(&kind);
   }
 
   /// Perform the identified binary operation on the two source registers, storing the result in the destination register.
@@ -291,6 +293,12 @@ impl FunctionBuilder {
     self.push_instruction(Instruction::BinOpLit(kind, v_dest, v_src, lit));
   }
 
+  /// An instruction the builder has no instruction for yet. The function can still be built, and
+  /// fails when it gets to the instruction.
+  pub fn unsupported(&mut self, instruction: &dyn std::fmt::Debug) {
+    self.push_instruction(Instruction::Unsupported(format!("{:?}", instruction)));
+  }
+
   /*
   /// invoke-polymorphic
   pub fn invoke_polymorphic(&mut self) {
@@ -301,17 +309,14 @@ impl FunctionBuilder {
   pub fn invoke_polymorphic_range(&mut self) {
     todo!();
   }
+  */
 
-  /// invoke-custom
-  pub fn invoke_custom(&mut self) {
-    todo!();
-  }
-
-  /// invoke-custom/range
-  pub fn invoke_custom_range(&mut self) {
-    todo!();
+  /// invoke-custom and invoke-custom/range: call the target of a call site with the arguments in `args`
+  pub fn invoke_custom(&mut self, call_site: Arc<CallSite>, args: Vec<u16>) {
+    self.push_instruction(Instruction::InvokeCustom(call_site, args));
   }
 
+  /*
   pub fn const_method_handle(&mut self) {
     todo!();
   }
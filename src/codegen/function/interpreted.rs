@@ -1,78 +1,192 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 use log::{debug, error, warn};
 
 use super::instructions;
-use super::instructions::Instruction;
+use super::instructions::{Handler, Instruction};
 use super::runtime;
-use super::{CallStack, InvokeResult, RuntimeError};
+use super::{java_name, CallStack, InvokeResult, Modifiers, RuntimeError, SourceInfo};
 
 pub type HandlerIdx = usize;
 
 macro_rules! throw {
-  ($e:expr, $instr:ident, $cur_exception:ident, $idx:ident, $labels:ident, $handlers:expr, $call_stack:ident) => {
-    if let Some(handler_idx) = $instr.1 {
-      debug!("Throw exception to handler {}", handler_idx);
-
-      match $handlers.get(handler_idx) {
-        None => unreachable!("handler idx not valid"),
-        Some(handler) => {
-          debug!("Handler: {:?}", handler);
-
-          //TODO Check super classes
-          match handler.get($e.get_class_type()) {
-            None => unimplemented!(),
-            Some(target) => {
-              debug!("Found a suitable handler: {}", target);
-
-              $cur_exception = Some($e);
-              $idx = match $labels.get(target) {
-                None => {
-                  unreachable!("The handler's jump target has no label...");
-                  // return InvokeResult::runtime(
-                  //   RuntimeError::BadJumpTarget,
-                  //   call_stack,
-                  // );
-                }
-                Some(index) => *index,
-              }
-            }
-          };
+  ($e:expr, $instr:ident, $cur_exception:ident, $idx:ident, $labels:ident, $handlers:expr, $env:ident, $call_stack:ident) => {
+    match find_handler($instr.1, $handlers, &$e, $env) {
+      Err(e) => return InvokeResult::runtime(e, $call_stack),
+      Ok(None) => return InvokeResult::Exception($e, $call_stack),
+      Ok(Some(target)) => {
+        debug!("Found a suitable handler: {}", target);
+
+        $cur_exception = Some($e);
+        $idx = match $labels.get(&target) {
+          None => return InvokeResult::runtime(RuntimeError::BadJumpTarget, $call_stack),
+          Some(index) => *index,
         }
       }
-    } else {
-      return InvokeResult::Exception($e, $call_stack);
     }
   };
 }
 
-#[derive(Debug)]
-pub struct InterpretedFunction {
-  n_regs: usize,
-  n_params: usize,
-  return_: bool,
-  instructions: Vec<(Instruction, Option<HandlerIdx>)>,
-  handlers: Vec<HashMap<String, usize>>,
+/// Initialize a class before its first use. If its static initializer throws, the exception is
+/// thrown from the current instruction, which is skipped.
+macro_rules! initialize {
+  ($class:expr, $instr:ident, $cur_exception:ident, $idx:ident, $labels:ident, $handlers:expr, $env:ident, $call_stack:ident) => {
+    match $env.initialize_class($class, &$call_stack) {
+      InvokeResult::Ok(_) => {}
+      InvokeResult::Exception(e, cs) => {
+        throw!(
+          e,
+          $instr,
+          $cur_exception,
+          $idx,
+          $labels,
+          $handlers,
+          $env,
+          cs
+        );
+        continue;
+      }
+      e => return e,
+    }
+  };
 }
 
-impl InterpretedFunction {
-  pub fn new(
-    n_regs: usize,
-    n_params: usize,
-    return_: bool,
-    instructions: Vec<(Instruction, Option<HandlerIdx>)>,
-    handlers: Vec<HashMap<String, usize>>,
-  ) -> Self {
-    Self {
-      n_regs,
-      n_params,
-      return_,
-      instructions,
-      handlers,
+/// Find the jump target of the first handler (in declaration order) able to catch an exception.
+/// An exception without a type is only caught by a catch-all.
+fn find_handler(
+  handler_idx: Option<HandlerIdx>,
+  handlers: &[Handler],
+  e: &runtime::Value,
+  env: &super::Module,
+) -> Result<Option<usize>, RuntimeError> {
+  let handler = match handler_idx {
+    None => return Ok(None),
+    Some(idx) => match handlers.get(idx) {
+      None => return Err(RuntimeError::BadHandler(idx)),
+      Some(h) => h,
+    },
+  };
+  debug!("Throw exception to handler {:?}", handler);
+
+  let caught = e.get_type().and_then(|type_| {
+    handler
+      .catches
+      .iter()
+      .find(|(catch_type, _)| env.is_assignable(&type_, catch_type))
+  });
+  Ok(caught.map(|(_, target)| *target).or(handler.catch_all))
+}
+
+/// `if-eq`: ints are compared by value and references by identity
+fn equal(a: &runtime::Value, b: &runtime::Value) -> bool {
+  match (a, b) {
+    (runtime::Value::U32(a), runtime::Value::U32(b)) => a == b,
+    (a, b) => a.same(b),
+  }
+}
+
+/// Whether two values compare as an if instruction tests: references (and ints) can be equal,
+/// and only ints are ordered
+fn compare(
+  kind: &instructions::IfKind,
+  a: &runtime::Value,
+  b: &runtime::Value,
+) -> Result<bool, RuntimeError> {
+  let order = match (kind, a, b) {
+    (instructions::IfKind::Eq, a, b) => return Ok(equal(a, b)),
+    (instructions::IfKind::Ne, a, b) => return Ok(!equal(a, b)),
+    (_, runtime::Value::U32(a), runtime::Value::U32(b)) => a.cmp(b),
+    (_, a, b) => {
+      return Err(RuntimeError::CastError(format!(
+        "{:?} and {:?} as ints",
+        a, b
+      )))
     }
+  };
+
+  Ok(match kind {
+    instructions::IfKind::Eq => order == Ordering::Equal,
+    instructions::IfKind::Ne => order != Ordering::Equal,
+    instructions::IfKind::Lt => order == Ordering::Less,
+    instructions::IfKind::Ge => order != Ordering::Less,
+    instructions::IfKind::Gt => order == Ordering::Greater,
+    instructions::IfKind::Le => order != Ordering::Greater,
+  })
+}
+
+/// The array and the index of an aget or aput instruction. Throws a `NullPointerException` for a
+/// null array and an `ArrayIndexOutOfBoundsException` for an index outside of it.
+fn array_element(
+  registers: &[runtime::Value],
+  v_arr: u8,
+  v_idx: u8,
+  env: &super::Module,
+  call_stack: &Rc<CallStack>,
+) -> Result<(Arc<Mutex<runtime::Array>>, usize), InvokeResult> {
+  let idx = match registers.get(v_idx as usize) {
+    None => {
+      return Err(InvokeResult::runtime(
+        RuntimeError::RegisterOutOfBounds,
+        call_stack.clone(),
+      ))
+    }
+    Some(runtime::Value::U32(idx)) => *idx,
+    Some(v) => {
+      return Err(InvokeResult::runtime(
+        RuntimeError::CastError(format!("{:?} as index", v)),
+        call_stack.clone(),
+      ))
+    }
+  };
+  let array = match registers.get(v_arr as usize) {
+    None => {
+      return Err(InvokeResult::runtime(
+        RuntimeError::RegisterOutOfBounds,
+        call_stack.clone(),
+      ))
+    }
+    Some(a) if a.is_null() => {
+      let e = env.new_exception("Ljava/lang/NullPointerException;", None, call_stack);
+      return Err(InvokeResult::Exception(e, call_stack.clone()));
+    }
+    Some(a) => match a.array() {
+      Ok(a) => a.clone(),
+      Err(e) => return Err(InvokeResult::runtime(e, call_stack.clone())),
+    },
+  };
+
+  let len = array.lock().unwrap().len();
+  if idx < 0 || idx as usize >= len {
+    let message = format!("Index {} out of bounds for length {}", idx, len);
+    let e = env.new_exception(
+      "Ljava/lang/ArrayIndexOutOfBoundsException;",
+      Some(&message),
+      call_stack,
+    );
+    return Err(InvokeResult::Exception(e, call_stack.clone()));
   }
 
+  Ok((array, idx as usize))
+}
+
+/// A function of codegen-IR, built by a `FunctionBuilder`
+#[derive(Debug)]
+pub struct InterpretedFunction {
+  pub(super) n_regs: usize,
+  pub(super) n_params: usize,
+  pub(super) return_: bool,
+  pub(super) instructions: Vec<(Instruction, Option<HandlerIdx>)>,
+  /// Bytecode offset of each instruction, used for stack traces
+  pub(super) offsets: Vec<u32>,
+  pub(super) handlers: Vec<Handler>,
+  pub(super) source: SourceInfo,
+  pub(super) modifiers: Modifiers,
+}
+
+impl InterpretedFunction {
   pub fn n_params(&self) -> usize {
     self.n_params
   }
@@ -85,20 +199,56 @@ impl InterpretedFunction {
     self.return_
   }
 
+  pub fn is_static(&self) -> bool {
+    self.modifiers.is_static
+  }
+
   pub fn instructions(&self) -> &Vec<(Instruction, Option<HandlerIdx>)> {
     &self.instructions
   }
 
-  pub fn handlers(&self) -> &Vec<HashMap<String, usize>> {
+  pub fn handlers(&self) -> &Vec<Handler> {
     &self.handlers
   }
 
+  pub fn source(&self) -> &SourceInfo {
+    &self.source
+  }
+
   /// Run this function using the interpreter
   pub fn run_interpreted(
     &self,
     parameters: Vec<runtime::Value>,
     call_stack: Rc<CallStack>,
     env: &super::Module,
+  ) -> InvokeResult {
+    if !self.modifiers.synchronized {
+      return self.run(parameters, call_stack, env);
+    }
+
+    // The monitor is released however the method ends, like the handler javac adds around a
+    // synchronized block
+    let object = match (self.is_static(), parameters.first()) {
+      (true, _) => env.class_object(&self.source.class),
+      (false, Some(this)) => this.clone(),
+      (false, None) => {
+        return InvokeResult::runtime(
+          RuntimeError::WrongNumberOfParameters(self.n_params, 0),
+          call_stack,
+        )
+      }
+    };
+    env.monitors().enter(&object);
+    let result = self.run(parameters, call_stack, env);
+    env.monitors().exit(&object);
+    result
+  }
+
+  fn run(
+    &self,
+    parameters: Vec<runtime::Value>,
+    call_stack: Rc<CallStack>,
+    env: &super::Module,
   ) -> InvokeResult {
     let mut registers: Vec<runtime::Value> = vec![runtime::Value::Void; self.n_regs];
 
@@ -124,16 +274,32 @@ impl InterpretedFunction {
     }
 
     let mut return_value: runtime::Value = runtime::Value::Void;
-    let mut cur_exception: Option<runtime::Instance> = None;
+    let mut cur_exception: Option<runtime::Value> = None;
     let mut i = 0;
     while i < self.instructions.len() {
       let instr = &self.instructions[i];
+      call_stack.pc.set(self.offsets[i]);
       i += 1;
 
       match &instr.0 {
         Instruction::Label(_) => {}
         Instruction::Nop => {}
-        // Instruction::Move
+        Instruction::Move(kind, v_dest, v_src) => {
+          let value = match registers.get(*v_src) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(v) => v.clone(),
+          };
+          // A wide value is kept in its first register, the second one only pads it
+          let value = match kind {
+            instructions::MoveKind::Wide => runtime::Value::U64(value.to_wide()),
+            _ => value,
+          };
+
+          match registers.get_mut(*v_dest) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(v) => *v = value,
+          }
+        }
         Instruction::MoveResult(kind, v) => {
           let dest = match registers.get_mut(*v as usize) {
             None => {
@@ -148,11 +314,30 @@ impl InterpretedFunction {
                 *dest = return_value;
                 return_value = runtime::Value::Void;
               }
-              _ => todo!("Cast error"),
+              rv => {
+                return InvokeResult::runtime(
+                  RuntimeError::CastError(format!("{:?} as single", rv)),
+                  call_stack,
+                );
+              }
+            },
+            instructions::MoveKind::Wide => match return_value {
+              runtime::Value::U64(_) => {
+                *dest = return_value;
+                return_value = runtime::Value::Void;
+              }
+              rv => {
+                return InvokeResult::runtime(
+                  RuntimeError::CastError(format!("{:?} as wide", rv)),
+                  call_stack,
+                );
+              }
             },
-            instructions::MoveKind::Wide => unimplemented!(),
             instructions::MoveKind::Object => match return_value {
-              runtime::Value::Instance(_) => {
+              runtime::Value::Instance(_)
+              | runtime::Value::Array(_)
+              | runtime::Value::String(_)
+              | runtime::Value::Void => {
                 *dest = return_value;
                 return_value = runtime::Value::Void;
               }
@@ -175,10 +360,13 @@ impl InterpretedFunction {
               Some(v) => v,
             };
 
-            *dest = runtime::Value::from_instance(e);
+            *dest = e;
             cur_exception = None;
           } else {
-            todo!("No exception to move");
+            return InvokeResult::runtime(
+              RuntimeError::Unimplemented("move-exception without an exception".to_owned()),
+              call_stack,
+            );
           }
         }
         Instruction::Return(ty) => match ty {
@@ -191,7 +379,14 @@ impl InterpretedFunction {
               Some(v) => v.to_single(),
             }))
           }
-          instructions::ReturnType::Wide(_) => unimplemented!(),
+          instructions::ReturnType::Wide(v) => {
+            return InvokeResult::Ok(runtime::Value::U64(match registers.get(*v) {
+              None => {
+                return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack);
+              }
+              Some(v) => v.to_wide(),
+            }))
+          }
           instructions::ReturnType::Object(v_o) => match registers.get(*v_o) {
             None => {
               return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack);
@@ -207,7 +402,7 @@ impl InterpretedFunction {
             Some(v) => v,
           };
 
-          *dest = runtime::Value::from(lit);
+          *dest = env.literal(lit);
         }
         Instruction::GoTo(label) => {
           i = match labels.get(label) {
@@ -217,26 +412,157 @@ impl InterpretedFunction {
             Some(index) => *index,
           }
         }
-        //? Do we actually need the type_idx?
-        Instruction::NewInstance(v, _ty) => match registers.get_mut(*v as usize) {
-          None => {
-            return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack);
+        Instruction::NewInstance(v, class) => {
+          initialize!(
+            class,
+            instr,
+            cur_exception,
+            i,
+            labels,
+            &self.handlers,
+            env,
+            call_stack
+          );
+
+          match registers.get_mut(*v as usize) {
+            None => {
+              return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack);
+            }
+            Some(v) => *v = runtime::Value::from_instance(env.new_instance(class)),
           }
-          Some(v) => *v = runtime::Value::new_instance(),
-        },
-        //? Do we actually need the type_idx?
-        Instruction::NewArray(v, size, _ty) => match registers.get_mut(*v as usize) {
-          None => {
-            return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack);
+        }
+        Instruction::NewArray(v, v_size, ty) => {
+          let size = match registers.get(*v_size as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(s) => s.to_single(),
+          };
+
+          if size < 0 {
+            let e = env.new_exception(
+              "Ljava/lang/NegativeArraySizeException;",
+              Some(&size.to_string()),
+              &call_stack,
+            );
+
+            throw!(
+              e,
+              instr,
+              cur_exception,
+              i,
+              labels,
+              &self.handlers,
+              env,
+              call_stack
+            );
+            continue;
           }
-          Some(v) => {
-            *v = runtime::Value::Array(Vec::with_capacity(*size));
+
+          match registers.get_mut(*v as usize) {
+            None => {
+              return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack);
+            }
+            Some(v) => {
+              let zero = runtime::Value::zero(&ty[1..]);
+              *v = runtime::Value::from_array(ty.to_owned(), vec![zero; size as usize]);
+            }
           }
-        },
-        Instruction::FillArrayData(v, data) => match registers.get_mut(*v as usize) {
-          None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
-          Some(r) => *r = runtime::Value::Array(data.clone()),
-        },
+        }
+        Instruction::FillArrayData(v, data) => {
+          let array = match registers.get(*v as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(r) => match r.array() {
+              Ok(a) => a,
+              Err(e) => return InvokeResult::runtime(e, call_stack),
+            },
+          };
+
+          let mut array = array.lock().unwrap();
+          for (idx, value) in data.iter().enumerate() {
+            if array.set(idx, value.clone()).is_none() {
+              return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack);
+            }
+          }
+        }
+        Instruction::MonitorEnter(v) | Instruction::MonitorExit(v) => {
+          let object = match registers.get(*v as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(o) => o.clone(),
+          };
+
+          let e = if object.is_null() {
+            Some(env.new_exception("Ljava/lang/NullPointerException;", None, &call_stack))
+          } else if let Instruction::MonitorEnter(_) = instr.0 {
+            env.monitors().enter(&object);
+            None
+          } else if !env.monitors().exit(&object) {
+            Some(env.new_exception(
+              "Ljava/lang/IllegalMonitorStateException;",
+              Some("current thread is not owner"),
+              &call_stack,
+            ))
+          } else {
+            None
+          };
+
+          if let Some(e) = e {
+            throw!(
+              e,
+              instr,
+              cur_exception,
+              i,
+              labels,
+              &self.handlers,
+              env,
+              call_stack
+            );
+          }
+        }
+        Instruction::CheckCast(v, type_) => {
+          let from = match registers.get(*v as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(r) => r.get_type(),
+          };
+
+          // Null can be cast to anything
+          if let Some(from) = from {
+            if !env.is_assignable(&from, type_) {
+              let e = env.new_exception(
+                "Ljava/lang/ClassCastException;",
+                Some(&format!(
+                  "{} cannot be cast to {}",
+                  java_name(&from),
+                  java_name(type_)
+                )),
+                &call_stack,
+              );
+
+              throw!(
+                e,
+                instr,
+                cur_exception,
+                i,
+                labels,
+                &self.handlers,
+                env,
+                call_stack
+              );
+            }
+          }
+        }
+        Instruction::InstanceOf(v_dest, v_ref, type_) => {
+          let is_instance = match registers.get(*v_ref as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(r) => match r.get_type() {
+              None => false,
+              Some(from) => env.is_assignable(&from, type_),
+            },
+          };
+
+          match registers.get_mut(*v_dest as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(d) => *d = runtime::Value::U32(is_instance as i32),
+          }
+        }
         Instruction::If(kind, v1, v2, label_id) => {
           let v1 = match registers.get(*v1 as usize) {
             None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
@@ -247,25 +573,9 @@ impl InterpretedFunction {
             Some(v) => v,
           };
 
-          let cond = match kind {
-            instructions::IfKind::Eq => unimplemented!(),
-            instructions::IfKind::Ne => match v1 {
-              runtime::Value::U32(a) => match v2 {
-                runtime::Value::U32(b) => a != b,
-                _ => unimplemented!(),
-              },
-              _ => unimplemented!(),
-            },
-            instructions::IfKind::Lt => unimplemented!(),
-            instructions::IfKind::Ge => match v1 {
-              runtime::Value::U32(a) => match v2 {
-                runtime::Value::U32(b) => a >= b,
-                _ => unimplemented!(),
-              },
-              _ => unimplemented!(),
-            },
-            instructions::IfKind::Gt => unimplemented!(),
-            instructions::IfKind::Le => unimplemented!(),
+          let cond = match compare(kind, v1, v2) {
+            Err(e) => return InvokeResult::runtime(e, call_stack),
+            Ok(c) => c,
           };
 
           if cond {
@@ -277,54 +587,93 @@ impl InterpretedFunction {
             }
           }
         }
-        Instruction::ArrayGet(kind, v_dest, v_arr, v_idx) => {
-          let idx = match registers.get(*v_idx as usize) {
+        Instruction::IfZ(kind, v, label_id) => {
+          let v = match registers.get(*v as usize) {
             None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
-            Some(idx) => {
-              let i = match idx {
-                //TODO If v is negative?
-                runtime::Value::U32(v) => *v as u32 as usize,
-                v => {
-                  return InvokeResult::runtime(
-                    RuntimeError::CastError(format!("{:?} as index", v)),
-                    call_stack,
-                  )
-                }
-              };
+            Some(v) => v,
+          };
 
-              i
-            }
+          // Null is zero too, so if-eqz and if-nez test references
+          let cond = match compare(kind, v, &runtime::Value::U32(0)) {
+            Err(e) => return InvokeResult::runtime(e, call_stack),
+            Ok(c) => c,
           };
-          let array = match registers.get(*v_arr as usize) {
-            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
-            Some(arr) => match arr {
-              runtime::Value::Array(a) => a,
-              v => {
-                return InvokeResult::runtime(
-                  RuntimeError::CastError(format!("{:?} as array", v)),
-                  call_stack,
-                )
+
+          if cond {
+            i = match labels.get(label_id) {
+              None => {
+                return InvokeResult::runtime(RuntimeError::BadJumpTarget, call_stack);
               }
-            },
+              Some(index) => *index,
+            }
+          }
+        }
+        Instruction::ArrayGet(_, v_dest, v_arr, v_idx) => {
+          let (array, idx) = match array_element(&registers, *v_arr, *v_idx, env, &call_stack) {
+            Ok(v) => v,
+            Err(InvokeResult::Exception(e, cs)) => {
+              throw!(e, instr, cur_exception, i, labels, &self.handlers, env, cs);
+              continue;
+            }
+            Err(e) => return e,
           };
+          let value = array.lock().unwrap().get(idx).cloned();
 
-          match kind {
-            instructions::GetPutKind::Single | instructions::GetPutKind::Boolean => {
-              let value = match array.get(idx) {
-                None => todo!("Exception..."),
-                Some(v) => v.to_owned(),
-              };
+          match registers.get_mut(*v_dest as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(v) => *v = value.unwrap_or(runtime::Value::Void),
+          }
+        }
+        Instruction::ArrayPut(kind, v_src, v_arr, v_idx) => {
+          let (array, idx) = match array_element(&registers, *v_arr, *v_idx, env, &call_stack) {
+            Ok(v) => v,
+            Err(InvokeResult::Exception(e, cs)) => {
+              throw!(e, instr, cur_exception, i, labels, &self.handlers, env, cs);
+              continue;
+            }
+            Err(e) => return e,
+          };
+          let value = match registers.get(*v_src as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(v) => v.clone(),
+          };
+          // The small kinds store the low bits of the int
+          let value = match kind {
+            instructions::GetPutKind::Byte => runtime::Value::U32(value.to_single() as i8 as i32),
+            instructions::GetPutKind::Char => runtime::Value::U32(value.to_single() as u16 as i32),
+            instructions::GetPutKind::Short => runtime::Value::U32(value.to_single() as i16 as i32),
+            instructions::GetPutKind::Wide => runtime::Value::U64(value.to_wide()),
+            _ => value,
+          };
 
-              match registers.get_mut(*v_dest as usize) {
-                None => {
-                  return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack)
-                }
-                Some(v) => {
-                  *v = value;
-                }
-              }
+          array.lock().unwrap().set(idx, value);
+        }
+        Instruction::ArrayLength(v_dest, v_arr) => {
+          let len = match registers.get(*v_arr as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(a) if a.is_null() => {
+              let e = env.new_exception("Ljava/lang/NullPointerException;", None, &call_stack);
+              throw!(
+                e,
+                instr,
+                cur_exception,
+                i,
+                labels,
+                &self.handlers,
+                env,
+                call_stack
+              );
+              continue;
             }
-            _ => unimplemented!(),
+            Some(a) => match a.array() {
+              Ok(a) => a.lock().unwrap().len(),
+              Err(e) => return InvokeResult::runtime(e, call_stack),
+            },
+          };
+
+          match registers.get_mut(*v_dest as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(v) => *v = runtime::Value::U32(len as i32),
           }
         }
         Instruction::InstanceGet(kind, v_dest, v_obj, field) => {
@@ -343,42 +692,25 @@ impl InterpretedFunction {
             }
           };
 
-          let field_value = match kind {
-            instructions::GetPutKind::Single => {
-              let field_value = match oi.lock().unwrap().get_field(field) {
-                None => {
-                  warn!("Access to un-set field");
-                  runtime::Value::Void
-                }
-                Some(v) => v.clone(),
-              };
-
-              if !field_value.is_u32() {
-                warn!("Ignoring cast-error while running instance get - single");
-              }
-
-              field_value
+          let field_value = match oi.lock().unwrap().get_field(*field) {
+            None => {
+              return InvokeResult::runtime(RuntimeError::FieldOutOfBounds(*field), call_stack);
             }
-            instructions::GetPutKind::Wide => unimplemented!(),
-            instructions::GetPutKind::Object => {
-              let field_value = match oi.lock().unwrap().get_field(field) {
-                None => {
-                  warn!("Access to un-set field");
-                  runtime::Value::Void
-                }
-                Some(v) => v.clone(),
-              };
+            Some(v) => v.clone(),
+          };
 
+          match kind {
+            instructions::GetPutKind::Object => {
               if !field_value.is_instance() {
                 warn!("Ignoring cast-error while running instance get - object");
               }
-
-              field_value
             }
-            instructions::GetPutKind::Boolean => unimplemented!(),
-            instructions::GetPutKind::Byte => unimplemented!(),
-            instructions::GetPutKind::Char => unimplemented!(),
-            instructions::GetPutKind::Short => unimplemented!(),
+            instructions::GetPutKind::Wide => {}
+            _ => {
+              if !field_value.is_u32() {
+                warn!("Ignoring cast-error while running instance get - single");
+              }
+            }
           };
 
           let dest = match registers.get_mut(*v_dest as usize) {
@@ -417,43 +749,41 @@ impl InterpretedFunction {
           };
 
           match kind {
-            instructions::GetPutKind::Single => {
-              if !src.is_u32() {
-                warn!("Ignoring cast error! (-> single)");
-              }
-
-              let cloned = src.clone();
-
-              {
-                let mut ob = ob.lock().unwrap();
-                ob.set_field(field.to_owned(), cloned);
-              }
-            }
-            instructions::GetPutKind::Wide => unimplemented!(),
             instructions::GetPutKind::Object => {
               if !src.is_instance() {
                 warn!("Ignoring cast error! (-> instance)");
-                // return Err(RuntimeError::CastError(format!("{:?} as instance", o)));
               }
-
-              // Clone the Arc
-              let cloned = src.clone();
-
-              {
-                let mut ob = ob.lock().unwrap();
-                ob.set_field(field.to_owned(), cloned);
+            }
+            instructions::GetPutKind::Wide => {}
+            _ => {
+              if !src.is_u32() {
+                warn!("Ignoring cast error! (-> single)");
               }
             }
-            instructions::GetPutKind::Boolean => unimplemented!(),
-            instructions::GetPutKind::Byte => unimplemented!(),
-            instructions::GetPutKind::Char => unimplemented!(),
-            instructions::GetPutKind::Short => unimplemented!(),
           };
+
+          if let Err(e) = ob.lock().unwrap().set_field(*field, src.clone()) {
+            return InvokeResult::runtime(e, call_stack);
+          }
         }
-        Instruction::StaticGet(kind, v_dest, name) => {
-          let s_value = match env.get_static(name) {
+        Instruction::StaticGet(_, v_dest, static_ref) => {
+          initialize!(
+            &static_ref.class,
+            instr,
+            cur_exception,
+            i,
+            labels,
+            &self.handlers,
+            env,
+            call_stack
+          );
+
+          let s_value = match env.get_static(&static_ref.name) {
             None => {
-              warn!("Could not find the specified static variable: {}", name);
+              warn!(
+                "Could not find the specified static variable: {}",
+                static_ref.name
+              );
               runtime::Value::Void
             }
             Some(v) => v,
@@ -463,53 +793,150 @@ impl InterpretedFunction {
             Some(v) => v,
           };
 
-          match kind {
-            instructions::GetPutKind::Single => *dest = s_value,
-            instructions::GetPutKind::Object => *dest = s_value,
-            a => unimplemented!("{:?}", a),
-          }
+          *dest = s_value;
         }
 
-        Instruction::StaticPut(kind, v_src, name) => {
+        Instruction::StaticPut(_, v_src, static_ref) => {
+          initialize!(
+            &static_ref.class,
+            instr,
+            cur_exception,
+            i,
+            labels,
+            &self.handlers,
+            env,
+            call_stack
+          );
+
           let src = match registers.get(*v_src as usize) {
             None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
             Some(v) => v.clone(),
           };
 
-          match kind {
-            instructions::GetPutKind::Single => env.set_static(name.to_owned(), src),
-            a => unimplemented!("{:?}", a),
-          }
+          env.set_static(static_ref.name.to_owned(), src);
         }
-        Instruction::Invoke(kind, name, argc, args) => match kind {
-          instructions::InvokeKind::Direct
-          | instructions::InvokeKind::Static
-          | instructions::InvokeKind::Virtual => {
-            let mut a = Vec::new();
-            for i in 0..*argc as usize {
-              a.push(match registers.get(args[i] as usize) {
-                None => {
-                  return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack)
-                }
-                Some(v) => v.clone(),
-              })
-            }
+        Instruction::Invoke(kind, method, args) => {
+          let mut a = Vec::new();
+          for arg in args {
+            a.push(match registers.get(*arg as usize) {
+              None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+              Some(v) => v.clone(),
+            })
+          }
+
+          if let instructions::InvokeKind::Static = kind {
+            initialize!(
+              &method.class,
+              instr,
+              cur_exception,
+              i,
+              labels,
+              &self.handlers,
+              env,
+              call_stack
+            );
+          }
 
-            let cs = Rc::new(CallStack::extend(name.to_owned(), call_stack.clone()));
-            let rv = env.invoke(name, cs, a);
-            match rv {
-              InvokeResult::Ok(v) => {
-                return_value = v;
+          let fn_name = match kind {
+            instructions::InvokeKind::Static => env.resolve_method(&method.class, &method.name),
+            _ => {
+              if a[0].is_null() {
+                let e = env.new_exception("Ljava/lang/NullPointerException;", None, &call_stack);
+                throw!(
+                  e,
+                  instr,
+                  cur_exception,
+                  i,
+                  labels,
+                  &self.handlers,
+                  env,
+                  call_stack
+                );
+                continue;
               }
-              InvokeResult::Exception(e, cs) => {
-                throw!(e, instr, cur_exception, i, labels, &self.handlers, cs);
+
+              match kind {
+                // Dispatch on the class of the object
+                instructions::InvokeKind::Virtual | instructions::InvokeKind::Interface => a[0]
+                  .get_type()
+                  .and_then(|class| env.resolve_method(&class, &method.name)),
+                _ => env.resolve_method(&method.class, &method.name),
               }
-              // RuntimeError:
-              e => return e,
             }
+          };
+          let fn_name = fn_name.unwrap_or_else(|| method.full_name.clone());
+
+          // Strings are immutable values, so `new String(..)` can't initialize the object created
+          // by new-instance. Its constructor returns the string instead, which replaces every
+          // reference to the uninitialized object (like ART does).
+          let string_init =
+            method.class == "Ljava/lang/String;" && method.name.starts_with("__init__(");
+          let this = if string_init {
+            a.first().cloned()
+          } else {
+            None
+          };
+
+          let cs = Rc::new(CallStack::extend(fn_name.clone(), call_stack.clone()));
+          let rv = env.invoke(&fn_name, cs, a);
+          match rv {
+            InvokeResult::Ok(v) if string_init => {
+              if let Some(this) = this {
+                for r in registers.iter_mut().filter(|r| r.same(&this)) {
+                  *r = v.clone();
+                }
+              }
+            }
+            InvokeResult::Ok(v) => {
+              return_value = v;
+            }
+            InvokeResult::Exception(e, cs) => {
+              throw!(e, instr, cur_exception, i, labels, &self.handlers, env, cs);
+            }
+            // RuntimeError:
+            e => return e,
           }
-          _ => unimplemented!(),
-        },
+        }
+        Instruction::InvokeCustom(call_site, args) => {
+          let mut a = Vec::new();
+          for r in args {
+            a.push(match registers.get(*r as usize) {
+              None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+              Some(v) => v.clone(),
+            })
+          }
+
+          match env.invoke_custom(call_site, call_stack.clone(), a) {
+            InvokeResult::Ok(v) => {
+              return_value = v;
+            }
+            InvokeResult::Exception(e, cs) => {
+              throw!(e, instr, cur_exception, i, labels, &self.handlers, env, cs);
+            }
+            // RuntimeError:
+            e => return e,
+          }
+        }
+        Instruction::Throw(v) => {
+          let e = match registers.get(*v as usize) {
+            None => return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack),
+            Some(e) if e.is_null() => {
+              env.new_exception("Ljava/lang/NullPointerException;", None, &call_stack)
+            }
+            Some(e) => e.clone(),
+          };
+
+          throw!(
+            e,
+            instr,
+            cur_exception,
+            i,
+            labels,
+            &self.handlers,
+            env,
+            call_stack
+          );
+        }
         Instruction::BinOp2Addr(kind, v_dest, v_src) => match kind {
           instructions::BinOpKind::AddInt => {
             let dest_val = match registers.get(*v_dest as usize) {
@@ -526,37 +953,12 @@ impl InterpretedFunction {
               Some(dest) => *dest = runtime::Value::U32(dest_val + src_val),
             }
           }
-          instructions::BinOpKind::SubInt => unimplemented!(),
-          instructions::BinOpKind::MulInt => unimplemented!(),
-          instructions::BinOpKind::DivInt => unimplemented!(),
-          instructions::BinOpKind::RemInt => unimplemented!(),
-          instructions::BinOpKind::AndInt => unimplemented!(),
-          instructions::BinOpKind::OrInt => unimplemented!(),
-          instructions::BinOpKind::XorInt => unimplemented!(),
-          instructions::BinOpKind::ShlInt => unimplemented!(),
-          instructions::BinOpKind::ShrInt => unimplemented!(),
-          instructions::BinOpKind::UshrInt => unimplemented!(),
-          instructions::BinOpKind::AddLong => unimplemented!(),
-          instructions::BinOpKind::SubLong => unimplemented!(),
-          instructions::BinOpKind::MulLong => unimplemented!(),
-          instructions::BinOpKind::DivLong => unimplemented!(),
-          instructions::BinOpKind::RemLong => unimplemented!(),
-          instructions::BinOpKind::AndLong => unimplemented!(),
-          instructions::BinOpKind::OrLong => unimplemented!(),
-          instructions::BinOpKind::XorLong => unimplemented!(),
-          instructions::BinOpKind::ShlLong => unimplemented!(),
-          instructions::BinOpKind::ShrLong => unimplemented!(),
-          instructions::BinOpKind::UshrLong => unimplemented!(),
-          instructions::BinOpKind::AddFloat => unimplemented!(),
-          instructions::BinOpKind::SubFloat => unimplemented!(),
-          instructions::BinOpKind::MulFloat => unimplemented!(),
-          instructions::BinOpKind::DivFloat => unimplemented!(),
-          instructions::BinOpKind::RemFloat => unimplemented!(),
-          instructions::BinOpKind::AddDouble => unimplemented!(),
-          instructions::BinOpKind::SubDouble => unimplemented!(),
-          instructions::BinOpKind::MulDouble => unimplemented!(),
-          instructions::BinOpKind::DivDouble => unimplemented!(),
-          instructions::BinOpKind::RemDouble => unimplemented!(),
+          kind => {
+            return InvokeResult::runtime(
+              RuntimeError::Unimplemented(format!("{:?}", kind)),
+              call_stack,
+            )
+          }
         },
         Instruction::BinOpLit(kind, v_dest, v_src, lit) => {
           match kind {
@@ -577,7 +979,7 @@ impl InterpretedFunction {
 
               *dest = runtime::Value::U32(src + *lit as i32);
             }
-            instructions::BinOpLitKind::DivInt => {
+            instructions::BinOpLitKind::DivInt | instructions::BinOpLitKind::RemInt => {
               let src = match registers.get(*v_src as usize) {
                 None => {
                   return InvokeResult::runtime(RuntimeError::RegisterOutOfBounds, call_stack)
@@ -585,11 +987,10 @@ impl InterpretedFunction {
                 Some(v) => v.to_single(),
               };
               if *lit == 0 {
-                let mut e = runtime::Instance::default();
-                e.set_class_type("Ljava/lang/ArithmeticException;".to_owned());
-                e.set_field(
-                  "value".to_owned(),
-                  runtime::Value::from(&instructions::LiteralValue::String("/ by zero".to_owned())),
+                let e = env.new_exception(
+                  "Ljava/lang/ArithmeticException;",
+                  Some("/ by zero"),
+                  &call_stack,
                 );
 
                 throw!(
@@ -599,6 +1000,7 @@ impl InterpretedFunction {
                   i,
                   labels,
                   &self.handlers,
+                  env,
                   call_stack
                 );
               } else {
@@ -609,20 +1011,31 @@ impl InterpretedFunction {
                   Some(v) => v,
                 };
 
-                *dest = runtime::Value::U32(src / *lit as i32)
+                *dest = runtime::Value::U32(match kind {
+                  instructions::BinOpLitKind::DivInt => src.wrapping_div(*lit as i32),
+                  _ => src.wrapping_rem(*lit as i32),
+                })
               }
             }
             // instructions::BinOpLitKind::RsubInt => {}
             // instructions::BinOpLitKind::MulInt => {}
-            // instructions::BinOpLitKind::DivInt => {}
-            // instructions::BinOpLitKind::RemInt => {}
             // instructions::BinOpLitKind::AndInt => {}
             // instructions::BinOpLitKind::OrInt => {}
             // instructions::BinOpLitKind::XorInt => {}
-            a => unimplemented!("BinOpLit: {:?}", a),
+            a => {
+              return InvokeResult::runtime(
+                RuntimeError::Unimplemented(format!("BinOpLit: {:?}", a)),
+                call_stack,
+              )
+            }
           }
         }
-        a => unimplemented!("Interp. {:?}", a),
+        Instruction::Unsupported(instruction) => {
+          return InvokeResult::runtime(
+            RuntimeError::Unimplemented(instruction.clone()),
+            call_stack,
+          )
+        }
       }
     }
 
@@ -645,3 +1058,36 @@ impl InterpretedFunction {
     out.join("\n")
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn handler(catch_all: Option<usize>) -> Handler {
+    Handler {
+      catches: vec![("Ljava/lang/Exception;".to_owned(), 1)],
+      catch_all,
+    }
+  }
+
+  #[test]
+  fn find_handler_catch_all_without_type() {
+    let env = super::super::Module::new("test".to_owned());
+    let handlers = vec![handler(None), handler(Some(2))];
+    let e = runtime::Value::Void;
+
+    assert_eq!(find_handler(None, &handlers, &e, &env).unwrap(), None);
+    assert_eq!(find_handler(Some(0), &handlers, &e, &env).unwrap(), None);
+    assert_eq!(find_handler(Some(1), &handlers, &e, &env).unwrap(), Some(2));
+  }
+
+  #[test]
+  fn find_handler_bad_index() {
+    let env = super::super::Module::new("test".to_owned());
+
+    match find_handler(Some(3), &vec![handler(None)], &runtime::Value::Void, &env) {
+      Err(RuntimeError::BadHandler(3)) => {}
+      r => panic!("{:?}", r),
+    }
+  }
+}
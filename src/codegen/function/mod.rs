@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::fmt;
 use std::rc::Rc;
 
@@ -6,7 +7,7 @@ pub mod interpreted;
 
 pub use interpreted::InterpretedFunction;
 
-use super::{instructions, runtime, InvokeResult, Module, RuntimeError};
+use super::{instructions, java_name, runtime, InvokeResult, Module, RuntimeError};
 
 #[derive(Debug)]
 pub enum Function {
@@ -21,11 +22,43 @@ impl Function {
       Function::Native(_) => format!("native func {:?};", name),
     }
   }
+
+  /// Whether the function implements a static method, which has no `this`
+  pub fn is_static(&self) -> bool {
+    match self {
+      Function::Interpreted(ifn) => ifn.is_static(),
+      Function::Native(f) => f.is_static,
+    }
+  }
 }
 
-pub struct NativeFunction(
-  pub fn(Vec<runtime::Value>, Rc<CallStack>, &super::Module) -> InvokeResult,
-);
+/// A function implemented in Rust. It can be a closure capturing host state, which is shared
+/// like the rest of the module.
+pub struct NativeFunction {
+  pub function: Box<NativeFn>,
+  /// Whether it implements a static method. Functions which aren't methods, like those of call
+  /// sites, are not.
+  pub is_static: bool,
+}
+
+/// The Rust side of a `NativeFunction`, called with the parameters of the function
+pub type NativeFn =
+  dyn Fn(Vec<runtime::Value>, Rc<CallStack>, &super::Module) -> InvokeResult + Send + Sync;
+
+impl NativeFunction {
+  pub fn new<F>(f: F) -> Self
+  where
+    F: Fn(Vec<runtime::Value>, Rc<CallStack>, &super::Module) -> InvokeResult
+      + Send
+      + Sync
+      + 'static,
+  {
+    NativeFunction {
+      function: Box::new(f),
+      is_static: false,
+    }
+  }
+}
 
 impl fmt::Debug for NativeFunction {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -33,9 +66,77 @@ impl fmt::Debug for NativeFunction {
   }
 }
 
+/// The modifiers of the method an interpreted function implements
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Modifiers {
+  /// A static method has no `this`
+  pub is_static: bool,
+  /// A synchronized method holds the monitor of `this`, or of its class if it is static, while
+  /// it runs
+  pub synchronized: bool,
+}
+
+/// Where an interpreted function comes from, used to build stack traces
+#[derive(Debug, Default)]
+pub struct SourceInfo {
+  /// Type descriptor of the declaring class
+  pub class: String,
+  pub method: String,
+  pub source_file: Option<String>,
+  /// The line number table as (bytecode offset, line), sorted by offset
+  pub lines: Vec<(u32, u32)>,
+}
+
+impl SourceInfo {
+  /// The source line of the instruction at a bytecode offset (in 16-bit code units)
+  pub fn line(&self, pc: u32) -> Option<u32> {
+    self
+      .lines
+      .iter()
+      .take_while(|(addr, _)| *addr <= pc)
+      .last()
+      .map(|(_, line)| *line)
+  }
+
+  pub fn frame(&self, pc: u32) -> StackFrame {
+    StackFrame {
+      class: java_name(&self.class),
+      method: self.method.clone(),
+      source_file: self.source_file.clone(),
+      line: self.line(pc),
+      native: false,
+    }
+  }
+}
+
+/// A frame of a Java stack trace (`java.lang.StackTraceElement`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+  /// The Java name of the declaring class (`com.foo.Bar`)
+  pub class: String,
+  pub method: String,
+  pub source_file: Option<String>,
+  pub line: Option<u32>,
+  pub native: bool,
+}
+
+impl fmt::Display for StackFrame {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}(", self.class, self.method)?;
+    match (&self.source_file, self.line) {
+      _ if self.native => write!(f, "Native Method)"),
+      (Some(file), Some(line)) => write!(f, "{}:{})", file, line),
+      (Some(file), None) => write!(f, "{})", file),
+      (None, _) => write!(f, "Unknown Source)"),
+    }
+  }
+}
+
 pub struct CallStack {
   pub prev: Option<Rc<CallStack>>,
   pub cur: String,
+  /// Bytecode offset of the instruction this frame is executing
+  pub pc: Cell<u32>,
 }
 
 impl Default for CallStack {
@@ -43,6 +144,7 @@ impl Default for CallStack {
     Self {
       prev: None,
       cur: String::from("root"),
+      pc: Cell::new(0),
     }
   }
 }
@@ -52,21 +154,29 @@ impl CallStack {
     Self {
       prev: Some(prev),
       cur,
+      pc: Cell::new(0),
     }
   }
-  /// Turn the call stack into a finished version, to be printed
-  pub fn finalize(&self) -> String {
-    let mut out = vec![];
 
-    out.push(format!("\tin {:?}", self.cur));
+  /// The frames in the stack, innermost first (without the root)
+  pub fn frames(&self) -> Vec<&CallStack> {
+    let mut out = vec![];
+    let mut cur = self;
+    while let Some(prev) = &cur.prev {
+      out.push(cur);
+      cur = prev;
+    }
 
-    match &self.prev {
-      Some(prev) => {
-        out.push(prev.finalize());
-      }
-      None => {}
-    };
+    out
+  }
 
-    out.join("\n")
+  /// Turn the call stack into a finished version, to be printed
+  pub fn finalize(&self) -> String {
+    self
+      .frames()
+      .iter()
+      .map(|f| format!("\tin {} at {:#06x}", f.cur, f.pc.get()))
+      .collect::<Vec<_>>()
+      .join("\n")
   }
 }
@@ -0,0 +1,642 @@
+//! Using Java from Rust, for programs embedding the runtime: look up classes, call their methods
+//! with typed arguments, create objects and access fields, e.g.
+//!
+//! ```ignore
+//! let sum: i32 = module
+//!   .class("com.foo.Calc")?
+//!   .static_method("add", "(II)I")?
+//!   .call((1, 2))?;
+//! ```
+//!
+//! Like JNI, methods and fields are looked up with their descriptors. Arguments are converted
+//! with `IntoValue` and results with `FromValue`, and a Rust type which doesn't convert to or from
+//! the Java type is a `HostError::WrongType`. Null arguments and results are `Option`s, and
+//! objects are `Value`s, or `ObjectHandle`s to call their methods.
+//!
+//! Java can also call back into Rust, through objects implementing interfaces with closures:
+//!
+//! ```ignore
+//! let clicks = Arc::new(AtomicUsize::new(0));
+//! let counter = clicks.clone();
+//! let listener = module
+//!   .implement(&["com.foo.ClickListener"])
+//!   .method("onClick", "(I)V", native!(|ctx, this: Value, button: i32| {
+//!     counter.fetch_add(1, Ordering::SeqCst);
+//!   }))?
+//!   .build();
+//! ```
+
+use std::fmt;
+use std::rc::Rc;
+
+use super::native::{Context, FromValue, IntoValue, TypedNative};
+use super::runtime::{Instance, Value};
+use super::{
+  format_classname, format_method, format_name, is_reference, java_name, parameter_types,
+  CallStack, InvokeResult, Module, ProxyClass, RuntimeErrorStack,
+};
+
+/// Why a call from Rust failed
+#[derive(Debug)]
+pub enum HostError {
+  /// No class with this name is loaded
+  NoSuchClass(String),
+  /// No method with this name and descriptor, e.g. `com.foo.Calc.add(II)I`
+  NoSuchMethod(String),
+  /// No field with this name, e.g. `com.foo.Calc.count`
+  NoSuchField(String),
+  /// A Rust type doesn't convert to or from a Java type: (Rust type, Java type)
+  WrongType(String, String),
+  /// A call was given the wrong number of arguments: (expected, got)
+  WrongNumberOfArguments(usize, usize),
+  /// The object is null
+  NullObject,
+  /// A closure doesn't match the method it implements
+  WrongNative(String),
+  /// The call threw an exception, which it didn't catch
+  Exception(Value),
+  /// The call failed with a runtime error
+  Runtime(RuntimeErrorStack),
+}
+
+impl fmt::Display for HostError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      HostError::NoSuchClass(c) => write!(f, "No such class: {}", c),
+      HostError::NoSuchMethod(m) => write!(f, "No such method: {}", m),
+      HostError::NoSuchField(field) => write!(f, "No such field: {}", field),
+      HostError::WrongType(rust, java) => write!(f, "{} doesn't convert to or from {}", rust, java),
+      HostError::WrongNumberOfArguments(expected, got) => {
+        write!(f, "Expected {} arguments, got {}", expected, got)
+      }
+      HostError::NullObject => write!(f, "The object is null"),
+      HostError::WrongNative(e) => write!(f, "Wrong native: {}", e),
+      HostError::Exception(e) => write!(
+        f,
+        "Uncaught exception: {}",
+        java_name(&e.get_type().unwrap_or_default())
+      ),
+      HostError::Runtime(e) => write!(f, "Runtime error: {:?}", e.error),
+    }
+  }
+}
+
+impl std::error::Error for HostError {}
+
+impl From<InvokeResult> for HostError {
+  fn from(r: InvokeResult) -> Self {
+    match r {
+      InvokeResult::Exception(e, _) => HostError::Exception(e),
+      InvokeResult::RuntimeError(e) => HostError::Runtime(e),
+      // Not an error, but converting it failed
+      InvokeResult::Ok(v) => HostError::WrongType(format!("{:?}", v), "a result".to_owned()),
+    }
+  }
+}
+
+/// The arguments of a call from Rust: `()`, or a tuple of values converting to the parameters
+pub trait IntoArguments {
+  /// The registers of the arguments, for parameters of some types
+  fn into_arguments(self, parameters: &[&str]) -> Result<Vec<Value>, HostError>;
+}
+
+/// Convert an argument, adding the padding register of longs and doubles
+fn push_argument<T: IntoValue>(
+  arg: T,
+  parameter: &str,
+  registers: &mut Vec<Value>,
+) -> Result<(), HostError> {
+  if !T::converts_to(parameter) {
+    return Err(wrong_type::<T>(parameter));
+  }
+
+  registers.push(arg.into_value(parameter));
+  if parameter == "J" || parameter == "D" {
+    registers.push(Value::Void);
+  }
+  Ok(())
+}
+
+fn wrong_type<T>(descriptor: &str) -> HostError {
+  HostError::WrongType(std::any::type_name::<T>().to_owned(), java_name(descriptor))
+}
+
+/// Implement `IntoArguments` for tuples of `IntoValue`s
+macro_rules! arguments {
+  ($n:literal $(, $arg:ident)*) => {
+    impl<$($arg: IntoValue),*> IntoArguments for ($($arg,)*) {
+      #[allow(non_snake_case, unused_mut, unused_variables)]
+      fn into_arguments(self, parameters: &[&str]) -> Result<Vec<Value>, HostError> {
+        if parameters.len() != $n {
+          return Err(HostError::WrongNumberOfArguments(parameters.len(), $n));
+        }
+
+        let ($($arg,)*) = self;
+        let mut parameters = parameters.iter();
+        let mut registers = Vec::new();
+        $(push_argument($arg, parameters.next().unwrap(), &mut registers)?;)*
+        Ok(registers)
+      }
+    }
+  };
+}
+
+arguments!(0);
+arguments!(1, A);
+arguments!(2, A, B);
+arguments!(3, A, B, C);
+arguments!(4, A, B, C, D);
+arguments!(5, A, B, C, D, E);
+arguments!(6, A, B, C, D, E, F);
+arguments!(7, A, B, C, D, E, F, G);
+arguments!(8, A, B, C, D, E, F, G, H);
+
+/// Run a function of the module from Rust, converting its result
+fn call<R: FromValue>(module: &Module, function: &str, params: Vec<Value>) -> Result<R, HostError> {
+  let cs = Rc::new(CallStack::extend(
+    function.to_owned(),
+    Rc::new(CallStack::default()),
+  ));
+  let result = match module.invoke(function, cs.clone(), params) {
+    InvokeResult::Ok(v) => v,
+    r => return Err(r.into()),
+  };
+
+  R::from_value(&result, &Context { module, cs: &cs }).map_err(HostError::from)
+}
+
+/// The return type of a method descriptor, checking that a Rust type converts from it
+fn return_type<R: FromValue>(descriptor: &str) -> Result<&str, HostError> {
+  let return_type = match descriptor.find(')') {
+    None => return Err(HostError::NoSuchMethod(descriptor.to_owned())),
+    Some(i) => &descriptor[i + 1..],
+  };
+  match R::accepts(return_type) {
+    false => Err(wrong_type::<R>(return_type)),
+    true => Ok(return_type),
+  }
+}
+
+/// The value of a field, which is zero until it is set
+fn field_value(v: Value, descriptor: &str) -> Value {
+  match v {
+    Value::Void => Value::zero(descriptor),
+    v => v,
+  }
+}
+
+/// The type descriptor of a class, by its Java name or its descriptor
+fn type_descriptor(name: &str) -> String {
+  match name {
+    n if n.starts_with('[') || (n.starts_with('L') && n.ends_with(';')) => n.to_owned(),
+    n => format!("L{};", n.replace('.', "/")),
+  }
+}
+
+/// A class, to call its static methods, create objects and access its static fields
+#[derive(Clone)]
+pub struct ClassHandle<'m> {
+  module: &'m Module,
+  /// Type descriptor of the class
+  class: String,
+}
+
+impl<'m> ClassHandle<'m> {
+  /// Find a class by its Java name, like `com.foo.Calc`, or its type descriptor
+  pub(super) fn new(module: &'m Module, name: &str) -> Result<Self, HostError> {
+    let class = type_descriptor(name);
+
+    // Classes of natives alone, like `java.lang.Math`, only have functions
+    let prefix = format!("{}__", format_classname(&class));
+    if module.get_class(&class).is_none() && !module.has_function_with_prefix(&prefix) {
+      return Err(HostError::NoSuchClass(name.to_owned()));
+    }
+
+    Ok(ClassHandle { module, class })
+  }
+
+  /// Type descriptor of the class
+  pub fn descriptor(&self) -> &str {
+    &self.class
+  }
+
+  /// Run the static initializer of the class, if it didn't run yet
+  fn initialize(&self) -> Result<(), HostError> {
+    let cs = Rc::new(CallStack::default());
+    match self.module.initialize_class(&self.class, &cs) {
+      InvokeResult::Ok(_) => Ok(()),
+      r => Err(r.into()),
+    }
+  }
+
+  /// `com.foo.Calc.add(II)I`, for errors
+  fn member(&self, name: &str) -> String {
+    format!("{}.{}", java_name(&self.class), name)
+  }
+
+  /// A static method, by its name and descriptor, e.g. `("add", "(II)I")`. Like in Java, it can
+  /// be declared by a superclass. An instance method with this descriptor is no such method,
+  /// since it needs an object.
+  pub fn static_method(&self, name: &str, descriptor: &str) -> Result<StaticMethod<'m>, HostError> {
+    let method = format_method(name, descriptor);
+    match self.module.resolve_method(&self.class, &method) {
+      Some(function) if self.module.is_static_function(&function) => Ok(StaticMethod {
+        class: self.clone(),
+        function,
+        descriptor: descriptor.to_owned(),
+      }),
+      _ => Err(HostError::NoSuchMethod(
+        self.member(&format!("{}{}", name, descriptor)),
+      )),
+    }
+  }
+
+  /// Create an object with the constructor of a descriptor, e.g. `"(I)V"`
+  pub fn new_object<A: IntoArguments>(
+    &self,
+    descriptor: &str,
+    args: A,
+  ) -> Result<ObjectHandle<'m>, HostError> {
+    // Like `invoke-direct`, this finds the natives of superclasses too, e.g. exceptions use the
+    // constructors of `java.lang.Throwable`
+    let constructor = format_method("<init>", descriptor);
+    let function = self
+      .module
+      .resolve_method(&self.class, &constructor)
+      .ok_or_else(|| HostError::NoSuchMethod(self.member(&format!("<init>{}", descriptor))))?;
+
+    self.initialize()?;
+    let object = Value::from_instance(self.module.new_instance(&self.class));
+    let mut params = vec![object.clone()];
+    params.extend(args.into_arguments(&parameter_types(descriptor))?);
+
+    // Like `new String(..)` in bytecode, a constructor returning a value replaces the object
+    let result: Value = call(self.module, &function, params)?;
+    let object = if result.is_null() { object } else { result };
+    Ok(ObjectHandle {
+      module: self.module,
+      object,
+    })
+  }
+
+  fn static_name(&self, name: &str) -> String {
+    format!("{}__{}", format_classname(&self.class), format_name(name))
+  }
+
+  /// Read a static field with a type descriptor, e.g. `("count", "I")`
+  pub fn get_static<T: FromValue>(&self, name: &str, descriptor: &str) -> Result<T, HostError> {
+    if !T::accepts(descriptor) {
+      return Err(wrong_type::<T>(descriptor));
+    }
+
+    self.initialize()?;
+    let value = match self.module.get_static(&self.static_name(name)) {
+      None => return Err(HostError::NoSuchField(self.member(name))),
+      Some(v) => field_value(v, descriptor),
+    };
+    let cs = Rc::new(CallStack::default());
+    T::from_value(
+      &value,
+      &Context {
+        module: self.module,
+        cs: &cs,
+      },
+    )
+    .map_err(HostError::from)
+  }
+
+  /// Set a static field with a type descriptor, e.g. `("count", "I", 3)`
+  pub fn set_static<T: IntoValue>(
+    &self,
+    name: &str,
+    descriptor: &str,
+    value: T,
+  ) -> Result<(), HostError> {
+    if !T::converts_to(descriptor) {
+      return Err(wrong_type::<T>(descriptor));
+    }
+
+    self.initialize()?;
+    let static_name = self.static_name(name);
+    if self.module.get_static(&static_name).is_none() {
+      return Err(HostError::NoSuchField(self.member(name)));
+    }
+    self
+      .module
+      .set_static(static_name, value.into_value(descriptor));
+    Ok(())
+  }
+}
+
+/// A static method of a class
+pub struct StaticMethod<'m> {
+  class: ClassHandle<'m>,
+  /// The function implementing it
+  function: String,
+  descriptor: String,
+}
+
+impl<'m> StaticMethod<'m> {
+  /// Call the method, after initializing its class. The result converts from the return type,
+  /// which is `()` for `void`.
+  pub fn call<R: FromValue, A: IntoArguments>(&self, args: A) -> Result<R, HostError> {
+    return_type::<R>(&self.descriptor)?;
+    let params = args.into_arguments(&parameter_types(&self.descriptor))?;
+
+    self.class.initialize()?;
+    call(self.class.module, &self.function, params)
+  }
+}
+
+/// An object, to call its methods and access its fields
+#[derive(Clone)]
+pub struct ObjectHandle<'m> {
+  module: &'m Module,
+  object: Value,
+}
+
+impl<'m> ObjectHandle<'m> {
+  /// A handle of an object which isn't null
+  pub(super) fn new(module: &'m Module, object: Value) -> Result<Self, HostError> {
+    match object.get_type() {
+      None => Err(HostError::NullObject),
+      Some(_) => Ok(ObjectHandle { module, object }),
+    }
+  }
+
+  /// The object, to pass it to Java
+  pub fn value(&self) -> &Value {
+    &self.object
+  }
+
+  /// Type descriptor of the class of the object
+  pub fn class(&self) -> String {
+    self.object.get_type().unwrap_or_default()
+  }
+
+  /// Call a method of the object, by its name and descriptor. Like `invokevirtual`, the method
+  /// of the class of the object is called.
+  pub fn call<R: FromValue, A: IntoArguments>(
+    &self,
+    name: &str,
+    descriptor: &str,
+    args: A,
+  ) -> Result<R, HostError> {
+    let class = self.class();
+    let function = match self
+      .module
+      .resolve_method(&class, &format_method(name, descriptor))
+    {
+      None => {
+        return Err(HostError::NoSuchMethod(format!(
+          "{}.{}{}",
+          java_name(&class),
+          name,
+          descriptor
+        )))
+      }
+      Some(f) => f,
+    };
+
+    return_type::<R>(descriptor)?;
+    let mut params = vec![self.object.clone()];
+    params.extend(args.into_arguments(&parameter_types(descriptor))?);
+    call(self.module, &function, params)
+  }
+
+  /// The slot of a field, checking that it holds values of a type
+  fn field_slot(&self, name: &str) -> Result<usize, HostError> {
+    let class = self.class();
+    self
+      .module
+      .get_class(&class)
+      .and_then(|c| c.field_slot(name))
+      .ok_or_else(|| HostError::NoSuchField(format!("{}.{}", java_name(&class), name)))
+  }
+
+  /// Read a field with a type descriptor, e.g. `("count", "I")`
+  pub fn get_field<T: FromValue>(&self, name: &str, descriptor: &str) -> Result<T, HostError> {
+    if !T::accepts(descriptor) {
+      return Err(wrong_type::<T>(descriptor));
+    }
+
+    let slot = self.field_slot(name)?;
+    let value = match self.object.instance() {
+      Err(e) => return Err(HostError::Runtime(RuntimeErrorStack::new(e, Rc::default()))),
+      Ok(i) => i.lock().unwrap().get_field(slot).cloned(),
+    };
+    let value = field_value(value.unwrap_or(Value::Void), descriptor);
+    let cs = Rc::new(CallStack::default());
+    T::from_value(
+      &value,
+      &Context {
+        module: self.module,
+        cs: &cs,
+      },
+    )
+    .map_err(HostError::from)
+  }
+
+  /// Set a field with a type descriptor, e.g. `("count", "I", 3)`
+  pub fn set_field<T: IntoValue>(
+    &self,
+    name: &str,
+    descriptor: &str,
+    value: T,
+  ) -> Result<(), HostError> {
+    if !T::converts_to(descriptor) {
+      return Err(wrong_type::<T>(descriptor));
+    }
+
+    let slot = self.field_slot(name)?;
+    let result = self.object.instance().and_then(|i| {
+      i.lock()
+        .unwrap()
+        .set_field(slot, value.into_value(descriptor))
+    });
+    result.map_err(|e| HostError::Runtime(RuntimeErrorStack::new(e, Rc::default())))
+  }
+}
+
+/// An object passed to Java
+impl IntoValue for &ObjectHandle<'_> {
+  fn converts_to(descriptor: &str) -> bool {
+    is_reference(descriptor)
+  }
+
+  fn into_value(self, _descriptor: &str) -> Value {
+    self.object.clone()
+  }
+}
+
+/// An object implemented in Rust, whose methods are closures. Methods it doesn't implement are
+/// the default methods of its interfaces and the methods of `Object`.
+pub struct Implementation<'m> {
+  module: &'m Module,
+  /// Type descriptors of the interfaces
+  interfaces: Vec<String>,
+  /// Mangled names of the methods, with their descriptors, and their natives
+  methods: Vec<(String, TypedNative)>,
+}
+
+impl<'m> Implementation<'m> {
+  /// Like lambdas, it can implement interfaces the module doesn't know, e.g. of the core library
+  pub(super) fn new(module: &'m Module, interfaces: &[&str]) -> Self {
+    Implementation {
+      module,
+      interfaces: interfaces.iter().map(|i| type_descriptor(i)).collect(),
+      methods: Vec::new(),
+    }
+  }
+
+  /// Implement a method, by its name and descriptor, with a native whose first parameter is the
+  /// object (`this`)
+  pub fn method(
+    mut self,
+    name: &str,
+    descriptor: &str,
+    native: TypedNative,
+  ) -> Result<Self, HostError> {
+    let return_type = match descriptor.find(')') {
+      None => return Err(HostError::NoSuchMethod(descriptor.to_owned())),
+      Some(i) => &descriptor[i + 1..],
+    };
+    let mut parameters = parameter_types(descriptor);
+    parameters.insert(0, "Ljava/lang/Object;");
+    if let Err(e) = native.check(&parameters, return_type) {
+      return Err(HostError::WrongNative(format!(
+        "The native of {}{} {}",
+        name, descriptor, e
+      )));
+    }
+
+    self.methods.push((format_method(name, descriptor), native));
+    Ok(self)
+  }
+
+  /// Create the object, of a new class named like `$Proxy0`
+  pub fn build(self) -> ObjectHandle<'m> {
+    let Implementation {
+      module,
+      interfaces,
+      methods,
+    } = self;
+    let class = module.define_proxy_class("L$Proxy", |class| ProxyClass {
+      interfaces,
+      methods: methods
+        .into_iter()
+        .map(|(method, native)| {
+          let function = format!("{}__{}", format_classname(class), method);
+          module.define_function(function.clone(), native.function);
+          (method, function)
+        })
+        .collect(),
+    });
+
+    ObjectHandle {
+      module,
+      object: Value::from_instance(Instance::new(class, 0)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::native;
+
+  #[test]
+  fn arguments() {
+    let registers = (1, "a", 2i64)
+      .into_arguments(&["I", "Ljava/lang/Object;", "J"])
+      .unwrap();
+    assert_eq!(registers.len(), 4);
+    assert!(matches!(
+      registers[..],
+      [Value::U32(1), Value::String(_), Value::U64(2), Value::Void]
+    ));
+
+    assert!(().into_arguments(&[]).unwrap().is_empty());
+    // A null string
+    let registers = (None::<&str>,)
+      .into_arguments(&["Ljava/lang/String;"])
+      .unwrap();
+    assert!(registers[0].is_null());
+  }
+
+  #[test]
+  fn arguments_arity() {
+    match (1, 2).into_arguments(&["I"]) {
+      Err(HostError::WrongNumberOfArguments(1, 2)) => {}
+      r => panic!("{:?}", r),
+    }
+    match ().into_arguments(&["I"]) {
+      Err(HostError::WrongNumberOfArguments(1, 0)) => {}
+      r => panic!("{:?}", r),
+    }
+  }
+
+  #[test]
+  fn argument_types() {
+    match (1i64,).into_arguments(&["I"]) {
+      Err(HostError::WrongType(rust, java)) => {
+        assert_eq!((rust.as_str(), java.as_str()), ("i64", "int"))
+      }
+      r => panic!("{:?}", r),
+    }
+    // A string isn't an `Integer`, and an int isn't a reference
+    assert!(("a",).into_arguments(&["Ljava/lang/Integer;"]).is_err());
+    assert!((1,).into_arguments(&["Ljava/lang/Object;"]).is_err());
+    assert!((vec![1, 2],).into_arguments(&["[I"]).is_ok());
+    assert!((vec![1, 2],).into_arguments(&["[J"]).is_err());
+  }
+
+  #[test]
+  fn return_types() {
+    assert_eq!(return_type::<i32>("(J)I").unwrap(), "I");
+    assert_eq!(return_type::<()>("()V").unwrap(), "V");
+    assert_eq!(
+      return_type::<Option<String>>("()Ljava/lang/String;").unwrap(),
+      "Ljava/lang/String;"
+    );
+    assert!(matches!(
+      return_type::<String>("()I"),
+      Err(HostError::WrongType(..))
+    ));
+    assert!(matches!(
+      return_type::<i32>("I"),
+      Err(HostError::NoSuchMethod(_))
+    ));
+  }
+
+  #[test]
+  fn static_methods() {
+    let mut module = Module::new("test".to_owned());
+    module
+      .natives()
+      .static_method(
+        "com/foo/Calc.add(II)I",
+        native!(|ctx, a: i32, b: i32| -> i32 { a + b }),
+      )
+      .method(
+        "com/foo/Calc.get()I",
+        native!(|ctx, _this: Value| -> i32 { 0 }),
+      );
+
+    let calc = module.class("com.foo.Calc").unwrap();
+    let sum: i32 = calc
+      .static_method("add", "(II)I")
+      .unwrap()
+      .call((1, 2))
+      .unwrap();
+    assert_eq!(sum, 3);
+    assert!(matches!(
+      calc.static_method("get", "()I"),
+      Err(HostError::NoSuchMethod(_))
+    ));
+    assert!(matches!(
+      calc.static_method("add", "(JJ)J"),
+      Err(HostError::NoSuchMethod(_))
+    ));
+  }
+}
@@ -1,16 +1,20 @@
+use std::sync::Arc;
+
 use super::runtime;
 
 pub type TypeIndex = usize;
 pub type StaticIndex = u16;
 // pub type MethodIndex = u16;
 pub type FieldRefIndex = u16;
+/// Index of an instance field, resolved when the code is generated
+pub type FieldSlot = usize;
 
 /// IRInstructions - later passed to selected backend
 #[derive(Debug)]
 pub enum Instruction {
   Label(usize),
   Nop,
-  /// Move from .1 to .2
+  /// Copy the value of register .2 to .1
   Move(MoveKind, usize, usize),
   /// Move result to .1
   MoveResult(MoveKind, usize),
@@ -18,20 +22,114 @@ pub enum Instruction {
   MoveException(usize),
   Return(ReturnType),
   ConstSet(usize, LiteralValue),
-  NewInstance(u8, usize),
-  NewArray(u8, usize, TypeIndex),
+  /// Create an instance of the class .1 in register .0
+  NewInstance(u8, String),
+  /// Create an array of type .2 in register .0, with the size in register .1
+  NewArray(u8, u8, String),
   FillArrayData(usize, Vec<runtime::Value>),
+  /// Enter the monitor of the object in .0, waiting for other threads to exit it
+  MonitorEnter(u8),
+  /// Exit the monitor of the object in .0
+  MonitorExit(u8),
+  /// Throw a ClassCastException unless the reference in .0 is null or of type .1
+  CheckCast(u8, String),
+  /// Store 1 in .0 if the reference in .1 is of type .2, otherwise 0
+  InstanceOf(u8, u8, String),
   GoTo(usize),
   If(IfKind, u8, u8, usize),
+  /// Jump to .2 if the value in .1 compares to zero as .0
+  IfZ(IfKind, u8, usize),
   /// Get from array (kind, v_dest, v_arr, v_idx)
   ArrayGet(GetPutKind, u8, u8, u8),
-  StaticGet(GetPutKind, u8, String),
-  StaticPut(GetPutKind, u8, String),
-  InstanceGet(GetPutKind, u8, u8, String),
-  InstancePut(GetPutKind, u8, u8, String),
-  Invoke(InvokeKind, String, u8, [u8; 5]),
+  /// Put into array (kind, v_src, v_arr, v_idx)
+  ArrayPut(GetPutKind, u8, u8, u8),
+  /// Store the length of the array in .1 in .0
+  ArrayLength(u8, u8),
+  StaticGet(GetPutKind, u8, StaticRef),
+  StaticPut(GetPutKind, u8, StaticRef),
+  InstanceGet(GetPutKind, u8, u8, FieldSlot),
+  InstancePut(GetPutKind, u8, u8, FieldSlot),
+  /// Invoke the method .1 with the registers .2
+  Invoke(InvokeKind, MethodRef, Vec<u16>),
+  /// Invoke the target of call site .0 with the registers .1
+  InvokeCustom(Arc<CallSite>, Vec<u16>),
+  /// Throw the exception in .0
+  Throw(u8),
   BinOp2Addr(BinOpKind, u8, u8),
   BinOpLit(BinOpLitKind, u8, u8, i16),
+  /// An instruction which isn't implemented yet, and fails with a `RuntimeError::Unimplemented`
+  /// when it runs
+  Unsupported(String),
+}
+
+/// The exception handlers of a try block
+#[derive(Debug, Clone)]
+pub struct Handler {
+  /// Exception type and jump target, in the order they were declared
+  pub catches: Vec<(String, usize)>,
+  /// Jump target for exceptions not caught by `catches` (e.g. `finally`)
+  pub catch_all: Option<usize>,
+}
+
+/// A static field, as referenced by sget and sput
+#[derive(Debug)]
+pub struct StaticRef {
+  /// Type descriptor of the class the field is referenced through
+  pub class: String,
+  /// Mangled name of the static, including the class
+  pub name: String,
+}
+
+/// A method, as referenced by an invoke instruction
+#[derive(Debug, Clone)]
+pub struct MethodRef {
+  /// Type descriptor of the class the method is referenced through
+  pub class: String,
+  /// Mangled name of the method with its descriptor, without the class
+  pub name: String,
+  /// Mangled name of the referenced function, used when nothing else resolves
+  pub full_name: String,
+}
+
+impl MethodRef {
+  /// The descriptor of the method, e.g. `(I)Ljava/lang/String;`
+  pub fn descriptor(&self) -> &str {
+    self.name.find('(').map_or("", |i| &self.name[i..])
+  }
+}
+
+/// A method handle constant: how the method is invoked, and the method
+#[derive(Debug, Clone)]
+pub struct MethodHandleRef {
+  pub kind: MethodHandleKind,
+  pub method: MethodRef,
+}
+
+/// The method handle types of the dex format, without field accessors
+#[derive(Debug, Clone)]
+pub enum MethodHandleKind {
+  Static,
+  /// A virtual method, dispatched on the receiver
+  Instance,
+  /// Create an object and call a constructor on it
+  Constructor,
+  Direct,
+  Interface,
+}
+
+/// A call site of invoke-custom. It is linked by calling its bootstrap method the first time
+/// it runs, which gives the method handle every later call goes to.
+#[derive(Debug)]
+pub struct CallSite {
+  /// Index of the call site in the dex file, after the call sites of the dex files loaded
+  /// before it, so it is unique in the module
+  pub id: usize,
+  pub bootstrap: MethodRef,
+  pub method_name: runtime::JavaString,
+  /// Method type descriptor of the call, e.g. `(ILjava/lang/String;)Ljava/lang/String;`
+  pub method_type: String,
+  /// Extra static arguments of the bootstrap method
+  pub arguments: Vec<LiteralValue>,
 }
 
 #[derive(Debug)]
@@ -117,7 +215,7 @@ pub enum BinOpLitKind {
   XorInt,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum InvokeKind {
   Virtual,
   Super,
@@ -157,14 +255,18 @@ pub enum MoveKind {
   // Exception,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LiteralValue {
   /// Literal 32-bit
   Lit(i32),
   /// Literal 64-bit
   Wide(i64),
-  /// String from StringIDX
-  String(String),
-  /// TypeIDX
-  Class(usize),
+  /// String from StringIDX, interned by the module
+  String(runtime::JavaString),
+  /// `java.lang.Class` of a type descriptor
+  Class(String),
+  /// `java.lang.invoke.MethodType` of a method descriptor
+  MethodType(String),
+  /// `java.lang.invoke.MethodHandle` of a method
+  MethodHandle(MethodHandleRef),
 }
@@ -1,14 +1,27 @@
-use log::debug;
-use std::collections::HashMap;
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
-use std::sync::Mutex;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread::{JoinHandle, ThreadId};
 
+pub mod class;
 pub mod function;
+pub mod host;
 pub mod instructions;
+pub mod monitor;
+pub mod native;
+pub mod output;
 pub mod runtime;
 
+pub use class::{
+	demangle, format_classname, format_method, format_name, is_reference, java_name,
+	parameter_types, strip_descriptor, Class, ProxyClass,
+};
 pub use function::function_builder::FunctionBuilder;
-pub use function::{CallStack, Function, InterpretedFunction};
+pub use function::{
+	CallStack, Function, InterpretedFunction, NativeFunction, SourceInfo, StackFrame,
+};
+pub use output::{CapturedOutput, StandardStream};
 pub use runtime::InvokeResult;
 
 //TODO Use that macro for deriving debug with custom formatting
@@ -22,6 +35,10 @@ pub enum RuntimeError {
 	WrongNumberOfParameters(usize, usize),
 	/// Tried to jump to an unknown label
 	BadJumpTarget,
+	/// An instruction refers to a handler the function doesn't have
+	BadHandler(usize),
+	/// Tried to access a field slot outside of the instance
+	FieldOutOfBounds(usize),
 	CastError(String),
 	Unimplemented(String),
 }
@@ -55,6 +72,16 @@ impl std::fmt::Debug for RuntimeErrorStack {
 	}
 }
 
+/// The initialization state of a class
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Initialization {
+	/// A thread is running the static initializers
+	Running(ThreadId),
+	Done,
+	/// A static initializer threw, so the class can't be used
+	Failed,
+}
+
 #[derive(Debug)]
 pub struct Module {
 	//TODO Is this necessary?
@@ -62,6 +89,31 @@ pub struct Module {
 	// statics: Vec<String>,
 	statics: Mutex<HashMap<String, runtime::Value>>,
 	functions: HashMap<String, Function>,
+	classes: HashMap<String, Class>,
+	/// `java.lang.Class` objects, created the first time a type is referenced
+	class_objects: Mutex<HashMap<String, runtime::Value>>,
+	/// Cache of `resolve_method`: class -> method -> function
+	resolved_methods: Mutex<HashMap<String, HashMap<String, String>>>,
+	/// Interned strings: literals and the results of `String.intern()`
+	strings: Mutex<HashSet<runtime::JavaString>>,
+	/// Targets of the invoke-custom call sites linked so far, by call site index
+	call_sites: Mutex<HashMap<usize, runtime::Value>>,
+	/// Classes defined while running, e.g. for lambdas
+	proxy_classes: Mutex<HashMap<String, ProxyClass>>,
+	/// Natives defined while running, e.g. the methods of objects implemented by the host
+	defined_functions: Mutex<HashMap<String, Arc<NativeFunction>>>,
+	/// Classes whose initialization has started, and how far it got
+	initialized: Mutex<HashMap<String, Initialization>>,
+	/// Notified when the initialization of a class ends
+	initialization_ended: Condvar,
+	/// Where `System.out` and `System.err` write to
+	output: output::Output,
+	/// The monitors of objects, which `synchronized` locks
+	monitors: monitor::Monitors,
+	/// The module itself, once it is shared with the threads it runs
+	shared: Weak<Module>,
+	/// The threads to wait for before exiting, which aren't daemons
+	threads: Mutex<Vec<JoinHandle<()>>>,
 }
 
 impl Module {
@@ -70,6 +122,65 @@ impl Module {
 			name,
 			statics: Mutex::new(HashMap::new()),
 			functions: HashMap::new(),
+			classes: HashMap::new(),
+			class_objects: Mutex::new(HashMap::new()),
+			resolved_methods: Mutex::new(HashMap::new()),
+			strings: Mutex::new(HashSet::new()),
+			call_sites: Mutex::new(HashMap::new()),
+			proxy_classes: Mutex::new(HashMap::new()),
+			defined_functions: Mutex::new(HashMap::new()),
+			initialized: Mutex::new(HashMap::new()),
+			initialization_ended: Condvar::new(),
+			output: output::Output::default(),
+			monitors: monitor::Monitors::default(),
+			shared: Weak::new(),
+			threads: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Share the module, which lets it run Java threads on threads of their own. In a module which
+	/// isn't shared, `Thread.start()` throws an `UnsupportedOperationException`.
+	pub fn into_shared(self) -> Arc<Module> {
+		Arc::new_cyclic(|shared| Module {
+			shared: shared.clone(),
+			..self
+		})
+	}
+
+	/// Run a function on a new thread, giving it the module. Only a shared module can, so the
+	/// function is given back otherwise.
+	pub fn spawn<F>(&self, name: String, daemon: bool, f: F) -> Result<(), F>
+	where
+		F: FnOnce(&Module) + Send + 'static,
+	{
+		let module = match self.shared.upgrade() {
+			None => return Err(f),
+			Some(m) => m,
+		};
+
+		let handle = std::thread::Builder::new()
+			.name(name)
+			.spawn(move || f(&module))
+			.expect("Failed to spawn a thread");
+		if !daemon {
+			self.threads.lock().unwrap().push(handle);
+		}
+		Ok(())
+	}
+
+	/// Wait for the threads which aren't daemons to end, including the ones they start, like the
+	/// JVM does before it exits
+	pub fn join_threads(&self) {
+		loop {
+			let thread = self.threads.lock().unwrap().pop();
+			match thread {
+				None => return,
+				Some(t) => {
+					if t.join().is_err() {
+						warn!("A thread panicked");
+					}
+				}
+			}
 		}
 	}
 
@@ -83,6 +194,11 @@ impl Module {
 		}
 		out.push(format!(""));
 
+		for (name, c) in &self.classes {
+			out.push(format!("class {:?} {:?};", name, c.fields()));
+		}
+		out.push(String::new());
+
 		for (name, f) in &self.functions {
 			out.push(f.build_ir(name));
 			out.push(format!(""));
@@ -91,14 +207,39 @@ impl Module {
 		out.join("\n")
 	}
 
-	/// Run a function from outside of the module
+	/// Run a function from outside of the module. The descriptor can be left out of its name
+	/// (e.g. `CLASS_MyCode__main`) when the method isn't overloaded.
 	pub fn run(&self, fn_name: &str, params: Vec<runtime::Value>) -> runtime::InvokeResult {
+		let fn_name = &self.find_function(fn_name);
 		let call_stack = CallStack::default();
 		let cs = Rc::new(CallStack::extend(fn_name.to_owned(), Rc::new(call_stack)));
 
+		// Like the main class of a java program, the class is initialized first
+		if let Some((class, _)) = demangle(fn_name) {
+			match self.initialize_class(&class, &cs) {
+				InvokeResult::Ok(_) => {}
+				r => return r,
+			}
+		}
+
 		self.invoke(fn_name, cs, params)
 	}
 
+	/// The full name of a function, given with or without its descriptor. A name without one
+	/// stands for the only function with that name, if there is exactly one.
+	fn find_function(&self, fn_name: &str) -> String {
+		if self.functions.contains_key(fn_name) || fn_name.contains('(') {
+			return fn_name.to_owned();
+		}
+
+		let prefix = format!("{}(", fn_name);
+		let mut overloads = self.functions.keys().filter(|f| f.starts_with(&prefix));
+		match (overloads.next(), overloads.next()) {
+			(Some(f), None) => f.clone(),
+			_ => fn_name.to_owned(),
+		}
+	}
+
 	/// Used internally to run functions
 	pub(self) fn invoke(
 		&self,
@@ -110,18 +251,22 @@ impl Module {
 
 		let fn_ = match self.functions.get(fn_name) {
 			None => {
-				return InvokeResult::runtime(
-					RuntimeError::InvokeOnNonExistingFunction(fn_name.to_owned()),
-					cs,
-				);
+				let defined = self.defined_functions.lock().unwrap().get(fn_name).cloned();
+				return match defined {
+					None => InvokeResult::runtime(
+						RuntimeError::InvokeOnNonExistingFunction(fn_name.to_owned()),
+						cs,
+					),
+					Some(f) => (f.function)(params, cs, self),
+				};
 			}
 			Some(a) => a,
 		};
 
 		debug!("Running function {:?}", fn_name);
 		match fn_ {
-			Function::Interpreted(f) => f.run_interpreted(params, cs, &self),
-			Function::Native(f) => f.0(params, cs, &self),
+			Function::Interpreted(f) => f.run_interpreted(params, cs, self),
+			Function::Native(f) => (f.function)(params, cs, self),
 		}
 	}
 
@@ -142,9 +287,72 @@ impl Module {
 		s.insert(static_name, new_value);
 	}
 
+	/// Send a standard stream of the program somewhere else than the process' own, e.g. a
+	/// `CapturedOutput` to check what a program prints
+	pub fn set_output(&self, stream: StandardStream, sink: Box<dyn std::io::Write + Send>) {
+		self.output.set(stream, sink);
+	}
+
+	/// Write to a standard stream of the program
+	pub fn write_output(&self, stream: StandardStream, bytes: &[u8]) -> std::io::Result<()> {
+		self.output.write(stream, bytes)
+	}
+
+	pub fn flush_output(&self, stream: StandardStream) -> std::io::Result<()> {
+		self.output.flush(stream)
+	}
+
+	/// The monitors of objects, for `synchronized` and `Object.wait()`
+	pub fn monitors(&self) -> &monitor::Monitors {
+		&self.monitors
+	}
+
 	/// Add a function to the module
 	pub fn add_function(&mut self, name: String, fn_: Function) {
 		self.functions.insert(name, fn_);
+		// A method resolved before may now resolve to the new function
+		self.resolved_methods.get_mut().unwrap().clear();
+	}
+
+	/// Register typed natives by the descriptors of the methods they implement
+	pub fn natives(&mut self) -> native::Natives<'_> {
+		native::Natives::new(self)
+	}
+
+	/// Whether the module has a function, interpreted or native
+	pub fn has_function(&self, name: &str) -> bool {
+		self.functions.contains_key(name)
+			|| self.defined_functions.lock().unwrap().contains_key(name)
+	}
+
+	/// Whether a function implements a static method. Functions defined while running, like the
+	/// methods of proxy classes, are instance methods.
+	fn is_static_function(&self, name: &str) -> bool {
+		self.functions.get(name).is_some_and(Function::is_static)
+	}
+
+	/// Whether the module has a function with a name starting with a prefix
+	fn has_function_with_prefix(&self, prefix: &str) -> bool {
+		self.functions.keys().any(|f| f.starts_with(prefix))
+	}
+
+	/// A class, by its Java name (like `com.foo.Calc`) or type descriptor, to use it from Rust
+	pub fn class(&self, name: &str) -> Result<host::ClassHandle<'_>, host::HostError> {
+		host::ClassHandle::new(self, name)
+	}
+
+	/// An object, to use it from Rust
+	pub fn object(
+		&self,
+		object: runtime::Value,
+	) -> Result<host::ObjectHandle<'_>, host::HostError> {
+		host::ObjectHandle::new(self, object)
+	}
+
+	/// An object implemented in Rust, of a new class implementing interfaces (by their Java
+	/// names or type descriptors) with closures
+	pub fn implement(&self, interfaces: &[&str]) -> host::Implementation<'_> {
+		host::Implementation::new(self, interfaces)
 	}
 
 	/// Add a *global* static variable
@@ -153,4 +361,888 @@ impl Module {
 		let mut s = self.statics.lock().unwrap();
 		s.insert(name, runtime::Value::Void);
 	}
+
+	/// Add a class. The superclass has to be added before its subclasses, since
+	/// the slots of the inherited fields are copied from it.
+	pub fn add_class(
+		&mut self,
+		name: String,
+		is_interface: bool,
+		superclass: Option<String>,
+		interfaces: Vec<String>,
+		own_fields: Vec<String>,
+	) {
+		let mut fields = match &superclass {
+			None => Vec::new(),
+			Some(s) => match self.classes.get(s) {
+				None => {
+					warn!("Superclass {} of {} is not known", s, name);
+					Vec::new()
+				}
+				Some(c) => c.fields().clone(),
+			},
+		};
+		fields.extend(own_fields);
+
+		let class = Class::new(name.clone(), is_interface, superclass, interfaces, fields);
+		self.classes.insert(name, class);
+	}
+
+	/// Initialize a class before its first use, by running the static initializers (`<clinit>`)
+	/// of its superclasses and then its own. Exceptions thrown by them are wrapped in an
+	/// `ExceptionInInitializerError`, unless they are errors already. A class whose initialization
+	/// failed can't be used anymore: every later use throws a `NoClassDefFoundError`.
+	///
+	/// Like the JVM (JVMS §5.5), other threads using the class wait until the thread initializing
+	/// it is done, while that thread sees it as initialized, so a class used by its own
+	/// initializer (or in a cycle) doesn't initialize again.
+	pub fn initialize_class(&self, class: &str, cs: &Rc<CallStack>) -> runtime::InvokeResult {
+		if class.starts_with('[') {
+			return InvokeResult::Ok(runtime::Value::Void);
+		}
+
+		let me = std::thread::current().id();
+		let mut initialized = self.initialized.lock().unwrap();
+		loop {
+			match initialized.get(class).copied() {
+				None => break,
+				Some(Initialization::Running(thread)) if thread != me => {
+					initialized = self.initialization_ended.wait(initialized).unwrap();
+				}
+				Some(Initialization::Failed) => {
+					drop(initialized);
+					let message = format!("Could not initialize class {}", java_name(class));
+					return InvokeResult::Exception(
+						self.new_exception("Ljava/lang/NoClassDefFoundError;", Some(&message), cs),
+						cs.clone(),
+					);
+				}
+				Some(_) => return InvokeResult::Ok(runtime::Value::Void),
+			}
+		}
+		initialized.insert(class.to_owned(), Initialization::Running(me));
+		drop(initialized);
+
+		let result = self.run_initializers(class, cs);
+		let state = match result {
+			InvokeResult::Ok(_) => Initialization::Done,
+			_ => Initialization::Failed,
+		};
+		self.initialized
+			.lock()
+			.unwrap()
+			.insert(class.to_owned(), state);
+		self.initialization_ended.notify_all();
+
+		result
+	}
+
+	/// Run the static initializers of a class and of its superclasses which aren't initialized yet
+	fn run_initializers(&self, class: &str, cs: &Rc<CallStack>) -> runtime::InvokeResult {
+		if let Some(superclass) = self.get_class(class).and_then(|c| c.superclass()) {
+			match self.initialize_class(superclass, cs) {
+				InvokeResult::Ok(_) => {}
+				r => return r,
+			}
+		}
+
+		let fn_name = format!(
+			"{}__{}",
+			format_classname(class),
+			format_method("<clinit>", "()V")
+		);
+		if !self.functions.contains_key(&fn_name) {
+			return InvokeResult::Ok(runtime::Value::Void);
+		}
+
+		debug!("Initializing class {}", class);
+		let clinit_cs = Rc::new(CallStack::extend(fn_name.clone(), cs.clone()));
+		match self.invoke(&fn_name, clinit_cs, Vec::new()) {
+			InvokeResult::Exception(e, _) if !self.is_error(&e) => InvokeResult::Exception(
+				self.wrap_exception("Ljava/lang/ExceptionInInitializerError;", None, e, cs),
+				cs.clone(),
+			),
+			r => r,
+		}
+	}
+
+	/// Get a class by its type descriptor
+	pub fn get_class(&self, name: &str) -> Option<&Class> {
+		self.classes.get(name)
+	}
+
+	/// Check if a reference of type `from` can be assigned to `to` (both type descriptors),
+	/// by walking superclasses, interfaces and array component types
+	pub fn is_assignable(&self, from: &str, to: &str) -> bool {
+		if from == to || to == "Ljava/lang/Object;" {
+			return true;
+		}
+
+		if let Some(from) = from.strip_prefix('[') {
+			return match to {
+				"Ljava/lang/Cloneable;" | "Ljava/io/Serializable;" => true,
+				// Arrays of primitives are only assignable to the exact same type
+				_ => to.strip_prefix('[').is_some_and(|to| {
+					is_reference(from) && is_reference(to) && self.is_assignable(from, to)
+				}),
+			};
+		}
+
+		let proxy_interfaces = self
+			.proxy_classes
+			.lock()
+			.unwrap()
+			.get(from)
+			.map(|p| p.interfaces.clone());
+		if let Some(interfaces) = proxy_interfaces {
+			return interfaces.iter().any(|i| self.is_assignable(i, to));
+		}
+
+		match self.classes.get(from) {
+			None => false,
+			Some(c) => {
+				c.superclass().is_some_and(|s| self.is_assignable(s, to))
+					|| c.interfaces().iter().any(|i| self.is_assignable(i, to))
+			}
+		}
+	}
+
+	/// Create a new instance of a class, with all of its fields unset
+	pub fn new_instance(&self, class: &str) -> runtime::Instance {
+		let n_fields = match self.classes.get(class) {
+			None => {
+				warn!("Creating an instance of an unknown class: {}", class);
+				0
+			}
+			Some(c) => c.n_fields(),
+		};
+
+		runtime::Instance::new(class.to_owned(), n_fields)
+	}
+
+	/// Whether a `Throwable` is a `java.lang.Error`
+	fn is_error(&self, e: &runtime::Value) -> bool {
+		e.get_type()
+			.is_some_and(|t| self.is_assignable(&t, "Ljava/lang/Error;"))
+	}
+
+	/// Create a `Throwable` caused by another one
+	fn wrap_exception(
+		&self,
+		class: &str,
+		message: Option<&str>,
+		cause: runtime::Value,
+		cs: &CallStack,
+	) -> runtime::Value {
+		let e = self.new_exception(class, message, cs);
+		let slot = self.get_class(class).and_then(|c| c.field_slot("cause"));
+		if let (Some(slot), Ok(i)) = (slot, e.instance()) {
+			i.lock().unwrap().set_field(slot, cause).unwrap();
+		}
+
+		e
+	}
+
+	/// Create a `Throwable` with a message and a stack trace, for exceptions thrown by the runtime itself
+	pub fn new_exception(
+		&self,
+		class: &str,
+		message: Option<&str>,
+		cs: &CallStack,
+	) -> runtime::Value {
+		let mut e = self.new_instance(class);
+
+		let slot = self
+			.get_class(class)
+			.and_then(|c| c.field_slot("detailMessage"));
+		if let (Some(slot), Some(message)) = (slot, message) {
+			e.set_field(slot, runtime::Value::new_string(message))
+				.unwrap();
+		}
+		self.fill_in_stack_trace(&mut e, cs);
+
+		runtime::Value::from_instance(e)
+	}
+
+	/// The Java stack trace of a call stack, innermost frame first
+	pub fn stack_trace(&self, cs: &CallStack) -> Vec<StackFrame> {
+		self.frames(cs.frames())
+	}
+
+	fn frames(&self, frames: Vec<&CallStack>) -> Vec<StackFrame> {
+		frames
+			.into_iter()
+			.filter_map(|f| match self.functions.get(&f.cur) {
+				Some(Function::Interpreted(ifn)) => Some(ifn.source().frame(f.pc.get())),
+				None if !self.has_function(&f.cur) => None,
+				// Natives, including the ones defined while running
+				_ => {
+					let (class, method) = demangle(&f.cur)?;
+					Some(StackFrame {
+						class: java_name(&class),
+						method,
+						source_file: None,
+						line: None,
+						native: true,
+					})
+				}
+			})
+			.collect()
+	}
+
+	/// Store the stack trace in a `Throwable`, as an array of `StackTraceElement`s. The frames of
+	/// its constructors are left out, so the trace starts where the throwable was created, like in java.
+	pub fn fill_in_stack_trace(&self, e: &mut runtime::Instance, cs: &CallStack) {
+		let slot = match self
+			.get_class(e.get_class_type())
+			.and_then(|c| c.field_slot("stackTrace"))
+		{
+			None => return,
+			Some(s) => s,
+		};
+
+		// Constructors of any descriptor
+		let mut constructors = Vec::new();
+		let mut class = Some(e.get_class_type().as_str());
+		while let Some(c) = class {
+			constructors.push(format!(
+				"{}__{}",
+				format_classname(c),
+				format_name("<init>")
+			));
+			class = self
+				.get_class(c)
+				.and_then(|c| c.superclass())
+				.map(|s| s.as_str());
+		}
+
+		let frames = cs
+			.frames()
+			.into_iter()
+			.skip_while(|f| constructors.iter().any(|c| c == strip_descriptor(&f.cur)))
+			.collect();
+		let elements = self
+			.frames(frames)
+			.iter()
+			.map(|f| self.stack_trace_element(f))
+			.collect();
+
+		e.set_field(
+			slot,
+			runtime::Value::from_array("[Ljava/lang/StackTraceElement;".to_owned(), elements),
+		)
+		.unwrap();
+	}
+
+	/// Create a `java.lang.StackTraceElement` describing a frame
+	fn stack_trace_element(&self, frame: &StackFrame) -> runtime::Value {
+		let type_ = "Ljava/lang/StackTraceElement;";
+		let mut element = self.new_instance(type_);
+		let line = match (frame.native, frame.line) {
+			(true, _) => -2,
+			(false, Some(l)) => l as i32,
+			(false, None) => -1,
+		};
+		let fields = [
+			("declaringClass", runtime::Value::new_string(&frame.class)),
+			("methodName", runtime::Value::new_string(&frame.method)),
+			(
+				"fileName",
+				frame
+					.source_file
+					.as_ref()
+					.map_or(runtime::Value::Void, |f| runtime::Value::new_string(f)),
+			),
+			("lineNumber", runtime::Value::U32(line)),
+		];
+		for (name, value) in fields {
+			if let Some(slot) = self.get_class(type_).and_then(|c| c.field_slot(name)) {
+				element.set_field(slot, value).unwrap();
+			}
+		}
+
+		runtime::Value::from_instance(element)
+	}
+
+	/// Find the function implementing a (mangled) method for a class, by looking
+	/// in the class itself and then walking up its superclasses. The method has its descriptor,
+	/// like `append(I)Ljava/lang/StringBuilder;`, so overloads resolve to different functions.
+	pub fn resolve_method(&self, class: &str, method: &str) -> Option<String> {
+		if let Some(f) = self
+			.resolved_methods
+			.lock()
+			.unwrap()
+			.get(class)
+			.and_then(|m| m.get(method))
+		{
+			return Some(f.clone());
+		}
+
+		let proxy = self.proxy_classes.lock().unwrap().get(class).cloned();
+		if let Some(proxy) = proxy {
+			let function = proxy
+				.methods
+				.iter()
+				.find(|(m, _)| m == method);
+			if let Some((_, function)) = function {
+				return Some(function.clone());
+			}
+
+			// Default methods of the interfaces, then the methods of Object
+			return proxy
+				.interfaces
+				.iter()
+				.find_map(|i| self.resolve_method(i, method))
+				.or_else(|| self.resolve_method("Ljava/lang/Object;", method));
+		}
+
+		// Arrays only have the methods of Object
+		let mut cur = Some(if class.starts_with('[') {
+			"Ljava/lang/Object;"
+		} else {
+			class
+		});
+		while let Some(c) = cur {
+			let fn_name = format!("{}__{}", format_classname(c), method);
+			if self.functions.contains_key(&fn_name) {
+				self.resolved_methods
+					.lock()
+					.unwrap()
+					.entry(class.to_owned())
+					.or_default()
+					.insert(method.to_owned(), fn_name.clone());
+				return Some(fn_name);
+			}
+
+			cur = self
+				.get_class(c)
+				.and_then(|c| c.superclass())
+				.map(|s| s.as_str());
+		}
+
+		None
+	}
+
+	/// Call a method on an object, dispatching on the class of the object (`params[0]`)
+	pub fn invoke_virtual(
+		&self,
+		method: &str,
+		cs: Rc<CallStack>,
+		params: Vec<runtime::Value>,
+	) -> runtime::InvokeResult {
+		let fn_name = match params.first().and_then(|o| o.get_type()) {
+			None => {
+				return InvokeResult::Exception(
+					self.new_exception("Ljava/lang/NullPointerException;", None, &cs),
+					cs,
+				)
+			}
+			Some(class) => match self.resolve_method(&class, method) {
+				None => {
+					return InvokeResult::runtime(
+						RuntimeError::InvokeOnNonExistingFunction(format!("{}.{}", class, method)),
+						cs,
+					)
+				}
+				Some(f) => f,
+			},
+		};
+
+		let cs = Rc::new(CallStack::extend(fn_name.clone(), cs));
+		self.invoke(&fn_name, cs, params)
+	}
+
+	/// Get the unique `java.lang.Class` object of a type descriptor
+	pub fn class_object(&self, type_: &str) -> runtime::Value {
+		let mut objects = self.class_objects.lock().unwrap();
+		if let Some(o) = objects.get(type_) {
+			return o.clone();
+		}
+
+		let mut o = self.new_instance("Ljava/lang/Class;");
+		o.set_field(0, runtime::Value::new_string(type_)).unwrap();
+		let o = runtime::Value::from_instance(o);
+		objects.insert(type_.to_owned(), o.clone());
+
+		o
+	}
+
+	/// The canonical instance of a string, so equal literals are the same object like in java
+	pub fn intern(&self, s: &runtime::JavaString) -> runtime::JavaString {
+		let mut strings = self.strings.lock().unwrap();
+		match strings.get(s.units()) {
+			Some(interned) => interned.clone(),
+			None => {
+				strings.insert(s.clone());
+				s.clone()
+			}
+		}
+	}
+
+	pub fn intern_str(&self, s: &str) -> runtime::JavaString {
+		self.intern(&runtime::JavaString::from(s))
+	}
+
+	/// Turn a literal into a runtime value
+	pub fn literal(&self, lit: &instructions::LiteralValue) -> runtime::Value {
+		match lit {
+			instructions::LiteralValue::Lit(i) => runtime::Value::U32(*i),
+			instructions::LiteralValue::Wide(wide) => runtime::Value::U64(*wide),
+			instructions::LiteralValue::String(s) => runtime::Value::String(s.clone()),
+			instructions::LiteralValue::Class(type_) => self.class_object(type_),
+			instructions::LiteralValue::MethodType(descriptor) => self.method_type(descriptor),
+			instructions::LiteralValue::MethodHandle(h) => {
+				let function = || {
+					self.resolve_method(&h.method.class, &h.method.name)
+						.unwrap_or_else(|| h.method.full_name.clone())
+				};
+				let descriptor = h.method.descriptor();
+				let (target, descriptor) = match h.kind {
+					instructions::MethodHandleKind::Instance
+					| instructions::MethodHandleKind::Interface => (
+						runtime::HandleTarget::Virtual(h.method.name.clone()),
+						format!("({}{}", h.method.class, &descriptor[1..]),
+					),
+					instructions::MethodHandleKind::Constructor => (
+						runtime::HandleTarget::Constructor {
+							class: h.method.class.clone(),
+							function: function(),
+						},
+						format!(
+							"{}{}",
+							&descriptor[..descriptor.find(')').map_or(0, |i| i + 1)],
+							h.method.class
+						),
+					),
+					instructions::MethodHandleKind::Direct => (
+						runtime::HandleTarget::Function(function()),
+						format!("({}{}", h.method.class, &descriptor[1..]),
+					),
+					instructions::MethodHandleKind::Static => (
+						runtime::HandleTarget::Function(function()),
+						descriptor.to_owned(),
+					),
+				};
+
+				self.method_handle(runtime::MethodHandle {
+					target,
+					bound: Vec::new(),
+					descriptor,
+				})
+			}
+		}
+	}
+
+	/// Read a field of an object by name
+	pub fn field(
+		&self,
+		object: &runtime::Value,
+		name: &str,
+	) -> Result<runtime::Value, RuntimeError> {
+		let instance = object.instance()?.lock().unwrap();
+		let class = instance.get_class_type();
+
+		self.get_class(class)
+			.and_then(|c| c.field_slot(name))
+			.and_then(|slot| instance.get_field(slot))
+			.cloned()
+			.ok_or_else(|| RuntimeError::CastError(format!("{} has no field {}", class, name)))
+	}
+
+	/// Create an object with some of its fields set
+	fn new_object(&self, class: &str, fields: Vec<(&str, runtime::Value)>) -> runtime::Value {
+		let mut o = self.new_instance(class);
+		for (name, value) in fields {
+			match self.get_class(class).and_then(|c| c.field_slot(name)) {
+				None => warn!("Class {} has no field {}", class, name),
+				Some(slot) => o.set_field(slot, value).unwrap(),
+			}
+		}
+
+		runtime::Value::from_instance(o)
+	}
+
+	/// Create a `java.lang.invoke.MethodType` of a method descriptor
+	pub fn method_type(&self, descriptor: &str) -> runtime::Value {
+		self.new_object(
+			"Ljava/lang/invoke/MethodType;",
+			vec![("descriptor", runtime::Value::new_string(descriptor))],
+		)
+	}
+
+	/// Create a `java.lang.invoke.MethodHandle`
+	pub fn method_handle(&self, handle: runtime::MethodHandle) -> runtime::Value {
+		self.new_object(
+			"Ljava/lang/invoke/MethodHandle;",
+			vec![("handle", runtime::Value::native(handle))],
+		)
+	}
+
+	/// Define a class while running. Its name is `{prefix}{n};`, with `n` making it unique, and it
+	/// is made from its name, e.g. to name the functions of its methods after it.
+	pub fn define_proxy_class(
+		&self,
+		prefix: &str,
+		class: impl FnOnce(&str) -> ProxyClass,
+	) -> String {
+		let mut classes = self.proxy_classes.lock().unwrap();
+		let name = format!("{}{};", prefix, classes.len());
+		classes.insert(name.clone(), class(&name));
+
+		name
+	}
+
+	/// Define a native while running. Unlike the functions added before running, it can be added
+	/// to a module which is in use, e.g. to implement the methods of a proxy class.
+	pub fn define_function(&self, name: String, function: NativeFunction) {
+		self.defined_functions
+			.lock()
+			.unwrap()
+			.insert(name, Arc::new(function));
+	}
+
+	/// The data of a `java.lang.invoke.MethodHandle`
+	pub fn method_handle_data(
+		&self,
+		handle: &runtime::Value,
+	) -> Result<runtime::MethodHandle, RuntimeError> {
+		let data = self.field(handle, "handle")?;
+		let data = data.native_data()?.lock().unwrap();
+		data.get::<runtime::MethodHandle>()
+			.cloned()
+			.ok_or_else(|| RuntimeError::CastError(format!("{:?} as method handle", handle)))
+	}
+
+	/// A new method handle, with `args` bound after the arguments already bound to `handle`
+	/// (`MethodHandles.insertArguments`)
+	pub fn bind_handle(
+		&self,
+		handle: &runtime::Value,
+		args: Vec<runtime::Value>,
+	) -> Result<runtime::Value, RuntimeError> {
+		let mut data = self.method_handle_data(handle)?;
+
+		// The bound arguments are no longer parameters of the handle
+		let mut registers = args.len();
+		let mut parameters = parameter_types(&data.descriptor).into_iter();
+		while registers > 0 {
+			match parameters.next() {
+				Some("J") | Some("D") => registers = registers.saturating_sub(2),
+				_ => registers -= 1,
+			}
+		}
+		data.descriptor = format!(
+			"({}{}",
+			parameters.collect::<String>(),
+			&data.descriptor[data.descriptor.find(')').unwrap_or(0)..]
+		);
+		data.bound.extend(args);
+
+		Ok(self.method_handle(data))
+	}
+
+	/// Create a `java.lang.invoke.CallSite` permanently linked to a method handle
+	pub fn constant_call_site(&self, target: runtime::Value) -> runtime::Value {
+		self.new_object(
+			"Ljava/lang/invoke/ConstantCallSite;",
+			vec![("target", target)],
+		)
+	}
+
+	/// Call a `java.lang.invoke.MethodHandle`, with its bound arguments before `params`
+	pub fn invoke_handle(
+		&self,
+		handle: &runtime::Value,
+		cs: Rc<CallStack>,
+		params: Vec<runtime::Value>,
+	) -> runtime::InvokeResult {
+		if handle.is_null() {
+			return InvokeResult::Exception(
+				self.new_exception("Ljava/lang/NullPointerException;", None, &cs),
+				cs,
+			);
+		}
+
+		let (target, mut args) = match self.method_handle_data(handle) {
+			Err(e) => return InvokeResult::runtime(e, cs),
+			Ok(h) => (h.target, h.bound),
+		};
+		args.extend(params);
+
+		match target {
+			runtime::HandleTarget::Function(fn_name) => {
+				let cs = Rc::new(CallStack::extend(fn_name.clone(), cs));
+				self.invoke(&fn_name, cs, args)
+			}
+			runtime::HandleTarget::Virtual(method) => self.invoke_virtual(&method, cs, args),
+			runtime::HandleTarget::Constructor { class, function } => {
+				let object = runtime::Value::from_instance(self.new_instance(&class));
+				args.insert(0, object.clone());
+
+				let cs = Rc::new(CallStack::extend(function.clone(), cs));
+				match self.invoke(&function, cs, args) {
+					// Strings are values, their constructors return the new string
+					InvokeResult::Ok(s) if class == "Ljava/lang/String;" => InvokeResult::Ok(s),
+					InvokeResult::Ok(_) => InvokeResult::Ok(object),
+					r => r,
+				}
+			}
+		}
+	}
+
+	/// Call the target of an invoke-custom call site. The call site is linked by its bootstrap
+	/// method the first time, and later calls go straight to the method handle it gave.
+	pub fn invoke_custom(
+		&self,
+		call_site: &instructions::CallSite,
+		cs: Rc<CallStack>,
+		params: Vec<runtime::Value>,
+	) -> runtime::InvokeResult {
+		let linked = self.call_sites.lock().unwrap().get(&call_site.id).cloned();
+		let target = match linked {
+			Some(target) => target,
+			None => match self.link_call_site(call_site, cs.clone()) {
+				InvokeResult::Ok(target) => {
+					// If the bootstrap method linked the call site itself, the first link wins
+					let mut call_sites = self.call_sites.lock().unwrap();
+					call_sites.entry(call_site.id).or_insert(target).clone()
+				}
+				r => return r,
+			},
+		};
+
+		self.invoke_handle(&target, cs, params)
+	}
+
+	/// Run the bootstrap method of a call site, giving the target of the `CallSite` it returns.
+	/// Exceptions are wrapped in a `BootstrapMethodError`, unless they already are errors.
+	fn link_call_site(
+		&self,
+		call_site: &instructions::CallSite,
+		cs: Rc<CallStack>,
+	) -> runtime::InvokeResult {
+		let bootstrap = &call_site.bootstrap;
+		let fn_name = self
+			.resolve_method(&bootstrap.class, &bootstrap.name)
+			.unwrap_or_else(|| bootstrap.full_name.clone());
+
+		// `MethodHandles.Lookup` isn't modelled, the lookup object is always null
+		let mut args = vec![
+			runtime::Value::Void,
+			runtime::Value::String(call_site.method_name.clone()),
+			self.method_type(&call_site.method_type),
+		];
+		args.extend(call_site.arguments.iter().map(|a| self.literal(a)));
+
+		let bootstrap_cs = Rc::new(CallStack::extend(fn_name.clone(), cs.clone()));
+		match self.invoke(&fn_name, bootstrap_cs, args) {
+			InvokeResult::Ok(site) if site.is_null() => InvokeResult::Exception(
+				self.new_exception(
+					"Ljava/lang/BootstrapMethodError;",
+					Some("call site initialization exception"),
+					&cs,
+				),
+				cs,
+			),
+			InvokeResult::Ok(site) => match self.field(&site, "target") {
+				Err(e) => InvokeResult::runtime(e, cs),
+				Ok(target) => InvokeResult::Ok(target),
+			},
+			InvokeResult::Exception(e, _) if !self.is_error(&e) => InvokeResult::Exception(
+				self.wrap_exception(
+					"Ljava/lang/BootstrapMethodError;",
+					Some("bootstrap method initialization exception"),
+					e,
+					&cs,
+				),
+				cs,
+			),
+			r => r,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	/// A module with `Derived extends Base implements Collection`, with `Collection extends
+	/// Iterable`, and `Other`
+	fn module() -> Module {
+		let mut m = Module::new("test".to_owned());
+		let object = Some("Ljava/lang/Object;");
+		let classes: &[(&str, bool, Option<&str>, &[&str])] = &[
+			("LIterable;", true, None, &[]),
+			("LCollection;", true, None, &["LIterable;"]),
+			("LBase;", false, object, &["LCollection;"]),
+			("LDerived;", false, Some("LBase;"), &[]),
+			("LOther;", false, object, &[]),
+		];
+		for (name, is_interface, superclass, interfaces) in classes {
+			m.add_class(
+				name.to_string(),
+				*is_interface,
+				superclass.map(|s| s.to_owned()),
+				interfaces.iter().map(|i| i.to_string()).collect(),
+				Vec::new(),
+			);
+		}
+		m
+	}
+
+	#[test]
+	fn assignable_classes() {
+		let m = module();
+
+		assert!(m.is_assignable("LDerived;", "LDerived;"));
+		assert!(m.is_assignable("LDerived;", "LBase;"));
+		assert!(m.is_assignable("LDerived;", "Ljava/lang/Object;"));
+		assert!(!m.is_assignable("LBase;", "LDerived;"));
+		assert!(!m.is_assignable("LOther;", "LBase;"));
+		// Interfaces of superclasses, and their superinterfaces
+		assert!(m.is_assignable("LDerived;", "LCollection;"));
+		assert!(m.is_assignable("LDerived;", "LIterable;"));
+		assert!(!m.is_assignable("LIterable;", "LCollection;"));
+		assert!(!m.is_assignable("LUnknown;", "LBase;"));
+	}
+
+	#[test]
+	fn assignable_arrays() {
+		let m = module();
+
+		assert!(m.is_assignable("[LDerived;", "[LBase;"));
+		assert!(m.is_assignable("[[LDerived;", "[[LIterable;"));
+		assert!(m.is_assignable("[[LDerived;", "[Ljava/lang/Object;"));
+		assert!(!m.is_assignable("[LBase;", "[LDerived;"));
+		assert!(m.is_assignable("[I", "[I"));
+		assert!(!m.is_assignable("[I", "[J"));
+		assert!(!m.is_assignable("[I", "[Ljava/lang/Object;"));
+		assert!(m.is_assignable("[I", "Ljava/lang/Object;"));
+		assert!(m.is_assignable("[I", "Ljava/lang/Cloneable;"));
+		assert!(m.is_assignable("[I", "Ljava/io/Serializable;"));
+		assert!(!m.is_assignable("[LBase;", "LBase;"));
+	}
+
+	#[test]
+	fn initialization_on_threads() {
+		let runs = Arc::new(AtomicUsize::new(0));
+		let mut m = Module::new("test".to_owned());
+		let counter = runs.clone();
+		m.natives().static_method(
+			"Slow.<clinit>()V",
+			crate::native!(|ctx| -> Result<(), InvokeResult> {
+				counter.fetch_add(1, Ordering::SeqCst);
+				// The thread initializing the class sees it as initialized
+				match ctx.module.initialize_class("LSlow;", ctx.cs) {
+					InvokeResult::Ok(_) => {}
+					r => return Err(r),
+				}
+				std::thread::sleep(std::time::Duration::from_millis(50));
+				counter.fetch_add(10, Ordering::SeqCst);
+				Ok(())
+			}),
+		);
+		let m = m.into_shared();
+
+		let threads: Vec<_> = (0..4)
+			.map(|_| {
+				let (m, runs) = (m.clone(), runs.clone());
+				std::thread::spawn(move || {
+					let r = m.initialize_class("LSlow;", &Rc::new(CallStack::default()));
+					// Other threads wait for the initializer to end
+					matches!(r, InvokeResult::Ok(_)) && runs.load(Ordering::SeqCst) == 11
+				})
+			})
+			.collect();
+		for t in threads {
+			assert!(t.join().unwrap());
+		}
+	}
+
+	#[test]
+	fn synchronized_methods() {
+		let count = Arc::new(AtomicUsize::new(0));
+		let mut m = Module::new("test".to_owned());
+		crate::std_env::add_all(&mut m);
+		let (get, set) = (count.clone(), count.clone());
+		m.natives()
+			.static_method(
+				"Counter.get()I",
+				crate::native!(|ctx| -> i32 {
+					let n = get.load(Ordering::SeqCst) as i32;
+					// Time for the other thread to read the same count, unless it waits
+					std::thread::sleep(std::time::Duration::from_millis(1));
+					n
+				}),
+			)
+			.static_method(
+				"Counter.set(I)V",
+				crate::native!(|ctx, n: i32| {
+					set.store(n as usize, Ordering::SeqCst);
+				}),
+			)
+			.static_method(
+				"Counter.fail()V",
+				crate::native!(|ctx| -> Result<(), InvokeResult> {
+					Err(ctx.throw("Ljava/lang/IllegalStateException;", None))
+				}),
+			);
+
+		// `static synchronized void increment() { set(get() + 1); }` and
+		// `static synchronized void check() { fail(); }`
+		let method = |name: &str| instructions::MethodRef {
+			class: "LCounter;".to_owned(),
+			name: name.to_owned(),
+			full_name: format!("CLASS_Counter__{}", name),
+		};
+		let builder = || {
+			let mut f = FunctionBuilder::new();
+			f.set_n_regs(1);
+			f.set_static(true);
+			f.set_synchronized(true);
+			f.set_source(SourceInfo {
+				class: "LCounter;".to_owned(),
+				..Default::default()
+			});
+			f
+		};
+		let mut f = builder();
+		f.invoke(instructions::InvokeKind::Static, method("get()I"), 0, [0; 5]);
+		f.move_results(instructions::MoveKind::Single, 0);
+		f.bin_op_lit(instructions::BinOpLitKind::AddInt, 0, 0, 1);
+		f.invoke(instructions::InvokeKind::Static, method("set(I)V"), 1, [0; 5]);
+		f.return_v(instructions::ReturnType::Void);
+		m.add_function("CLASS_Counter__increment()V".to_owned(), f.build());
+		let mut f = builder();
+		f.invoke(instructions::InvokeKind::Static, method("fail()V"), 0, [0; 5]);
+		f.return_v(instructions::ReturnType::Void);
+		m.add_function("CLASS_Counter__check()V".to_owned(), f.build());
+		let m = m.into_shared();
+
+		let threads: Vec<_> = (0..2)
+			.map(|_| {
+				let m = m.clone();
+				std::thread::spawn(move || {
+					(0..20).all(|_| {
+						matches!(
+							m.run("CLASS_Counter__increment()V", Vec::new()),
+							InvokeResult::Ok(_)
+						)
+					})
+				})
+			})
+			.collect();
+		for t in threads {
+			assert!(t.join().unwrap());
+		}
+		assert_eq!(count.load(Ordering::SeqCst), 40);
+
+		// The monitor of the class is released when the method throws
+		match m.run("CLASS_Counter__check()V", Vec::new()) {
+			InvokeResult::Exception(..) => {}
+			r => panic!("{:?}", r),
+		}
+		assert!(!m.monitors().exit(&m.class_object("LCounter;")));
+	}
 }
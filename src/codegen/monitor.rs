@@ -0,0 +1,323 @@
+//! The monitors of objects, which `synchronized` locks and `Object.wait()` waits on. Every object
+//! can be a monitor, so they are only created while a thread holds or waits for one.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use super::runtime::Value;
+
+#[derive(Debug, Default)]
+pub struct Monitors {
+  /// The monitors in use, by the address of their object
+  monitors: Mutex<HashMap<usize, Arc<Monitor>>>,
+}
+
+#[derive(Debug)]
+struct Monitor {
+  /// Keeps the object, and so its address, alive while the monitor is in use
+  _object: Value,
+  state: Mutex<State>,
+  /// Notified when the monitor is released
+  released: Condvar,
+  /// Notified when `notify()` takes threads out of the wait set
+  notified: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+  owner: Option<ThreadId>,
+  /// How many times the owner entered the monitor
+  count: usize,
+  /// The threads holding or waiting for the monitor. It is only changed while the map of
+  /// monitors is locked, so a monitor isn't removed while a thread is about to use it.
+  users: usize,
+  /// The threads in `wait()` which weren't notified yet, by their tickets, oldest first
+  wait_set: VecDeque<u64>,
+  next_ticket: u64,
+}
+
+/// How a `wait()` ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wait {
+  /// It was notified, or its time passed
+  Done,
+  /// The thread was interrupted while waiting
+  Interrupted,
+  /// The thread doesn't hold the monitor, which is an `IllegalMonitorStateException`
+  NotOwner,
+}
+
+/// How often a waiting thread checks if it is interrupted
+const INTERRUPT_POLL: Duration = Duration::from_millis(10);
+
+impl Monitors {
+  /// Enter the monitor of an object (`monitor-enter`), waiting until no other thread holds it.
+  /// A thread can enter a monitor it holds again. Returns `false` for null.
+  pub fn enter(&self, object: &Value) -> bool {
+    let address = match object.address() {
+      None => return false,
+      Some(a) => a,
+    };
+    let me = thread::current().id();
+
+    let monitor = {
+      let mut monitors = self.monitors.lock().unwrap();
+      let monitor = monitors
+        .entry(address)
+        .or_insert_with(|| Arc::new(Monitor::new(object.clone())))
+        .clone();
+
+      let mut state = monitor.state.lock().unwrap();
+      if state.owner == Some(me) {
+        state.count += 1;
+        return true;
+      }
+      state.users += 1;
+      drop(state);
+      monitor
+    };
+
+    let mut state = monitor.state.lock().unwrap();
+    while state.owner.is_some() {
+      state = monitor.released.wait(state).unwrap();
+    }
+    state.owner = Some(me);
+    state.count = 1;
+    true
+  }
+
+  /// Exit the monitor of an object (`monitor-exit`). Returns `false` if the current thread
+  /// doesn't hold it, which is an `IllegalMonitorStateException`.
+  pub fn exit(&self, object: &Value) -> bool {
+    let address = match object.address() {
+      None => return false,
+      Some(a) => a,
+    };
+
+    let mut monitors = self.monitors.lock().unwrap();
+    let monitor = match monitors.get(&address) {
+      None => return false,
+      Some(m) => m.clone(),
+    };
+
+    let mut state = monitor.state.lock().unwrap();
+    if state.owner != Some(thread::current().id()) {
+      return false;
+    }
+    state.count -= 1;
+    if state.count == 0 {
+      state.owner = None;
+      state.users -= 1;
+      if state.users == 0 {
+        monitors.remove(&address);
+      } else {
+        monitor.released.notify_one();
+      }
+    }
+    true
+  }
+
+  /// The monitor of an object, if the current thread holds it
+  fn owned(&self, object: &Value) -> Option<Arc<Monitor>> {
+    let monitor = self
+      .monitors
+      .lock()
+      .unwrap()
+      .get(&object.address()?)?
+      .clone();
+    let owner = monitor.state.lock().unwrap().owner;
+    match owner == Some(thread::current().id()) {
+      false => None,
+      true => Some(monitor),
+    }
+  }
+
+  /// `Object.wait()`: release the monitor of an object, which the current thread holds, until
+  /// another thread notifies it, some time passed (`None` waits without end) or `interrupted`
+  /// returns `true`. The monitor is then entered again, as many times as it was before.
+  pub fn wait(
+    &self,
+    object: &Value,
+    timeout: Option<Duration>,
+    interrupted: impl Fn() -> bool,
+  ) -> Wait {
+    let monitor = match self.owned(object) {
+      None => return Wait::NotOwner,
+      Some(m) => m,
+    };
+    let deadline = timeout.map(|t| Instant::now() + t);
+
+    let mut state = monitor.state.lock().unwrap();
+    let ticket = state.next_ticket;
+    state.next_ticket += 1;
+    state.wait_set.push_back(ticket);
+    let count = std::mem::replace(&mut state.count, 0);
+    state.owner = None;
+    monitor.released.notify_one();
+
+    let mut end = Wait::Done;
+    while state.wait_set.contains(&ticket) {
+      let mut poll = INTERRUPT_POLL;
+      if let Some(deadline) = deadline {
+        match deadline.checked_duration_since(Instant::now()) {
+          Some(left) if !left.is_zero() => poll = poll.min(left),
+          _ => break,
+        }
+      }
+      if interrupted() {
+        end = Wait::Interrupted;
+        break;
+      }
+      state = monitor.notified.wait_timeout(state, poll).unwrap().0;
+    }
+    state.wait_set.retain(|t| *t != ticket);
+
+    while state.owner.is_some() {
+      state = monitor.released.wait(state).unwrap();
+    }
+    state.owner = Some(thread::current().id());
+    state.count = count;
+    end
+  }
+
+  /// `Object.notify()` (`all` is `false`) and `notifyAll()`: wake up the oldest thread waiting
+  /// on the monitor of an object, or all of them. They run once the current thread, which has
+  /// to hold the monitor, releases it. Returns `false` if it doesn't hold it.
+  pub fn notify(&self, object: &Value, all: bool) -> bool {
+    let monitor = match self.owned(object) {
+      None => return false,
+      Some(m) => m,
+    };
+
+    let mut state = monitor.state.lock().unwrap();
+    match all {
+      false => drop(state.wait_set.pop_front()),
+      true => state.wait_set.clear(),
+    }
+    monitor.notified.notify_all();
+    true
+  }
+}
+
+impl Monitor {
+  fn new(object: Value) -> Self {
+    Self {
+      _object: object,
+      state: Mutex::new(State::default()),
+      released: Condvar::new(),
+      notified: Condvar::new(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reentrant() {
+    let monitors = Monitors::default();
+    let o = Value::new_string("o");
+
+    assert!(monitors.enter(&o));
+    assert!(monitors.enter(&o));
+    assert!(monitors.exit(&o));
+    assert!(monitors.exit(&o));
+    assert!(!monitors.exit(&o));
+    assert!(monitors.monitors.lock().unwrap().is_empty());
+  }
+
+  #[test]
+  fn null_and_not_owner() {
+    let monitors = Arc::new(Monitors::default());
+    let o = Value::new_string("o");
+
+    assert!(!monitors.enter(&Value::Void));
+    assert!(!monitors.exit(&o));
+
+    monitors.enter(&o);
+    let (m, o2) = (monitors.clone(), o.clone());
+    assert!(!thread::spawn(move || m.exit(&o2)).join().unwrap());
+    assert!(monitors.exit(&o));
+  }
+
+  #[test]
+  fn wait_and_notify() {
+    let monitors = Arc::new(Monitors::default());
+    let o = Value::new_string("o");
+    let never = || false;
+
+    assert_eq!(monitors.wait(&o, None, never), Wait::NotOwner);
+    assert!(!monitors.notify(&o, false));
+
+    monitors.enter(&o);
+    monitors.enter(&o);
+    let start = Instant::now();
+    let timeout = Some(Duration::from_millis(20));
+    assert_eq!(monitors.wait(&o, timeout, never), Wait::Done);
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert_eq!(monitors.wait(&o, None, || true), Wait::Interrupted);
+
+    let waiter = {
+      let (monitors, o) = (monitors.clone(), o.clone());
+      thread::spawn(move || {
+        monitors.enter(&o);
+        let end = monitors.wait(&o, None, || false);
+        monitors.exit(&o);
+        end
+      })
+    };
+    // Waiting released the monitor, which was entered twice
+    monitors.exit(&o);
+    monitors.exit(&o);
+    loop {
+      monitors.enter(&o);
+      let waiting = !monitors.monitors.lock().unwrap()[&o.address().unwrap()]
+        .state
+        .lock()
+        .unwrap()
+        .wait_set
+        .is_empty();
+      if waiting {
+        assert!(monitors.notify(&o, false));
+        monitors.exit(&o);
+        break;
+      }
+      monitors.exit(&o);
+      thread::yield_now();
+    }
+
+    assert_eq!(waiter.join().unwrap(), Wait::Done);
+    assert!(monitors.monitors.lock().unwrap().is_empty());
+  }
+
+  #[test]
+  fn mutual_exclusion() {
+    let monitors = Arc::new(Monitors::default());
+    let o = Value::new_string("o");
+    let inside = Arc::new(Mutex::new(0));
+
+    let threads: Vec<_> = (0..4)
+      .map(|_| {
+        let (monitors, o, inside) = (monitors.clone(), o.clone(), inside.clone());
+        thread::spawn(move || {
+          for _ in 0..100 {
+            monitors.enter(&o);
+            *inside.lock().unwrap() += 1;
+            assert_eq!(*inside.lock().unwrap(), 1);
+            thread::yield_now();
+            *inside.lock().unwrap() -= 1;
+            monitors.exit(&o);
+          }
+        })
+      })
+      .collect();
+    for t in threads {
+      t.join().unwrap();
+    }
+
+    assert!(monitors.monitors.lock().unwrap().is_empty());
+  }
+}
@@ -0,0 +1,739 @@
+//! Typed natives: Rust functions with typed parameters and results, registered by the Java
+//! descriptor of the method they implement, e.g.
+//!
+//! ```ignore
+//! m.natives().method(
+//!   "java/lang/Thread.getName()Ljava/lang/String;",
+//!   native!(|ctx, this: Value| -> Value { ... }),
+//! );
+//! ```
+//!
+//! The arguments are converted with `FromValue`, and the result with `IntoResult`. A call with
+//! the wrong number of arguments is a `WrongNumberOfParameters` error, an argument of the wrong
+//! type a `CastError`, and a null passed to a parameter which can't be null throws a
+//! `NullPointerException`.
+
+use std::rc::Rc;
+
+use super::function::NativeFunction;
+use super::runtime::{JavaString, Value};
+use super::{
+  format_classname, format_method, is_reference, parameter_types, CallStack, Function,
+  InvokeResult, Module, RuntimeError,
+};
+
+/// What a typed native runs with: the module and the call stack of the call
+pub struct Context<'a> {
+  pub module: &'a Module,
+  pub cs: &'a Rc<CallStack>,
+}
+
+impl<'a> Context<'a> {
+  /// Throw a new exception of a class, e.g. `Ljava/lang/IllegalStateException;`
+  pub fn throw(&self, class: &str, message: Option<&str>) -> InvokeResult {
+    InvokeResult::Exception(
+      self.module.new_exception(class, message, self.cs),
+      self.cs.clone(),
+    )
+  }
+
+  /// Fail with a runtime error
+  pub fn error(&self, error: RuntimeError) -> InvokeResult {
+    InvokeResult::runtime(error, self.cs.clone())
+  }
+}
+
+/// A Rust type a Java value can be converted to
+pub trait FromValue: Sized {
+  /// Number of registers a value takes: 2 for longs and doubles, 1 for anything else
+  const REGISTERS: usize = 1;
+
+  /// Whether values of a Java type (a type descriptor) convert to this type
+  fn accepts(descriptor: &str) -> bool;
+
+  fn from_value(v: &Value, ctx: &Context) -> Result<Self, InvokeResult>;
+}
+
+/// A Rust type which converts to a Java value, like the arguments of calls from Rust
+pub trait IntoValue {
+  /// Whether this type converts to values of a Java type (a type descriptor)
+  fn converts_to(descriptor: &str) -> bool;
+
+  /// The value of this as a value of a Java type it converts to, which gives the type of arrays
+  fn into_value(self, descriptor: &str) -> Value;
+}
+
+/// A Rust type a native can return
+pub trait IntoResult {
+  /// Whether this type converts to values of a Java return type (a type descriptor)
+  fn returns(descriptor: &str) -> bool;
+
+  fn into_result(self, ctx: &Context) -> InvokeResult;
+}
+
+fn cast_error<T>(v: &Value, to: &str, ctx: &Context) -> Result<T, InvokeResult> {
+  Err(ctx.error(RuntimeError::CastError(format!("{:?} as {}", v, to))))
+}
+
+/// Convert the value of a register holding an int-like primitive
+fn single(v: &Value, to: &str, ctx: &Context) -> Result<i32, InvokeResult> {
+  match v {
+    Value::U32(i) => Ok(*i),
+    v => cast_error(v, to, ctx),
+  }
+}
+
+/// Convert the value of a pair of registers holding a long or double
+fn wide(v: &Value, to: &str, ctx: &Context) -> Result<i64, InvokeResult> {
+  match v {
+    Value::U64(l) => Ok(*l),
+    v => cast_error(v, to, ctx),
+  }
+}
+
+/// Implement the conversions of a primitive type
+macro_rules! primitive {
+  ($t:ty, $descriptor:literal, $registers:literal, |$v:ident, $ctx:ident| $from:expr, |$r:ident| $into:expr) => {
+    impl FromValue for $t {
+      const REGISTERS: usize = $registers;
+
+      fn accepts(descriptor: &str) -> bool {
+        descriptor == $descriptor
+      }
+
+      fn from_value($v: &Value, $ctx: &Context) -> Result<Self, InvokeResult> {
+        $from
+      }
+    }
+
+    impl IntoResult for $t {
+      fn returns(descriptor: &str) -> bool {
+        descriptor == $descriptor
+      }
+
+      fn into_result(self, _ctx: &Context) -> InvokeResult {
+        InvokeResult::Ok(self.into_value($descriptor))
+      }
+    }
+
+    impl IntoValue for $t {
+      fn converts_to(descriptor: &str) -> bool {
+        descriptor == $descriptor
+      }
+
+      fn into_value(self, _descriptor: &str) -> Value {
+        let $r = self;
+        $into
+      }
+    }
+  };
+}
+
+primitive!(
+  bool,
+  "Z",
+  1,
+  |v, ctx| single(v, "boolean", ctx).map(|b| b != 0),
+  |r| Value::U32(r as i32)
+);
+primitive!(
+  i8,
+  "B",
+  1,
+  |v, ctx| single(v, "byte", ctx).map(|b| b as i8),
+  |r| Value::U32(r as i32)
+);
+primitive!(
+  i16,
+  "S",
+  1,
+  |v, ctx| single(v, "short", ctx).map(|s| s as i16),
+  |r| Value::U32(r as i32)
+);
+primitive!(
+  u16,
+  "C",
+  1,
+  |v, ctx| single(v, "char", ctx).map(|c| c as u16),
+  |r| Value::U32(r as i32)
+);
+primitive!(i32, "I", 1, |v, ctx| single(v, "int", ctx), |r| Value::U32(
+  r
+));
+primitive!(i64, "J", 2, |v, ctx| wide(v, "long", ctx), |r| Value::U64(
+  r
+));
+primitive!(
+  f32,
+  "F",
+  1,
+  |v, ctx| single(v, "float", ctx).map(|f| f32::from_bits(f as u32)),
+  |r| Value::U32(r.to_bits() as i32)
+);
+primitive!(
+  f64,
+  "D",
+  2,
+  |v, ctx| wide(v, "double", ctx).map(|d| f64::from_bits(d as u64)),
+  |r| Value::U64(r.to_bits() as i64)
+);
+
+/// Any reference, including null
+impl FromValue for Value {
+  fn accepts(descriptor: &str) -> bool {
+    is_reference(descriptor)
+  }
+
+  fn from_value(v: &Value, ctx: &Context) -> Result<Self, InvokeResult> {
+    match v {
+      Value::U64(_) => cast_error(v, "reference", ctx),
+      // Null is zero in a register
+      Value::U32(0) => Ok(Value::Void),
+      Value::U32(_) => cast_error(v, "reference", ctx),
+      v => Ok(v.clone()),
+    }
+  }
+}
+
+impl IntoResult for Value {
+  fn returns(descriptor: &str) -> bool {
+    is_reference(descriptor)
+  }
+
+  fn into_result(self, _ctx: &Context) -> InvokeResult {
+    InvokeResult::Ok(self)
+  }
+}
+
+impl IntoValue for Value {
+  fn converts_to(descriptor: &str) -> bool {
+    is_reference(descriptor)
+  }
+
+  fn into_value(self, _descriptor: &str) -> Value {
+    self
+  }
+}
+
+/// A `java.lang.String` which can't be null
+impl FromValue for JavaString {
+  fn accepts(descriptor: &str) -> bool {
+    descriptor == "Ljava/lang/String;"
+  }
+
+  fn from_value(v: &Value, ctx: &Context) -> Result<Self, InvokeResult> {
+    match v {
+      Value::String(s) => Ok(s.clone()),
+      v if v.is_null() => Err(ctx.throw("Ljava/lang/NullPointerException;", None)),
+      v => cast_error(v, "java.lang.String", ctx),
+    }
+  }
+}
+
+impl IntoResult for JavaString {
+  fn returns(descriptor: &str) -> bool {
+    descriptor == "Ljava/lang/String;"
+  }
+
+  fn into_result(self, _ctx: &Context) -> InvokeResult {
+    InvokeResult::Ok(Value::String(self))
+  }
+}
+
+/// The types a string is assignable to
+const STRING_TYPES: &[&str] = &[
+  "Ljava/lang/String;",
+  "Ljava/lang/Object;",
+  "Ljava/lang/CharSequence;",
+  "Ljava/lang/Comparable;",
+  "Ljava/io/Serializable;",
+];
+
+impl IntoValue for JavaString {
+  fn converts_to(descriptor: &str) -> bool {
+    STRING_TYPES.contains(&descriptor)
+  }
+
+  fn into_value(self, _descriptor: &str) -> Value {
+    Value::String(self)
+  }
+}
+
+/// A `java.lang.String` which can't be null, as a Rust string
+impl FromValue for String {
+  fn accepts(descriptor: &str) -> bool {
+    JavaString::accepts(descriptor)
+  }
+
+  fn from_value(v: &Value, ctx: &Context) -> Result<Self, InvokeResult> {
+    JavaString::from_value(v, ctx).map(|s| s.to_string())
+  }
+}
+
+impl IntoResult for String {
+  fn returns(descriptor: &str) -> bool {
+    JavaString::returns(descriptor)
+  }
+
+  fn into_result(self, _ctx: &Context) -> InvokeResult {
+    InvokeResult::Ok(Value::new_string(&self))
+  }
+}
+
+impl IntoValue for String {
+  fn converts_to(descriptor: &str) -> bool {
+    JavaString::converts_to(descriptor)
+  }
+
+  fn into_value(self, _descriptor: &str) -> Value {
+    Value::new_string(&self)
+  }
+}
+
+impl IntoValue for &str {
+  fn converts_to(descriptor: &str) -> bool {
+    JavaString::converts_to(descriptor)
+  }
+
+  fn into_value(self, _descriptor: &str) -> Value {
+    Value::new_string(self)
+  }
+}
+
+/// An array which can't be null, with elements of a type converting to the component type
+impl<T: FromValue> FromValue for Vec<T> {
+  fn accepts(descriptor: &str) -> bool {
+    descriptor.starts_with('[') && T::accepts(&descriptor[1..])
+  }
+
+  fn from_value(v: &Value, ctx: &Context) -> Result<Self, InvokeResult> {
+    if v.is_null() {
+      return Err(ctx.throw("Ljava/lang/NullPointerException;", None));
+    }
+
+    let items = match v.array() {
+      Err(_) => return cast_error(v, "array", ctx),
+      Ok(a) => a.lock().unwrap().data().clone(),
+    };
+    items.iter().map(|item| T::from_value(item, ctx)).collect()
+  }
+}
+
+/// A new array, of the type it is converted to
+impl<T: IntoValue> IntoValue for Vec<T> {
+  fn converts_to(descriptor: &str) -> bool {
+    descriptor.starts_with('[') && T::converts_to(&descriptor[1..])
+  }
+
+  fn into_value(self, descriptor: &str) -> Value {
+    let items = self
+      .into_iter()
+      .map(|item| item.into_value(&descriptor[1..]))
+      .collect();
+    Value::from_array(descriptor.to_owned(), items)
+  }
+}
+
+impl<T: IntoValue + Clone> IntoValue for &[T] {
+  fn converts_to(descriptor: &str) -> bool {
+    Vec::<T>::converts_to(descriptor)
+  }
+
+  fn into_value(self, descriptor: &str) -> Value {
+    self.to_vec().into_value(descriptor)
+  }
+}
+
+/// A reference which may be null
+impl<T: FromValue> FromValue for Option<T> {
+  fn accepts(descriptor: &str) -> bool {
+    is_reference(descriptor) && T::accepts(descriptor)
+  }
+
+  fn from_value(v: &Value, ctx: &Context) -> Result<Self, InvokeResult> {
+    match v {
+      v if v.is_null() => Ok(None),
+      v => T::from_value(v, ctx).map(Some),
+    }
+  }
+}
+
+/// `None` is null
+impl<T: IntoValue> IntoValue for Option<T> {
+  fn converts_to(descriptor: &str) -> bool {
+    is_reference(descriptor) && T::converts_to(descriptor)
+  }
+
+  fn into_value(self, descriptor: &str) -> Value {
+    match self {
+      None => Value::Void,
+      Some(v) => v.into_value(descriptor),
+    }
+  }
+}
+
+/// `None` returns null
+impl<T: IntoResult> IntoResult for Option<T> {
+  fn returns(descriptor: &str) -> bool {
+    is_reference(descriptor) && T::returns(descriptor)
+  }
+
+  fn into_result(self, ctx: &Context) -> InvokeResult {
+    match self {
+      None => InvokeResult::Ok(Value::Void),
+      Some(v) => v.into_result(ctx),
+    }
+  }
+}
+
+/// The result of a `void` method
+impl FromValue for () {
+  fn accepts(descriptor: &str) -> bool {
+    descriptor == "V"
+  }
+
+  fn from_value(_v: &Value, _ctx: &Context) -> Result<Self, InvokeResult> {
+    Ok(())
+  }
+}
+
+/// `void`
+impl IntoResult for () {
+  fn returns(descriptor: &str) -> bool {
+    descriptor == "V"
+  }
+
+  fn into_result(self, _ctx: &Context) -> InvokeResult {
+    InvokeResult::Ok(Value::Void)
+  }
+}
+
+/// A result, or the exception (or error) stopping the native
+impl<T: IntoResult> IntoResult for Result<T, InvokeResult> {
+  fn returns(descriptor: &str) -> bool {
+    T::returns(descriptor)
+  }
+
+  fn into_result(self, ctx: &Context) -> InvokeResult {
+    match self {
+      Err(r) => r,
+      Ok(v) => v.into_result(ctx),
+    }
+  }
+}
+
+/// The arguments of a call, converted one by one
+pub struct Arguments<'a> {
+  params: &'a [Value],
+  next: usize,
+}
+
+impl<'a> Arguments<'a> {
+  /// Check that a call has as many registers as the parameters take
+  pub fn new(params: &'a [Value], registers: usize, ctx: &Context) -> Result<Self, InvokeResult> {
+    match params.len() {
+      n if n == registers => Ok(Arguments { params, next: 0 }),
+      n => Err(ctx.error(RuntimeError::WrongNumberOfParameters(registers, n))),
+    }
+  }
+
+  /// Convert the next argument, skipping the padding register of longs and doubles
+  pub fn next<T: FromValue>(&mut self, ctx: &Context) -> Result<T, InvokeResult> {
+    let v = &self.params[self.next];
+    self.next += T::REGISTERS;
+    T::from_value(v, ctx)
+  }
+}
+
+/// A native made by `native!`, with the Java types its parameters and result convert from and to
+pub struct TypedNative {
+  pub parameters: Vec<fn(&str) -> bool>,
+  pub returns: fn(&str) -> bool,
+  pub function: NativeFunction,
+}
+
+impl TypedNative {
+  /// Check that the native implements a method with some parameters (`this` first, for instance
+  /// methods) and return type, describing the first mismatch
+  pub fn check(&self, parameters: &[&str], return_type: &str) -> Result<(), String> {
+    if parameters.len() != self.parameters.len() {
+      return Err(format!(
+        "takes {} parameters instead of {}",
+        self.parameters.len(),
+        parameters.len()
+      ));
+    }
+    for (i, (p, accepts)) in parameters.iter().zip(&self.parameters).enumerate() {
+      if !accepts(p) {
+        return Err(format!("can't take a {} as parameter {}", p, i));
+      }
+    }
+    match (self.returns)(return_type) {
+      false => Err(format!("can't return a {}", return_type)),
+      true => Ok(()),
+    }
+  }
+}
+
+/// Turn a Rust closure with typed parameters into a `TypedNative`. The first parameter is the
+/// `Context`; the types of the others implement `FromValue`, and the result implements
+/// `IntoResult`, `()` if it isn't given. The closure can capture host state, which it owns (it is
+/// a `move` closure), and which has to be `Send` and `Sync`.
+#[macro_export]
+macro_rules! native {
+  (|$ctx:ident $(, $arg:ident : $ty:ty)* $(,)?| $body:block) => {
+    $crate::native!(|$ctx $(, $arg: $ty)*| -> () $body)
+  };
+  (|$ctx:ident $(, $arg:ident : $ty:ty)* $(,)?| -> $ret:ty $body:block) => {
+    $crate::codegen::native::TypedNative {
+      parameters: vec![$(<$ty as $crate::codegen::native::FromValue>::accepts),*],
+      returns: <$ret as $crate::codegen::native::IntoResult>::returns,
+      function: $crate::codegen::function::NativeFunction::new(move |params, cs, module| {
+        #[allow(unused_variables)]
+        let $ctx = &$crate::codegen::native::Context {
+          module,
+          cs: &cs,
+        };
+        let registers = 0 $(+ <$ty as $crate::codegen::native::FromValue>::REGISTERS)*;
+        #[allow(unused_mut, unused_variables)]
+        let mut args = match $crate::codegen::native::Arguments::new(&params, registers, $ctx) {
+          Err(r) => return r,
+          Ok(a) => a,
+        };
+        $(
+          let $arg: $ty = match args.next($ctx) {
+            Err(r) => return r,
+            Ok(v) => v,
+          };
+        )*
+
+        // A closure, so a `return` in the body returns its result
+        let body = || -> $ret { $body };
+        let result = body();
+        $crate::codegen::native::IntoResult::into_result(result, $ctx)
+      }),
+    }
+  };
+}
+
+/// Registers typed natives in a module, by the descriptors of the methods they implement
+pub struct Natives<'m> {
+  module: &'m mut Module,
+}
+
+impl<'m> Natives<'m> {
+  pub fn new(module: &'m mut Module) -> Self {
+    Natives { module }
+  }
+
+  /// Register an instance method, whose first parameter is `this`. The descriptor is like
+  /// `java/lang/StringBuilder.append(I)Ljava/lang/StringBuilder;`.
+  ///
+  /// Panics if the types of the native don't match the descriptor. Strings are immutable values,
+  /// so a constructor of `java/lang/String` returns the new string instead of `void`.
+  pub fn method(&mut self, descriptor: &str, native: TypedNative) -> &mut Self {
+    self.add(descriptor, false, native)
+  }
+
+  /// Register a static method. Panics if the types of the native don't match the descriptor.
+  pub fn static_method(&mut self, descriptor: &str, native: TypedNative) -> &mut Self {
+    self.add(descriptor, true, native)
+  }
+
+  /// Register an instance method with a native taking the arguments as they are, for natives
+  /// working on the values themselves, like those of the collections which hold any type.
+  /// Nothing is checked against the descriptor.
+  pub fn untyped_method<F>(&mut self, descriptor: &str, f: F) -> &mut Self
+  where
+    F: Fn(Vec<Value>, Rc<CallStack>, &Module) -> InvokeResult + Send + Sync + 'static,
+  {
+    self.register(descriptor, false, NativeFunction::new(f))
+  }
+
+  /// Register a static method with a native taking the arguments as they are
+  pub fn untyped_static_method<F>(&mut self, descriptor: &str, f: F) -> &mut Self
+  where
+    F: Fn(Vec<Value>, Rc<CallStack>, &Module) -> InvokeResult + Send + Sync + 'static,
+  {
+    self.register(descriptor, true, NativeFunction::new(f))
+  }
+
+  fn add(&mut self, descriptor: &str, is_static: bool, native: TypedNative) -> &mut Self {
+    let (class, name, method_descriptor) = match split_descriptor(descriptor) {
+      None => panic!("Not a method descriptor: {}", descriptor),
+      Some(d) => d,
+    };
+
+    let mut parameters = parameter_types(method_descriptor);
+    if !is_static {
+      parameters.insert(0, &class);
+    }
+    let return_type = match (class.as_str(), name) {
+      ("Ljava/lang/String;", "<init>") => "Ljava/lang/String;",
+      _ => &method_descriptor[method_descriptor.find(')').unwrap() + 1..],
+    };
+    if let Err(e) = native.check(&parameters, return_type) {
+      panic!("The native of {} {}", descriptor, e);
+    }
+
+    self.register(descriptor, is_static, native.function)
+  }
+
+  fn register(
+    &mut self,
+    descriptor: &str,
+    is_static: bool,
+    mut function: NativeFunction,
+  ) -> &mut Self {
+    let (class, name, method_descriptor) = match split_descriptor(descriptor) {
+      None => panic!("Not a method descriptor: {}", descriptor),
+      Some(d) => d,
+    };
+
+    function.is_static = is_static;
+    self.module.add_function(
+      format!(
+        "{}__{}",
+        format_classname(&class),
+        format_method(name, method_descriptor)
+      ),
+      Function::Native(function),
+    );
+    self
+  }
+}
+
+/// Split `java/lang/String.charAt(I)C` into the class (`Ljava/lang/String;`), the method name and
+/// the method descriptor
+fn split_descriptor(descriptor: &str) -> Option<(String, &str, &str)> {
+  let paren = descriptor.find('(')?;
+  descriptor[paren..].find(')')?;
+  let dot = descriptor[..paren].rfind('.')?;
+
+  Some((
+    format!("L{};", &descriptor[..dot]),
+    &descriptor[dot + 1..paren],
+    &descriptor[paren..],
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn call(native: &TypedNative, params: Vec<Value>) -> InvokeResult {
+    let module = Module::new("test".to_owned());
+    (native.function.function)(params, Rc::new(CallStack::default()), &module)
+  }
+
+  #[test]
+  fn split_descriptors() {
+    assert_eq!(
+      split_descriptor("java/lang/String.charAt(I)C"),
+      Some(("Ljava/lang/String;".to_owned(), "charAt", "(I)C"))
+    );
+    assert_eq!(
+      split_descriptor("java/util/HashMap$Node.<init>()V"),
+      Some(("Ljava/util/HashMap$Node;".to_owned(), "<init>", "()V"))
+    );
+    assert_eq!(split_descriptor("java/lang/String.charAt"), None);
+    assert_eq!(split_descriptor("java/lang/String.charAt(I"), None);
+    assert_eq!(split_descriptor("charAt(I)C"), None);
+  }
+
+  #[test]
+  fn check_types() {
+    let native = crate::native!(|ctx, this: Value, i: i32, l: i64| -> Option<String> {
+      Some(format!("{:?} {} {}", this, i, l))
+    });
+
+    let object = "Ljava/lang/Object;";
+    assert!(native
+      .check(&[object, "I", "J"], "Ljava/lang/String;")
+      .is_ok());
+    assert_eq!(
+      native.check(&[object, "I"], "Ljava/lang/String;"),
+      Err("takes 3 parameters instead of 2".to_owned())
+    );
+    assert_eq!(
+      native.check(&[object, "J", "J"], "Ljava/lang/String;"),
+      Err("can't take a J as parameter 1".to_owned())
+    );
+    assert_eq!(
+      native.check(&["I", "I", "J"], "Ljava/lang/String;"),
+      Err("can't take a I as parameter 0".to_owned())
+    );
+    assert_eq!(
+      native.check(&[object, "I", "J"], "I"),
+      Err("can't return a I".to_owned())
+    );
+  }
+
+  #[test]
+  fn arguments() {
+    let native =
+      crate::native!(|ctx, l: i64, s: String, c: u16| -> String { format!("{} {} {}", l, s, c) });
+
+    // A long takes two registers
+    let args = vec![
+      Value::U64(-5),
+      Value::Void,
+      Value::new_string("a"),
+      Value::U32(66),
+    ];
+    match call(&native, args) {
+      InvokeResult::Ok(v) => assert_eq!(v.string(), Some("-5 a 66".to_owned())),
+      _ => panic!("The call failed"),
+    }
+
+    match call(&native, vec![Value::U64(-5), Value::new_string("a")]) {
+      InvokeResult::RuntimeError(e) => match e.error {
+        RuntimeError::WrongNumberOfParameters(4, 2) => {}
+        e => panic!("{:?}", e),
+      },
+      _ => panic!("The call didn't fail"),
+    }
+
+    let args = vec![
+      Value::U32(1),
+      Value::Void,
+      Value::new_string("a"),
+      Value::U32(66),
+    ];
+    match call(&native, args) {
+      InvokeResult::RuntimeError(e) => match e.error {
+        RuntimeError::CastError(_) => {}
+        e => panic!("{:?}", e),
+      },
+      _ => panic!("The call didn't fail"),
+    }
+
+    // A string can't be null
+    let args = vec![Value::U64(1), Value::Void, Value::Void, Value::U32(66)];
+    match call(&native, args) {
+      InvokeResult::Exception(e, _) => {
+        assert_eq!(
+          e.get_type(),
+          Some("Ljava/lang/NullPointerException;".to_owned())
+        )
+      }
+      _ => panic!("The call didn't throw"),
+    }
+  }
+
+  #[test]
+  fn references() {
+    let native =
+      crate::native!(|ctx, o: Option<String>, v: Value| -> bool { o.is_none() && v.is_null() });
+
+    // Null is zero in a register
+    match call(&native, vec![Value::U32(0), Value::Void]) {
+      InvokeResult::Ok(v) => assert_eq!(v.to_single(), 1),
+      _ => panic!("The call failed"),
+    }
+    match call(&native, vec![Value::U32(1), Value::Void]) {
+      InvokeResult::RuntimeError(_) => {}
+      _ => panic!("The call didn't fail"),
+    }
+  }
+}
@@ -0,0 +1,92 @@
+//! Sinks for the standard streams of a program (`System.out` and `System.err`), so an
+//! embedder or a test can capture what the program prints.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// A standard stream, numbered like its file descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StandardStream {
+  Out = 1,
+  Err = 2,
+}
+
+impl StandardStream {
+  pub fn from_fd(fd: i32) -> Option<Self> {
+    match fd {
+      1 => Some(StandardStream::Out),
+      2 => Some(StandardStream::Err),
+      _ => None,
+    }
+  }
+}
+
+/// Where the standard streams go, the process' own stdout and stderr by default
+pub struct Output {
+  out: Mutex<Box<dyn Write + Send>>,
+  err: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Default for Output {
+  fn default() -> Self {
+    Self {
+      out: Mutex::new(Box::new(io::stdout())),
+      err: Mutex::new(Box::new(io::stderr())),
+    }
+  }
+}
+
+impl fmt::Debug for Output {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("Output")
+  }
+}
+
+impl Output {
+  fn sink(&self, stream: StandardStream) -> &Mutex<Box<dyn Write + Send>> {
+    match stream {
+      StandardStream::Out => &self.out,
+      StandardStream::Err => &self.err,
+    }
+  }
+
+  pub fn set(&self, stream: StandardStream, sink: Box<dyn Write + Send>) {
+    *self.sink(stream).lock().unwrap() = sink;
+  }
+
+  pub fn write(&self, stream: StandardStream, bytes: &[u8]) -> io::Result<()> {
+    self.sink(stream).lock().unwrap().write_all(bytes)
+  }
+
+  pub fn flush(&self, stream: StandardStream) -> io::Result<()> {
+    self.sink(stream).lock().unwrap().flush()
+  }
+}
+
+/// A sink keeping everything written to it in memory. Clones share the same buffer, so one
+/// can be given to `Module::set_output` and the other read afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedOutput {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Everything written so far, decoded as UTF-8
+  pub fn contents(&self) -> String {
+    String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+  }
+}
+
+impl Write for CapturedOutput {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0.lock().unwrap().extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
@@ -1,17 +1,19 @@
-use std::collections::HashMap;
+use std::any::Any;
+use std::borrow::Borrow;
+use std::fmt;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 
 use log::warn;
 
-use super::instructions::LiteralValue;
+use super::instructions::FieldSlot;
 use super::{CallStack, RuntimeError, RuntimeErrorStack};
 
 pub enum InvokeResult {
   /// Ok with the return value inside.
   Ok(Value),
   /// Exception with the `Throwable` object inside.
-  Exception(Instance, Rc<CallStack>),
+  Exception(Value, Rc<CallStack>),
   /// Runtime exception for non-catchable errors.
   RuntimeError(RuntimeErrorStack),
 }
@@ -27,7 +29,7 @@ impl std::fmt::Debug for InvokeResult {
     match self {
       InvokeResult::Ok(v) => writeln!(f, "Ok({})", format!("{:?}", v))?,
       InvokeResult::RuntimeError(e) => writeln!(f, "RuntimeError({})", format!("{:?}", e))?,
-      InvokeResult::Exception(_e, _cs) => unimplemented!(),
+      InvokeResult::Exception(e, _cs) => writeln!(f, "Exception({:?})", e)?,
     };
     Ok(())
   }
@@ -36,35 +38,45 @@ impl std::fmt::Debug for InvokeResult {
 #[derive(Clone, Debug)]
 pub enum Value {
   Void,
-  Char(char),
   U32(i32),
   U64(i64),
   Instance(Arc<Mutex<Instance>>),
-  Array(Vec<Value>),
-  // String(String),
-  // Native(std::rc::Rc<dyn NativeValue>),
-  // Native(Box<dyn NativeValue>),
+  Array(Arc<Mutex<Array>>),
+  String(JavaString),
+  /// Rust data behind a native object, like the buffer of a `StringBuilder`
+  Native(Arc<Mutex<NativeData>>),
 }
 
 impl Value {
-  pub fn new_instance() -> Self {
-    Self::Instance(Arc::new(Mutex::new(Instance::default())))
-  }
-
   pub fn from_instance(instance: Instance) -> Self {
     Self::Instance(Arc::new(Mutex::new(instance)))
   }
 
-  pub fn is_void(&self) -> bool {
-    match self {
-      Value::Void => true,
-      _ => false,
+  /// Create a `java.lang.String`
+  pub fn new_string(s: &str) -> Self {
+    Value::String(JavaString::from(s))
+  }
+
+  pub fn native<T: Any + Send>(data: T) -> Self {
+    Self::Native(Arc::new(Mutex::new(NativeData(Box::new(data)))))
+  }
+
+  pub fn from_array(type_: String, data: Vec<Value>) -> Self {
+    Self::Array(Arc::new(Mutex::new(Array::new(type_, data))))
+  }
+
+  /// The zero value of a type descriptor, used for new arrays
+  pub fn zero(type_: &str) -> Self {
+    match type_ {
+      "J" | "D" => Value::U64(0),
+      "Z" | "B" | "S" | "C" | "I" | "F" => Value::U32(0),
+      _ => Value::Void,
     }
   }
 
-  pub fn is_char(&self) -> bool {
+  pub fn is_void(&self) -> bool {
     match self {
-      Value::Char(_) => true,
+      Value::Void => true,
       _ => false,
     }
   }
@@ -97,12 +109,9 @@ impl Value {
     }
   }
 
-  // pub fn is_string(&self) -> bool {
-  //   match self {
-  //     Value::String(_) => true,
-  //     _ => false,
-  //   }
-  // }
+  pub fn is_string(&self) -> bool {
+    matches!(self, Value::String(_))
+  }
 
   pub fn instance(&self) -> Result<&Arc<Mutex<Instance>>, RuntimeError> {
     match self {
@@ -111,67 +120,140 @@ impl Value {
     }
   }
 
+  pub fn array(&self) -> Result<&Arc<Mutex<Array>>, RuntimeError> {
+    match self {
+      Value::Array(a) => Ok(a),
+      _ => Err(RuntimeError::CastError(format!("{:?} as array", self))),
+    }
+  }
+
+  pub fn java_string(&self) -> Result<&JavaString, RuntimeError> {
+    match self {
+      Value::String(s) => Ok(s),
+      _ => Err(RuntimeError::CastError(format!("{:?} as string", self))),
+    }
+  }
+
+  pub fn native_data(&self) -> Result<&Arc<Mutex<NativeData>>, RuntimeError> {
+    match self {
+      Value::Native(n) => Ok(n),
+      _ => Err(RuntimeError::CastError(format!(
+        "{:?} as native data",
+        self
+      ))),
+    }
+  }
+
+  /// Reference equality (`==` on objects in java)
+  pub fn same(&self, other: &Value) -> bool {
+    match (self, other) {
+      (Value::Instance(a), Value::Instance(b)) => Arc::ptr_eq(a, b),
+      (Value::Array(a), Value::Array(b)) => Arc::ptr_eq(a, b),
+      (Value::String(a), Value::String(b)) => JavaString::ptr_eq(a, b),
+      (Value::Native(a), Value::Native(b)) => Arc::ptr_eq(a, b),
+      (a, b) => a.is_null() && b.is_null(),
+    }
+  }
+
+  /// The address of a reference, which identifies the object while it is alive
+  pub fn address(&self) -> Option<usize> {
+    match self {
+      Value::Instance(a) => Some(Arc::as_ptr(a) as usize),
+      Value::Array(a) => Some(Arc::as_ptr(a) as usize),
+      Value::String(s) => Some(s.units().as_ptr() as usize),
+      Value::Native(a) => Some(Arc::as_ptr(a) as usize),
+      _ => None,
+    }
+  }
+
+  /// Identity hash of a reference (`System.identityHashCode`), derived from its address so it
+  /// stays the same for the lifetime of the object. Null and primitives hash to 0.
+  pub fn identity_hash(&self) -> i32 {
+    let address = match self.address() {
+      None => return 0,
+      Some(a) => a,
+    };
+
+    // Mix the bits of the (aligned) address, the high ones are the most random
+    let h = (address as u64 >> 3).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    (h >> 33) as i32
+  }
+
+  /// Null is either an unset value or the literal 0 (`const/4 vX, 0`)
+  pub fn is_null(&self) -> bool {
+    matches!(self, Value::Void | Value::U32(0))
+  }
+
+  /// Read a `java.lang.String` into a rust string
+  pub fn string(&self) -> Option<String> {
+    match self {
+      Value::String(s) => Some(s.to_string()),
+      _ => None,
+    }
+  }
+
+  /// The type descriptor of a reference, or `None` for null and primitives
+  pub fn get_type(&self) -> Option<String> {
+    match self {
+      Value::Instance(i) => Some(i.lock().unwrap().get_class_type().clone()),
+      Value::Array(a) => Some(a.lock().unwrap().get_array_type().clone()),
+      Value::String(_) => Some("Ljava/lang/String;".to_owned()),
+      _ => None,
+    }
+  }
+
   pub fn to_single(&self) -> i32 {
     match self {
       Value::Void => 0,
-      Value::Char(a) => *a as i32,
       Value::U32(a) => *a,
       Value::U64(a) => *a as i32,
       Value::Instance(_i) => {
         warn!("Trying to convert Instance to single value.");
         1
       }
-      // Value::String(_s) => {
-      //   warn!("Trying to convert String to single value.");
-      //   1
-      // }
+      Value::String(_) => {
+        warn!("Trying to convert String to single value.");
+        1
+      }
+      Value::Native(_) => {
+        warn!("Trying to convert native data to single value.");
+        1
+      }
       Value::Array(_) => {
         warn!("Trying to convert Array to single value.");
         1
       }
     }
   }
-}
-
-impl From<&LiteralValue> for Value {
-  fn from(value: &LiteralValue) -> Self {
-    match value {
-      LiteralValue::Lit(i) => Value::U32(*i),
-      LiteralValue::Wide(wide) => Value::U64(*wide),
-      LiteralValue::String(s) => {
-        //TODO Find a more effective way to store and handle strings?
-        let out = Value::new_instance();
-        {
-          let mut inst = out.instance().unwrap().lock().unwrap();
-          inst.set_class_type("java_lang_string".to_owned());
-          let v = s.chars().map(|ch| Value::Char(ch)).collect();
-          inst.set_field("data".to_owned(), Value::Array(v));
-        }
 
-        out
+  pub fn to_wide(&self) -> i64 {
+    match self {
+      Value::Void => 0,
+      Value::U32(a) => *a as i64,
+      Value::U64(a) => *a,
+      v => {
+        warn!("Trying to convert {:?} to wide value.", v);
+        1
       }
-      // LiteralValue::Class
-      _ => unimplemented!(),
     }
   }
 }
 
 #[derive(Debug, Clone)]
 pub struct Instance {
-  fields: HashMap<String, Value>,
+  /// Field values, indexed by the slots of the instance's class
+  fields: Vec<Value>,
   type_: String,
 }
 
-impl Default for Instance {
-  fn default() -> Self {
+impl Instance {
+  pub fn new(type_: String, n_fields: usize) -> Self {
     Self {
-      fields: HashMap::new(),
-      type_: "unknown".to_owned(),
+      fields: vec![Value::Void; n_fields],
+      type_,
     }
   }
-}
 
-impl Instance {
   pub fn set_class_type(&mut self, type_: String) {
     self.type_ = type_;
   }
@@ -180,11 +262,163 @@ impl Instance {
     &self.type_
   }
 
-  pub fn set_field(&mut self, field: String, value: Value) {
-    self.fields.insert(field, value);
+  pub fn set_field(&mut self, slot: FieldSlot, value: Value) -> Result<(), RuntimeError> {
+    match self.fields.get_mut(slot) {
+      None => Err(RuntimeError::FieldOutOfBounds(slot)),
+      Some(f) => {
+        *f = value;
+        Ok(())
+      }
+    }
   }
 
-  pub fn get_field(&self, field: &str) -> Option<&Value> {
-    self.fields.get(field)
+  pub fn get_field(&self, slot: FieldSlot) -> Option<&Value> {
+    self.fields.get(slot)
   }
 }
+
+#[derive(Debug, Clone)]
+pub struct Array {
+  data: Vec<Value>,
+  /// Type descriptor of the array itself, e.g. `[I`
+  type_: String,
+}
+
+impl Array {
+  pub fn new(type_: String, data: Vec<Value>) -> Self {
+    Self { data, type_ }
+  }
+
+  pub fn get_array_type(&self) -> &String {
+    &self.type_
+  }
+
+  pub fn len(&self) -> usize {
+    self.data.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.data.is_empty()
+  }
+
+  pub fn get(&self, idx: usize) -> Option<&Value> {
+    self.data.get(idx)
+  }
+
+  pub fn set(&mut self, idx: usize, value: Value) -> Option<()> {
+    let el = self.data.get_mut(idx)?;
+    *el = value;
+    Some(())
+  }
+
+  pub fn data(&self) -> &Vec<Value> {
+    &self.data
+  }
+}
+
+/// A `java.lang.String`: immutable UTF-16 code units behind a shared pointer, so cloning
+/// a string is cheap and reference equality can be checked with `ptr_eq`
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct JavaString(Arc<[u16]>);
+
+impl JavaString {
+  pub fn from_units(units: Vec<u16>) -> Self {
+    Self(units.into())
+  }
+
+  pub fn units(&self) -> &[u16] {
+    &self.0
+  }
+
+  /// Length in UTF-16 code units, like `String.length()`
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  pub fn char_at(&self, idx: usize) -> Option<u16> {
+    self.0.get(idx).copied()
+  }
+
+  /// Whether both are the same string object
+  pub fn ptr_eq(a: &JavaString, b: &JavaString) -> bool {
+    Arc::ptr_eq(&a.0, &b.0)
+  }
+
+  /// `String.hashCode()`: `s[0]*31^(n-1) + s[1]*31^(n-2) + ... + s[n-1]`
+  pub fn hash_code(&self) -> i32 {
+    self
+      .0
+      .iter()
+      .fold(0i32, |h, c| h.wrapping_mul(31).wrapping_add(*c as i32))
+  }
+}
+
+impl Borrow<[u16]> for JavaString {
+  fn borrow(&self) -> &[u16] {
+    &self.0
+  }
+}
+
+impl From<&str> for JavaString {
+  fn from(s: &str) -> Self {
+    Self::from_units(s.encode_utf16().collect())
+  }
+}
+
+impl fmt::Display for JavaString {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", String::from_utf16_lossy(&self.0))
+  }
+}
+
+impl fmt::Debug for JavaString {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", String::from_utf16_lossy(&self.0))
+  }
+}
+
+/// Any rust value, owned by a java object implemented natively
+pub struct NativeData(Box<dyn Any + Send>);
+
+impl NativeData {
+  pub fn get<T: Any>(&self) -> Option<&T> {
+    self.0.downcast_ref()
+  }
+
+  pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+    self.0.downcast_mut()
+  }
+}
+
+impl fmt::Debug for NativeData {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "NativeData")
+  }
+}
+
+/// What a `java.lang.invoke.MethodHandle` calls
+#[derive(Debug, Clone)]
+pub enum HandleTarget {
+  /// A function of the module
+  Function(String),
+  /// A method, dispatched on the class of the first argument
+  Virtual(String),
+  /// A constructor: the function is called on a new instance of the class, which is returned
+  Constructor { class: String, function: String },
+}
+
+/// The native data of a `java.lang.invoke.MethodHandle`: its target, and the arguments bound to
+/// it which are passed before the arguments of every call
+#[derive(Debug, Clone)]
+pub struct MethodHandle {
+  pub target: HandleTarget,
+  pub bound: Vec<Value>,
+  /// The type of the handle (`MethodHandle.type()`) as a method descriptor, without the bound
+  /// arguments. The receiver of a method is its first parameter, and a constructor returns the
+  /// object.
+  pub descriptor: String,
+}
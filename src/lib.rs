@@ -1,28 +1,89 @@
-use dexparser::DexFile;
-use log::{debug, info};
+use dexparser::{ClassDefinition, DexFile};
+use log::{debug, info, warn};
 
 pub mod codegen;
 mod parser;
 mod std_env;
 
-use codegen::Module;
+use codegen::{runtime::Value, Module};
 
-/// Takes a reference to a DexFile as input and spits out a module, ready to be used.
-pub fn process(input: &DexFile) -> Module {
+/// Takes a reference to a DexFile and the bytes it was parsed from as input and spits out a
+/// module, ready to be used.
+pub fn process(input: &DexFile, bytes: &[u8]) -> Module {
+	process_with_boot_classpath(&[], input, bytes)
+}
+
+/// Like `process`, but with a core library (e.g. `core-oj.dex` of an Android image) loaded as
+/// the boot classpath first. Its bytecode is run instead of the natives of the runtime, which
+/// only provides its `native` methods and the classes it represents itself (like `String`).
+pub fn process_with_boot_classpath(
+	boot_classpath: &[(&DexFile, &[u8])],
+	input: &DexFile,
+	bytes: &[u8],
+) -> Module {
 	info!("Parsing dex input");
 
 	let mut module = Module::new("undexed".to_owned());
 
 	std_env::add_all(&mut module);
 
-	for class in &input.classes {
-		debug!("Generating class: {}", class.class_type);
-		parser::parse_class(&class, &input, &mut module).unwrap();
+	// The boot classpath comes first, since the classes of the input extend its classes
+	let dex_files: Vec<(&DexFile, &[u8], bool)> = boot_classpath
+		.iter()
+		.map(|(dex, bytes)| (*dex, *bytes, true))
+		.chain(std::iter::once((input, bytes, false)))
+		.collect();
+	for (dex, _, is_boot) in &dex_files {
+		for class in loaded_classes(dex, *is_boot) {
+			parser::declare_class(&class, &mut module);
+		}
+	}
+
+	let mut first_call_site = 0;
+	for (dex, bytes, is_boot) in &dex_files {
+		// A class of the boot classpath which can't be loaded is left out, so that a program which
+		// doesn't use it still runs
+		let call_sites = match parser::parse_call_sites(bytes, dex, &module, first_call_site) {
+			Err(e) if *is_boot => {
+				warn!("Could not read the call sites of the boot classpath: {}", e);
+				Default::default()
+			}
+			r => r.unwrap(),
+		};
+		first_call_site += call_sites.sites.len();
+
+		for class in loaded_classes(dex, *is_boot) {
+			debug!("Generating class: {}", class.class_type);
+			match parser::parse_class(&class, dex, &call_sites, &mut module) {
+				Err(e) if *is_boot => warn!("Could not load {}: {}", class.class_type, e),
+				r => r.unwrap(),
+			}
+		}
 	}
 
 	module
 }
 
+/// The classes of a dex file to load. The runtime's own version of an intrinsic class is kept
+/// over the one of the boot classpath.
+fn loaded_classes(dex: &DexFile, is_boot: bool) -> impl Iterator<Item = &ClassDefinition> {
+	dex.classes
+		.iter()
+		.filter(move |c| !(is_boot && std_env::is_intrinsic(&c.class_type)))
+}
+
+/// Describe an exception that reached the top of a thread, like the JVM does:
+/// `Exception in thread "main" java.lang.ArithmeticException: / by zero`, the stack trace and its causes
+pub fn format_uncaught_exception(exception: &Value, thread: &str) -> String {
+	match std_env::format_uncaught(exception, thread) {
+		Ok(s) => s,
+		Err(e) => format!(
+			"Exception in thread \"{}\" {:?} (could not be formatted: {:?})",
+			thread, exception, e
+		),
+	}
+}
+
 //TODO `process` but append to an already existing module (useful for parsing multiple dex files)
 pub fn process_and_append(_input: &DexFile, _module: &mut Module) {
 	unimplemented!();
@@ -2,22 +2,53 @@
 // aar input.dex --main MyClass.main -- argument1 argument 2
 
 use log::{info, warn};
+use std::env;
 use std::fs::{self, File};
 use std::io;
 use std::io::prelude::*;
+use std::time::Instant;
 
 use aar::codegen::runtime::{InvokeResult, Value};
-use aar::process;
+use aar::codegen::StandardStream;
+use aar::{format_uncaught_exception, process_with_boot_classpath};
 
 fn main() -> io::Result<()> {
   env_logger::init();
 
-  let path = "resources/MyCode/classes.dex";
+  let mut args: Vec<String> = env::args().collect();
+  // `--bootclasspath core-oj.dex:other.dex` loads a core library before the input
+  let boot_classpath: Vec<String> = match args.iter().position(|a| a == "--bootclasspath") {
+    None => Vec::new(),
+    Some(i) => {
+      let option: Vec<String> = args.drain(i..(i + 2).min(args.len())).collect();
+      match option.get(1) {
+        None => Vec::new(),
+        Some(paths) => paths.split(':').map(|p| p.to_owned()).collect(),
+      }
+    }
+  };
+  let path = args
+    .get(1)
+    .map(|s| s.as_str())
+    .unwrap_or("resources/MyCode/classes.dex");
+  let main_fn = args
+    .get(2)
+    .map(|s| s.as_str())
+    .unwrap_or("CLASS_MyCode__main");
 
   info!("Reading dex file: {}", &path);
-  let mut file = File::open(path).unwrap();
-  let mut bytes = Vec::new();
-  file.read_to_end(&mut bytes)?;
+  let bytes = read(path)?;
+
+  let mut boot = Vec::new();
+  for path in &boot_classpath {
+    info!("Reading boot classpath dex file: {}", path);
+    let bytes = read(path)?;
+    match dexparser::parse(&bytes) {
+      Ok(dex) => boot.push((dex, bytes)),
+      Err(e) => panic!("Failed to parse dex file {}: {}", path, e),
+    }
+  }
+  let boot: Vec<_> = boot.iter().map(|(dex, b)| (dex, b.as_slice())).collect();
 
   //TODO Warn about overwriting a directory?
   match fs::create_dir_all("out/analysis") {
@@ -27,21 +58,47 @@ fn main() -> io::Result<()> {
 
   match dexparser::parse(&bytes) {
     Ok(res) => {
-      let mut module = process(&res);
+      let mut module = process_with_boot_classpath(&boot, &res, &bytes);
 
       let out = module.build_ir();
       let mut file = File::create("out/out")?;
       file.write_all(out.as_bytes())?;
       info!("IR Output saved");
 
-      let res = module.run("CLASS_MyCode__main", vec![Value::Array(Vec::new())]);
-      match res {
-        InvokeResult::Ok(v) => info!("Return value: {:?}", v),
-        InvokeResult::Exception(e, cs) => println!("Exception: {:?}\n{}", e, cs.finalize()),
-        InvokeResult::RuntimeError(e) => {
-          let f = e.finalize();
-          println!("{}", f);
+      // Shared, so its threads run on threads of their own
+      let module = module.into_shared();
+      let start = Instant::now();
+      let res = module.run(
+        main_fn,
+        vec![Value::from_array(
+          "[Ljava/lang/String;".to_owned(),
+          Vec::new(),
+        )],
+      );
+      info!("Ran {} in {:?}", main_fn, start.elapsed());
+
+      // Written to the program's standard error, like the uncaught exceptions of other threads
+      let message = match res {
+        InvokeResult::Ok(v) => {
+          info!("Return value: {:?}", v);
+          None
         }
+        InvokeResult::Exception(e, _) => Some(format_uncaught_exception(&e, "main")),
+        InvokeResult::RuntimeError(e) => Some(e.finalize()),
+      };
+      let failed = message.is_some();
+      if let Some(message) = message {
+        if let Err(e) =
+          module.write_output(StandardStream::Err, format!("{}\n", message).as_bytes())
+        {
+          warn!("Could not write to {:?}: {:?}", StandardStream::Err, e);
+        }
+      }
+
+      // Like the JVM, exit once the other threads end too
+      module.join_threads();
+      if failed {
+        std::process::exit(1);
       }
     }
 
@@ -50,3 +107,9 @@ fn main() -> io::Result<()> {
 
   Ok(())
 }
+
+fn read(path: &str) -> io::Result<Vec<u8>> {
+  let mut bytes = Vec::new();
+  File::open(path)?.read_to_end(&mut bytes)?;
+  Ok(bytes)
+}
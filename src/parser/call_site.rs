@@ -0,0 +1,332 @@
+//! Call sites and method handles of invoke-custom, which `dexparser` doesn't read. They are
+//! parsed from the raw bytes of the dex file, using the map list to find their sections.
+
+use std::sync::Arc;
+
+use log::warn;
+
+use super::codegen::{self, instructions};
+use super::{format_classname, format_method, DexFile, ParserError, Prototype};
+
+const TYPE_CALL_SITE_ID_ITEM: u16 = 0x0007;
+const TYPE_METHOD_HANDLE_ITEM: u16 = 0x0008;
+
+/// Offset of `map_off` in the header
+const MAP_OFF: usize = 0x34;
+
+/// The call sites and method handles of a dex file
+#[derive(Debug, Default)]
+pub struct CallSites {
+  /// Call sites by their index in the dex file
+  pub sites: Vec<Arc<instructions::CallSite>>,
+  /// Method handles by their index, for const-method-handle, or why they aren't supported. This
+  /// is only an error once an instruction uses the handle.
+  pub method_handles: Vec<Result<instructions::MethodHandleRef, ParserError>>,
+}
+
+/// Parse all call sites and method handles of a dex file. The ids of its call sites start at
+/// `first_id`, the number of call sites in the dex files loaded before it.
+pub fn parse_call_sites(
+  bytes: &[u8],
+  dex: &DexFile,
+  module: &codegen::Module,
+  first_id: usize,
+) -> Result<CallSites, ParserError> {
+  let mut call_site_ids = (0, 0);
+  let mut method_handles = (0, 0);
+
+  let map = read_u32(bytes, MAP_OFF)? as usize;
+  for i in 0..read_u32(bytes, map)? as usize {
+    let item = map + 4 + i * 12;
+    let section = (
+      read_u32(bytes, item + 8)? as usize,
+      read_u32(bytes, item + 4)? as usize,
+    );
+
+    match read_u16(bytes, item)? {
+      TYPE_CALL_SITE_ID_ITEM => call_site_ids = section,
+      TYPE_METHOD_HANDLE_ITEM => method_handles = section,
+      _ => {}
+    }
+  }
+
+  let p = Parser {
+    bytes,
+    dex,
+    module,
+    method_handles,
+  };
+
+  let (offset, size) = call_site_ids;
+  let sites = (0..size)
+    .map(|id| {
+      let mut pos = read_u32(bytes, offset + id * 4)? as usize;
+      let mut values = p.encoded_array(&mut pos)?.into_iter();
+
+      let bootstrap = match values.next() {
+        Some(instructions::LiteralValue::MethodHandle(h)) => h.method,
+        v => {
+          warn!("Call site {} has no bootstrap method: {:?}", id, v);
+          return Err(ParserError::EOF);
+        }
+      };
+      let method_name = match values.next() {
+        Some(instructions::LiteralValue::String(s)) => s,
+        _ => return Err(ParserError::EOF),
+      };
+      let method_type = match values.next() {
+        Some(instructions::LiteralValue::MethodType(t)) => t,
+        _ => return Err(ParserError::EOF),
+      };
+
+      Ok(Arc::new(instructions::CallSite {
+        id: first_id + id,
+        bootstrap,
+        method_name,
+        method_type,
+        arguments: values.collect(),
+      }))
+    })
+    .collect::<Result<_, _>>()?;
+
+  Ok(CallSites {
+    sites,
+    method_handles: (0..method_handles.1)
+      .map(|idx| p.method_handle(idx))
+      .collect(),
+  })
+}
+
+/// The method descriptor of a prototype, e.g. `(ILjava/lang/String;)V`
+pub fn method_descriptor(proto: &Prototype) -> String {
+  format!(
+    "({}){}",
+    proto
+      .parameters
+      .iter()
+      .map(|t| t.as_str())
+      .collect::<String>(),
+    proto.return_type
+  )
+}
+
+struct Parser<'a> {
+  bytes: &'a [u8],
+  dex: &'a DexFile,
+  module: &'a codegen::Module,
+  /// Offset and size of the method handle section
+  method_handles: (usize, usize),
+}
+
+impl<'a> Parser<'a> {
+  fn encoded_array(&self, pos: &mut usize) -> Result<Vec<instructions::LiteralValue>, ParserError> {
+    let size = read_uleb128(self.bytes, pos)?;
+    (0..size).map(|_| self.encoded_value(pos)).collect()
+  }
+
+  /// Read an `encoded_value`. Only the value types allowed in call sites are supported.
+  fn encoded_value(&self, pos: &mut usize) -> Result<instructions::LiteralValue, ParserError> {
+    use instructions::LiteralValue;
+
+    let header = read_u8(self.bytes, *pos)?;
+    *pos += 1;
+    let (value_type, value_arg) = (header & 0x1f, (header >> 5) as usize);
+
+    // Boolean and null carry their value in the header
+    match value_type {
+      0x1e => return Ok(LiteralValue::Lit(0)),
+      0x1f => return Ok(LiteralValue::Lit(value_arg as i32)),
+      // Arrays and annotations are skipped, so the values after them can still be read
+      0x1c => {
+        self.encoded_array(pos)?;
+        return Err(ParserError::UnsupportedValue(value_type));
+      }
+      0x1d => {
+        read_uleb128(self.bytes, pos)?;
+        for _ in 0..read_uleb128(self.bytes, pos)? {
+          read_uleb128(self.bytes, pos)?;
+          self.encoded_value(pos)?;
+        }
+        return Err(ParserError::UnsupportedValue(value_type));
+      }
+      0x00..=0x06 | 0x10 | 0x11 | 0x15..=0x1b => {}
+      _ => return Err(ParserError::UnsupportedValue(value_type)),
+    }
+
+    let size = value_arg + 1;
+    let data = self.bytes.get(*pos..*pos + size).ok_or(ParserError::EOF)?;
+    *pos += size;
+
+    let (unsigned, signed, right_extended) = extend(data);
+
+    Ok(match value_type {
+      // byte, short, int
+      0x00 | 0x02 | 0x04 => LiteralValue::Lit(signed as i32),
+      // char
+      0x03 => LiteralValue::Lit(unsigned as i32),
+      0x06 => LiteralValue::Wide(signed),
+      0x10 => LiteralValue::Lit((right_extended >> 32) as i32),
+      0x11 => LiteralValue::Wide(right_extended as i64),
+      0x15 => match self.dex.file_data.prototypes.get(unsigned as usize) {
+        None => return Err(ParserError::BadIndex("prototype", unsigned as usize)),
+        Some(p) => LiteralValue::MethodType(method_descriptor(p)),
+      },
+      0x16 => LiteralValue::MethodHandle(self.method_handle(unsigned as usize)?),
+      0x17 => match self.dex.file_data.string_data.get(unsigned as usize) {
+        None => return Err(ParserError::BadIndex("string", unsigned as usize)),
+        Some(s) => LiteralValue::String(self.module.intern_str(s)),
+      },
+      0x18 => match self.dex.file_data.type_identifiers.get(unsigned as usize) {
+        None => return Err(ParserError::BadIndex("type", unsigned as usize)),
+        Some(t) => LiteralValue::Class((**t).clone()),
+      },
+      // field, method and enum
+      _ => return Err(ParserError::UnsupportedValue(value_type)),
+    })
+  }
+
+  fn method_handle(&self, idx: usize) -> Result<instructions::MethodHandleRef, ParserError> {
+    let (offset, size) = self.method_handles;
+    if idx >= size {
+      return Err(ParserError::BadIndex("method handle", idx));
+    }
+
+    let item = offset + idx * 8;
+    let kind = match read_u16(self.bytes, item)? {
+      0x04 => instructions::MethodHandleKind::Static,
+      0x05 => instructions::MethodHandleKind::Instance,
+      0x06 => instructions::MethodHandleKind::Constructor,
+      0x07 => instructions::MethodHandleKind::Direct,
+      0x08 => instructions::MethodHandleKind::Interface,
+      //TODO Field accessor handles (static-put, static-get, instance-put and instance-get)
+      t => return Err(ParserError::UnsupportedMethodHandle(t)),
+    };
+
+    let method_idx = read_u16(self.bytes, item + 4)? as usize;
+    let method = match self.dex.file_data.methods.get(method_idx) {
+      None => return Err(ParserError::BadIndex("method", method_idx)),
+      Some(m) => m,
+    };
+    let name = format_method(&method.name, &method_descriptor(&method.prototype));
+
+    Ok(instructions::MethodHandleRef {
+      kind,
+      method: instructions::MethodRef {
+        full_name: format!("{}__{}", format_classname(&method.definer), name),
+        class: (*method.definer).clone(),
+        name,
+      },
+    })
+  }
+}
+
+/// The little-endian bytes of a number in an `encoded_value`, which leaves out the bytes it
+/// doesn't need: zero-extended, sign-extended and zero-extended to the right (for floats and
+/// doubles, which leave out their low bytes)
+fn extend(data: &[u8]) -> (u64, i64, u64) {
+  let unused = 64 - data.len() * 8;
+  let unsigned = data
+    .iter()
+    .rev()
+    .fold(0u64, |acc, b| (acc << 8) | *b as u64);
+  let signed = ((unsigned << unused) as i64) >> unused;
+
+  (unsigned, signed, unsigned << unused)
+}
+
+fn read_u8(bytes: &[u8], pos: usize) -> Result<u8, ParserError> {
+  bytes.get(pos).copied().ok_or(ParserError::EOF)
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Result<u16, ParserError> {
+  Ok(u16::from_le_bytes([
+    read_u8(bytes, pos)?,
+    read_u8(bytes, pos + 1)?,
+  ]))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32, ParserError> {
+  Ok(u32::from_le_bytes([
+    read_u8(bytes, pos)?,
+    read_u8(bytes, pos + 1)?,
+    read_u8(bytes, pos + 2)?,
+    read_u8(bytes, pos + 3)?,
+  ]))
+}
+
+fn read_uleb128(bytes: &[u8], pos: &mut usize) -> Result<usize, ParserError> {
+  let mut result = 0;
+  let mut shift = 0;
+  loop {
+    let b = read_u8(bytes, *pos)?;
+    *pos += 1;
+    result |= ((b & 0x7f) as usize) << shift;
+    if b & 0x80 == 0 {
+      return Ok(result);
+    }
+    shift += 7;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn uleb128(bytes: &[u8]) -> (usize, usize) {
+    let mut pos = 0;
+    let value = read_uleb128(bytes, &mut pos).unwrap();
+    (value, pos)
+  }
+
+  #[test]
+  fn uleb128_values() {
+    assert_eq!(uleb128(&[0x00]), (0, 1));
+    assert_eq!(uleb128(&[0x01, 0xff]), (1, 1));
+    assert_eq!(uleb128(&[0x7f]), (127, 1));
+    assert_eq!(uleb128(&[0x80, 0x7f]), (16256, 2));
+    assert_eq!(uleb128(&[0xe5, 0x8e, 0x26]), (624485, 3));
+    assert_eq!(uleb128(&[0xff, 0xff, 0xff, 0xff, 0x0f]), (0xffff_ffff, 5));
+  }
+
+  #[test]
+  fn uleb128_past_the_end() {
+    let mut pos = 0;
+    match read_uleb128(&[0x80, 0x80], &mut pos) {
+      Err(ParserError::EOF) => {}
+      r => panic!("{:?}", r),
+    }
+  }
+
+  #[test]
+  fn sign_extension() {
+    assert_eq!(extend(&[0x7f]).1, 127);
+    assert_eq!(extend(&[0xff]).1, -1);
+    assert_eq!(extend(&[0x80]).1, -128);
+    assert_eq!(extend(&[0x00, 0x80]).1, -32768);
+    assert_eq!(extend(&[0xff, 0x7f]).1, 32767);
+    assert_eq!(extend(&[0xfe, 0xff, 0xff]).1, -2);
+    assert_eq!(extend(&[0xff; 8]).1, -1);
+  }
+
+  #[test]
+  fn zero_extension() {
+    // A char is unsigned
+    assert_eq!(extend(&[0xff, 0xff]).0, 0xffff);
+    assert_eq!(extend(&[0x80]).0, 0x80);
+    assert_eq!(extend(&[0x01, 0x02, 0x03, 0x04]).0, 0x0403_0201);
+  }
+
+  #[test]
+  fn right_extension() {
+    // 0.5f is 0x3f000000 and 1.0f is 0x3f800000, written without their zero bytes
+    let float = (extend(&[0x3f]).2 >> 32) as u32;
+    assert_eq!(f32::from_bits(float), 0.5);
+    let float = (extend(&[0x80, 0x3f]).2 >> 32) as u32;
+    assert_eq!(f32::from_bits(float), 1.0);
+    // 2.0 is 0x4000000000000000
+    assert_eq!(f64::from_bits(extend(&[0x40]).2), 2.0);
+    assert_eq!(f64::from_bits(extend(&[0xf0, 0xbf]).2), -1.0);
+    let pi = std::f64::consts::PI;
+    assert_eq!(f64::from_bits(extend(&pi.to_bits().to_le_bytes()).2), pi);
+  }
+}
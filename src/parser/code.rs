@@ -1,28 +1,58 @@
 use std::fs::File;
 use std::io::prelude::*;
+use std::sync::Arc;
 
-use super::codegen::{self, instructions};
+use log::warn;
+
+use super::codegen::{self, instructions, runtime::JavaString};
 use super::generated::{self, ASTInstruction};
 use super::{
-	control_flow, format_classname, format_name, AccessFlag, Code, DexFile, EncodedMethod,
-	InstructionQueue, ParserError,
+	control_flow, format_classname, format_method, format_name, method_descriptor, AccessFlag,
+	CallSites, Code, DebugItemBytecodes, DexFile, EncodedMethod, InstructionQueue,
+	ParserError,
 };
 
 /// Generate codegen-IR from a method
 pub fn generate_code(
 	c: &Code,
 	m: &EncodedMethod,
-	c_name: &str,
-	_class: &dexparser::ClassDefinition,
+	class: &dexparser::ClassDefinition,
 	dex: &DexFile,
+	call_sites: &CallSites,
+	module: &codegen::Module,
 	fnb: &mut codegen::FunctionBuilder,
-) {
+) -> Result<(), ParserError> {
+	let c_name = format_classname(&class.class_type);
 	let is_instance = !m.access_flags.contains(&AccessFlag::ACC_STATIC);
-	let n_params = m.method.prototype.parameters.len() + if is_instance { 1 } else { 0 };
+	// Parameters are counted in registers, of which longs and doubles take two
+	let n_params = m
+		.method
+		.prototype
+		.parameters
+		.iter()
+		.map(|p| {
+			if p.as_str() == "J" || p.as_str() == "D" {
+				2
+			} else {
+				1
+			}
+		})
+		.sum::<usize>()
+		+ if is_instance { 1 } else { 0 };
 
 	fnb.set_n_regs(c.registers_size as _);
 	fnb.set_n_params(n_params);
 	fnb.set_return(&*m.method.prototype.return_type != "V");
+	fnb.set_source(codegen::SourceInfo {
+		class: (*class.class_type).to_owned(),
+		method: (*m.method.name).to_owned(),
+		source_file: class.source_file_name.as_ref().map(|f| (**f).to_owned()),
+		lines: c
+			.debug_info
+			.as_ref()
+			.map(|d| line_table(d.line_start, &d.bytecode))
+			.unwrap_or_default(),
+	});
 
 	//TODO? Init instance fields before use?
 	// if *m.method.name == "<init>" {
@@ -42,9 +72,20 @@ pub fn generate_code(
 			Ok(ins) => insns.push(ins),
 			//TODO Handle this?
 			Err(ParserError::EOF) => break,
+			Err(e) => return Err(e),
 		}
 	}
 
+	// The bytecode offset of every instruction
+	let offsets: Vec<u32> = insns
+		.iter()
+		.scan(0, |addr, ins| {
+			let cur = *addr;
+			*addr += generated::instruction_length(ins) as u32;
+			Some(cur)
+		})
+		.collect();
+
 	let analysis = control_flow::analyse_control_flow(&insns, &c.tries);
 	//TODO Dynamic (and optional) out-directory
 	let mut file = File::create(&format!("out/analysis/{}__{}", c_name, m.method.name)).unwrap();
@@ -58,29 +99,65 @@ pub fn generate_code(
 	fnb.set_handlers(handlers);
 
 	let mut last_target = 0;
-	for (i, block) in flow {
+	for (block_id, block) in flow {
+		fnb.set_offset(offsets[*block_id]);
 		if block.entries.len() != 0 && block.entries != vec![last_target] || block.is_handler {
-			fnb.label(*i);
+			fnb.label(*block_id);
 		}
 
-		for i in &block.body {
+		for (n, i) in block.body.iter().enumerate() {
 			fnb.set_next_handler(block.handler);
+			fnb.set_offset(offsets[*block_id + n]);
+
+			// A field the runtime's version of a class doesn't have only fails the instruction
+			// using it, like a NoSuchFieldError
+			if let Some(field) = unresolved_field(i, dex, module) {
+				warn!("Unresolved field {} in {}.{}", field, c_name, m.method.name);
+				fnb.unsupported(&format!("{:?} of the unresolved field {}", i, field));
+				continue;
+			}
 
 			match i {
 				ASTInstruction::Nop(_) => {}
-				ASTInstruction::Move(_) => todo!(),
-				ASTInstruction::MoveFrom16(_) => todo!(),
-				ASTInstruction::Move16(_) => todo!(),
-				ASTInstruction::MoveWide(_) => todo!(),
-				ASTInstruction::MoveWideFrom16(_) => todo!(),
-				ASTInstruction::MoveWide16(_) => todo!(),
-				ASTInstruction::MoveObject(_) => todo!(),
-				ASTInstruction::MoveObjectFrom16(_) => todo!(),
-				ASTInstruction::MoveObject16(_) => todo!(),
+				ASTInstruction::Move(generated::IF12x(va, vb)) => fnb.move_v(
+					instructions::MoveKind::Single,
+					u8::from(*va) as u16,
+					u8::from(*vb) as u16,
+				),
+				ASTInstruction::MoveFrom16(generated::IF22x(va, vb)) => {
+					fnb.move_v(instructions::MoveKind::Single, *va as u16, *vb)
+				}
+				ASTInstruction::Move16(generated::IF32x(va, vb)) => {
+					fnb.move_v(instructions::MoveKind::Single, *va, *vb)
+				}
+				ASTInstruction::MoveWide(generated::IF12x(va, vb)) => fnb.move_v(
+					instructions::MoveKind::Wide,
+					u8::from(*va) as u16,
+					u8::from(*vb) as u16,
+				),
+				ASTInstruction::MoveWideFrom16(generated::IF22x(va, vb)) => {
+					fnb.move_v(instructions::MoveKind::Wide, *va as u16, *vb)
+				}
+				ASTInstruction::MoveWide16(generated::IF32x(va, vb)) => {
+					fnb.move_v(instructions::MoveKind::Wide, *va, *vb)
+				}
+				ASTInstruction::MoveObject(generated::IF12x(va, vb)) => fnb.move_v(
+					instructions::MoveKind::Object,
+					u8::from(*va) as u16,
+					u8::from(*vb) as u16,
+				),
+				ASTInstruction::MoveObjectFrom16(generated::IF22x(va, vb)) => {
+					fnb.move_v(instructions::MoveKind::Object, *va as u16, *vb)
+				}
+				ASTInstruction::MoveObject16(generated::IF32x(va, vb)) => {
+					fnb.move_v(instructions::MoveKind::Object, *va, *vb)
+				}
 				ASTInstruction::MoveResult(generated::IF11x(a)) => {
 					fnb.move_results(instructions::MoveKind::Single, *a as usize)
 				}
-				ASTInstruction::MoveResultWide(_) => todo!(),
+				ASTInstruction::MoveResultWide(generated::IF11x(a)) => {
+					fnb.move_results(instructions::MoveKind::Wide, *a as usize)
+				}
 				ASTInstruction::MoveResultObject(generated::IF11x(a)) => {
 					fnb.move_results(instructions::MoveKind::Object, *a as usize)
 				}
@@ -91,64 +168,101 @@ pub fn generate_code(
 				ASTInstruction::Return(generated::IF11x(v)) => {
 					fnb.return_v(instructions::ReturnType::Single(*v as _))
 				}
-				ASTInstruction::ReturnWide(_) => todo!(),
+				ASTInstruction::ReturnWide(generated::IF11x(v)) => {
+					fnb.return_v(instructions::ReturnType::Wide(*v as _))
+				}
 				ASTInstruction::ReturnObject(generated::IF11x(v)) => {
 					fnb.return_v(instructions::ReturnType::Object(*v as usize))
 				}
 				ASTInstruction::Const4(generated::IF11n(dest, signed_int)) => fnb.const_set(
 					u8::from(*dest) as _,
-					instructions::LiteralValue::Lit(u8::from(*signed_int) as i8 as i32), //? Does this make a difference?
+					// The literal is a signed nibble
+					instructions::LiteralValue::Lit(
+						((u8::from(*signed_int) << 4) as i8 >> 4) as i32,
+					),
 				),
 				ASTInstruction::Const16(generated::IF21s(v, lit)) => {
 					fnb.const_set(*v as _, instructions::LiteralValue::Lit(*lit as i16 as i32))
 				}
-				ASTInstruction::Const(_) => todo!(),
-				ASTInstruction::ConstHigh16(_) => todo!(),
-				ASTInstruction::ConstWide16(_) => todo!(),
-				ASTInstruction::ConstWide32(_) => todo!(),
-				ASTInstruction::ConstWide(_) => todo!(),
-				ASTInstruction::ConstWideHigh16(_) => todo!(),
+				ASTInstruction::Const(generated::IF31i(v, lit)) => {
+					fnb.const_set(*v as _, instructions::LiteralValue::Lit(*lit as i32))
+				}
+				ASTInstruction::ConstHigh16(generated::IF21h(v, lit)) => fnb.const_set(
+					*v as _,
+					instructions::LiteralValue::Lit((*lit as i32) << 16),
+				),
+				ASTInstruction::ConstWide16(generated::IF21s(v, lit)) => {
+					fnb.const_set(*v as _, instructions::LiteralValue::Wide(*lit as i64))
+				}
+				ASTInstruction::ConstWide32(generated::IF31i(v, lit)) => fnb.const_set(
+					*v as _,
+					instructions::LiteralValue::Wide(*lit as i32 as i64),
+				),
+				ASTInstruction::ConstWide(generated::IF51l(v, lit)) => {
+					fnb.const_set(*v as _, instructions::LiteralValue::Wide(*lit as i64))
+				}
+				ASTInstruction::ConstWideHigh16(generated::IF21h(v, lit)) => fnb.const_set(
+					*v as _,
+					instructions::LiteralValue::Wide((*lit as i64) << 48),
+				),
 				ASTInstruction::ConstString(generated::IF21c(v, s_idx)) => fnb.const_set(
 					*v as _,
-					instructions::LiteralValue::String(
-						match dex.file_data.string_data.get(*s_idx as usize) {
-							None => todo!(),
-							Some(s) => (**s).clone(),
-						},
-					),
+					instructions::LiteralValue::String(get_string(*s_idx as usize, dex, module)?),
+				),
+				ASTInstruction::ConstStringJumbo(generated::IF31c(v, s_idx)) => fnb.const_set(
+					*v as _,
+					instructions::LiteralValue::String(get_string(*s_idx as usize, dex, module)?),
+				),
+				ASTInstruction::ConstClass(generated::IF21c(v, ty)) => fnb.const_set(
+					*v as _,
+					instructions::LiteralValue::Class(get_type_name(*ty as usize, dex)?),
 				),
-				ASTInstruction::ConstStringJumbo(_) => todo!(),
-				ASTInstruction::ConstClass(_) => todo!(),
-				ASTInstruction::MonitorEnter(_) => todo!(),
-				ASTInstruction::MonitorExit(_) => todo!(),
-				ASTInstruction::CheckCast(_) => todo!(),
-				ASTInstruction::InstanceOf(_) => todo!(),
-				ASTInstruction::ArrayLength(_) => todo!(),
+				ASTInstruction::MonitorEnter(generated::IF11x(v)) => fnb.monitor_enter(*v),
+				ASTInstruction::MonitorExit(generated::IF11x(v)) => fnb.monitor_exit(*v),
+				ASTInstruction::CheckCast(generated::IF21c(v, ty)) => {
+					fnb.check_cast(*v, get_type_name(*ty as usize, dex)?)
+				}
+				ASTInstruction::InstanceOf(generated::IF22c(v_dest, v_ref, ty)) => fnb.instance_of(
+					u8::from(*v_dest),
+					u8::from(*v_ref),
+					get_type_name(*ty as usize, dex)?,
+				),
+				ASTInstruction::ArrayLength(generated::IF12x(v_dest, v_arr)) => {
+					fnb.array_length(u8::from(*v_dest), u8::from(*v_arr))
+				}
 				ASTInstruction::NewInstance(generated::IF21c(dest, ty)) => {
-					fnb.new_instance(*dest, *ty as usize)
+					fnb.new_instance(*dest, get_type_name(*ty as usize, dex)?)
 				}
 				ASTInstruction::NewArray(generated::IF22c(v_dest, v_size, type_)) => fnb.new_array(
 					u8::from(*v_dest),
-					u8::from(*v_size) as usize,
-					*type_ as usize,
+					u8::from(*v_size),
+					get_type_name(*type_ as usize, dex)?,
 				),
-				ASTInstruction::FilledNewArray(_) => todo!(),
-				ASTInstruction::FilledNewArrayRange(_) => todo!(),
+				ASTInstruction::FilledNewArray(_) => fnb.unsupported(i),
+				ASTInstruction::FilledNewArrayRange(_) => fnb.unsupported(i),
 				ASTInstruction::FillArrayData(generated::IFFillArrayData(v, el_width, data)) => {
 					fnb.fill_array_data(*v as usize, *el_width, data.clone())
 				}
-				ASTInstruction::Throw(_) => todo!(),
+				ASTInstruction::Throw(generated::IF11x(v)) => fnb.throw(*v),
 				ASTInstruction::Goto(_) | ASTInstruction::Goto16(_) | ASTInstruction::Goto32(_) => {
 					fnb.goto(block.exits[0])
 				}
-				ASTInstruction::PackedSwitch(_) => todo!(),
-				ASTInstruction::SparseSwitch(_) => todo!(),
-				ASTInstruction::CmplFloat(_) => todo!(),
-				ASTInstruction::CmpgFloat(_) => todo!(),
-				ASTInstruction::CmplDouble(_) => todo!(),
-				ASTInstruction::CmpgDouble(_) => todo!(),
-				ASTInstruction::CmpLong(_) => todo!(),
-				ASTInstruction::IfEq(_) => todo!(),
+				ASTInstruction::PackedSwitch(_) => fnb.unsupported(i),
+				ASTInstruction::SparseSwitch(_) => fnb.unsupported(i),
+				ASTInstruction::CmplFloat(_) => fnb.unsupported(i),
+				ASTInstruction::CmpgFloat(_) => fnb.unsupported(i),
+				ASTInstruction::CmplDouble(_) => fnb.unsupported(i),
+				ASTInstruction::CmpgDouble(_) => fnb.unsupported(i),
+				ASTInstruction::CmpLong(_) => fnb.unsupported(i),
+				ASTInstruction::IfEq(generated::IF22t(v1, v2, _)) => {
+					//TODO: Better solution to jumps? Can't just trust the order of the exits...
+					fnb.if_test(
+						instructions::IfKind::Eq,
+						u8::from(*v1),
+						u8::from(*v2),
+						block.exits[1],
+					)
+				}
 				ASTInstruction::IfNe(generated::IF22t(v1, v2, _)) => {
 					//TODO: Better solution to jumps? Can't just trust the order of the exits...
 					fnb.if_test(
@@ -158,7 +272,15 @@ pub fn generate_code(
 						block.exits[1],
 					)
 				}
-				ASTInstruction::IfLt(_) => todo!(),
+				ASTInstruction::IfLt(generated::IF22t(v1, v2, _)) => {
+					//TODO: Better solution to jumps? Can't just trust the order of the exits...
+					fnb.if_test(
+						instructions::IfKind::Lt,
+						u8::from(*v1),
+						u8::from(*v2),
+						block.exits[1],
+					)
+				}
 				ASTInstruction::IfGe(generated::IF22t(v1, v2, _)) => {
 					//TODO: Better solution to jumps? Can't just trust the order of the exits...
 					fnb.if_test(
@@ -168,102 +290,254 @@ pub fn generate_code(
 						block.exits[1],
 					)
 				}
-				ASTInstruction::IfGt(_) => todo!(),
-				ASTInstruction::IfLe(_) => todo!(),
-				ASTInstruction::IfEqz(_) => todo!(),
-				ASTInstruction::IfNez(_) => todo!(),
-				ASTInstruction::IfLtz(_) => todo!(),
-				ASTInstruction::IfGez(_) => todo!(),
-				ASTInstruction::IfGtz(_) => todo!(),
-				ASTInstruction::IfLez(_) => todo!(),
+				ASTInstruction::IfGt(generated::IF22t(v1, v2, _)) => {
+					//TODO: Better solution to jumps? Can't just trust the order of the exits...
+					fnb.if_test(
+						instructions::IfKind::Gt,
+						u8::from(*v1),
+						u8::from(*v2),
+						block.exits[1],
+					)
+				}
+				ASTInstruction::IfLe(generated::IF22t(v1, v2, _)) => {
+					//TODO: Better solution to jumps? Can't just trust the order of the exits...
+					fnb.if_test(
+						instructions::IfKind::Le,
+						u8::from(*v1),
+						u8::from(*v2),
+						block.exits[1],
+					)
+				}
+				ASTInstruction::IfEqz(generated::IF21t(v, _)) => {
+					fnb.ifz_test(instructions::IfKind::Eq, *v, block.exits[1])
+				}
+				ASTInstruction::IfNez(generated::IF21t(v, _)) => {
+					fnb.ifz_test(instructions::IfKind::Ne, *v, block.exits[1])
+				}
+				ASTInstruction::IfLtz(generated::IF21t(v, _)) => {
+					fnb.ifz_test(instructions::IfKind::Lt, *v, block.exits[1])
+				}
+				ASTInstruction::IfGez(generated::IF21t(v, _)) => {
+					fnb.ifz_test(instructions::IfKind::Ge, *v, block.exits[1])
+				}
+				ASTInstruction::IfGtz(generated::IF21t(v, _)) => {
+					fnb.ifz_test(instructions::IfKind::Gt, *v, block.exits[1])
+				}
+				ASTInstruction::IfLez(generated::IF21t(v, _)) => {
+					fnb.ifz_test(instructions::IfKind::Le, *v, block.exits[1])
+				}
 				ASTInstruction::Aget(generated::IF23x(v_dest, v_arr, v_idx)) => {
 					fnb.array_get(instructions::GetPutKind::Single, *v_dest, *v_arr, *v_idx)
 				}
-				ASTInstruction::AgetWide(_) => todo!(),
+				ASTInstruction::AgetWide(generated::IF23x(v_dest, v_arr, v_idx)) => {
+					fnb.array_get(instructions::GetPutKind::Wide, *v_dest, *v_arr, *v_idx)
+				}
 				ASTInstruction::AgetObject(generated::IF23x(v_dest, v_arr, v_idx)) => {
 					fnb.array_get(instructions::GetPutKind::Object, *v_dest, *v_arr, *v_idx)
 				}
 				ASTInstruction::AgetBoolean(generated::IF23x(v_dest, v_arr, v_idx)) => {
 					fnb.array_get(instructions::GetPutKind::Boolean, *v_dest, *v_arr, *v_idx)
 				}
-				ASTInstruction::AgetByte(_) => todo!(),
-				ASTInstruction::AgetChar(_) => todo!(),
-				ASTInstruction::AgetShort(_) => todo!(),
-				ASTInstruction::Aput(_) => todo!(),
-				ASTInstruction::AputWide(_) => todo!(),
-				ASTInstruction::AputObject(generated::IF23x(v_dest, v_arr, v_idx)) => {
-					fnb.array_get(instructions::GetPutKind::Object, *v_dest, *v_arr, *v_idx)
+				ASTInstruction::AgetByte(generated::IF23x(v_dest, v_arr, v_idx)) => {
+					fnb.array_get(instructions::GetPutKind::Byte, *v_dest, *v_arr, *v_idx)
+				}
+				ASTInstruction::AgetChar(generated::IF23x(v_dest, v_arr, v_idx)) => {
+					fnb.array_get(instructions::GetPutKind::Char, *v_dest, *v_arr, *v_idx)
+				}
+				ASTInstruction::AgetShort(generated::IF23x(v_dest, v_arr, v_idx)) => {
+					fnb.array_get(instructions::GetPutKind::Short, *v_dest, *v_arr, *v_idx)
+				}
+				ASTInstruction::Aput(generated::IF23x(v_src, v_arr, v_idx)) => {
+					fnb.array_put(instructions::GetPutKind::Single, *v_src, *v_arr, *v_idx)
+				}
+				ASTInstruction::AputWide(generated::IF23x(v_src, v_arr, v_idx)) => {
+					fnb.array_put(instructions::GetPutKind::Wide, *v_src, *v_arr, *v_idx)
+				}
+				ASTInstruction::AputObject(generated::IF23x(v_src, v_arr, v_idx)) => {
+					fnb.array_put(instructions::GetPutKind::Object, *v_src, *v_arr, *v_idx)
+				}
+				ASTInstruction::AputBoolean(generated::IF23x(v_src, v_arr, v_idx)) => {
+					fnb.array_put(instructions::GetPutKind::Boolean, *v_src, *v_arr, *v_idx)
+				}
+				ASTInstruction::AputByte(generated::IF23x(v_src, v_arr, v_idx)) => {
+					fnb.array_put(instructions::GetPutKind::Byte, *v_src, *v_arr, *v_idx)
+				}
+				ASTInstruction::AputChar(generated::IF23x(v_src, v_arr, v_idx)) => {
+					fnb.array_put(instructions::GetPutKind::Char, *v_src, *v_arr, *v_idx)
+				}
+				ASTInstruction::AputShort(generated::IF23x(v_src, v_arr, v_idx)) => {
+					fnb.array_put(instructions::GetPutKind::Short, *v_src, *v_arr, *v_idx)
 				}
-				ASTInstruction::AputBoolean(_) => todo!(),
-				ASTInstruction::AputByte(_) => todo!(),
-				ASTInstruction::AputChar(_) => todo!(),
-				ASTInstruction::AputShort(_) => todo!(),
 				ASTInstruction::Iget(generated::IF22c(v_dest, v_inst, field_idx)) => fnb
 					.instance_get(
 						instructions::GetPutKind::Single,
 						u8::from(*v_dest),
 						u8::from(*v_inst),
-						get_field_name(*field_idx as usize, false, dex),
+						get_field_slot(*field_idx as usize, dex, module)?,
+					),
+				ASTInstruction::IgetWide(generated::IF22c(v_dest, v_inst, field_idx)) => fnb
+					.instance_get(
+						instructions::GetPutKind::Wide,
+						u8::from(*v_dest),
+						u8::from(*v_inst),
+						get_field_slot(*field_idx as usize, dex, module)?,
 					),
-				ASTInstruction::IgetWide(_) => todo!(),
 				ASTInstruction::IgetObject(generated::IF22c(v_dest, v_inst, field_idx)) => {
 					fnb.instance_get(
 						instructions::GetPutKind::Object,
 						u8::from(*v_dest),
 						u8::from(*v_inst),
-						get_field_name(*field_idx as usize, false, dex),
+						get_field_slot(*field_idx as usize, dex, module)?,
 					);
 				}
-				ASTInstruction::IgetBoolean(_) => todo!(),
-				ASTInstruction::IgetByte(_) => todo!(),
-				ASTInstruction::IgetChar(_) => todo!(),
-				ASTInstruction::IgetShort(_) => todo!(),
+				ASTInstruction::IgetBoolean(generated::IF22c(v_dest, v_inst, field_idx)) => fnb
+					.instance_get(
+						instructions::GetPutKind::Boolean,
+						u8::from(*v_dest),
+						u8::from(*v_inst),
+						get_field_slot(*field_idx as usize, dex, module)?,
+					),
+				ASTInstruction::IgetByte(generated::IF22c(v_dest, v_inst, field_idx)) => fnb
+					.instance_get(
+						instructions::GetPutKind::Byte,
+						u8::from(*v_dest),
+						u8::from(*v_inst),
+						get_field_slot(*field_idx as usize, dex, module)?,
+					),
+				ASTInstruction::IgetChar(generated::IF22c(v_dest, v_inst, field_idx)) => fnb
+					.instance_get(
+						instructions::GetPutKind::Char,
+						u8::from(*v_dest),
+						u8::from(*v_inst),
+						get_field_slot(*field_idx as usize, dex, module)?,
+					),
+				ASTInstruction::IgetShort(generated::IF22c(v_dest, v_inst, field_idx)) => fnb
+					.instance_get(
+						instructions::GetPutKind::Short,
+						u8::from(*v_dest),
+						u8::from(*v_inst),
+						get_field_slot(*field_idx as usize, dex, module)?,
+					),
 				ASTInstruction::Iput(generated::IF22c(v_src, v_inst, field_ref_idx)) => fnb
 					.instance_put(
 						instructions::GetPutKind::Single,
 						u8::from(*v_src),
 						u8::from(*v_inst),
-						get_field_name(*field_ref_idx as _, false, dex),
+						get_field_slot(*field_ref_idx as usize, dex, module)?,
+					),
+				ASTInstruction::IputWide(generated::IF22c(v_src, v_inst, field_idx)) => fnb
+					.instance_put(
+						instructions::GetPutKind::Wide,
+						u8::from(*v_src),
+						u8::from(*v_inst),
+						get_field_slot(*field_idx as usize, dex, module)?,
 					),
-				ASTInstruction::IputWide(_) => todo!(),
 				ASTInstruction::IputObject(generated::IF22c(src, inst, field_ref_idx)) => fnb
 					.instance_put(
 						instructions::GetPutKind::Object,
 						u8::from(*src),
 						u8::from(*inst),
-						get_field_name(*field_ref_idx as _, false, dex),
+						get_field_slot(*field_ref_idx as usize, dex, module)?,
+					),
+				ASTInstruction::IputBoolean(generated::IF22c(v_src, v_inst, field_idx)) => fnb
+					.instance_put(
+						instructions::GetPutKind::Boolean,
+						u8::from(*v_src),
+						u8::from(*v_inst),
+						get_field_slot(*field_idx as usize, dex, module)?,
+					),
+				ASTInstruction::IputByte(generated::IF22c(v_src, v_inst, field_idx)) => fnb
+					.instance_put(
+						instructions::GetPutKind::Byte,
+						u8::from(*v_src),
+						u8::from(*v_inst),
+						get_field_slot(*field_idx as usize, dex, module)?,
+					),
+				ASTInstruction::IputChar(generated::IF22c(v_src, v_inst, field_idx)) => fnb
+					.instance_put(
+						instructions::GetPutKind::Char,
+						u8::from(*v_src),
+						u8::from(*v_inst),
+						get_field_slot(*field_idx as usize, dex, module)?,
+					),
+				ASTInstruction::IputShort(generated::IF22c(v_src, v_inst, field_idx)) => fnb
+					.instance_put(
+						instructions::GetPutKind::Short,
+						u8::from(*v_src),
+						u8::from(*v_inst),
+						get_field_slot(*field_idx as usize, dex, module)?,
 					),
-				ASTInstruction::IputBoolean(_) => todo!(),
-				ASTInstruction::IputByte(_) => todo!(),
-				ASTInstruction::IputChar(_) => todo!(),
-				ASTInstruction::IputShort(_) => todo!(),
 				ASTInstruction::Sget(generated::IF21c(v_dest, static_idx)) => fnb.static_get(
 					instructions::GetPutKind::Single,
 					*v_dest,
-					get_field_name(*static_idx as usize, true, dex),
+					get_static_ref(*static_idx as usize, dex)?,
+				),
+				ASTInstruction::SgetWide(generated::IF21c(v_dest, static_idx)) => fnb.static_get(
+					instructions::GetPutKind::Wide,
+					*v_dest,
+					get_static_ref(*static_idx as usize, dex)?,
 				),
-				ASTInstruction::SgetWide(_) => todo!(),
 				ASTInstruction::SgetObject(generated::IF21c(v_dest, static_idx)) => fnb.static_get(
 					instructions::GetPutKind::Object,
 					*v_dest,
-					get_field_name(*static_idx as usize, true, dex),
+					get_static_ref(*static_idx as usize, dex)?,
+				),
+				ASTInstruction::SgetBoolean(generated::IF21c(v_dest, static_idx)) => fnb
+					.static_get(
+						instructions::GetPutKind::Boolean,
+						*v_dest,
+						get_static_ref(*static_idx as usize, dex)?,
+					),
+				ASTInstruction::SgetByte(generated::IF21c(v_dest, static_idx)) => fnb.static_get(
+					instructions::GetPutKind::Byte,
+					*v_dest,
+					get_static_ref(*static_idx as usize, dex)?,
+				),
+				ASTInstruction::SgetChar(generated::IF21c(v_dest, static_idx)) => fnb.static_get(
+					instructions::GetPutKind::Char,
+					*v_dest,
+					get_static_ref(*static_idx as usize, dex)?,
+				),
+				ASTInstruction::SgetShort(generated::IF21c(v_dest, static_idx)) => fnb.static_get(
+					instructions::GetPutKind::Short,
+					*v_dest,
+					get_static_ref(*static_idx as usize, dex)?,
 				),
-				ASTInstruction::SgetBoolean(_) => todo!(),
-				ASTInstruction::SgetByte(_) => todo!(),
-				ASTInstruction::SgetChar(_) => todo!(),
-				ASTInstruction::SgetShort(_) => todo!(),
 				ASTInstruction::Sput(generated::IF21c(v_src, static_idx)) => fnb.static_put(
 					instructions::GetPutKind::Single,
-					u8::from(*v_src),
-					get_field_name(*static_idx as usize, true, dex),
-					// *static_idx,
-				),
-				ASTInstruction::SputWide(_) => todo!(),
-				ASTInstruction::SputObject(_) => todo!(),
-				ASTInstruction::SputBoolean(_) => todo!(),
-				ASTInstruction::SputByte(_) => todo!(),
-				ASTInstruction::SputChar(_) => todo!(),
-				ASTInstruction::SputShort(_) => todo!(),
+					*v_src,
+					get_static_ref(*static_idx as usize, dex)?,
+				),
+				ASTInstruction::SputWide(generated::IF21c(v_src, static_idx)) => fnb.static_put(
+					instructions::GetPutKind::Wide,
+					*v_src,
+					get_static_ref(*static_idx as usize, dex)?,
+				),
+				ASTInstruction::SputObject(generated::IF21c(v_src, static_idx)) => fnb.static_put(
+					instructions::GetPutKind::Object,
+					*v_src,
+					get_static_ref(*static_idx as usize, dex)?,
+				),
+				ASTInstruction::SputBoolean(generated::IF21c(v_src, static_idx)) => fnb.static_put(
+					instructions::GetPutKind::Boolean,
+					*v_src,
+					get_static_ref(*static_idx as usize, dex)?,
+				),
+				ASTInstruction::SputByte(generated::IF21c(v_src, static_idx)) => fnb.static_put(
+					instructions::GetPutKind::Byte,
+					*v_src,
+					get_static_ref(*static_idx as usize, dex)?,
+				),
+				ASTInstruction::SputChar(generated::IF21c(v_src, static_idx)) => fnb.static_put(
+					instructions::GetPutKind::Char,
+					*v_src,
+					get_static_ref(*static_idx as usize, dex)?,
+				),
+				ASTInstruction::SputShort(generated::IF21c(v_src, static_idx)) => fnb.static_put(
+					instructions::GetPutKind::Short,
+					*v_src,
+					get_static_ref(*static_idx as usize, dex)?,
+				),
 				ASTInstruction::InvokeVirtual(generated::IF35c(
 					method_ref_idx,
 					argc,
@@ -274,7 +548,27 @@ pub fn generate_code(
 					a5,
 				)) => fnb.invoke(
 					instructions::InvokeKind::Virtual,
-					get_method_full_name(*method_ref_idx as usize, dex),
+					get_method_ref(*method_ref_idx as usize, dex)?,
+					u8::from(*argc),
+					[
+						u8::from(*a1),
+						u8::from(*a2),
+						u8::from(*a3),
+						u8::from(*a4),
+						u8::from(*a5),
+					],
+				),
+				ASTInstruction::InvokeSuper(generated::IF35c(
+					method_ref_idx,
+					argc,
+					a1,
+					a2,
+					a3,
+					a4,
+					a5,
+				)) => fnb.invoke(
+					instructions::InvokeKind::Super,
+					get_method_ref(*method_ref_idx as usize, dex)?,
 					u8::from(*argc),
 					[
 						u8::from(*a1),
@@ -284,7 +578,6 @@ pub fn generate_code(
 						u8::from(*a5),
 					],
 				),
-				ASTInstruction::InvokeSuper(_) => todo!(),
 				//TODO: macro for invoke?
 				ASTInstruction::InvokeDirect(generated::IF35c(
 					method_ref_idx,
@@ -296,7 +589,7 @@ pub fn generate_code(
 					a5,
 				)) => fnb.invoke(
 					instructions::InvokeKind::Direct,
-					get_method_full_name(*method_ref_idx as usize, dex),
+					get_method_ref(*method_ref_idx as usize, dex)?,
 					u8::from(*argc),
 					[
 						u8::from(*a1),
@@ -316,7 +609,7 @@ pub fn generate_code(
 					a5,
 				)) => fnb.invoke(
 					instructions::InvokeKind::Static,
-					get_method_full_name(*method_ref_idx as usize, dex),
+					get_method_ref(*method_ref_idx as usize, dex)?,
 					u8::from(*argc),
 					[
 						u8::from(*a1),
@@ -327,118 +620,180 @@ pub fn generate_code(
 					],
 				),
 				// ASTInstruction::InvokeStatic(_) => todo!(),
-				ASTInstruction::InvokeInterface(_) => todo!(),
-				ASTInstruction::InvokeVirtualRange(_) => todo!(),
-				ASTInstruction::InvokeSuperRange(_) => todo!(),
-				ASTInstruction::InvokeDirectRange(_) => todo!(),
-				ASTInstruction::InvokeStaticRange(_) => todo!(),
-				ASTInstruction::InvokeInterfaceRange(_) => todo!(),
-				ASTInstruction::NegInt(_) => todo!(),
-				ASTInstruction::NotInt(_) => todo!(),
-				ASTInstruction::NegLong(_) => todo!(),
-				ASTInstruction::NotLong(_) => todo!(),
-				ASTInstruction::NegFloat(_) => todo!(),
-				ASTInstruction::NegDouble(_) => todo!(),
-				ASTInstruction::IntToLong(_) => todo!(),
-				ASTInstruction::IntToFloat(_) => todo!(),
-				ASTInstruction::IntToDouble(_) => todo!(),
-				ASTInstruction::LongToInt(_) => todo!(),
-				ASTInstruction::LongToFloat(_) => todo!(),
-				ASTInstruction::LongToDouble(_) => todo!(),
-				ASTInstruction::FloatToInt(_) => todo!(),
-				ASTInstruction::FloatToLong(_) => todo!(),
-				ASTInstruction::FloatToDouble(_) => todo!(),
-				ASTInstruction::DoubleToInt(_) => todo!(),
-				ASTInstruction::DoubleToLong(_) => todo!(),
-				ASTInstruction::DoubleToFloat(_) => todo!(),
-				ASTInstruction::IntToByte(_) => todo!(),
-				ASTInstruction::IntToChar(_) => todo!(),
-				ASTInstruction::IntToShort(_) => todo!(),
-				ASTInstruction::AddInt(_) => todo!(),
-				ASTInstruction::SubInt(_) => todo!(),
-				ASTInstruction::MulInt(_) => todo!(),
-				ASTInstruction::DivInt(_) => todo!(),
-				ASTInstruction::RemInt(_) => todo!(),
-				ASTInstruction::AndInt(_) => todo!(),
-				ASTInstruction::OrInt(_) => todo!(),
-				ASTInstruction::XorInt(_) => todo!(),
-				ASTInstruction::ShlInt(_) => todo!(),
-				ASTInstruction::ShrInt(_) => todo!(),
-				ASTInstruction::UshrInt(_) => todo!(),
-				ASTInstruction::AddLong(_) => todo!(),
-				ASTInstruction::SubLong(_) => todo!(),
-				ASTInstruction::MulLong(_) => todo!(),
-				ASTInstruction::DivLong(_) => todo!(),
-				ASTInstruction::RemLong(_) => todo!(),
-				ASTInstruction::AndLong(_) => todo!(),
-				ASTInstruction::OrLong(_) => todo!(),
-				ASTInstruction::XorLong(_) => todo!(),
-				ASTInstruction::ShlLong(_) => todo!(),
-				ASTInstruction::ShrLong(_) => todo!(),
-				ASTInstruction::UshrLong(_) => todo!(),
-				ASTInstruction::AddFloat(_) => todo!(),
-				ASTInstruction::SubFloat(_) => todo!(),
-				ASTInstruction::MulFloat(_) => todo!(),
-				ASTInstruction::DivFloat(_) => todo!(),
-				ASTInstruction::RemFloat(_) => todo!(),
-				ASTInstruction::AddDouble(_) => todo!(),
-				ASTInstruction::SubDouble(_) => todo!(),
-				ASTInstruction::MulDouble(_) => todo!(),
-				ASTInstruction::DivDouble(_) => todo!(),
-				ASTInstruction::RemDouble(_) => todo!(),
+				ASTInstruction::InvokeInterface(generated::IF35c(
+					method_ref_idx,
+					argc,
+					a1,
+					a2,
+					a3,
+					a4,
+					a5,
+				)) => fnb.invoke(
+					instructions::InvokeKind::Interface,
+					get_method_ref(*method_ref_idx as usize, dex)?,
+					u8::from(*argc),
+					[
+						u8::from(*a1),
+						u8::from(*a2),
+						u8::from(*a3),
+						u8::from(*a4),
+						u8::from(*a5),
+					],
+				),
+				ASTInstruction::InvokeVirtualRange(generated::IF3rc(
+					method_ref_idx,
+					first,
+					argc,
+				)) => fnb.invoke_range(
+					instructions::InvokeKind::Virtual,
+					get_method_ref(*method_ref_idx as usize, dex)?,
+					*argc,
+					*first,
+				),
+				ASTInstruction::InvokeSuperRange(generated::IF3rc(method_ref_idx, first, argc)) => {
+					fnb.invoke_range(
+						instructions::InvokeKind::Super,
+						get_method_ref(*method_ref_idx as usize, dex)?,
+						*argc,
+						*first,
+					)
+				}
+				ASTInstruction::InvokeDirectRange(generated::IF3rc(
+					method_ref_idx,
+					first,
+					argc,
+				)) => fnb.invoke_range(
+					instructions::InvokeKind::Direct,
+					get_method_ref(*method_ref_idx as usize, dex)?,
+					*argc,
+					*first,
+				),
+				ASTInstruction::InvokeStaticRange(generated::IF3rc(
+					method_ref_idx,
+					first,
+					argc,
+				)) => fnb.invoke_range(
+					instructions::InvokeKind::Static,
+					get_method_ref(*method_ref_idx as usize, dex)?,
+					*argc,
+					*first,
+				),
+				ASTInstruction::InvokeInterfaceRange(generated::IF3rc(
+					method_ref_idx,
+					first,
+					argc,
+				)) => fnb.invoke_range(
+					instructions::InvokeKind::Interface,
+					get_method_ref(*method_ref_idx as usize, dex)?,
+					*argc,
+					*first,
+				),
+				ASTInstruction::NegInt(_) => fnb.unsupported(i),
+				ASTInstruction::NotInt(_) => fnb.unsupported(i),
+				ASTInstruction::NegLong(_) => fnb.unsupported(i),
+				ASTInstruction::NotLong(_) => fnb.unsupported(i),
+				ASTInstruction::NegFloat(_) => fnb.unsupported(i),
+				ASTInstruction::NegDouble(_) => fnb.unsupported(i),
+				ASTInstruction::IntToLong(_) => fnb.unsupported(i),
+				ASTInstruction::IntToFloat(_) => fnb.unsupported(i),
+				ASTInstruction::IntToDouble(_) => fnb.unsupported(i),
+				ASTInstruction::LongToInt(_) => fnb.unsupported(i),
+				ASTInstruction::LongToFloat(_) => fnb.unsupported(i),
+				ASTInstruction::LongToDouble(_) => fnb.unsupported(i),
+				ASTInstruction::FloatToInt(_) => fnb.unsupported(i),
+				ASTInstruction::FloatToLong(_) => fnb.unsupported(i),
+				ASTInstruction::FloatToDouble(_) => fnb.unsupported(i),
+				ASTInstruction::DoubleToInt(_) => fnb.unsupported(i),
+				ASTInstruction::DoubleToLong(_) => fnb.unsupported(i),
+				ASTInstruction::DoubleToFloat(_) => fnb.unsupported(i),
+				ASTInstruction::IntToByte(_) => fnb.unsupported(i),
+				ASTInstruction::IntToChar(_) => fnb.unsupported(i),
+				ASTInstruction::IntToShort(_) => fnb.unsupported(i),
+				ASTInstruction::AddInt(_) => fnb.unsupported(i),
+				ASTInstruction::SubInt(_) => fnb.unsupported(i),
+				ASTInstruction::MulInt(_) => fnb.unsupported(i),
+				ASTInstruction::DivInt(_) => fnb.unsupported(i),
+				ASTInstruction::RemInt(_) => fnb.unsupported(i),
+				ASTInstruction::AndInt(_) => fnb.unsupported(i),
+				ASTInstruction::OrInt(_) => fnb.unsupported(i),
+				ASTInstruction::XorInt(_) => fnb.unsupported(i),
+				ASTInstruction::ShlInt(_) => fnb.unsupported(i),
+				ASTInstruction::ShrInt(_) => fnb.unsupported(i),
+				ASTInstruction::UshrInt(_) => fnb.unsupported(i),
+				ASTInstruction::AddLong(_) => fnb.unsupported(i),
+				ASTInstruction::SubLong(_) => fnb.unsupported(i),
+				ASTInstruction::MulLong(_) => fnb.unsupported(i),
+				ASTInstruction::DivLong(_) => fnb.unsupported(i),
+				ASTInstruction::RemLong(_) => fnb.unsupported(i),
+				ASTInstruction::AndLong(_) => fnb.unsupported(i),
+				ASTInstruction::OrLong(_) => fnb.unsupported(i),
+				ASTInstruction::XorLong(_) => fnb.unsupported(i),
+				ASTInstruction::ShlLong(_) => fnb.unsupported(i),
+				ASTInstruction::ShrLong(_) => fnb.unsupported(i),
+				ASTInstruction::UshrLong(_) => fnb.unsupported(i),
+				ASTInstruction::AddFloat(_) => fnb.unsupported(i),
+				ASTInstruction::SubFloat(_) => fnb.unsupported(i),
+				ASTInstruction::MulFloat(_) => fnb.unsupported(i),
+				ASTInstruction::DivFloat(_) => fnb.unsupported(i),
+				ASTInstruction::RemFloat(_) => fnb.unsupported(i),
+				ASTInstruction::AddDouble(_) => fnb.unsupported(i),
+				ASTInstruction::SubDouble(_) => fnb.unsupported(i),
+				ASTInstruction::MulDouble(_) => fnb.unsupported(i),
+				ASTInstruction::DivDouble(_) => fnb.unsupported(i),
+				ASTInstruction::RemDouble(_) => fnb.unsupported(i),
 				ASTInstruction::AddInt2addr(generated::IF12x(v_dest_and_src_a, v_src_b)) => fnb
 					.bin_op_2_addr(
 						instructions::BinOpKind::AddInt,
 						u8::from(*v_dest_and_src_a),
 						u8::from(*v_src_b),
 					),
-				ASTInstruction::SubInt2addr(_) => todo!(),
-				ASTInstruction::MulInt2addr(_) => todo!(),
-				ASTInstruction::DivInt2addr(_) => todo!(),
-				ASTInstruction::RemInt2addr(_) => todo!(),
-				ASTInstruction::AndInt2addr(_) => todo!(),
-				ASTInstruction::OrInt2addr(_) => todo!(),
-				ASTInstruction::XorInt2addr(_) => todo!(),
-				ASTInstruction::ShlInt2addr(_) => todo!(),
-				ASTInstruction::ShrInt2addr(_) => todo!(),
-				ASTInstruction::UshrInt2addr(_) => todo!(),
-				ASTInstruction::AddLong2addr(_) => todo!(),
-				ASTInstruction::SubLong2addr(_) => todo!(),
-				ASTInstruction::MulLong2addr(_) => todo!(),
-				ASTInstruction::DivLong2addr(_) => todo!(),
-				ASTInstruction::RemLong2addr(_) => todo!(),
-				ASTInstruction::AndLong2addr(_) => todo!(),
-				ASTInstruction::OrLong2addr(_) => todo!(),
-				ASTInstruction::XorLong2addr(_) => todo!(),
-				ASTInstruction::ShlLong2addr(_) => todo!(),
-				ASTInstruction::ShrLong2addr(_) => todo!(),
-				ASTInstruction::UshrLong2addr(_) => todo!(),
-				ASTInstruction::AddFloat2addr(_) => todo!(),
-				ASTInstruction::SubFloat2addr(_) => todo!(),
-				ASTInstruction::MulFloat2addr(_) => todo!(),
-				ASTInstruction::DivFloat2addr(_) => todo!(),
-				ASTInstruction::RemFloat2addr(_) => todo!(),
-				ASTInstruction::AddDouble2addr(_) => todo!(),
-				ASTInstruction::SubDouble2addr(_) => todo!(),
-				ASTInstruction::MulDouble2addr(_) => todo!(),
-				ASTInstruction::DivDouble2addr(_) => todo!(),
-				ASTInstruction::RemDouble2addr(_) => todo!(),
-				ASTInstruction::AddIntLit16(_) => todo!(),
-				ASTInstruction::RsubInt(_) => todo!(),
-				ASTInstruction::MulIntLit16(_) => todo!(),
-				ASTInstruction::DivIntLit16(_) => todo!(),
-				ASTInstruction::RemIntLit16(_) => todo!(),
-				ASTInstruction::AndIntLit16(_) => todo!(),
-				ASTInstruction::OrIntLit16(_) => todo!(),
-				ASTInstruction::XorIntLit16(_) => todo!(),
+				ASTInstruction::SubInt2addr(_) => fnb.unsupported(i),
+				ASTInstruction::MulInt2addr(_) => fnb.unsupported(i),
+				ASTInstruction::DivInt2addr(_) => fnb.unsupported(i),
+				ASTInstruction::RemInt2addr(_) => fnb.unsupported(i),
+				ASTInstruction::AndInt2addr(_) => fnb.unsupported(i),
+				ASTInstruction::OrInt2addr(_) => fnb.unsupported(i),
+				ASTInstruction::XorInt2addr(_) => fnb.unsupported(i),
+				ASTInstruction::ShlInt2addr(_) => fnb.unsupported(i),
+				ASTInstruction::ShrInt2addr(_) => fnb.unsupported(i),
+				ASTInstruction::UshrInt2addr(_) => fnb.unsupported(i),
+				ASTInstruction::AddLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::SubLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::MulLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::DivLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::RemLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::AndLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::OrLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::XorLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::ShlLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::ShrLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::UshrLong2addr(_) => fnb.unsupported(i),
+				ASTInstruction::AddFloat2addr(_) => fnb.unsupported(i),
+				ASTInstruction::SubFloat2addr(_) => fnb.unsupported(i),
+				ASTInstruction::MulFloat2addr(_) => fnb.unsupported(i),
+				ASTInstruction::DivFloat2addr(_) => fnb.unsupported(i),
+				ASTInstruction::RemFloat2addr(_) => fnb.unsupported(i),
+				ASTInstruction::AddDouble2addr(_) => fnb.unsupported(i),
+				ASTInstruction::SubDouble2addr(_) => fnb.unsupported(i),
+				ASTInstruction::MulDouble2addr(_) => fnb.unsupported(i),
+				ASTInstruction::DivDouble2addr(_) => fnb.unsupported(i),
+				ASTInstruction::RemDouble2addr(_) => fnb.unsupported(i),
+				ASTInstruction::AddIntLit16(_) => fnb.unsupported(i),
+				ASTInstruction::RsubInt(_) => fnb.unsupported(i),
+				ASTInstruction::MulIntLit16(_) => fnb.unsupported(i),
+				ASTInstruction::DivIntLit16(_) => fnb.unsupported(i),
+				ASTInstruction::RemIntLit16(_) => fnb.unsupported(i),
+				ASTInstruction::AndIntLit16(_) => fnb.unsupported(i),
+				ASTInstruction::OrIntLit16(_) => fnb.unsupported(i),
+				ASTInstruction::XorIntLit16(_) => fnb.unsupported(i),
 				ASTInstruction::AddIntLit8(generated::IF22b(v_dest, v_src, lit)) => fnb.bin_op_lit(
 					instructions::BinOpLitKind::AddInt,
 					*v_dest,
 					*v_src,
 					*lit as i8 as i16,
 				),
-				ASTInstruction::RsubIntLit8(_) => todo!(),
-				ASTInstruction::MulIntLit8(_) => todo!(),
+				ASTInstruction::RsubIntLit8(_) => fnb.unsupported(i),
+				ASTInstruction::MulIntLit8(_) => fnb.unsupported(i),
 				ASTInstruction::DivIntLit8(generated::IF22b(v_dest, v_src, lit)) => fnb.bin_op_lit(
 					instructions::BinOpLitKind::DivInt,
 					*v_dest,
@@ -446,74 +801,275 @@ pub fn generate_code(
 					*lit as i8 as i16,
 				),
 				ASTInstruction::RemIntLit8(generated::IF22b(v_dest, v_src, lit)) => fnb.bin_op_lit(
-					instructions::BinOpLitKind::DivInt,
+					instructions::BinOpLitKind::RemInt,
 					*v_dest,
 					*v_src,
 					*lit as i8 as i16,
 				),
-				ASTInstruction::AndIntLit8(_) => todo!(),
-				ASTInstruction::OrIntLit8(_) => todo!(),
-				ASTInstruction::XorIntLit8(_) => todo!(),
-				ASTInstruction::ShlIntLit8(_) => todo!(),
-				ASTInstruction::ShrIntLit8(_) => todo!(),
-				ASTInstruction::UshrIntLit8(_) => todo!(),
-				ASTInstruction::IgetVolatile(_) => todo!(),
-				ASTInstruction::IputVolatile(_) => todo!(),
-				ASTInstruction::SgetVolatile(_) => todo!(),
-				ASTInstruction::SputVolatile(_) => todo!(),
-				ASTInstruction::IgetObjectVolatile(_) => todo!(),
-				ASTInstruction::IgetWideVolatile(_) => todo!(),
-				ASTInstruction::IputWideVolatile(_) => todo!(),
-				ASTInstruction::SgetWideVolatile(_) => todo!(),
-				ASTInstruction::SputWideVolatile(_) => todo!(),
-				ASTInstruction::Breakpoint(_) => todo!(),
-				ASTInstruction::ThrowVerificationError(_) => todo!(),
-				ASTInstruction::ExecuteInline(_) => todo!(),
-				ASTInstruction::ExecuteInlineRange(_) => todo!(),
-				ASTInstruction::InvokeObjectInitRange(_) => todo!(),
-				ASTInstruction::ReturnVoidBarrier(_) => todo!(),
-				ASTInstruction::IgetQuick(_) => todo!(),
-				ASTInstruction::IgetWideQuick(_) => todo!(),
-				ASTInstruction::IgetObjectQuick(_) => todo!(),
-				ASTInstruction::IputQuick(_) => todo!(),
-				ASTInstruction::IputWideQuick(_) => todo!(),
-				ASTInstruction::IputObjectQuick(_) => todo!(),
-				ASTInstruction::InvokeVirtualQuick(_) => todo!(),
-				ASTInstruction::InvokeVirtualQuickRange(_) => todo!(),
-				ASTInstruction::InvokeSuperQuick(_) => todo!(),
-				ASTInstruction::InvokeSuperQuickRange(_) => todo!(),
-				ASTInstruction::IputObjectVolatile(_) => todo!(),
-				ASTInstruction::SgetObjectVolatile(_) => todo!(),
-				ASTInstruction::SputObjectVolatile(_) => todo!(),
+				ASTInstruction::AndIntLit8(_) => fnb.unsupported(i),
+				ASTInstruction::OrIntLit8(_) => fnb.unsupported(i),
+				ASTInstruction::XorIntLit8(_) => fnb.unsupported(i),
+				ASTInstruction::ShlIntLit8(_) => fnb.unsupported(i),
+				ASTInstruction::ShrIntLit8(_) => fnb.unsupported(i),
+				ASTInstruction::UshrIntLit8(_) => fnb.unsupported(i),
+				ASTInstruction::IgetVolatile(_) => fnb.unsupported(i),
+				ASTInstruction::IputVolatile(_) => fnb.unsupported(i),
+				ASTInstruction::SgetVolatile(_) => fnb.unsupported(i),
+				ASTInstruction::SputVolatile(_) => fnb.unsupported(i),
+				ASTInstruction::IgetObjectVolatile(_) => fnb.unsupported(i),
+				ASTInstruction::IgetWideVolatile(_) => fnb.unsupported(i),
+				ASTInstruction::IputWideVolatile(_) => fnb.unsupported(i),
+				ASTInstruction::SgetWideVolatile(_) => fnb.unsupported(i),
+				ASTInstruction::SputWideVolatile(_) => fnb.unsupported(i),
+				ASTInstruction::Breakpoint(_) => fnb.unsupported(i),
+				ASTInstruction::ThrowVerificationError(_) => fnb.unsupported(i),
+				ASTInstruction::ExecuteInline(_) => fnb.unsupported(i),
+				ASTInstruction::ExecuteInlineRange(_) => fnb.unsupported(i),
+				ASTInstruction::InvokeObjectInitRange(_) => fnb.unsupported(i),
+				ASTInstruction::ReturnVoidBarrier(_) => fnb.unsupported(i),
+				ASTInstruction::IgetQuick(_) => fnb.unsupported(i),
+				ASTInstruction::IgetWideQuick(_) => fnb.unsupported(i),
+				ASTInstruction::IgetObjectQuick(_) => fnb.unsupported(i),
+				ASTInstruction::IputQuick(_) => fnb.unsupported(i),
+				ASTInstruction::IputWideQuick(_) => fnb.unsupported(i),
+				ASTInstruction::IputObjectQuick(_) => fnb.unsupported(i),
+				ASTInstruction::InvokeVirtualQuick(_) => fnb.unsupported(i),
+				ASTInstruction::InvokeVirtualQuickRange(_) => fnb.unsupported(i),
+				ASTInstruction::InvokePolymorphic(_) => fnb.unsupported(i),
+				ASTInstruction::InvokePolymorphicRange(_) => fnb.unsupported(i),
+				ASTInstruction::InvokeCustom(generated::IF35c(
+					call_site_idx,
+					argc,
+					a1,
+					a2,
+					a3,
+					a4,
+					a5,
+				)) => fnb.invoke_custom(
+					get_call_site(*call_site_idx as usize, call_sites)?,
+					[a1, a2, a3, a4, a5]
+						.iter()
+						.take(u8::from(*argc) as usize)
+						.map(|a| u8::from(**a) as u16)
+						.collect(),
+				),
+				ASTInstruction::InvokeCustomRange(generated::IF3rc(call_site_idx, first, argc)) => {
+					fnb.invoke_custom(
+						get_call_site(*call_site_idx as usize, call_sites)?,
+						(*first..*first + *argc as u16).collect(),
+					)
+				}
+				ASTInstruction::ConstMethodHandle(generated::IF21c(v, handle_idx)) => fnb
+					.const_set(
+						*v as _,
+						instructions::LiteralValue::MethodHandle(get_method_handle(
+							*handle_idx as usize,
+							call_sites,
+						)?),
+					),
+				ASTInstruction::ConstMethodType(generated::IF21c(v, proto_idx)) => fnb.const_set(
+					*v as _,
+					instructions::LiteralValue::MethodType(get_method_type(
+						*proto_idx as usize,
+						dex,
+					)?),
+				),
 			}
 		}
 
-		last_target = *i;
+		last_target = *block_id;
 	}
+
+	Ok(())
+}
+
+/// Run the line number state machine of a method's debug info, from its first line and its
+/// bytecodes, giving a table of (bytecode offset, line) entries
+fn line_table(line_start: u32, bytecode: &[DebugItemBytecodes]) -> Vec<(u32, u32)> {
+	let mut lines = Vec::new();
+	let mut address: u32 = 0;
+	let mut line = line_start as i64;
+
+	for b in bytecode {
+		match b {
+			DebugItemBytecodes::DBG_END_SEQUENCE => break,
+			DebugItemBytecodes::DBG_ADVANCE_PC(n) => address += n,
+			DebugItemBytecodes::DBG_ADVANCE_LINE(n) => line += *n as i64,
+			DebugItemBytecodes::SPECIAL_OPCODE(op) => {
+				// See "Special opcodes" in the dex format documentation
+				let adjusted = *op as u32 - 0x0a;
+				line += -4 + (adjusted % 15) as i64;
+				address += adjusted / 15;
+				lines.push((address, line as u32));
+			}
+			_ => {}
+		}
+	}
+
+	lines
 }
 
-fn get_method_full_name(m_id: usize, dex: &DexFile) -> String {
-	let (c_name, m_name) = match dex.file_data.methods.get(m_id) {
-		None => todo!("handle errors"),
-		Some(v) => (v.definer.clone(), v.name.clone()),
+fn get_method_ref(m_id: usize, dex: &DexFile) -> Result<instructions::MethodRef, ParserError> {
+	let m = match dex.file_data.methods.get(m_id) {
+		None => return Err(ParserError::BadIndex("method", m_id)),
+		Some(v) => v,
 	};
 
-	format!("{}__{}", format_classname(&c_name), format_name(&m_name))
+	let c_name = &m.definer;
+	let name = format_method(&m.name, &method_descriptor(&m.prototype));
+	Ok(instructions::MethodRef {
+		full_name: format!("{}__{}", format_classname(c_name), name),
+		class: (**c_name).clone(),
+		name,
+	})
+}
+
+fn get_call_site(
+	call_site_idx: usize,
+	call_sites: &CallSites,
+) -> Result<Arc<instructions::CallSite>, ParserError> {
+	match call_sites.sites.get(call_site_idx) {
+		None => Err(ParserError::BadIndex("call site", call_site_idx)),
+		Some(c) => Ok(c.clone()),
+	}
+}
+
+fn get_method_handle(
+	handle_idx: usize,
+	call_sites: &CallSites,
+) -> Result<instructions::MethodHandleRef, ParserError> {
+	match call_sites.method_handles.get(handle_idx) {
+		None => Err(ParserError::BadIndex("method handle", handle_idx)),
+		Some(h) => h.clone(),
+	}
+}
+
+fn get_method_type(proto_idx: usize, dex: &DexFile) -> Result<String, ParserError> {
+	match dex.file_data.prototypes.get(proto_idx) {
+		None => Err(ParserError::BadIndex("prototype", proto_idx)),
+		Some(p) => Ok(method_descriptor(p)),
+	}
+}
+
+/// The interned string of a string index
+fn get_string(
+	s_idx: usize,
+	dex: &DexFile,
+	module: &codegen::Module,
+) -> Result<JavaString, ParserError> {
+	match dex.file_data.string_data.get(s_idx) {
+		None => Err(ParserError::BadIndex("string", s_idx)),
+		Some(s) => Ok(module.intern_str(s)),
+	}
+}
+
+fn get_type_name(type_idx: usize, dex: &DexFile) -> Result<String, ParserError> {
+	match dex.file_data.type_identifiers.get(type_idx) {
+		None => Err(ParserError::BadIndex("type", type_idx)),
+		Some(t) => Ok((**t).clone()),
+	}
 }
 
-fn get_field_name(f_ref_idx: usize, is_static: bool, dex: &DexFile) -> String {
+fn get_static_ref(f_ref_idx: usize, dex: &DexFile) -> Result<instructions::StaticRef, ParserError> {
 	match dex.file_data.fields.get(f_ref_idx) {
-		None => unimplemented!(),
-		Some(v) => {
-			if is_static {
-				format!(
-					"{}__{}",
-					format_classname(&*v.definer),
-					format_name(&*v.name)
-				)
-			} else {
-				(*v.name).clone()
-			}
-		}
+		None => Err(ParserError::BadIndex("field", f_ref_idx)),
+		Some(v) => Ok(instructions::StaticRef {
+			class: (*v.definer).clone(),
+			name: format!(
+				"{}__{}",
+				format_classname(&*v.definer),
+				format_name(&*v.name)
+			),
+		}),
+	}
+}
+
+/// Resolve an instance field reference to its slot in the defining class
+/// The field of an instance field instruction, if its class has no field with its name
+fn unresolved_field(i: &ASTInstruction, dex: &DexFile, module: &codegen::Module) -> Option<String> {
+	let field_idx = match i {
+		ASTInstruction::Iget(generated::IF22c(_, _, f))
+		| ASTInstruction::IgetWide(generated::IF22c(_, _, f))
+		| ASTInstruction::IgetObject(generated::IF22c(_, _, f))
+		| ASTInstruction::IgetBoolean(generated::IF22c(_, _, f))
+		| ASTInstruction::IgetByte(generated::IF22c(_, _, f))
+		| ASTInstruction::IgetChar(generated::IF22c(_, _, f))
+		| ASTInstruction::IgetShort(generated::IF22c(_, _, f))
+		| ASTInstruction::Iput(generated::IF22c(_, _, f))
+		| ASTInstruction::IputWide(generated::IF22c(_, _, f))
+		| ASTInstruction::IputObject(generated::IF22c(_, _, f))
+		| ASTInstruction::IputBoolean(generated::IF22c(_, _, f))
+		| ASTInstruction::IputByte(generated::IF22c(_, _, f))
+		| ASTInstruction::IputChar(generated::IF22c(_, _, f))
+		| ASTInstruction::IputShort(generated::IF22c(_, _, f)) => *f as usize,
+		_ => return None,
+	};
+
+	match get_field_slot(field_idx, dex, module) {
+		Err(ParserError::UnresolvedField(field)) => Some(field),
+		_ => None,
+	}
+}
+
+fn get_field_slot(
+	f_ref_idx: usize,
+	dex: &DexFile,
+	module: &codegen::Module,
+) -> Result<usize, ParserError> {
+	let f = match dex.file_data.fields.get(f_ref_idx) {
+		None => return Err(ParserError::BadIndex("field", f_ref_idx)),
+		Some(v) => v,
+	};
+
+	module
+		.get_class(&f.definer)
+		.and_then(|c| c.field_slot(&f.name))
+		.ok_or_else(|| ParserError::UnresolvedField(format!("{}.{}", f.definer, f.name)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use DebugItemBytecodes::*;
+
+	/// The special opcode of a line and address change
+	fn special(line_diff: i32, address_diff: u32) -> DebugItemBytecodes {
+		SPECIAL_OPCODE((0x0a + (line_diff + 4) as u32 + address_diff * 15) as u8)
+	}
+
+	#[test]
+	fn special_opcodes() {
+		// The lowest opcode goes 4 lines back, and the highest 16 code units and a line on
+		assert_eq!(line_table(10, &[SPECIAL_OPCODE(0x0a)]), vec![(0, 6)]);
+		assert_eq!(line_table(10, &[SPECIAL_OPCODE(0xff)]), vec![(16, 11)]);
+
+		let bytecode = [special(0, 0), special(1, 3), special(-2, 2), special(10, 0)];
+		assert_eq!(
+			line_table(5, &bytecode),
+			vec![(0, 5), (3, 6), (5, 4), (5, 14)]
+		);
+	}
+
+	#[test]
+	fn advances() {
+		// Advancing doesn't add a line, only special opcodes do
+		let bytecode = [
+			DBG_ADVANCE_PC(4),
+			DBG_ADVANCE_LINE(-3),
+			DBG_SET_PROLOGUE_END,
+			special(0, 1),
+			DBG_ADVANCE_LINE(100),
+			DBG_ADVANCE_PC(20),
+			special(0, 0),
+		];
+		assert_eq!(line_table(20, &bytecode), vec![(5, 17), (25, 117)]);
+		assert_eq!(line_table(20, &bytecode[..3]), vec![]);
+	}
+
+	#[test]
+	fn end_sequence() {
+		let bytecode = [special(0, 0), DBG_END_SEQUENCE, special(1, 1)];
+		assert_eq!(line_table(1, &bytecode), vec![(0, 1)]);
+		assert_eq!(line_table(1, &[]), vec![]);
 	}
 }
@@ -1,5 +1,6 @@
 //TODO? Find "pattern"-blocks (if, switch, etc)  -  reloop
 
+use super::super::codegen::instructions::Handler;
 use super::generated;
 use super::generated::ASTInstruction;
 use log::{debug, warn};
@@ -19,7 +20,7 @@ pub fn analyse_control_flow<'a>(
   insns: &'a Vec<ASTInstruction>,
   tries: &Vec<TryItem>,
   // ) -> HashMap<usize, BasicBlock<'a>> {
-) -> (HashMap<usize, BasicBlock<'a>>, Vec<HashMap<String, usize>>) {
+) -> (HashMap<usize, BasicBlock<'a>>, Vec<Handler>) {
   let mut cfa = Vec::new();
   let mut cfa_exits: Vec<Vec<usize>> = Vec::new();
 
@@ -29,9 +30,8 @@ pub fn analyse_control_flow<'a>(
   //? Do we need to make sure the handler is left in the correct way (with jump or return?)
 
   let mut current_address = 0;
-  // Vector of (from, to), exception-type -> target
-  //TODO Catch-all address?
-  let mut handlers: Vec<((usize, usize), HashMap<String, usize>)> = vec![];
+  // Vector of (from, to), handler
+  let mut handlers: Vec<((usize, usize), Handler)> = vec![];
   for (i, ins) in insns.iter().enumerate() {
     // Calculate start and end index of a try-block:
     for t in tries {
@@ -53,20 +53,18 @@ pub fn analyse_control_flow<'a>(
       };
 
       // Calculate this blocks handlers' id
-      let mut hs = HashMap::new();
-      for h in &t.handler.handlers {
-        let idx: usize = {
-          let mut word_count = 0;
-          let mut i_count = 0;
-          while word_count < h.addr {
-            word_count += generated::instruction_length(&insns[i_count]) as u32;
-            i_count += 1;
-          }
-          i_count
-        };
-
-        hs.insert((*h.type_).to_owned(), idx);
-      }
+      let hs = Handler {
+        catches: t
+          .handler
+          .handlers
+          .iter()
+          .map(|h| ((*h.type_).to_owned(), addr_to_index(h.addr, insns)))
+          .collect(),
+        catch_all: t
+          .handler
+          .catch_all_addr
+          .map(|addr| addr_to_index(addr, insns)),
+      };
       handlers.push(((start_idx, start_idx + insn_count), hs));
 
       debug!(
@@ -106,6 +104,18 @@ pub fn analyse_control_flow<'a>(
   into_blocks(&cfa, &handlers)
 }
 
+/// Turn an address (in words) into an instruction index
+fn addr_to_index(addr: u32, insns: &[ASTInstruction]) -> usize {
+  let mut word_count = 0;
+  let mut i_count = 0;
+  while word_count < addr {
+    word_count += generated::instruction_length(&insns[i_count]) as u32;
+    i_count += 1;
+  }
+
+  i_count
+}
+
 /// Calculate the exits of an instruction
 fn exits(idx: usize, ins: &ASTInstruction, instructions: &Vec<ASTInstruction>) -> Vec<usize> {
   //TODO Fill this with all "non-linear" instructions
@@ -209,9 +219,9 @@ impl<'a> BasicBlock<'a> {
 /// https://en.wikipedia.org/wiki/Basic_block#Creation_algorithm
 fn into_blocks<'a>(
   cfa: &Vec<CFInstruction<'a>>,
-  // Start, End, (type->target-idx)
-  handlers: &Vec<((usize, usize), HashMap<String, usize>)>,
-) -> (HashMap<usize, BasicBlock<'a>>, Vec<HashMap<String, usize>>) {
+  // Start, End, handler
+  handlers: &Vec<((usize, usize), Handler)>,
+) -> (HashMap<usize, BasicBlock<'a>>, Vec<Handler>) {
   let mut blocks: HashMap<usize, BasicBlock> = HashMap::new();
   let mut cur_block = BasicBlock::new();
   let mut cur_block_id = 0;
@@ -224,9 +234,10 @@ fn into_blocks<'a>(
   let handler_indices: Vec<usize> = {
     let mut out = vec![];
     for h in handlers {
-      for (_, v) in &h.1 {
+      for (_, v) in &h.1.catches {
         out.push(*v);
       }
+      out.extend(h.1.catch_all);
     }
 
     out
@@ -325,9 +336,7 @@ fn into_blocks<'a>(
   (blocks, new_handlers)
 }
 
-pub fn format_analysis(
-  analysis: &(HashMap<usize, BasicBlock>, Vec<HashMap<String, usize>>),
-) -> String {
+pub fn format_analysis(analysis: &(HashMap<usize, BasicBlock>, Vec<Handler>)) -> String {
   // pub fn format_analysis(a: &HashMap<usize, BasicBlock>) -> String {
   let mut out = Vec::new();
 
@@ -71,6 +71,10 @@ pub struct IF35c(pub u16, pub u4, pub u4, pub u4, pub u4, pub u4, pub u4);
 #[derive(Debug, Clone)]
 pub struct IF3rc(pub u16, pub u16, pub u8);
 #[derive(Debug, Clone)]
+pub struct IF45cc(pub u16, pub u4, pub u4, pub u4, pub u4, pub u4, pub u4, pub u16);
+#[derive(Debug, Clone)]
+pub struct IF4rcc(pub u16, pub u16, pub u8, pub u16);
+#[derive(Debug, Clone)]
 pub struct IF00x();
 #[derive(Debug, Clone)]
 pub struct IF20bc(pub u8, pub u16);
@@ -275,6 +279,32 @@ macro_rules! instr_format {
 
     IF3rc(join_bytes!(16, b1, b2), join_bytes!(16, b3, b4), b0)
   }};
+  (45cc, $q:expr) => {{
+    let b0 = $q.incr()?;
+    let b1 = $q.incr()?;
+    let b2 = $q.incr()?;
+    let b3 = $q.incr()?;
+    let b4 = $q.incr()?;
+    let b5 = $q.incr()?;
+    let b6 = $q.incr()?;
+
+    let (g, count) = split_byte!(b0);
+    let b = join_bytes!(16, b1, b2);
+    let (c, d) = split_byte!(b3);
+    let (e, f) = split_byte!(b4);
+    IF45cc(b, count, c, d, e, f, g, join_bytes!(16, b5, b6))
+  }};
+  (4rcc, $q:expr) => {{
+    let b0 = $q.incr()?;
+    let b1 = $q.incr()?;
+    let b2 = $q.incr()?;
+    let b3 = $q.incr()?;
+    let b4 = $q.incr()?;
+    let b5 = $q.incr()?;
+    let b6 = $q.incr()?;
+
+    IF4rcc(join_bytes!(16, b1, b2), join_bytes!(16, b3, b4), b0, join_bytes!(16, b5, b6))
+  }};
   (00x, $q:expr) => {{
     IF00x()
   }};
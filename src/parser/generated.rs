@@ -8,7 +8,7 @@ use ux::u4;
 
 macro_rules! split_byte {
   ($b:expr) => {
-    (u4::new(($b as u8 & 0xf)), u4::new(($b as u8 >> 4)))
+    (u4::new($b as u8 & 0xf), u4::new($b as u8 >> 4))
   };
 }
 
@@ -75,6 +75,10 @@ pub struct IF35c(pub u16, pub u4, pub u4, pub u4, pub u4, pub u4, pub u4);
 #[derive(Debug, Clone)]
 pub struct IF3rc(pub u16, pub u16, pub u8);
 #[derive(Debug, Clone)]
+pub struct IF45cc(pub u16, pub u4, pub u4, pub u4, pub u4, pub u4, pub u4, pub u16);
+#[derive(Debug, Clone)]
+pub struct IF4rcc(pub u16, pub u16, pub u8, pub u16);
+#[derive(Debug, Clone)]
 pub struct IF00x();
 #[derive(Debug, Clone)]
 pub struct IF20bc(pub u8, pub u16);
@@ -279,6 +283,32 @@ macro_rules! instr_format {
 
     IF3rc(join_bytes!(16, b1, b2), join_bytes!(16, b3, b4), b0)
   }};
+  (45cc, $q:expr) => {{
+    let b0 = $q.incr()?;
+    let b1 = $q.incr()?;
+    let b2 = $q.incr()?;
+    let b3 = $q.incr()?;
+    let b4 = $q.incr()?;
+    let b5 = $q.incr()?;
+    let b6 = $q.incr()?;
+
+    let (g, count) = split_byte!(b0);
+    let b = join_bytes!(16, b1, b2);
+    let (c, d) = split_byte!(b3);
+    let (e, f) = split_byte!(b4);
+    IF45cc(b, count, c, d, e, f, g, join_bytes!(16, b5, b6))
+  }};
+  (4rcc, $q:expr) => {{
+    let b0 = $q.incr()?;
+    let b1 = $q.incr()?;
+    let b2 = $q.incr()?;
+    let b3 = $q.incr()?;
+    let b4 = $q.incr()?;
+    let b5 = $q.incr()?;
+    let b6 = $q.incr()?;
+
+    IF4rcc(join_bytes!(16, b1, b2), join_bytes!(16, b3, b4), b0, join_bytes!(16, b5, b6))
+  }};
   (00x, $q:expr) => {{
     IF00x()
   }};
@@ -678,11 +708,12 @@ pub enum ASTInstruction{
 	IputObjectQuick(IF22cs), // 22cs
 	InvokeVirtualQuick(IF35ms), // 35ms
 	InvokeVirtualQuickRange(IF3rms), // 3rms
-	InvokeSuperQuick(IF35ms), // 35ms
-	InvokeSuperQuickRange(IF3rms), // 3rms
-	IputObjectVolatile(IF22c), // 22c
-	SgetObjectVolatile(IF21c), // 21c
-	SputObjectVolatile(IF21c), // 21c
+	InvokePolymorphic(IF45cc), // 45cc
+	InvokePolymorphicRange(IF4rcc), // 4rcc
+	InvokeCustom(IF35c), // 35c
+	InvokeCustomRange(IF3rc), // 3rc
+	ConstMethodHandle(IF21c), // 21c
+	ConstMethodType(IF21c), // 21c
 }
 
 pub fn parse_instruction(q: &mut InstructionQueue) -> Result<ASTInstruction, ParserError> {
@@ -938,12 +969,12 @@ pub fn parse_instruction(q: &mut InstructionQueue) -> Result<ASTInstruction, Par
 		0xf7 => Ok(ASTInstruction::IputObjectQuick(instr_format!(22cs, q))),
 		0xf8 => Ok(ASTInstruction::InvokeVirtualQuick(instr_format!(35ms, q))),
 		0xf9 => Ok(ASTInstruction::InvokeVirtualQuickRange(instr_format!(3rms, q))),
-		0xfa => Ok(ASTInstruction::InvokeSuperQuick(instr_format!(35ms, q))),
-		0xfb => Ok(ASTInstruction::InvokeSuperQuickRange(instr_format!(3rms, q))),
-		0xfc => Ok(ASTInstruction::IputObjectVolatile(instr_format!(22c, q))),
-		0xfd => Ok(ASTInstruction::SgetObjectVolatile(instr_format!(21c, q))),
-		0xfe => Ok(ASTInstruction::SputObjectVolatile(instr_format!(21c, q))),
-		0xff => unreachable!(),
+		0xfa => Ok(ASTInstruction::InvokePolymorphic(instr_format!(45cc, q))),
+		0xfb => Ok(ASTInstruction::InvokePolymorphicRange(instr_format!(4rcc, q))),
+		0xfc => Ok(ASTInstruction::InvokeCustom(instr_format!(35c, q))),
+		0xfd => Ok(ASTInstruction::InvokeCustomRange(instr_format!(3rc, q))),
+		0xfe => Ok(ASTInstruction::ConstMethodHandle(instr_format!(21c, q))),
+		0xff => Ok(ASTInstruction::ConstMethodType(instr_format!(21c, q))),
 	}
 }
 
@@ -1190,10 +1221,11 @@ pub fn instruction_length(instr: &ASTInstruction) -> u8 {
 		ASTInstruction::IputObjectQuick(_) => 2,
 		ASTInstruction::InvokeVirtualQuick(_) => 3,
 		ASTInstruction::InvokeVirtualQuickRange(_) => 3,
-		ASTInstruction::InvokeSuperQuick(_) => 3,
-		ASTInstruction::InvokeSuperQuickRange(_) => 3,
-		ASTInstruction::IputObjectVolatile(_) => 2,
-		ASTInstruction::SgetObjectVolatile(_) => 2,
-		ASTInstruction::SputObjectVolatile(_) => 2,
+		ASTInstruction::InvokePolymorphic(_) => 4,
+		ASTInstruction::InvokePolymorphicRange(_) => 4,
+		ASTInstruction::InvokeCustom(_) => 3,
+		ASTInstruction::InvokeCustomRange(_) => 3,
+		ASTInstruction::ConstMethodHandle(_) => 2,
+		ASTInstruction::ConstMethodType(_) => 2,
 	}
 }
\ No newline at end of file
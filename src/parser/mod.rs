@@ -1,21 +1,47 @@
 pub use dexparser::{
-  AccessFlag, ClassDefinition, Code, DexFile, EncodedField, EncodedMethod, Field,
+  AccessFlag, ClassDefinition, Code, DebugItemBytecodes, DexFile, EncodedField, EncodedMethod,
+  Field, Prototype,
 };
 use log::{debug, warn};
 
+mod call_site;
 pub mod code;
 pub mod control_flow;
 pub mod generated;
 
 use super::codegen;
+pub use call_site::{method_descriptor, parse_call_sites, CallSites};
 use code::generate_code;
+pub use codegen::{format_classname, format_method, format_name};
 
 mod instruction_queue;
 pub(crate) use instruction_queue::InstructionQueue;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ParserError {
   EOF,
+  /// An instance field which isn't declared by its class or a superclass, as `Class.field`
+  UnresolvedField(String),
+  /// An index into a section of the dex file (e.g. "method" or "type") which is out of bounds
+  BadIndex(&'static str, usize),
+  /// A method handle of a type which isn't supported, like the field accessors
+  UnsupportedMethodHandle(u16),
+  /// An `encoded_value` of a type call sites can't have, like an array or an annotation
+  UnsupportedValue(u8),
+}
+
+impl std::fmt::Display for ParserError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ParserError::EOF => write!(f, "Unexpected end of the data"),
+      ParserError::UnresolvedField(field) => write!(f, "Unresolved field {}", field),
+      ParserError::BadIndex(section, idx) => write!(f, "Bad {} index {}", section, idx),
+      ParserError::UnsupportedMethodHandle(t) => {
+        write!(f, "Unsupported method handle type {:#04x}", t)
+      }
+      ParserError::UnsupportedValue(t) => write!(f, "Unsupported value type {:#04x}", t),
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -24,9 +50,31 @@ pub struct ParserOutput {
   pub source: String,
 }
 
+/// Declare a class and the layout of its instances in the module. All classes
+/// are declared before any code is generated, so field references can be resolved.
+pub fn declare_class(c: &ClassDefinition, module: &mut codegen::Module) {
+  let fields = match &c.class_data {
+    None => Vec::new(),
+    Some(cd) => cd
+      .instance_fields
+      .iter()
+      .map(|f| (*f.field.name).clone())
+      .collect(),
+  };
+
+  module.add_class(
+    (*c.class_type).clone(),
+    c.access_flags.contains(&AccessFlag::ACC_INTERFACE),
+    c.superclass.as_ref().map(|s| (**s).clone()),
+    c.interfaces.iter().map(|i| (**i).clone()).collect(),
+    fields,
+  );
+}
+
 pub fn parse_class(
   c: &ClassDefinition,
   dex: &DexFile,
+  call_sites: &CallSites,
   module: &mut codegen::Module,
 ) -> Result<(), ParserError> {
   //TODO: Generate a destructor? (close open files, threads, etc)
@@ -46,13 +94,21 @@ pub fn parse_class(
   if let Some(cd) = &c.class_data {
     for dm in &cd.direct_methods {
       debug!("DM: {}: {}", &dm.method.name, &dm.method.prototype.shorty);
-      let (name, fn_) = parse_method(&c_name, dm, &c, dex);
+      if is_native(dm) {
+        check_native(&c.class_type, dm, module);
+        continue;
+      }
+      let (name, fn_) = parse_method(&c_name, dm, &c, dex, call_sites, module)?;
       module.add_function(name, fn_);
     }
 
     for vm in &cd.virtual_methods {
       debug!("VM: {}: {}", &vm.method.name, &vm.method.prototype.shorty);
-      let (name, fn_) = parse_method(&c_name, vm, &c, dex);
+      if is_native(vm) {
+        check_native(&c.class_type, vm, module);
+        continue;
+      }
+      let (name, fn_) = parse_method(&c_name, vm, &c, dex, call_sites, module)?;
       module.add_function(name, fn_);
     }
   } else {
@@ -62,42 +118,53 @@ pub fn parse_class(
   Ok(())
 }
 
+fn is_native(method: &EncodedMethod) -> bool {
+  method.access_flags.contains(&AccessFlag::ACC_NATIVE)
+}
+
+/// A `native` method has no code, so it has to be provided by the runtime. It isn't added to
+/// the module, which would replace the runtime's function with an empty one.
+fn check_native(class: &str, method: &EncodedMethod, module: &codegen::Module) {
+  let m = &method.method;
+  let name = format_method(&m.name, &method_descriptor(&m.prototype));
+  if module.resolve_method(class, &name).is_none() {
+    debug!(
+      "Native method without an implementation: {}__{}",
+      format_classname(class),
+      name
+    );
+  }
+}
+
 fn parse_method(
   c_name: &str,
   method: &EncodedMethod,
   class: &ClassDefinition,
   dex: &DexFile,
-) -> (String, codegen::Function) {
+  call_sites: &CallSites,
+  module: &codegen::Module,
+) -> Result<(String, codegen::Function), ParserError> {
   let m = &method.method;
 
-  let m_name = format_name(&m.name);
+  let m_name = format_method(&m.name, &method_descriptor(&m.prototype));
   let m_full_name = format!("{}__{}", c_name, m_name);
   // let params = parse_params(&c_name, &method);
 
   let mut cg_fn = codegen::FunctionBuilder::new();
+  cg_fn.set_static(method.access_flags.contains(&AccessFlag::ACC_STATIC));
+  // Only native methods keep ACC_SYNCHRONIZED in a dex file, others are declared synchronized
+  cg_fn.set_synchronized(
+    method.access_flags.contains(&AccessFlag::ACC_SYNCHRONIZED)
+      || method
+        .access_flags
+        .contains(&AccessFlag::ACC_DECLARED_SYNCHRONIZED),
+  );
 
   if let Some(code) = &method.code {
-    generate_code(code, &method, c_name, &class, dex, &mut cg_fn);
+    generate_code(code, method, class, dex, call_sites, module, &mut cg_fn)?;
   } else {
     warn!("No code associated with method: {} ({})", m_name, c_name);
   }
 
-  (m_full_name, cg_fn.build())
-}
-
-pub fn format_classname(classname: &str) -> String {
-  format!(
-    "CLASS_{}",
-    classname[1..]
-      .replace("__", "____")
-      .replace("/", "__")
-      .replace(";", "")
-  )
-}
-
-pub fn format_name(name: &str) -> String {
-  name
-    .replace("__", "____")
-    .replace("<", "__")
-    .replace(">", "__")
+  Ok((m_full_name, cg_fn.build()))
 }
@@ -0,0 +1,456 @@
+//! `java.util.Arrays`, and `Arrays$ArrayList`: the fixed-size list of `Arrays.asList`, which
+//! reads and writes through to its array
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use super::collection::{arg, equals, exception, hash_code, int_arg, merge_sort, non_null, sort};
+use super::math::args;
+use super::number::{double_to_string, float_to_string};
+use super::string_builder::object_text;
+use super::{runtime, CallStack, InvokeResult, Module, Native, RuntimeError};
+
+// Slot of the `a` field of an `Arrays$ArrayList`: its array
+const ARRAY: usize = 0;
+
+/// The element types of arrays, which each have their own overloads
+const ELEMENTS: &[&str] = &["Z", "B", "C", "S", "I", "J", "F", "D", "Ljava/lang/Object;"];
+
+pub fn add_functions(m: &mut Module) {
+  let method = |descriptor: &str| format!("java/util/Arrays.{}", descriptor);
+  m.natives().untyped_static_method(
+    &method("asList([Ljava/lang/Object;)Ljava/util/List;"),
+    as_list,
+  );
+
+  for element in ELEMENTS {
+    let a = format!("[{}", element);
+    let mut overloads: Vec<(String, Native)> = vec![
+      (format!("toString({})Ljava/lang/String;", a), to_string),
+      (format!("fill({}{})V", a, element), fill),
+      (format!("fill({}II{})V", a, element), fill),
+      (format!("copyOf({}I){}", a, a), copy_of),
+      (format!("copyOfRange({}II){}", a, a), copy_of_range),
+      (format!("equals({}{})Z", a, a), equals_),
+      (format!("hashCode({})I", a), hash_code_),
+    ];
+    // Booleans have no order
+    if *element != "Z" {
+      overloads.push((format!("sort({})V", a), sort_));
+      overloads.push((format!("sort({}II)V", a), sort_));
+    }
+
+    for (descriptor, f) in overloads {
+      m.natives().untyped_static_method(&method(&descriptor), f);
+    }
+  }
+  m.natives()
+    .untyped_static_method(
+      &method("sort([Ljava/lang/Object;Ljava/util/Comparator;)V"),
+      sort_,
+    )
+    .untyped_static_method(
+      &method("sort([Ljava/lang/Object;IILjava/util/Comparator;)V"),
+      sort_,
+    );
+
+  m.natives()
+    .untyped_method(
+      "java/util/Arrays$ArrayList.get(I)Ljava/lang/Object;",
+      list_get,
+    )
+    .untyped_method(
+      "java/util/Arrays$ArrayList.set(ILjava/lang/Object;)Ljava/lang/Object;",
+      list_set,
+    )
+    .untyped_method("java/util/Arrays$ArrayList.size()I", list_size);
+}
+
+fn array<'a>(
+  params: &'a [runtime::Value],
+  idx: usize,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<&'a runtime::Value, InvokeResult> {
+  match non_null(params, idx, cs, env)? {
+    a if a.is_array() => Ok(a),
+    a => Err(InvokeResult::runtime(
+      RuntimeError::CastError(format!("{:?} as array", a)),
+      cs.clone(),
+    )),
+  }
+}
+
+/// The type descriptor and the elements of an array
+fn contents(a: &runtime::Value) -> Result<(String, Vec<runtime::Value>), RuntimeError> {
+  let a = a.array()?.lock().unwrap();
+  Ok((a.get_array_type()[1..].to_owned(), a.data().clone()))
+}
+
+fn replace(
+  a: &runtime::Value,
+  from: usize,
+  items: Vec<runtime::Value>,
+) -> Result<(), RuntimeError> {
+  let mut a = a.array()?.lock().unwrap();
+  for (i, item) in items.into_iter().enumerate() {
+    a.set(from + i, item);
+  }
+
+  Ok(())
+}
+
+/// Check a range of an array, like `Arrays.rangeCheck`
+fn check_range(
+  len: usize,
+  from: i32,
+  to: i32,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<(usize, usize), InvokeResult> {
+  if from > to {
+    let message = format!("fromIndex({}) > toIndex({})", from, to);
+    return Err(exception(
+      "Ljava/lang/IllegalArgumentException;",
+      Some(&message),
+      cs,
+      env,
+    ));
+  }
+  for index in &[from, to] {
+    if *index < 0 || *index as usize > len {
+      let message = format!("Array index out of range: {}", index);
+      return Err(exception(
+        "Ljava/lang/ArrayIndexOutOfBoundsException;",
+        Some(&message),
+        cs,
+        env,
+      ));
+    }
+  }
+
+  Ok((from as usize, to as usize))
+}
+
+/// The text of an element of an array of a type
+fn element_text(
+  type_: &str,
+  v: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Vec<u16>, InvokeResult> {
+  let text = match (type_, v) {
+    ("Z", v) => (v.to_single() != 0).to_string(),
+    ("C", v) => String::from_utf16_lossy(&[v.to_single() as u16]),
+    ("F", v) => float_to_string(f32::from_bits(v.to_single() as u32)),
+    ("D", runtime::Value::U64(d)) => double_to_string(f64::from_bits(*d as u64)),
+    ("J", runtime::Value::U64(l)) => l.to_string(),
+    ("B", v) | ("S", v) | ("I", v) | ("J", v) | ("D", v) => v.to_single().to_string(),
+    (_, v) => return object_text(v, cs, env),
+  };
+
+  Ok(text.encode_utf16().collect())
+}
+
+/// Compare primitive elements of an array of a type. Floats and doubles are in the order of
+/// `Double.compare`: `-0.0` before `0.0`, and NaN last.
+fn compare_primitives(type_: &str, a: &runtime::Value, b: &runtime::Value) -> Ordering {
+  let double = |v: &runtime::Value| match (type_, v) {
+    ("D", runtime::Value::U64(d)) => f64::from_bits(*d as u64),
+    (_, v) => f32::from_bits(v.to_single() as u32) as f64,
+  };
+
+  match type_ {
+    "F" | "D" => {
+      let (a, b) = (double(a), double(b));
+      match a.partial_cmp(&b) {
+        Some(Ordering::Equal) => b.is_sign_negative().cmp(&a.is_sign_negative()),
+        Some(o) => o,
+        None => a.is_nan().cmp(&b.is_nan()),
+      }
+    }
+    "J" => match (a, b) {
+      (runtime::Value::U64(a), runtime::Value::U64(b)) => a.cmp(b),
+      (a, b) => a.to_single().cmp(&b.to_single()),
+    },
+    // Chars are unsigned
+    "C" => (a.to_single() as u16).cmp(&(b.to_single() as u16)),
+    _ => a.to_single().cmp(&b.to_single()),
+  }
+}
+
+/// `Arrays.asList(T... a)`: a list backed by the array
+fn as_list(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let a = try_invoke!(array(&params, 0, &cs, env));
+  let mut list = env.new_instance("Ljava/util/Arrays$ArrayList;");
+  unwrap_or_error!(list.set_field(ARRAY, a.clone()), cs);
+  InvokeResult::Ok(runtime::Value::from_instance(list))
+}
+
+/// `Arrays.toString(a)`: the elements like `[1, 2, 3]`, or `null`
+fn to_string(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let a = try_invoke!(arg(&params, 0, &cs));
+  if a.is_null() {
+    return InvokeResult::Ok(runtime::Value::new_string("null"));
+  }
+
+  let (type_, items) = unwrap_or_error!(contents(a), cs);
+  let mut text: Vec<u16> = "[".encode_utf16().collect();
+  for (i, item) in items.iter().enumerate() {
+    if i > 0 {
+      text.extend(", ".encode_utf16());
+    }
+    text.extend(try_invoke!(element_text(&type_, item, &cs, env)));
+  }
+  text.push(']' as u16);
+
+  InvokeResult::Ok(runtime::Value::String(runtime::JavaString::from_units(
+    text,
+  )))
+}
+
+/// `Arrays.fill(a, v)` and `Arrays.fill(a, from, to, v)`
+fn fill(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let a = try_invoke!(array(&params, 0, &cs, env));
+  let args = args(&params);
+  let len = unwrap_or_error!(a.array(), cs).lock().unwrap().len();
+
+  let (from, to, v) = match args[..] {
+    [_, v] => (0, len, v),
+    [_, from, to, v] => {
+      let (from, to) = (from.to_single(), to.to_single());
+      let (from, to) = try_invoke!(check_range(len, from, to, &cs, env));
+      (from, to, v)
+    }
+    _ => return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(2, params.len()), cs),
+  };
+
+  unwrap_or_error!(replace(a, from, vec![v.clone(); to - from]), cs);
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// A new array of the same type with some of the elements of an array, padded with zeros (or
+/// nulls) up to a length
+fn copy(a: &runtime::Value, from: usize, len: usize) -> Result<runtime::Value, RuntimeError> {
+  let (type_, items) = contents(a)?;
+  let mut copy: Vec<_> = items.into_iter().skip(from).take(len).collect();
+  copy.resize(len, runtime::Value::zero(&type_));
+  Ok(runtime::Value::from_array(format!("[{}", type_), copy))
+}
+
+/// `Arrays.copyOf(a, newLength)`
+fn copy_of(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let a = try_invoke!(array(&params, 0, &cs, env));
+  let len = try_invoke!(int_arg(&params, 1, &cs));
+  if len < 0 {
+    return exception(
+      "Ljava/lang/NegativeArraySizeException;",
+      Some(&len.to_string()),
+      &cs,
+      env,
+    );
+  }
+
+  InvokeResult::Ok(unwrap_or_error!(copy(a, 0, len as usize), cs))
+}
+
+/// `Arrays.copyOfRange(a, from, to)`, where `to` may be after the end of the array
+fn copy_of_range(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let a = try_invoke!(array(&params, 0, &cs, env));
+  let from = try_invoke!(int_arg(&params, 1, &cs));
+  let to = try_invoke!(int_arg(&params, 2, &cs));
+  let len = unwrap_or_error!(a.array(), cs).lock().unwrap().len();
+
+  if from > to {
+    let message = format!("{} > {}", from, to);
+    return exception(
+      "Ljava/lang/IllegalArgumentException;",
+      Some(&message),
+      &cs,
+      env,
+    );
+  }
+  if from < 0 || from as usize > len {
+    let message = format!("Array index out of range: {}", from);
+    return exception(
+      "Ljava/lang/ArrayIndexOutOfBoundsException;",
+      Some(&message),
+      &cs,
+      env,
+    );
+  }
+
+  let copy = copy(a, from as usize, (to - from) as usize);
+  InvokeResult::Ok(unwrap_or_error!(copy, cs))
+}
+
+/// `Arrays.sort(a)`, `sort(a, comparator)`, `sort(a, from, to)` and `sort(a, from, to,
+/// comparator)`. Objects are sorted with their natural ordering if there is no comparator.
+fn sort_(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let a = try_invoke!(array(&params, 0, &cs, env));
+  let (type_, items) = unwrap_or_error!(contents(a), cs);
+
+  let (from, to, comparator) = match params.len() {
+    1 | 2 => (0, items.len(), params.get(1)),
+    _ => {
+      let from = try_invoke!(int_arg(&params, 1, &cs));
+      let to = try_invoke!(int_arg(&params, 2, &cs));
+      let (from, to) = try_invoke!(check_range(items.len(), from, to, &cs, env));
+      (from, to, params.get(3))
+    }
+  };
+
+  let mut range = items[from..to].to_vec();
+  if type_.starts_with('L') || type_.starts_with('[') {
+    let comparator = comparator.cloned().unwrap_or(runtime::Value::Void);
+    try_invoke!(sort(&mut range, &comparator, &cs, env));
+  } else {
+    let sorted: Result<(), ()> =
+      merge_sort(&mut range, &mut |a, b| Ok(compare_primitives(&type_, a, b)));
+    sorted.unwrap();
+  }
+
+  unwrap_or_error!(replace(a, from, range), cs);
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// Whether primitive elements are equal, like `==` except that floats and doubles are compared by
+/// their bits (so NaN equals NaN)
+fn equal_primitives(type_: &str, a: &runtime::Value, b: &runtime::Value) -> bool {
+  match (type_, a, b) {
+    ("F", a, b) if f32::from_bits(a.to_single() as u32).is_nan() => {
+      f32::from_bits(b.to_single() as u32).is_nan()
+    }
+    ("D", runtime::Value::U64(a), runtime::Value::U64(b)) if f64::from_bits(*a as u64).is_nan() => {
+      f64::from_bits(*b as u64).is_nan()
+    }
+    (_, runtime::Value::U64(a), runtime::Value::U64(b)) => a == b,
+    (_, a, b) => a.to_single() == b.to_single(),
+  }
+}
+
+/// `Arrays.equals(a, b)`: both null, or arrays with equal elements
+fn equals_(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let a = try_invoke!(arg(&params, 0, &cs));
+  let b = try_invoke!(arg(&params, 1, &cs));
+  if a.is_null() || b.is_null() || a.same(b) {
+    return InvokeResult::Ok(runtime::Value::U32((a.same(b)) as i32));
+  }
+
+  let (type_, a) = unwrap_or_error!(contents(a), cs);
+  let (_, b) = unwrap_or_error!(contents(b), cs);
+  if a.len() != b.len() {
+    return InvokeResult::Ok(runtime::Value::U32(0));
+  }
+  for (a, b) in a.iter().zip(b.iter()) {
+    let equal = match type_.starts_with('L') || type_.starts_with('[') {
+      true => try_invoke!(equals(a, b, &cs, env)),
+      false => equal_primitives(&type_, a, b),
+    };
+    if !equal {
+      return InvokeResult::Ok(runtime::Value::U32(0));
+    }
+  }
+
+  InvokeResult::Ok(runtime::Value::U32(1))
+}
+
+/// The hash of a primitive element, the same as the `hashCode()` of its box
+fn primitive_hash(type_: &str, v: &runtime::Value) -> i32 {
+  let fold = |l: i64| (l ^ ((l as u64) >> 32) as i64) as i32;
+  match (type_, v) {
+    ("Z", v) if v.to_single() != 0 => 1231,
+    ("Z", _) => 1237,
+    ("F", v) if f32::from_bits(v.to_single() as u32).is_nan() => f32::NAN.to_bits() as i32,
+    ("D", runtime::Value::U64(d)) if f64::from_bits(*d as u64).is_nan() => {
+      fold(f64::NAN.to_bits() as i64)
+    }
+    (_, runtime::Value::U64(l)) => fold(*l),
+    (_, v) => v.to_single(),
+  }
+}
+
+/// `Arrays.hashCode(a)`: `31 * h + hash` over the elements starting at 1, or 0 for null
+fn hash_code_(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let a = try_invoke!(arg(&params, 0, &cs));
+  if a.is_null() {
+    return InvokeResult::Ok(runtime::Value::U32(0));
+  }
+
+  let (type_, items) = unwrap_or_error!(contents(a), cs);
+  let mut h = 1i32;
+  for item in items.iter() {
+    let hash = match type_.starts_with('L') || type_.starts_with('[') {
+      true => try_invoke!(hash_code(item, &cs, env)),
+      false => primitive_hash(&type_, item),
+    };
+    h = h.wrapping_mul(31).wrapping_add(hash);
+  }
+
+  InvokeResult::Ok(runtime::Value::U32(h))
+}
+
+/// The array of an `Arrays$ArrayList`, and an index of it checked like `Objects.checkIndex`
+fn list_element(
+  params: &[runtime::Value],
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<(runtime::Value, usize), InvokeResult> {
+  let this = non_null(params, 0, cs, env)?;
+  let index = int_arg(params, 1, cs)?;
+  let a = this
+    .instance()
+    .map(|i| i.lock().unwrap().get_field(ARRAY).cloned())
+    .map_err(|e| InvokeResult::runtime(e, cs.clone()))?
+    .ok_or_else(|| InvokeResult::runtime(RuntimeError::FieldOutOfBounds(ARRAY), cs.clone()))?;
+
+  let len = a
+    .array()
+    .map_err(|e| InvokeResult::runtime(e, cs.clone()))?
+    .lock()
+    .unwrap()
+    .len();
+  if index < 0 || index as usize >= len {
+    let message = format!("Index {} out of bounds for length {}", index, len);
+    return Err(exception(
+      "Ljava/lang/ArrayIndexOutOfBoundsException;",
+      Some(&message),
+      cs,
+      env,
+    ));
+  }
+
+  Ok((a, index as usize))
+}
+
+fn list_get(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let (a, index) = try_invoke!(list_element(&params, &cs, env));
+  let a = unwrap_or_error!(a.array(), cs).lock().unwrap();
+  InvokeResult::Ok(a.get(index).cloned().unwrap_or(runtime::Value::Void))
+}
+
+/// `set(index, e)`, which writes to the array. Returns the element it replaced.
+fn list_set(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let (a, index) = try_invoke!(list_element(&params, &cs, env));
+  let item = try_invoke!(arg(&params, 2, &cs)).clone();
+  let mut a = unwrap_or_error!(a.array(), cs).lock().unwrap();
+  let old = a.get(index).cloned().unwrap_or(runtime::Value::Void);
+  a.set(index, item);
+  InvokeResult::Ok(old)
+}
+
+fn list_size(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(non_null(&params, 0, &cs, env));
+  let a = unwrap_or_error!(
+    this
+      .instance()
+      .map(|i| i.lock().unwrap().get_field(ARRAY).cloned()),
+    cs
+  );
+  let len = match a.as_ref().map(|a| a.array()) {
+    Some(Ok(a)) => a.lock().unwrap().len(),
+    _ => return InvokeResult::runtime(RuntimeError::FieldOutOfBounds(ARRAY), cs),
+  };
+
+  InvokeResult::Ok(runtime::Value::U32(len as i32))
+}
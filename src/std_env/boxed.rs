@@ -0,0 +1,1138 @@
+//! The wrapper classes of the primitive types (`Integer`, `Long`, `Boolean`, ...), used by
+//! autoboxing. Small values are cached like in java, so `==` on them behaves the same.
+
+use std::rc::Rc;
+
+use super::number::{double_to_string, float_to_string};
+use super::{runtime, CallStack, InvokeResult, Module, Native, RuntimeError};
+use crate::codegen::runtime::JavaString;
+
+// Slot of the `value` field of every wrapper
+const VALUE: usize = 0;
+
+/// The primitive type of a wrapper class
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+  Boolean,
+  Char,
+  Byte,
+  Short,
+  Int,
+  Long,
+  Float,
+  Double,
+}
+
+impl Kind {
+  const ALL: [Kind; 8] = [
+    Kind::Boolean,
+    Kind::Char,
+    Kind::Byte,
+    Kind::Short,
+    Kind::Int,
+    Kind::Long,
+    Kind::Float,
+    Kind::Double,
+  ];
+
+  /// The simple name of the wrapper class
+  fn name(self) -> &'static str {
+    match self {
+      Kind::Boolean => "Boolean",
+      Kind::Char => "Character",
+      Kind::Byte => "Byte",
+      Kind::Short => "Short",
+      Kind::Int => "Integer",
+      Kind::Long => "Long",
+      Kind::Float => "Float",
+      Kind::Double => "Double",
+    }
+  }
+
+  fn class(self) -> String {
+    format!("Ljava/lang/{};", self.name())
+  }
+
+  fn of(class: &str) -> Option<Kind> {
+    Kind::ALL.iter().copied().find(|k| k.class() == class)
+  }
+
+  /// The type descriptor of the primitive type
+  fn descriptor(self) -> &'static str {
+    match self {
+      Kind::Boolean => "Z",
+      Kind::Char => "C",
+      Kind::Byte => "B",
+      Kind::Short => "S",
+      Kind::Int => "I",
+      Kind::Long => "J",
+      Kind::Float => "F",
+      Kind::Double => "D",
+    }
+  }
+
+  fn of_primitive(descriptor: &str) -> Option<Kind> {
+    Kind::ALL
+      .iter()
+      .copied()
+      .find(|k| k.descriptor() == descriptor)
+  }
+
+  /// Prefix of the functions of the wrapper class
+  fn functions(self) -> String {
+    format!("CLASS_java__lang__{}__", self.name())
+  }
+
+  /// Longs and doubles take two registers, so their arguments are followed by another one
+  fn is_wide(self) -> bool {
+    self == Kind::Long || self == Kind::Double
+  }
+
+  fn is_floating(self) -> bool {
+    self == Kind::Float || self == Kind::Double
+  }
+
+  /// The range of cached values, as required by `valueOf`
+  fn cache_range(self) -> Option<(i64, i64)> {
+    match self {
+      Kind::Boolean => Some((0, 1)),
+      Kind::Char => Some((0, 127)),
+      Kind::Byte | Kind::Short | Kind::Int | Kind::Long => Some((-128, 127)),
+      Kind::Float | Kind::Double => None,
+    }
+  }
+
+  /// The name of the static holding the cache, an `Object[]` like the `IntegerCache` of java
+  fn cache(self) -> String {
+    format!("{}cache", self.functions())
+  }
+}
+
+pub fn add_functions(m: &mut Module) {
+  // The natives get the primitive type from their class, since the arguments don't tell (a
+  // float and an int are both a `U32`)
+  for kind in Kind::ALL.iter().copied() {
+    let (primitive, class) = (kind.descriptor(), kind.class());
+    let method = |descriptor: &str| format!("java/lang/{}.{}", kind.name(), descriptor);
+
+    m.natives()
+      .untyped_static_method(
+        &method(&format!("valueOf({}){}", primitive, class)),
+        move |p, cs, env| value_of(kind, p, cs, env),
+      )
+      .untyped_method(
+        &method("toString()Ljava/lang/String;"),
+        move |p, cs, env| to_string(kind, p, cs, env),
+      )
+      .untyped_static_method(
+        &method(&format!("toString({})Ljava/lang/String;", primitive)),
+        move |p, cs, env| to_string(kind, p, cs, env),
+      )
+      .untyped_method(&method("hashCode()I"), move |p, cs, env| {
+        hash_code(kind, p, cs, env)
+      })
+      .untyped_static_method(
+        &method(&format!("hashCode({})I", primitive)),
+        move |p, cs, env| hash_code(kind, p, cs, env),
+      )
+      .untyped_static_method(
+        &method(&format!("compare({}{})I", primitive, primitive)),
+        move |p, cs, env| compare(kind, p, cs, env),
+      )
+      .untyped_method(&method("equals(Ljava/lang/Object;)Z"), equals)
+      .untyped_method(&method(&format!("compareTo({})I", class)), compare_to)
+      // The bridge of `Comparable`
+      .untyped_method(&method("compareTo(Ljava/lang/Object;)I"), compare_to);
+
+    if kind != Kind::Char {
+      m.natives().untyped_static_method(
+        &method(&format!("valueOf(Ljava/lang/String;){}", class)),
+        move |p, cs, env| value_of_string(kind, p, cs, env),
+      );
+    }
+    // The integral types also parse in other radixes
+    if !kind.is_floating() && kind != Kind::Boolean && kind != Kind::Char {
+      m.natives()
+        .untyped_static_method(
+          &method(&format!("valueOf(Ljava/lang/String;I){}", class)),
+          move |p, cs, env| value_of_string(kind, p, cs, env),
+        )
+        .untyped_static_method(
+          &method(&format!(
+            "parse{}(Ljava/lang/String;I){}",
+            parse_name(kind),
+            primitive
+          )),
+          move |p, cs, env| parse(kind, p, cs, env),
+        );
+    }
+    if kind != Kind::Char {
+      m.natives().untyped_static_method(
+        &method(&format!(
+          "parse{}(Ljava/lang/String;){}",
+          parse_name(kind),
+          primitive
+        )),
+        move |p, cs, env| parse(kind, p, cs, env),
+      );
+    }
+  }
+
+  // `Number` and its subclasses
+  let values: &[(&str, Kind)] = &[
+    ("byteValue()B", Kind::Byte),
+    ("shortValue()S", Kind::Short),
+    ("intValue()I", Kind::Int),
+    ("longValue()J", Kind::Long),
+    ("floatValue()F", Kind::Float),
+    ("doubleValue()D", Kind::Double),
+  ];
+  for (descriptor, to) in values.iter().copied() {
+    m.natives().untyped_method(
+      &format!("java/lang/Number.{}", descriptor),
+      move |p, cs, env| value(to, p, cs, env),
+    );
+  }
+  m.natives()
+    .untyped_method("java/lang/Boolean.booleanValue()Z", |p, cs, env| {
+      value(Kind::Boolean, p, cs, env)
+    })
+    .untyped_method("java/lang/Character.charValue()C", |p, cs, env| {
+      value(Kind::Char, p, cs, env)
+    });
+
+  let statics: &[(&str, Native)] = &[
+    ("Integer.sum(II)I", |p, cs, _env| sum(Kind::Int, p, cs)),
+    ("Integer.toHexString(I)Ljava/lang/String;", |p, cs, _env| {
+      to_unsigned_string(Kind::Int, 4, p, cs)
+    }),
+    (
+      "Integer.toOctalString(I)Ljava/lang/String;",
+      |p, cs, _env| to_unsigned_string(Kind::Int, 3, p, cs),
+    ),
+    (
+      "Integer.toBinaryString(I)Ljava/lang/String;",
+      |p, cs, _env| to_unsigned_string(Kind::Int, 1, p, cs),
+    ),
+    ("Integer.toString(II)Ljava/lang/String;", |p, cs, env| {
+      to_string(Kind::Int, p, cs, env)
+    }),
+    ("Long.sum(JJ)J", |p, cs, _env| sum(Kind::Long, p, cs)),
+    ("Long.toHexString(J)Ljava/lang/String;", |p, cs, _env| {
+      to_unsigned_string(Kind::Long, 4, p, cs)
+    }),
+    ("Long.toOctalString(J)Ljava/lang/String;", |p, cs, _env| {
+      to_unsigned_string(Kind::Long, 3, p, cs)
+    }),
+    ("Long.toBinaryString(J)Ljava/lang/String;", |p, cs, _env| {
+      to_unsigned_string(Kind::Long, 1, p, cs)
+    }),
+    ("Long.toString(JI)Ljava/lang/String;", |p, cs, env| {
+      to_string(Kind::Long, p, cs, env)
+    }),
+    ("Float.isNaN(F)Z", |p, cs, _env| {
+      test_floating(Kind::Float, f64::is_nan, p, cs)
+    }),
+    ("Float.isInfinite(F)Z", |p, cs, _env| {
+      test_floating(Kind::Float, f64::is_infinite, p, cs)
+    }),
+    ("Float.floatToIntBits(F)I", float_to_int_bits),
+    ("Float.floatToRawIntBits(F)I", identity),
+    ("Float.intBitsToFloat(I)F", identity),
+    ("Double.isNaN(D)Z", |p, cs, _env| {
+      test_floating(Kind::Double, f64::is_nan, p, cs)
+    }),
+    ("Double.isInfinite(D)Z", |p, cs, _env| {
+      test_floating(Kind::Double, f64::is_infinite, p, cs)
+    }),
+    ("Double.doubleToLongBits(D)J", double_to_long_bits),
+    ("Double.doubleToRawLongBits(D)J", identity),
+    ("Double.longBitsToDouble(J)D", identity),
+    // The tests of `Character` take a char or a code point
+    ("Character.isDigit(C)Z", |p, cs, _env| {
+      character_test(is_java_digit, p, cs)
+    }),
+    ("Character.isDigit(I)Z", |p, cs, _env| {
+      character_test(is_java_digit, p, cs)
+    }),
+    ("Character.isLetter(C)Z", |p, cs, _env| {
+      character_test(is_java_letter, p, cs)
+    }),
+    ("Character.isLetter(I)Z", |p, cs, _env| {
+      character_test(is_java_letter, p, cs)
+    }),
+    ("Character.isLetterOrDigit(C)Z", |p, cs, _env| {
+      character_test(|c| is_java_letter(c) || is_java_digit(c), p, cs)
+    }),
+    ("Character.isLetterOrDigit(I)Z", |p, cs, _env| {
+      character_test(|c| is_java_letter(c) || is_java_digit(c), p, cs)
+    }),
+    ("Character.isAlphabetic(I)Z", |p, cs, _env| {
+      character_test(char::is_alphabetic, p, cs)
+    }),
+    ("Character.isWhitespace(C)Z", |p, cs, _env| {
+      character_test(is_java_whitespace, p, cs)
+    }),
+    ("Character.isWhitespace(I)Z", |p, cs, _env| {
+      character_test(is_java_whitespace, p, cs)
+    }),
+    ("Character.isUpperCase(C)Z", |p, cs, _env| {
+      character_test(char::is_uppercase, p, cs)
+    }),
+    ("Character.isUpperCase(I)Z", |p, cs, _env| {
+      character_test(char::is_uppercase, p, cs)
+    }),
+    ("Character.isLowerCase(C)Z", |p, cs, _env| {
+      character_test(char::is_lowercase, p, cs)
+    }),
+    ("Character.isLowerCase(I)Z", |p, cs, _env| {
+      character_test(char::is_lowercase, p, cs)
+    }),
+    ("Character.toUpperCase(C)C", |p, cs, _env| {
+      map_char(|c| single(c.to_uppercase()), p, cs)
+    }),
+    ("Character.toUpperCase(I)I", |p, cs, _env| {
+      map_char(|c| single(c.to_uppercase()), p, cs)
+    }),
+    ("Character.toLowerCase(C)C", |p, cs, _env| {
+      map_char(|c| single(c.to_lowercase()), p, cs)
+    }),
+    ("Character.toLowerCase(I)I", |p, cs, _env| {
+      map_char(|c| single(c.to_lowercase()), p, cs)
+    }),
+  ];
+  for (descriptor, f) in statics {
+    m.natives()
+      .untyped_static_method(&format!("java/lang/{}", descriptor), *f);
+  }
+
+  // `isNaN()` and `isInfinite()` of a box
+  m.natives()
+    .untyped_method("java/lang/Float.isNaN()Z", |p, cs, _env| {
+      test_floating(Kind::Float, f64::is_nan, p, cs)
+    })
+    .untyped_method("java/lang/Float.isInfinite()Z", |p, cs, _env| {
+      test_floating(Kind::Float, f64::is_infinite, p, cs)
+    })
+    .untyped_method("java/lang/Double.isNaN()Z", |p, cs, _env| {
+      test_floating(Kind::Double, f64::is_nan, p, cs)
+    })
+    .untyped_method("java/lang/Double.isInfinite()Z", |p, cs, _env| {
+      test_floating(Kind::Double, f64::is_infinite, p, cs)
+    });
+
+  add_statics(m);
+}
+
+/// The name of the `parseX` method of a wrapper, like `Int` for `parseInt`
+fn parse_name(kind: Kind) -> &'static str {
+  match kind {
+    Kind::Int => "Int",
+    _ => kind.name(),
+  }
+}
+
+/// The constants of the wrapper classes, and the caches of `valueOf`
+fn add_statics(m: &mut Module) {
+  let constants = [
+    (Kind::Char, "MIN_VALUE", runtime::Value::U32(0)),
+    (
+      Kind::Char,
+      "MAX_VALUE",
+      runtime::Value::U32(u16::MAX as i32),
+    ),
+    (Kind::Byte, "MIN_VALUE", runtime::Value::U32(i8::MIN as i32)),
+    (Kind::Byte, "MAX_VALUE", runtime::Value::U32(i8::MAX as i32)),
+    (
+      Kind::Short,
+      "MIN_VALUE",
+      runtime::Value::U32(i16::MIN as i32),
+    ),
+    (
+      Kind::Short,
+      "MAX_VALUE",
+      runtime::Value::U32(i16::MAX as i32),
+    ),
+    (Kind::Int, "MIN_VALUE", runtime::Value::U32(i32::MIN)),
+    (Kind::Int, "MAX_VALUE", runtime::Value::U32(i32::MAX)),
+    (Kind::Long, "MIN_VALUE", runtime::Value::U64(i64::MIN)),
+    (Kind::Long, "MAX_VALUE", runtime::Value::U64(i64::MAX)),
+    (Kind::Float, "MIN_VALUE", float(f32::from_bits(1))),
+    (Kind::Float, "MAX_VALUE", float(f32::MAX)),
+    (Kind::Float, "POSITIVE_INFINITY", float(f32::INFINITY)),
+    (Kind::Float, "NEGATIVE_INFINITY", float(f32::NEG_INFINITY)),
+    (Kind::Float, "NaN", float(f32::NAN)),
+    (Kind::Double, "MIN_VALUE", double(f64::from_bits(1))),
+    (Kind::Double, "MAX_VALUE", double(f64::MAX)),
+    (Kind::Double, "POSITIVE_INFINITY", double(f64::INFINITY)),
+    (Kind::Double, "NEGATIVE_INFINITY", double(f64::NEG_INFINITY)),
+    (Kind::Double, "NaN", double(f64::NAN)),
+  ];
+  for (kind, name, value) in constants.iter() {
+    m.set_static(format!("{}{}", kind.functions(), name), value.clone());
+  }
+
+  for kind in Kind::ALL.iter() {
+    if let Some((low, high)) = kind.cache_range() {
+      let values = (low..=high)
+        .map(|v| {
+          new_box(
+            *kind,
+            primitive(Kind::Long, *kind, runtime::Value::U64(v)),
+            m,
+          )
+        })
+        .collect();
+      m.set_static(
+        kind.cache(),
+        runtime::Value::from_array("[Ljava/lang/Object;".to_owned(), values),
+      );
+    }
+  }
+
+  // `Boolean.TRUE` and `Boolean.FALSE` are the cached values
+  for (name, b) in &[("FALSE", 0), ("TRUE", 1)] {
+    let cached = cached(Kind::Boolean, *b, m).unwrap();
+    m.set_static(format!("{}{}", Kind::Boolean.functions(), name), cached);
+  }
+}
+
+fn float(f: f32) -> runtime::Value {
+  runtime::Value::U32(f.to_bits() as i32)
+}
+
+fn double(d: f64) -> runtime::Value {
+  runtime::Value::U64(d.to_bits() as i64)
+}
+
+/// A primitive value as an integer, with the `(long)` cast of java for floats
+fn as_i64(kind: Kind, v: &runtime::Value) -> i64 {
+  match (kind, v) {
+    (Kind::Float, v) => f32::from_bits(v.to_single() as u32) as i64,
+    (Kind::Double, runtime::Value::U64(d)) => f64::from_bits(*d as u64) as i64,
+    (_, runtime::Value::U64(l)) => *l,
+    (_, v) => v.to_single() as i64,
+  }
+}
+
+fn as_f64(kind: Kind, v: &runtime::Value) -> f64 {
+  match (kind, v) {
+    (Kind::Float, v) => f32::from_bits(v.to_single() as u32) as f64,
+    (Kind::Double, runtime::Value::U64(d)) => f64::from_bits(*d as u64),
+    (kind, v) => as_i64(kind, v) as f64,
+  }
+}
+
+/// Convert a primitive value to another primitive type, like a cast in java
+fn primitive(from: Kind, to: Kind, v: runtime::Value) -> runtime::Value {
+  if from == to {
+    return v;
+  }
+
+  match to {
+    Kind::Boolean => runtime::Value::U32((as_i64(from, &v) != 0) as i32),
+    Kind::Char => runtime::Value::U32(as_i64(from, &v) as u16 as i32),
+    Kind::Byte => runtime::Value::U32(as_i64(from, &v) as i8 as i32),
+    Kind::Short => runtime::Value::U32(as_i64(from, &v) as i16 as i32),
+    // Floats are cast straight to int, so large values saturate at the int range
+    Kind::Int if from.is_floating() => runtime::Value::U32(as_f64(from, &v) as i32),
+    Kind::Int => runtime::Value::U32(as_i64(from, &v) as i32),
+    Kind::Long => runtime::Value::U64(as_i64(from, &v)),
+    Kind::Float if from == Kind::Long => float(as_i64(from, &v) as f32),
+    Kind::Float => float(as_f64(from, &v) as f32),
+    Kind::Double => double(as_f64(from, &v)),
+  }
+}
+
+/// The text of a primitive value, like `String.valueOf`
+fn text(kind: Kind, v: &runtime::Value) -> String {
+  match kind {
+    Kind::Boolean => (v.to_single() != 0).to_string(),
+    Kind::Char => String::from_utf16_lossy(&[v.to_single() as u16]),
+    Kind::Float => float_to_string(as_f64(kind, v) as f32),
+    Kind::Double => double_to_string(as_f64(kind, v)),
+    _ => as_i64(kind, v).to_string(),
+  }
+}
+
+/// The cached box of a value, if it is in the range of the cache
+fn cached(kind: Kind, v: i64, env: &Module) -> Option<runtime::Value> {
+  let (low, high) = kind.cache_range()?;
+  if v < low || v > high {
+    return None;
+  }
+
+  let cache = env.get_static(&kind.cache())?;
+  let cache = cache.array().ok()?.lock().unwrap();
+  cache.get((v - low) as usize).cloned()
+}
+
+fn new_box(kind: Kind, v: runtime::Value, env: &Module) -> runtime::Value {
+  let mut o = env.new_instance(&kind.class());
+  o.set_field(VALUE, v).unwrap();
+  runtime::Value::from_instance(o)
+}
+
+/// `valueOf` of a primitive value: the cached box or a new one
+fn box_value(kind: Kind, v: runtime::Value, env: &Module) -> runtime::Value {
+  let cached = match kind.is_floating() {
+    true => None,
+    false => cached(kind, as_i64(kind, &v), env),
+  };
+
+  cached.unwrap_or_else(|| new_box(kind, v, env))
+}
+
+/// The primitive type and value of a box, throwing a `NullPointerException` for null
+fn unbox(
+  v: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<(Kind, runtime::Value), InvokeResult> {
+  if v.is_null() {
+    return Err(InvokeResult::Exception(
+      env.new_exception("Ljava/lang/NullPointerException;", None, cs),
+      cs.clone(),
+    ));
+  }
+
+  let instance = v
+    .instance()
+    .map_err(|e| InvokeResult::runtime(e, cs.clone()))?
+    .lock()
+    .unwrap();
+  let kind = Kind::of(instance.get_class_type()).ok_or_else(|| {
+    InvokeResult::runtime(
+      RuntimeError::CastError(format!(
+        "{} as a primitive wrapper",
+        instance.get_class_type()
+      )),
+      cs.clone(),
+    )
+  })?;
+
+  match instance.get_field(VALUE) {
+    None => Err(InvokeResult::runtime(
+      RuntimeError::FieldOutOfBounds(VALUE),
+      cs.clone(),
+    )),
+    Some(v) => Ok((kind, v.clone())),
+  }
+}
+
+/// Convert a value from one type to another, like method handles adapt the arguments and
+/// result of their method: primitives are boxed or widened, and wrappers unboxed, throwing a
+/// `NullPointerException` for null. References stay the same.
+pub fn convert(
+  from: &str,
+  to: &str,
+  v: runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<runtime::Value, InvokeResult> {
+  match (Kind::of_primitive(from), Kind::of_primitive(to)) {
+    (Some(from), Some(to)) => Ok(primitive(from, to, v)),
+    (Some(from), None) => Ok(box_value(from, v, env)),
+    (None, Some(to)) => {
+      let (kind, v) = unbox(&v, cs, env)?;
+      Ok(primitive(kind, to, v))
+    }
+    (None, None) => Ok(v),
+  }
+}
+
+/// Whether a native was called on a wrapper object (`this`) rather than statically
+fn is_instance(params: &[runtime::Value], kind: Kind) -> bool {
+  params
+    .first()
+    .and_then(|p| p.get_type())
+    .is_some_and(|t| t == kind.class())
+}
+
+fn arg(params: &[runtime::Value], idx: usize) -> Result<&runtime::Value, RuntimeError> {
+  params
+    .get(idx)
+    .ok_or(RuntimeError::WrongNumberOfParameters(idx + 1, params.len()))
+}
+
+/// The primitive value of the receiver, or of the first argument of a static method
+fn primitive_arg(
+  kind: Kind,
+  params: &[runtime::Value],
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<runtime::Value, InvokeResult> {
+  if is_instance(params, kind) {
+    return Ok(unbox(&params[0], cs, env)?.1);
+  }
+
+  arg(params, 0)
+    .cloned()
+    .map_err(|e| InvokeResult::runtime(e, cs.clone()))
+}
+
+/// `valueOf(primitive)`
+fn value_of(
+  kind: Kind,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  match params.first() {
+    None => InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(v) => InvokeResult::Ok(box_value(kind, v.clone(), env)),
+  }
+}
+
+/// `valueOf(String)` and `valueOf(String, radix)`: the boxed value of `parseX`, so null throws
+/// like it (except for `Boolean`, where it is false)
+fn value_of_string(
+  kind: Kind,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  match parse(kind, params, cs, env) {
+    InvokeResult::Ok(v) => InvokeResult::Ok(box_value(kind, v, env)),
+    r => r,
+  }
+}
+
+/// `xValue()`: the value of a box, converted to a primitive type
+fn value(to: Kind, params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = match params.first() {
+    None => return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(t) => t,
+  };
+
+  match unbox(this, &cs, env) {
+    Err(r) => r,
+    Ok((kind, v)) => InvokeResult::Ok(primitive(kind, to, v)),
+  }
+}
+
+/// `parseX(String)` and `parseX(String, radix)`
+fn parse(kind: Kind, params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let s = match params.first() {
+    None => return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(s) if s.is_null() => None,
+    Some(s) => match s.java_string() {
+      Err(e) => return InvokeResult::runtime(e, cs),
+      Ok(s) => Some(s),
+    },
+  };
+  let radix = params.get(1).map_or(10, |r| r.to_single());
+
+  match parse_primitive(kind, s, radix, &cs, env) {
+    Err(r) => r,
+    Ok(v) => InvokeResult::Ok(v),
+  }
+}
+
+/// Parse a primitive value, throwing a `NumberFormatException` (or a `NullPointerException`
+/// for floats) like java
+fn parse_primitive(
+  kind: Kind,
+  s: Option<&JavaString>,
+  radix: i32,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<runtime::Value, InvokeResult> {
+  let number_format = |message: String| {
+    InvokeResult::Exception(
+      env.new_exception("Ljava/lang/NumberFormatException;", Some(&message), cs),
+      cs.clone(),
+    )
+  };
+
+  let s = match s {
+    // Only false, like `"false"`
+    None if kind == Kind::Boolean => return Ok(runtime::Value::U32(0)),
+    None if kind.is_floating() => {
+      return Err(InvokeResult::Exception(
+        env.new_exception("Ljava/lang/NullPointerException;", None, cs),
+        cs.clone(),
+      ))
+    }
+    None => return Err(number_format("Cannot parse null string: null".to_owned())),
+    Some(s) => s.to_string(),
+  };
+
+  match kind {
+    Kind::Boolean => Ok(runtime::Value::U32(s.eq_ignore_ascii_case("true") as i32)),
+    Kind::Float | Kind::Double => match parse_floating(&s) {
+      None if s.trim().is_empty() => Err(number_format("empty String".to_owned())),
+      None => Err(number_format(format!("For input string: \"{}\"", s))),
+      Some(d) if kind == Kind::Float => Ok(float(d as f32)),
+      Some(d) => Ok(double(d)),
+    },
+    _ => {
+      let for_input = || match radix {
+        10 => format!("For input string: \"{}\"", s),
+        _ => format!("For input string: \"{}\" under radix {}", s, radix),
+      };
+      if !(2..=36).contains(&radix) {
+        return Err(number_format(format!("radix {} out of range", radix)));
+      }
+
+      // Rust allows a leading `+` too, but no other signs or spaces
+      let value = match s.chars().next() {
+        Some(c) if c == '+' || c == '-' || c.is_digit(radix as u32) => {
+          i64::from_str_radix(&s, radix as u32).map_err(|_| number_format(for_input()))?
+        }
+        _ => return Err(number_format(for_input())),
+      };
+
+      let (low, high) = match kind {
+        Kind::Byte => (i8::MIN as i64, i8::MAX as i64),
+        Kind::Short => (i16::MIN as i64, i16::MAX as i64),
+        Kind::Int => (i32::MIN as i64, i32::MAX as i64),
+        _ => (i64::MIN, i64::MAX),
+      };
+      match kind {
+        Kind::Int | Kind::Long if value < low || value > high => Err(number_format(for_input())),
+        _ if value < low || value > high => Err(number_format(format!(
+          "Value out of range. Value:\"{}\" Radix:{}",
+          s, radix
+        ))),
+        _ => Ok(primitive(Kind::Long, kind, runtime::Value::U64(value))),
+      }
+    }
+  }
+}
+
+/// `Double.parseDouble`: decimal numbers with an optional exponent and type suffix, `NaN` and
+/// `Infinity`. Leading and trailing whitespace is ignored.
+//TODO Hexadecimal floating point literals (`0x1.8p1`)
+fn parse_floating(s: &str) -> Option<f64> {
+  let s = s.trim_matches(|c: char| c <= ' ');
+  let (sign, unsigned) = match s.chars().next()? {
+    '-' => (-1.0, &s[1..]),
+    '+' => (1.0, &s[1..]),
+    _ => (1.0, s),
+  };
+
+  match unsigned {
+    "NaN" => return Some(f64::NAN),
+    "Infinity" => return Some(sign * f64::INFINITY),
+    _ => {}
+  }
+
+  let number = unsigned.trim_end_matches(|c| "fFdD".contains(c));
+  if number.len() + 1 < unsigned.len()
+    || !number.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+    || !number
+      .chars()
+      .all(|c| c.is_ascii_digit() || ".eE+-".contains(c))
+  {
+    return None;
+  }
+
+  number.parse::<f64>().ok().map(|d| sign * d)
+}
+
+/// `toString()` of a box, or the static `toString(primitive)` (and `toString(int, radix)`)
+fn to_string(
+  kind: Kind,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let v = match primitive_arg(kind, &params, &cs, env) {
+    Err(r) => return r,
+    Ok(v) => v,
+  };
+
+  // The radix comes after the second register of a long
+  let radix = match (is_instance(&params, kind), kind) {
+    (false, Kind::Int) => params.get(1),
+    (false, Kind::Long) => params.get(2),
+    _ => None,
+  };
+  let s = match radix.map(|r| r.to_single()) {
+    Some(radix) if radix != 10 && (2..=36).contains(&radix) => {
+      let v = as_i64(kind, &v);
+      let digits = radix_digits(v.unsigned_abs(), radix as u32);
+      if v < 0 {
+        format!("-{}", digits)
+      } else {
+        digits
+      }
+    }
+    _ => text(kind, &v),
+  };
+
+  InvokeResult::Ok(runtime::Value::new_string(&s))
+}
+
+/// The digits of a number in a radix, lower case like java
+fn radix_digits(mut v: u64, radix: u32) -> String {
+  let mut digits = Vec::new();
+  loop {
+    digits.push(std::char::from_digit((v % radix as u64) as u32, radix).unwrap());
+    v /= radix as u64;
+    if v == 0 {
+      break;
+    }
+  }
+
+  digits.iter().rev().collect()
+}
+
+/// `toHexString`, `toOctalString` and `toBinaryString`: the unsigned value, `shift` bits per digit
+fn to_unsigned_string(
+  kind: Kind,
+  shift: u32,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+) -> InvokeResult {
+  let v = match arg(&params, 0) {
+    Err(e) => return InvokeResult::runtime(e, cs),
+    Ok(v) => as_i64(kind, v),
+  };
+  let unsigned = match kind {
+    Kind::Int => v as u32 as u64,
+    _ => v as u64,
+  };
+
+  InvokeResult::Ok(runtime::Value::new_string(&radix_digits(
+    unsigned,
+    1 << shift,
+  )))
+}
+
+/// The bits of a float or double, with all NaNs the same, like `floatToIntBits`
+fn canonical_bits(kind: Kind, v: &runtime::Value) -> i64 {
+  let d = as_f64(kind, v);
+  match kind {
+    Kind::Float if d.is_nan() => f32::NAN.to_bits() as i32 as i64,
+    Kind::Float => (d as f32).to_bits() as i32 as i64,
+    _ if d.is_nan() => f64::NAN.to_bits() as i64,
+    _ => d.to_bits() as i64,
+  }
+}
+
+/// `hashCode()` of a box, or the static `hashCode(primitive)`
+fn hash_code(
+  kind: Kind,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let v = match primitive_arg(kind, &params, &cs, env) {
+    Err(r) => return r,
+    Ok(v) => v,
+  };
+
+  let fold = |l: i64| (l ^ ((l as u64) >> 32) as i64) as i32;
+  let hash = match kind {
+    Kind::Boolean if v.to_single() != 0 => 1231,
+    Kind::Boolean => 1237,
+    Kind::Long => fold(as_i64(kind, &v)),
+    Kind::Float => canonical_bits(kind, &v) as i32,
+    Kind::Double => fold(canonical_bits(kind, &v)),
+    _ => as_i64(kind, &v) as i32,
+  };
+
+  InvokeResult::Ok(runtime::Value::U32(hash))
+}
+
+/// Compare two primitive values of the same type, like `Integer.compare` or `Double.compare`
+/// (where `-0.0` is less than `0.0` and `NaN` is the greatest)
+fn compare_values(kind: Kind, a: &runtime::Value, b: &runtime::Value) -> i32 {
+  let ordering = match kind {
+    Kind::Float | Kind::Double => {
+      let (x, y) = (as_f64(kind, a), as_f64(kind, b));
+      if x < y {
+        std::cmp::Ordering::Less
+      } else if x > y {
+        std::cmp::Ordering::Greater
+      } else {
+        canonical_bits(kind, a).cmp(&canonical_bits(kind, b))
+      }
+    }
+    _ => as_i64(kind, a).cmp(&as_i64(kind, b)),
+  };
+
+  ordering as i32
+}
+
+/// The static `compare(x, y)`
+fn compare(
+  kind: Kind,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  let second = if kind.is_wide() { 2 } else { 1 };
+  match (arg(&params, 0), arg(&params, second)) {
+    (Ok(a), Ok(b)) => InvokeResult::Ok(runtime::Value::U32(compare_values(kind, a, b))),
+    (Err(e), _) | (_, Err(e)) => InvokeResult::runtime(e, cs),
+  }
+}
+
+/// `sum(a, b)`, which overflows like `+`
+fn sum(kind: Kind, params: Vec<runtime::Value>, cs: Rc<CallStack>) -> InvokeResult {
+  let second = if kind.is_wide() { 2 } else { 1 };
+  match (arg(&params, 0), arg(&params, second)) {
+    (Ok(a), Ok(b)) => {
+      let sum = as_i64(kind, a).wrapping_add(as_i64(kind, b));
+      InvokeResult::Ok(primitive(Kind::Long, kind, runtime::Value::U64(sum)))
+    }
+    (Err(e), _) | (_, Err(e)) => InvokeResult::runtime(e, cs),
+  }
+}
+
+/// `compareTo(other)`, throwing a `NullPointerException` for null
+fn compare_to(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  if params.len() < 2 {
+    return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(2, params.len()), cs);
+  }
+
+  match (unbox(&params[0], &cs, env), unbox(&params[1], &cs, env)) {
+    (Ok((kind, a)), Ok((_, b))) => {
+      InvokeResult::Ok(runtime::Value::U32(compare_values(kind, &a, &b)))
+    }
+    (Err(r), _) | (_, Err(r)) => r,
+  }
+}
+
+/// `equals(Object)`: a box of the same type and value. Floats and doubles are compared by
+/// their bits, so `NaN` equals itself and `0.0` doesn't equal `-0.0`.
+fn equals(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  if params.len() < 2 {
+    return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(2, params.len()), cs);
+  }
+
+  let (kind, a) = match unbox(&params[0], &cs, env) {
+    Err(r) => return r,
+    Ok(v) => v,
+  };
+  let other = &params[1];
+  if other.get_type() != Some(kind.class()) {
+    return InvokeResult::Ok(runtime::Value::U32(0));
+  }
+  let b = match unbox(other, &cs, env) {
+    Err(r) => return r,
+    Ok((_, b)) => b,
+  };
+
+  let equal = match kind {
+    Kind::Float | Kind::Double => canonical_bits(kind, &a) == canonical_bits(kind, &b),
+    _ => as_i64(kind, &a) == as_i64(kind, &b),
+  };
+  InvokeResult::Ok(runtime::Value::U32(equal as i32))
+}
+
+/// `isNaN` and `isInfinite`, static or on a box
+fn test_floating(
+  kind: Kind,
+  test: fn(f64) -> bool,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+) -> InvokeResult {
+  let v = match params.first() {
+    None => return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(runtime::Value::Instance(i)) => match i.lock().unwrap().get_field(VALUE) {
+      None => return InvokeResult::runtime(RuntimeError::FieldOutOfBounds(VALUE), cs),
+      Some(v) => v.clone(),
+    },
+    Some(v) => v.clone(),
+  };
+
+  InvokeResult::Ok(runtime::Value::U32(test(as_f64(kind, &v)) as i32))
+}
+
+/// `floatToRawIntBits`, `intBitsToFloat` and their double versions: floats are already
+/// kept as their bits
+fn identity(params: Vec<runtime::Value>, cs: Rc<CallStack>, _env: &Module) -> InvokeResult {
+  match params.first() {
+    None => InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(v) => InvokeResult::Ok(v.clone()),
+  }
+}
+
+fn float_to_int_bits(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  match params.first() {
+    None => InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(v) => InvokeResult::Ok(runtime::Value::U32(canonical_bits(Kind::Float, v) as i32)),
+  }
+}
+
+fn double_to_long_bits(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  match params.first() {
+    None => InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(v) => InvokeResult::Ok(runtime::Value::U64(canonical_bits(Kind::Double, v))),
+  }
+}
+
+/// `Character.isWhitespace`: unlike rust, java doesn't count non-breaking spaces
+fn is_java_whitespace(c: char) -> bool {
+  c.is_whitespace() && !['\u{a0}', '\u{2007}', '\u{202f}'].contains(&c)
+    || ('\u{1c}'..='\u{1f}').contains(&c)
+}
+
+/// The zeros of the runs of ten decimal digits (Unicode category Nd) past ASCII
+const DIGIT_ZEROS: &[u32] = &[
+  0x660, 0x6f0, 0x7c0, 0x966, 0x9e6, 0xa66, 0xae6, 0xb66, 0xbe6, 0xc66, 0xce6, 0xd66, 0xde6, 0xe50,
+  0xed0, 0xf20, 0x1040, 0x1090, 0x17e0, 0x1810, 0x1946, 0x19d0, 0x1a80, 0x1a90, 0x1b50, 0x1bb0,
+  0x1c40, 0x1c50, 0xa620, 0xa8d0, 0xa900, 0xa9d0, 0xa9f0, 0xaa50, 0xabf0, 0xff10, 0x104a0, 0x10d30,
+  0x11066, 0x110f0, 0x11136, 0x111d0, 0x112f0, 0x11450, 0x114d0, 0x11650, 0x116c0, 0x11730,
+  0x118e0, 0x11950, 0x11c50, 0x11d50, 0x11da0, 0x16a60, 0x16ac0, 0x16b50, 0x1d7ce, 0x1d7d8,
+  0x1d7e2, 0x1d7ec, 0x1d7f6, 0x1e140, 0x1e2f0, 0x1e950, 0x1fbf0,
+];
+
+/// `Character.isDigit`: only decimal digits, while `char::is_numeric` also takes `²` and `½`
+fn is_java_digit(c: char) -> bool {
+  c.is_ascii_digit()
+    || DIGIT_ZEROS
+      .iter()
+      .any(|zero| (*zero..*zero + 10).contains(&(c as u32)))
+}
+
+/// `Character.isLetter`: rust also counts the letter numbers like `Ⅻ` as alphabetic
+fn is_java_letter(c: char) -> bool {
+  c.is_alphabetic() && !c.is_numeric()
+}
+
+fn char_arg(params: &[runtime::Value]) -> Result<Option<char>, RuntimeError> {
+  Ok(std::char::from_u32(arg(params, 0)?.to_single() as u32))
+}
+
+/// The `Character.isX(char)` tests
+fn character_test(
+  test: fn(char) -> bool,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+) -> InvokeResult {
+  match char_arg(&params) {
+    Err(e) => InvokeResult::runtime(e, cs),
+    Ok(c) => InvokeResult::Ok(runtime::Value::U32(c.is_some_and(test) as i32)),
+  }
+}
+
+/// The char of a case mapping, if it maps to a single one
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+  match (chars.next(), chars.next()) {
+    (Some(c), None) => Some(c),
+    _ => None,
+  }
+}
+
+/// `Character.toUpperCase(char)` and `toLowerCase(char)`, which keep the chars mapping to
+/// several ones, like `ß` to `SS`
+fn map_char(
+  f: fn(char) -> Option<char>,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+) -> InvokeResult {
+  let c = match arg(&params, 0) {
+    Err(e) => return InvokeResult::runtime(e, cs),
+    Ok(c) => c.to_single(),
+  };
+
+  // Only mappings to a single char, in the same plane
+  let mapped = std::char::from_u32(c as u32)
+    .and_then(f)
+    .filter(|m| (*m as u32 > 0xffff) == (c > 0xffff))
+    .map_or(c, |m| m as i32);
+  InvokeResult::Ok(runtime::Value::U32(mapped))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn call(
+    f: impl FnOnce(Vec<runtime::Value>, Rc<CallStack>, &Module) -> InvokeResult,
+    params: Vec<runtime::Value>,
+  ) -> Result<runtime::Value, String> {
+    let mut env = Module::new("test".to_owned());
+    super::super::add_all(&mut env);
+    match f(params, Rc::new(CallStack::default()), &env) {
+      InvokeResult::Ok(v) => Ok(v),
+      InvokeResult::Exception(e, _) => Err(e.get_type().unwrap_or_default()),
+      InvokeResult::RuntimeError(e) => Err(format!("{:?}", e.error)),
+    }
+  }
+
+  fn unbox_value(v: &runtime::Value) -> Result<i64, String> {
+    let env = Module::new("test".to_owned());
+    match unbox(v, &Rc::new(CallStack::default()), &env) {
+      Ok((kind, v)) => Ok(as_i64(kind, &v)),
+      Err(_) => Err("not a box".to_owned()),
+    }
+  }
+
+  #[test]
+  fn digits_and_letters() {
+    for c in &['0', '9', '٣', '૭', '０', '𝟘'] {
+      assert!(is_java_digit(*c), "{}", c);
+    }
+    for c in &['²', '½', 'Ⅻ', '〇', 'a'] {
+      assert!(!is_java_digit(*c), "{}", c);
+    }
+    for c in &['a', 'Z', 'ß', 'ǅ', '中'] {
+      assert!(is_java_letter(*c), "{}", c);
+    }
+    for c in &['Ⅻ', '1', '_', ' '] {
+      assert!(!is_java_letter(*c), "{}", c);
+    }
+  }
+
+  #[test]
+  fn case_mappings() {
+    let upper = |c: char| {
+      let mapped = call(
+        |p, cs, _env| map_char(|c| single(c.to_uppercase()), p, cs),
+        vec![runtime::Value::U32(c as i32)],
+      );
+      std::char::from_u32(mapped.unwrap().to_single() as u32).unwrap()
+    };
+    let lower = |c: char| {
+      let mapped = call(
+        |p, cs, _env| map_char(|c| single(c.to_lowercase()), p, cs),
+        vec![runtime::Value::U32(c as i32)],
+      );
+      std::char::from_u32(mapped.unwrap().to_single() as u32).unwrap()
+    };
+
+    assert_eq!(upper('a'), 'A');
+    assert_eq!(upper('ß'), 'ß');
+    assert_eq!(upper('ŉ'), 'ŉ');
+    assert_eq!(upper('1'), '1');
+    assert_eq!(lower('Σ'), 'σ');
+    assert_eq!(lower('A'), 'a');
+  }
+
+  #[test]
+  fn value_of_strings() {
+    let value_of = |kind: Kind, s: Option<&str>| {
+      let s = s.map_or(runtime::Value::Void, runtime::Value::new_string);
+      call(|p, cs, env| value_of_string(kind, p, cs, env), vec![s]).and_then(|v| unbox_value(&v))
+    };
+    let number_format = Err("Ljava/lang/NumberFormatException;".to_owned());
+
+    assert_eq!(value_of(Kind::Int, Some("-42")), Ok(-42));
+    assert_eq!(value_of(Kind::Int, None), number_format);
+    assert_eq!(value_of(Kind::Long, None), number_format);
+    assert_eq!(value_of(Kind::Short, None), number_format);
+    assert_eq!(value_of(Kind::Byte, None), number_format);
+    assert_eq!(value_of(Kind::Int, Some("x")), number_format);
+    assert_eq!(
+      value_of(Kind::Double, None),
+      Err("Ljava/lang/NullPointerException;".to_owned())
+    );
+    // Only a null boolean is false
+    assert_eq!(value_of(Kind::Boolean, None), Ok(0));
+    assert_eq!(value_of(Kind::Boolean, Some("TRUE")), Ok(1));
+  }
+}
@@ -0,0 +1,137 @@
+use std::rc::Rc;
+
+use super::{runtime, CallStack, InvokeResult, Module, RuntimeError};
+use crate::codegen::{is_reference, java_name};
+
+pub fn add_functions(m: &mut Module) {
+  m.natives()
+    .untyped_method(
+      "java/lang/Class.getName()Ljava/lang/String;",
+      java_lang_class_get_name,
+    )
+    .untyped_method(
+      "java/lang/Class.getSimpleName()Ljava/lang/String;",
+      java_lang_class_get_simple_name,
+    )
+    .untyped_method(
+      "java/lang/Class.getSuperclass()Ljava/lang/Class;",
+      java_lang_class_get_superclass,
+    )
+    .untyped_method(
+      "java/lang/Class.isInstance(Ljava/lang/Object;)Z",
+      java_lang_class_is_instance,
+    )
+    .untyped_method("java/lang/Class.isArray()Z", java_lang_class_is_array);
+}
+
+/// Get the type descriptor of the `java.lang.Class` passed as `this`
+fn descriptor(params: &[runtime::Value]) -> Result<String, RuntimeError> {
+  let this = match params.first() {
+    None => return Err(RuntimeError::WrongNumberOfParameters(1, 0)),
+    Some(t) => t.instance()?,
+  };
+
+  let this = this.lock().unwrap();
+  match this.get_field(0).and_then(|d| d.string()) {
+    None => Err(RuntimeError::CastError(format!(
+      "{:?} as java.lang.Class",
+      this
+    ))),
+    Some(d) => Ok(d),
+  }
+}
+
+/// `Class.getSimpleName()` of a type descriptor, e.g. `Outer$Inner` -> `Inner`
+fn simple_name(descriptor: &str) -> String {
+  if let Some(element) = descriptor.strip_prefix('[') {
+    return format!("{}[]", simple_name(element));
+  }
+
+  let name = java_name(descriptor);
+  let name = name.rsplit('.').next().unwrap_or("");
+  match name.rfind('$') {
+    None => name.to_owned(),
+    // Local classes are named `Outer$1Local`, anonymous ones just `Outer$1`
+    Some(i) => name[i + 1..]
+      .trim_start_matches(|c: char| c.is_ascii_digit())
+      .to_owned(),
+  }
+}
+
+fn java_lang_class_get_name(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  match descriptor(&params) {
+    Err(e) => InvokeResult::runtime(e, cs),
+    Ok(d) => InvokeResult::Ok(runtime::Value::new_string(&java_name(&d))),
+  }
+}
+
+fn java_lang_class_get_simple_name(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  match descriptor(&params) {
+    Err(e) => InvokeResult::runtime(e, cs),
+    Ok(d) => InvokeResult::Ok(runtime::Value::new_string(&simple_name(&d))),
+  }
+}
+
+fn java_lang_class_get_superclass(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let d = match descriptor(&params) {
+    Err(e) => return InvokeResult::runtime(e, cs),
+    Ok(d) => d,
+  };
+
+  // Primitives, interfaces and Object itself have no superclass
+  if !is_reference(&d) || d == "Ljava/lang/Object;" {
+    return InvokeResult::Ok(runtime::Value::Void);
+  }
+  if d.starts_with('[') {
+    return InvokeResult::Ok(env.class_object("Ljava/lang/Object;"));
+  }
+
+  match env.get_class(&d) {
+    Some(c) if !c.is_interface() => match c.superclass() {
+      None => InvokeResult::Ok(runtime::Value::Void),
+      Some(s) => InvokeResult::Ok(env.class_object(s)),
+    },
+    _ => InvokeResult::Ok(runtime::Value::Void),
+  }
+}
+
+fn java_lang_class_is_instance(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let d = match descriptor(&params) {
+    Err(e) => return InvokeResult::runtime(e, cs),
+    Ok(d) => d,
+  };
+
+  let is_instance = match params.get(1).and_then(|o| o.get_type()) {
+    None => false,
+    Some(t) => env.is_assignable(&t, &d),
+  };
+
+  InvokeResult::Ok(runtime::Value::U32(is_instance as i32))
+}
+
+fn java_lang_class_is_array(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  match descriptor(&params) {
+    Err(e) => InvokeResult::runtime(e, cs),
+    Ok(d) => InvokeResult::Ok(runtime::Value::U32(d.starts_with('[') as i32)),
+  }
+}
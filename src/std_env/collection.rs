@@ -0,0 +1,793 @@
+//! The skeletons of the `java.util` collections: `AbstractCollection`, `AbstractList`,
+//! `AbstractSet` and `AbstractMap`. They only call the methods a subclass implements
+//! (`iterator`, `size`, `get`, `entrySet`, ...), so they work for the native collections as well
+//! as for classes of the program extending them.
+//!
+//! Also the helpers of all collections, which call back into `equals`, `hashCode` and
+//! `compareTo` of the elements.
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use super::list;
+use super::string_builder::object_text;
+use super::{runtime, CallStack, InvokeResult, Module, Native, RuntimeError};
+
+pub fn add_functions(m: &mut Module) {
+  let classes: &[(&str, &[(&str, Native)])] = &[
+    (
+      "AbstractCollection",
+      &[
+        ("isEmpty()Z", collection_is_empty),
+        ("contains(Ljava/lang/Object;)Z", collection_contains),
+        (
+          "containsAll(Ljava/util/Collection;)Z",
+          collection_contains_all,
+        ),
+        ("add(Ljava/lang/Object;)Z", unsupported),
+        ("addAll(Ljava/util/Collection;)Z", collection_add_all),
+        ("remove(Ljava/lang/Object;)Z", collection_remove),
+        ("removeAll(Ljava/util/Collection;)Z", |p, cs, env| {
+          collection_remove_where(false, p, cs, env)
+        }),
+        ("retainAll(Ljava/util/Collection;)Z", |p, cs, env| {
+          collection_remove_where(true, p, cs, env)
+        }),
+        (
+          "removeIf(Ljava/util/function/Predicate;)Z",
+          collection_remove_if,
+        ),
+        ("clear()V", collection_clear),
+        ("toArray()[Ljava/lang/Object;", collection_to_array),
+        (
+          "toArray([Ljava/lang/Object;)[Ljava/lang/Object;",
+          collection_to_array,
+        ),
+        (
+          "forEach(Ljava/util/function/Consumer;)V",
+          collection_for_each,
+        ),
+        ("toString()Ljava/lang/String;", collection_to_string),
+      ],
+    ),
+    (
+      "AbstractList",
+      &[
+        ("add(Ljava/lang/Object;)Z", list_add),
+        ("add(ILjava/lang/Object;)V", unsupported),
+        ("set(ILjava/lang/Object;)Ljava/lang/Object;", unsupported),
+        ("remove(I)Ljava/lang/Object;", unsupported),
+        ("indexOf(Ljava/lang/Object;)I", |p, cs, env| {
+          list_index_of(false, p, cs, env)
+        }),
+        ("lastIndexOf(Ljava/lang/Object;)I", |p, cs, env| {
+          list_index_of(true, p, cs, env)
+        }),
+        ("iterator()Ljava/util/Iterator;", list::iterator),
+        ("sort(Ljava/util/Comparator;)V", list_sort),
+        ("equals(Ljava/lang/Object;)Z", list_equals),
+        ("hashCode()I", list_hash_code),
+      ],
+    ),
+    (
+      "AbstractSet",
+      &[
+        ("equals(Ljava/lang/Object;)Z", set_equals),
+        ("hashCode()I", set_hash_code),
+      ],
+    ),
+    (
+      "AbstractMap",
+      &[
+        ("isEmpty()Z", collection_is_empty),
+        ("equals(Ljava/lang/Object;)Z", map_equals),
+        ("hashCode()I", map_hash_code),
+        ("toString()Ljava/lang/String;", map_to_string),
+      ],
+    ),
+  ];
+
+  for (class, functions) in classes {
+    for (method, f) in functions.iter() {
+      m.natives()
+        .untyped_method(&format!("java/util/{}.{}", class, method), *f);
+    }
+  }
+}
+
+/// Throw an exception created by the runtime
+pub(super) fn exception(
+  class: &str,
+  message: Option<&str>,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  InvokeResult::Exception(env.new_exception(class, message, cs), cs.clone())
+}
+
+/// A parameter of a native
+pub(super) fn arg<'a>(
+  params: &'a [runtime::Value],
+  idx: usize,
+  cs: &Rc<CallStack>,
+) -> Result<&'a runtime::Value, InvokeResult> {
+  params.get(idx).ok_or_else(|| {
+    InvokeResult::runtime(
+      RuntimeError::WrongNumberOfParameters(idx + 1, params.len()),
+      cs.clone(),
+    )
+  })
+}
+
+/// A parameter which can't be null, throwing a `NullPointerException` if it is
+pub(super) fn non_null<'a>(
+  params: &'a [runtime::Value],
+  idx: usize,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<&'a runtime::Value, InvokeResult> {
+  match arg(params, idx, cs)? {
+    v if v.is_null() => Err(exception("Ljava/lang/NullPointerException;", None, cs, env)),
+    v => Ok(v),
+  }
+}
+
+pub(super) fn int_arg(
+  params: &[runtime::Value],
+  idx: usize,
+  cs: &Rc<CallStack>,
+) -> Result<i32, InvokeResult> {
+  match arg(params, idx, cs)? {
+    runtime::Value::U32(i) => Ok(*i),
+    v => Err(InvokeResult::runtime(
+      RuntimeError::CastError(format!("{:?} as int", v)),
+      cs.clone(),
+    )),
+  }
+}
+
+/// Call a method of an object, dispatching on its class
+pub(super) fn call(
+  method: &str,
+  params: Vec<runtime::Value>,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<runtime::Value, InvokeResult> {
+  match env.invoke_virtual(method, cs.clone(), params) {
+    InvokeResult::Ok(v) => Ok(v),
+    r => Err(r),
+  }
+}
+
+pub(super) fn boolean(b: bool) -> runtime::Value {
+  runtime::Value::U32(b as i32)
+}
+
+/// `hashCode()` of an object, 0 for null
+pub(super) fn hash_code(
+  v: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<i32, InvokeResult> {
+  match v {
+    v if v.is_null() => Ok(0),
+    runtime::Value::String(s) => Ok(s.hash_code()),
+    v => call("hashCode()I", vec![v.clone()], cs, env).map(|h| h.to_single()),
+  }
+}
+
+/// `Objects.equals(a, b)`: both null, the same object, or `a.equals(b)`
+pub(super) fn equals(
+  a: &runtime::Value,
+  b: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<bool, InvokeResult> {
+  match (a, b) {
+    (a, b) if a.is_null() || b.is_null() => Ok(a.is_null() && b.is_null()),
+    (a, b) if a.same(b) => Ok(true),
+    (runtime::Value::String(a), runtime::Value::String(b)) => Ok(a == b),
+    (a, b) => call(
+      "equals(Ljava/lang/Object;)Z",
+      vec![a.clone(), b.clone()],
+      cs,
+      env,
+    )
+    .map(|e| e.to_single() != 0),
+  }
+}
+
+/// Compare two objects with a `Comparator`, or with their `compareTo` if it is null
+pub(super) fn compare(
+  a: &runtime::Value,
+  b: &runtime::Value,
+  comparator: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Ordering, InvokeResult> {
+  let c = match comparator {
+    c if !c.is_null() => call(
+      "compare(Ljava/lang/Object;Ljava/lang/Object;)I",
+      vec![c.clone(), a.clone(), b.clone()],
+      cs,
+      env,
+    )?,
+    _ if a.is_null() => return Err(exception("Ljava/lang/NullPointerException;", None, cs, env)),
+    _ => call(
+      "compareTo(Ljava/lang/Object;)I",
+      vec![a.clone(), b.clone()],
+      cs,
+      env,
+    )?,
+  };
+
+  Ok(c.to_single().cmp(&0))
+}
+
+/// Sort objects with a `Comparator` (or their natural ordering). Like java the sort is stable,
+/// and a comparator throwing an exception stops it.
+pub(super) fn sort(
+  items: &mut [runtime::Value],
+  comparator: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<(), InvokeResult> {
+  merge_sort(items, &mut |a, b| compare(a, b, comparator, cs, env))
+}
+
+/// A stable merge sort with a fallible comparison. Rust's own sorts can't be stopped, and may
+/// panic when the comparison is not a total order, which a comparator of the program could be.
+pub(super) fn merge_sort<E>(
+  items: &mut [runtime::Value],
+  cmp: &mut impl FnMut(&runtime::Value, &runtime::Value) -> Result<Ordering, E>,
+) -> Result<(), E> {
+  if items.len() <= 1 {
+    return Ok(());
+  }
+
+  let mid = items.len() / 2;
+  merge_sort(&mut items[..mid], cmp)?;
+  merge_sort(&mut items[mid..], cmp)?;
+
+  let mut merged = Vec::with_capacity(items.len());
+  let (mut i, mut j) = (0, mid);
+  while i < mid && j < items.len() {
+    // Take from the right only when it is strictly less, which keeps the sort stable
+    if cmp(&items[j], &items[i])? == Ordering::Less {
+      merged.push(items[j].clone());
+      j += 1;
+    } else {
+      merged.push(items[i].clone());
+      i += 1;
+    }
+  }
+  merged.extend_from_slice(&items[i..mid]);
+  merged.extend_from_slice(&items[j..]);
+
+  items.clone_from_slice(&merged);
+  Ok(())
+}
+
+/// The elements of a collection (or anything `Iterable`), read with its iterator
+pub(super) fn elements(
+  c: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Vec<runtime::Value>, InvokeResult> {
+  if c.is_null() {
+    return Err(exception("Ljava/lang/NullPointerException;", None, cs, env));
+  }
+  if let Some(items) = list::snapshot(c) {
+    return Ok(items);
+  }
+
+  let iterator = call("iterator()Ljava/util/Iterator;", vec![c.clone()], cs, env)?;
+  let mut items = Vec::new();
+  while call("hasNext()Z", vec![iterator.clone()], cs, env)?.to_single() != 0 {
+    items.push(call(
+      "next()Ljava/lang/Object;",
+      vec![iterator.clone()],
+      cs,
+      env,
+    )?);
+  }
+
+  Ok(items)
+}
+
+/// `size()` of a collection or map
+pub(super) fn size(
+  c: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<usize, InvokeResult> {
+  call("size()I", vec![c.clone()], cs, env).map(|s| s.to_single().max(0) as usize)
+}
+
+/// Whether an object is an instance of a class or interface
+pub(super) fn instance_of(v: &runtime::Value, class: &str, env: &Module) -> bool {
+  v.get_type().is_some_and(|t| env.is_assignable(&t, class))
+}
+
+/// Join the texts of some objects, like `[a, b, c]`. The collection itself is written as
+/// `(this Collection)` instead of recursing forever.
+pub(super) fn join(
+  this: &runtime::Value,
+  items: &[runtime::Value],
+  (open, close): (&str, &str),
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<runtime::Value, InvokeResult> {
+  let mut text: Vec<u16> = open.encode_utf16().collect();
+  for (i, item) in items.iter().enumerate() {
+    if i > 0 {
+      text.extend(", ".encode_utf16());
+    }
+    match item {
+      item if item.same(this) => text.extend("(this Collection)".encode_utf16()),
+      item => text.extend(object_text(item, cs, env)?),
+    }
+  }
+  text.extend(close.encode_utf16());
+
+  Ok(runtime::Value::String(runtime::JavaString::from_units(
+    text,
+  )))
+}
+
+/// The default of the methods of an unmodifiable collection
+fn unsupported(_params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  exception("Ljava/lang/UnsupportedOperationException;", None, &cs, env)
+}
+
+fn collection_is_empty(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let size = try_invoke!(size(this, &cs, env));
+  InvokeResult::Ok(boolean(size == 0))
+}
+
+/// Whether any of the elements equals an object
+pub(super) fn contains(
+  items: &[runtime::Value],
+  o: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<bool, InvokeResult> {
+  for item in items {
+    if equals(o, item, cs, env)? {
+      return Ok(true);
+    }
+  }
+
+  Ok(false)
+}
+
+fn collection_contains(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let items = try_invoke!(elements(try_invoke!(arg(&params, 0, &cs)), &cs, env));
+  let o = try_invoke!(arg(&params, 1, &cs));
+  InvokeResult::Ok(boolean(try_invoke!(contains(&items, o, &cs, env))))
+}
+
+fn collection_contains_all(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  for o in try_invoke!(elements(try_invoke!(arg(&params, 1, &cs)), &cs, env)) {
+    let contains = vec![this.clone(), o];
+    let contained = try_invoke!(call("contains(Ljava/lang/Object;)Z", contains, &cs, env));
+    if contained.to_single() == 0 {
+      return InvokeResult::Ok(boolean(false));
+    }
+  }
+
+  InvokeResult::Ok(boolean(true))
+}
+
+/// `addAll(collection)`: `add` every element, whether the collection changed
+fn collection_add_all(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let mut changed = false;
+  for item in try_invoke!(elements(try_invoke!(arg(&params, 1, &cs)), &cs, env)) {
+    let added = try_invoke!(call(
+      "add(Ljava/lang/Object;)Z",
+      vec![this.clone(), item],
+      &cs,
+      env
+    ));
+    changed |= added.to_single() != 0;
+  }
+
+  InvokeResult::Ok(boolean(changed))
+}
+
+/// Remove the elements matching a test with the collection's iterator, whether any was removed
+fn remove_matching(
+  this: &runtime::Value,
+  first_only: bool,
+  test: &mut dyn FnMut(&runtime::Value) -> Result<bool, InvokeResult>,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<bool, InvokeResult> {
+  let iterator = call(
+    "iterator()Ljava/util/Iterator;",
+    vec![this.clone()],
+    cs,
+    env,
+  )?;
+  let mut removed = false;
+  while call("hasNext()Z", vec![iterator.clone()], cs, env)?.to_single() != 0 {
+    let item = call("next()Ljava/lang/Object;", vec![iterator.clone()], cs, env)?;
+    if test(&item)? {
+      call("remove()V", vec![iterator.clone()], cs, env)?;
+      removed = true;
+      if first_only {
+        break;
+      }
+    }
+  }
+
+  Ok(removed)
+}
+
+/// `remove(Object)`: remove the first element equal to an object
+fn collection_remove(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let o = try_invoke!(arg(&params, 1, &cs));
+  let removed = remove_matching(this, true, &mut |item| equals(o, item, &cs, env), &cs, env);
+  InvokeResult::Ok(boolean(try_invoke!(removed)))
+}
+
+/// `removeAll(collection)`, or `retainAll(collection)` which removes the elements it doesn't
+/// contain
+fn collection_remove_where(
+  retain: bool,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let other = try_invoke!(non_null(&params, 1, &cs, env));
+  let removed = remove_matching(
+    this,
+    false,
+    &mut |item| {
+      let contained = call(
+        "contains(Ljava/lang/Object;)Z",
+        vec![other.clone(), item.clone()],
+        &cs,
+        env,
+      )?;
+      Ok((contained.to_single() != 0) != retain)
+    },
+    &cs,
+    env,
+  );
+
+  InvokeResult::Ok(boolean(try_invoke!(removed)))
+}
+
+/// `removeIf(predicate)`
+fn collection_remove_if(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let predicate = try_invoke!(non_null(&params, 1, &cs, env));
+  let removed = remove_matching(
+    this,
+    false,
+    &mut |item| {
+      call(
+        "test(Ljava/lang/Object;)Z",
+        vec![predicate.clone(), item.clone()],
+        &cs,
+        env,
+      )
+      .map(|t| t.to_single() != 0)
+    },
+    &cs,
+    env,
+  );
+
+  InvokeResult::Ok(boolean(try_invoke!(removed)))
+}
+
+fn collection_clear(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  try_invoke!(remove_matching(this, false, &mut |_| Ok(true), &cs, env));
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `toArray()` gives an `Object[]`, and `toArray(T[])` fills the array if it is large enough
+/// (followed by a null) or creates a new one of its type
+fn collection_to_array(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let mut items = try_invoke!(elements(try_invoke!(arg(&params, 0, &cs)), &cs, env));
+
+  let type_ = match params.get(1) {
+    None => "[Ljava/lang/Object;".to_owned(),
+    Some(a) if a.is_null() => return exception("Ljava/lang/NullPointerException;", None, &cs, env),
+    Some(runtime::Value::Array(a)) => {
+      let mut a_ = a.lock().unwrap();
+      if a_.len() >= items.len() {
+        let n = items.len();
+        for (i, item) in items.into_iter().enumerate() {
+          a_.set(i, item);
+        }
+        if a_.len() > n {
+          a_.set(n, runtime::Value::Void);
+        }
+        return InvokeResult::Ok(runtime::Value::Array(a.clone()));
+      }
+      a_.get_array_type().clone()
+    }
+    Some(v) => {
+      return InvokeResult::runtime(RuntimeError::CastError(format!("{:?} as array", v)), cs)
+    }
+  };
+
+  items.shrink_to_fit();
+  InvokeResult::Ok(runtime::Value::from_array(type_, items))
+}
+
+/// `forEach(action)`: `action.accept` every element
+fn collection_for_each(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let items = try_invoke!(elements(try_invoke!(arg(&params, 0, &cs)), &cs, env));
+  let action = try_invoke!(non_null(&params, 1, &cs, env));
+  for item in items {
+    try_invoke!(call(
+      "accept(Ljava/lang/Object;)V",
+      vec![action.clone(), item],
+      &cs,
+      env
+    ));
+  }
+
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `toString()`: the elements like `[a, b, c]`
+fn collection_to_string(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let items = try_invoke!(elements(this, &cs, env));
+  InvokeResult::Ok(try_invoke!(join(this, &items, ("[", "]"), &cs, env)))
+}
+
+/// `add(e)` appends with `add(size(), e)`
+fn list_add(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let size = try_invoke!(size(this, &cs, env));
+  let item = try_invoke!(arg(&params, 1, &cs));
+  let params = vec![this.clone(), runtime::Value::U32(size as i32), item.clone()];
+  try_invoke!(call("add(ILjava/lang/Object;)V", params, &cs, env));
+  InvokeResult::Ok(boolean(true))
+}
+
+/// `indexOf(o)` or `lastIndexOf(o)`, -1 if the list doesn't contain it
+fn list_index_of(
+  last: bool,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let items = try_invoke!(elements(try_invoke!(arg(&params, 0, &cs)), &cs, env));
+  let o = try_invoke!(arg(&params, 1, &cs));
+
+  let indices: Vec<usize> = match last {
+    true => (0..items.len()).rev().collect(),
+    false => (0..items.len()).collect(),
+  };
+  for i in indices {
+    if try_invoke!(equals(o, &items[i], &cs, env)) {
+      return InvokeResult::Ok(runtime::Value::U32(i as i32));
+    }
+  }
+
+  InvokeResult::Ok(runtime::Value::U32(-1))
+}
+
+/// `sort(comparator)`: sort the elements, then put them back with `set`
+fn list_sort(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let comparator = params.get(1).cloned().unwrap_or(runtime::Value::Void);
+  let mut items = try_invoke!(elements(this, &cs, env));
+  try_invoke!(sort(&mut items, &comparator, &cs, env));
+
+  for (i, item) in items.into_iter().enumerate() {
+    let params = vec![this.clone(), runtime::Value::U32(i as i32), item];
+    try_invoke!(call(
+      "set(ILjava/lang/Object;)Ljava/lang/Object;",
+      params,
+      &cs,
+      env
+    ));
+  }
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `equals(o)`: another list with equal elements in the same order
+fn list_equals(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let other = try_invoke!(arg(&params, 1, &cs));
+  if this.same(other) {
+    return InvokeResult::Ok(boolean(true));
+  }
+  if !instance_of(other, "Ljava/util/List;", env) {
+    return InvokeResult::Ok(boolean(false));
+  }
+
+  let a = try_invoke!(elements(this, &cs, env));
+  let b = try_invoke!(elements(other, &cs, env));
+  if a.len() != b.len() {
+    return InvokeResult::Ok(boolean(false));
+  }
+  for (a, b) in a.iter().zip(b.iter()) {
+    if !try_invoke!(equals(a, b, &cs, env)) {
+      return InvokeResult::Ok(boolean(false));
+    }
+  }
+
+  InvokeResult::Ok(boolean(true))
+}
+
+/// `hashCode()`: `31 * h + e.hashCode()` over the elements, starting at 1
+fn list_hash_code(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let items = try_invoke!(elements(try_invoke!(arg(&params, 0, &cs)), &cs, env));
+  let mut h = 1i32;
+  for item in items.iter() {
+    h = h
+      .wrapping_mul(31)
+      .wrapping_add(try_invoke!(hash_code(item, &cs, env)));
+  }
+
+  InvokeResult::Ok(runtime::Value::U32(h))
+}
+
+/// `equals(o)`: another set of the same size, containing all the elements of this one
+fn set_equals(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let other = try_invoke!(arg(&params, 1, &cs));
+  if this.same(other) {
+    return InvokeResult::Ok(boolean(true));
+  }
+  if !instance_of(other, "Ljava/util/Set;", env)
+    || try_invoke!(size(this, &cs, env)) != try_invoke!(size(other, &cs, env))
+  {
+    return InvokeResult::Ok(boolean(false));
+  }
+
+  collection_contains_all(params, cs, env)
+}
+
+/// `hashCode()`: the sum of the hashes of the elements
+fn set_hash_code(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let items = try_invoke!(elements(try_invoke!(arg(&params, 0, &cs)), &cs, env));
+  let mut h = 0i32;
+  for item in items.iter() {
+    h = h.wrapping_add(try_invoke!(hash_code(item, &cs, env)));
+  }
+
+  InvokeResult::Ok(runtime::Value::U32(h))
+}
+
+/// The keys and values of a map, read from its `entrySet()`
+pub(super) fn entries(
+  map: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Vec<(runtime::Value, runtime::Value)>, InvokeResult> {
+  let entry_set = call("entrySet()Ljava/util/Set;", vec![map.clone()], cs, env)?;
+  elements(&entry_set, cs, env)?
+    .into_iter()
+    .map(|e| {
+      let key = call("getKey()Ljava/lang/Object;", vec![e.clone()], cs, env)?;
+      let value = call("getValue()Ljava/lang/Object;", vec![e], cs, env)?;
+      Ok((key, value))
+    })
+    .collect()
+}
+
+/// `equals(o)`: another map with the same keys, mapped to equal values
+fn map_equals(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let other = try_invoke!(arg(&params, 1, &cs));
+  if this.same(other) {
+    return InvokeResult::Ok(boolean(true));
+  }
+  if !instance_of(other, "Ljava/util/Map;", env)
+    || try_invoke!(size(this, &cs, env)) != try_invoke!(size(other, &cs, env))
+  {
+    return InvokeResult::Ok(boolean(false));
+  }
+
+  for (key, value) in try_invoke!(entries(this, &cs, env)) {
+    let other_value = try_invoke!(call(
+      "get(Ljava/lang/Object;)Ljava/lang/Object;",
+      vec![other.clone(), key.clone()],
+      &cs,
+      env
+    ));
+    let equal = match value.is_null() {
+      true if !other_value.is_null() => false,
+      true => {
+        let contains_key = vec![other.clone(), key];
+        let contained = try_invoke!(call(
+          "containsKey(Ljava/lang/Object;)Z",
+          contains_key,
+          &cs,
+          env
+        ));
+        contained.to_single() != 0
+      }
+      false => try_invoke!(equals(&value, &other_value, &cs, env)),
+    };
+    if !equal {
+      return InvokeResult::Ok(boolean(false));
+    }
+  }
+
+  InvokeResult::Ok(boolean(true))
+}
+
+/// `hashCode()`: the sum of `key.hashCode() ^ value.hashCode()` over the entries
+fn map_hash_code(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let entries = try_invoke!(entries(try_invoke!(arg(&params, 0, &cs)), &cs, env));
+  let mut h = 0i32;
+  for (key, value) in entries.iter() {
+    let entry = try_invoke!(hash_code(key, &cs, env)) ^ try_invoke!(hash_code(value, &cs, env));
+    h = h.wrapping_add(entry);
+  }
+
+  InvokeResult::Ok(runtime::Value::U32(h))
+}
+
+/// `toString()`: the entries like `{a=1, b=2}`
+fn map_to_string(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(arg(&params, 0, &cs));
+  let mut text: Vec<u16> = "{".encode_utf16().collect();
+  for (i, (key, value)) in try_invoke!(entries(this, &cs, env)).iter().enumerate() {
+    if i > 0 {
+      text.extend(", ".encode_utf16());
+    }
+    for (j, v) in [key, value].iter().enumerate() {
+      if j > 0 {
+        text.push('=' as u16);
+      }
+      match v {
+        v if v.same(this) => text.extend("(this Map)".encode_utf16()),
+        v => text.extend(try_invoke!(object_text(v, &cs, env))),
+      }
+    }
+  }
+  text.push('}' as u16);
+
+  InvokeResult::Ok(runtime::Value::String(runtime::JavaString::from_units(
+    text,
+  )))
+}
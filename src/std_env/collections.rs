@@ -0,0 +1,181 @@
+//! `java.util.Collections`, whose algorithms work on any collection through its methods
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use super::collection::{
+  arg, boolean, call, compare, elements, equals, exception, int_arg, non_null, size,
+};
+use super::{runtime, CallStack, InvokeResult, Module, Native};
+
+const GET: &str = "get(I)Ljava/lang/Object;";
+const SET: &str = "set(ILjava/lang/Object;)Ljava/lang/Object;";
+
+pub fn add_functions(m: &mut Module) {
+  let functions: &[(&str, Native)] = &[
+    ("sort(Ljava/util/List;)V", sort),
+    ("sort(Ljava/util/List;Ljava/util/Comparator;)V", sort),
+    ("reverse(Ljava/util/List;)V", reverse),
+    ("swap(Ljava/util/List;II)V", swap),
+    (
+      "max(Ljava/util/Collection;)Ljava/lang/Object;",
+      |p, cs, env| extreme(Ordering::Greater, p, cs, env),
+    ),
+    (
+      "max(Ljava/util/Collection;Ljava/util/Comparator;)Ljava/lang/Object;",
+      |p, cs, env| extreme(Ordering::Greater, p, cs, env),
+    ),
+    (
+      "min(Ljava/util/Collection;)Ljava/lang/Object;",
+      |p, cs, env| extreme(Ordering::Less, p, cs, env),
+    ),
+    (
+      "min(Ljava/util/Collection;Ljava/util/Comparator;)Ljava/lang/Object;",
+      |p, cs, env| extreme(Ordering::Less, p, cs, env),
+    ),
+    (
+      "addAll(Ljava/util/Collection;[Ljava/lang/Object;)Z",
+      add_all,
+    ),
+    (
+      "frequency(Ljava/util/Collection;Ljava/lang/Object;)I",
+      frequency,
+    ),
+  ];
+
+  for (method, f) in functions {
+    m.natives()
+      .untyped_static_method(&format!("java/util/Collections.{}", method), *f);
+  }
+}
+
+/// `sort(list)` and `sort(list, comparator)`, which is `list.sort(comparator)`
+fn sort(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let list = try_invoke!(non_null(&params, 0, &cs, env));
+  let comparator = params.get(1).cloned().unwrap_or(runtime::Value::Void);
+  try_invoke!(call(
+    "sort(Ljava/util/Comparator;)V",
+    vec![list.clone(), comparator],
+    &cs,
+    env
+  ));
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+fn get(
+  list: &runtime::Value,
+  i: usize,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<runtime::Value, InvokeResult> {
+  call(
+    GET,
+    vec![list.clone(), runtime::Value::U32(i as i32)],
+    cs,
+    env,
+  )
+}
+
+/// `list.set(i, e)`, returning the element it replaced
+fn set(
+  list: &runtime::Value,
+  i: usize,
+  e: runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<runtime::Value, InvokeResult> {
+  call(
+    SET,
+    vec![list.clone(), runtime::Value::U32(i as i32), e],
+    cs,
+    env,
+  )
+}
+
+/// `reverse(list)`, swapping the elements from both ends
+fn reverse(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let list = try_invoke!(non_null(&params, 0, &cs, env));
+  let n = try_invoke!(size(list, &cs, env));
+
+  for i in 0..n / 2 {
+    let first = try_invoke!(get(list, i, &cs, env));
+    let last = try_invoke!(set(list, n - 1 - i, first, &cs, env));
+    try_invoke!(set(list, i, last, &cs, env));
+  }
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `swap(list, i, j)`, which is `list.set(i, list.set(j, list.get(i)))`
+fn swap(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let list = try_invoke!(non_null(&params, 0, &cs, env));
+  let i = try_invoke!(int_arg(&params, 1, &cs));
+  let j = try_invoke!(int_arg(&params, 2, &cs));
+
+  // Let the list check the indices, so the exceptions are its own
+  let index = |i: i32| runtime::Value::U32(i);
+  let e = try_invoke!(call(GET, vec![list.clone(), index(i)], &cs, env));
+  let e = try_invoke!(call(SET, vec![list.clone(), index(j), e], &cs, env));
+  try_invoke!(call(SET, vec![list.clone(), index(i), e], &cs, env));
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `max(c)`, `min(c)` and their versions with a comparator. Like java the first of equal
+/// elements is kept, and an empty collection throws a `NoSuchElementException`.
+fn extreme(
+  wanted: Ordering,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let c = try_invoke!(non_null(&params, 0, &cs, env));
+  let comparator = params.get(1).cloned().unwrap_or(runtime::Value::Void);
+  let mut items = try_invoke!(elements(c, &cs, env)).into_iter();
+
+  let mut best = match items.next() {
+    None => return exception("Ljava/util/NoSuchElementException;", None, &cs, env),
+    Some(e) => e,
+  };
+  for e in items {
+    if try_invoke!(compare(&e, &best, &comparator, &cs, env)) == wanted {
+      best = e;
+    }
+  }
+
+  InvokeResult::Ok(best)
+}
+
+/// `addAll(c, T... elements)`: add each element, returning whether `c` changed
+fn add_all(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let c = try_invoke!(non_null(&params, 0, &cs, env));
+  let items = try_invoke!(non_null(&params, 1, &cs, env));
+  let items = match items.array() {
+    Err(e) => return InvokeResult::runtime(e, cs),
+    Ok(a) => a.lock().unwrap().data().clone(),
+  };
+
+  let mut changed = false;
+  for item in items {
+    let added = try_invoke!(call(
+      "add(Ljava/lang/Object;)Z",
+      vec![c.clone(), item],
+      &cs,
+      env
+    ));
+    changed |= added.to_single() != 0;
+  }
+  InvokeResult::Ok(boolean(changed))
+}
+
+/// `frequency(c, o)`: the number of elements equal to `o`
+fn frequency(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let c = try_invoke!(non_null(&params, 0, &cs, env));
+  let o = try_invoke!(arg(&params, 1, &cs));
+
+  let mut n = 0;
+  for item in try_invoke!(elements(c, &cs, env)).iter() {
+    if try_invoke!(equals(o, item, &cs, env)) {
+      n += 1;
+    }
+  }
+  InvokeResult::Ok(runtime::Value::U32(n))
+}
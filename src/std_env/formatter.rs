@@ -0,0 +1,947 @@
+//! `java.util.Formatter`: the format strings of `String.format`, `PrintStream.printf` and
+//! `Formatter.format`, like `%-10s`, `%08.2f` or `%2$x`
+
+use std::rc::Rc;
+
+use super::string_builder::object_text;
+use super::{runtime, CallStack, InvokeResult, Module, RuntimeError};
+use crate::codegen::java_name;
+
+/// Flags a format specifier may have
+const FLAGS: &str = "-#+ 0,(<";
+/// Conversions of a format specifier
+//TODO Dates and times (`%t`) and hexadecimal floating point (`%a`)
+const CONVERSIONS: &str = "bBhHsScCdoxXeEfgGn%";
+
+// Slot of the `out` field of `java.util.Formatter`: where the formatted text is appended
+const OUT: usize = 0;
+
+pub fn add_functions(m: &mut Module) {
+  // The locale is ignored
+  for parameters in &[
+    "",
+    "Ljava/lang/Appendable;",
+    "Ljava/io/PrintStream;",
+    "Ljava/util/Locale;",
+    "Ljava/lang/Appendable;Ljava/util/Locale;",
+  ] {
+    m.natives().untyped_method(
+      &format!("java/util/Formatter.<init>({})V", parameters),
+      java_util_formatter_init_,
+    );
+  }
+
+  m.natives()
+    .untyped_method(
+      "java/util/Formatter.format(Ljava/lang/String;[Ljava/lang/Object;)Ljava/util/Formatter;",
+      java_util_formatter_format,
+    )
+    .untyped_method(
+      "java/util/Formatter.format(Ljava/util/Locale;Ljava/lang/String;[Ljava/lang/Object;)Ljava/util/Formatter;",
+      java_util_formatter_format,
+    )
+    .untyped_method(
+      "java/util/Formatter.out()Ljava/lang/Appendable;",
+      java_util_formatter_out,
+    )
+    .untyped_method(
+      "java/util/Formatter.toString()Ljava/lang/String;",
+      java_util_formatter_to_string,
+    )
+    .untyped_method("java/util/Formatter.flush()V", java_util_formatter_flush)
+    .untyped_method("java/util/Formatter.close()V", java_util_formatter_flush);
+}
+
+/// Which argument a specifier formats
+#[derive(Debug, Clone, Copy)]
+enum Index {
+  /// The one after the last ordinary specifier
+  Next,
+  /// `%2$s`, counting from 1
+  Explicit(usize),
+  /// `%<s`, the same as the previous specifier
+  Previous,
+}
+
+/// A format specifier: `%[argument_index$][flags][width][.precision]conversion`
+#[derive(Debug)]
+struct Specifier {
+  index: Index,
+  flags: String,
+  width: Option<usize>,
+  precision: Option<usize>,
+  conversion: char,
+  /// The specifier as written, for error messages
+  text: String,
+}
+
+impl Specifier {
+  fn has(&self, flag: char) -> bool {
+    self.flags.contains(flag)
+  }
+}
+
+/// A number argument, unboxed
+enum Number {
+  /// A value and the bits of its type, e.g. 32 for an `Integer`
+  Integral(i64, u32),
+  /// A value and whether it is a `Float`, which is printed with the digits of a float
+  Floating(f64, bool),
+}
+
+/// Format the arguments after the format string: `(format, args)` or `(locale, format, args)`,
+/// with `args` the `Object[]` of the varargs. The locale is ignored.
+pub(super) fn format_varargs(
+  params: &[runtime::Value],
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Vec<u16>, InvokeResult> {
+  let (pattern, args) = match params {
+    [f, args] | [_, f, args] if f.is_string() => (f, args),
+    [f, ..] | [_, f, _] if f.is_null() => {
+      return Err(exception("Ljava/lang/NullPointerException;", None, cs, env))
+    }
+    _ => {
+      return Err(InvokeResult::runtime(
+        RuntimeError::WrongNumberOfParameters(2, params.len()),
+        cs.clone(),
+      ))
+    }
+  };
+
+  // A null array passes no arguments
+  let args = if args.is_null() {
+    Vec::new()
+  } else {
+    args
+      .array()
+      .map_err(|e| InvokeResult::runtime(e, cs.clone()))?
+      .lock()
+      .unwrap()
+      .data()
+      .clone()
+  };
+  let pattern = pattern
+    .java_string()
+    .map_err(|e| InvokeResult::runtime(e, cs.clone()))?;
+
+  format(pattern.units(), &args, cs, env)
+}
+
+/// Format arguments with a format string
+pub(super) fn format(
+  pattern: &[u16],
+  args: &[runtime::Value],
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Vec<u16>, InvokeResult> {
+  let mut out = Vec::new();
+  // The next ordinary argument, and the last one used
+  let mut next = 0;
+  let mut last = None;
+
+  let mut i = 0;
+  while i < pattern.len() {
+    if pattern[i] != '%' as u16 {
+      out.push(pattern[i]);
+      i += 1;
+      continue;
+    }
+
+    let spec = match parse(pattern, &mut i) {
+      Err(conversion) => {
+        return Err(exception(
+          "Ljava/util/UnknownFormatConversionException;",
+          Some(&format!("Conversion = '{}'", conversion)),
+          cs,
+          env,
+        ))
+      }
+      Ok(s) => s,
+    };
+    check(&spec, cs, env)?;
+
+    let text = match spec.conversion {
+      'n' => vec!['\n' as u16],
+      '%' => vec!['%' as u16],
+      _ => {
+        let idx = match spec.index {
+          Index::Next => {
+            next += 1;
+            Some(next - 1)
+          }
+          Index::Explicit(n) => n.checked_sub(1),
+          Index::Previous => last,
+        };
+        let arg = match idx.and_then(|idx| args.get(idx)) {
+          None => {
+            return Err(exception(
+              "Ljava/util/MissingFormatArgumentException;",
+              Some(&format!("Format specifier '{}'", spec.text)),
+              cs,
+              env,
+            ))
+          }
+          Some(a) => a,
+        };
+        last = idx;
+
+        convert(&spec, arg, cs, env)?
+      }
+    };
+
+    out.extend(justify(text, &spec));
+  }
+
+  Ok(out)
+}
+
+/// Parse the specifier starting at the `%` at `pos`, and move past it. Gives the conversion
+/// if it is unknown.
+fn parse(pattern: &[u16], pos: &mut usize) -> Result<Specifier, String> {
+  let start = *pos;
+  *pos += 1;
+
+  let at = |i: usize| pattern.get(i).and_then(|c| char::from_u32(*c as u32));
+  let digits = |pos: &mut usize| {
+    let mut n: Option<usize> = None;
+    while let Some(d) = at(*pos).and_then(|c| c.to_digit(10)) {
+      n = Some(n.unwrap_or(0).saturating_mul(10).saturating_add(d as usize));
+      *pos += 1;
+    }
+    n
+  };
+
+  let mut index = Index::Next;
+  let before_index = *pos;
+  match digits(pos) {
+    Some(n) if at(*pos) == Some('$') => {
+      *pos += 1;
+      index = Index::Explicit(n);
+    }
+    _ => *pos = before_index,
+  }
+
+  let mut flags = String::new();
+  while let Some(c) = at(*pos).filter(|c| FLAGS.contains(*c)) {
+    flags.push(c);
+    *pos += 1;
+  }
+  if flags.contains('<') {
+    index = Index::Previous;
+  }
+
+  let width = digits(pos);
+  let precision = match at(*pos) {
+    Some('.') => {
+      *pos += 1;
+      match digits(pos) {
+        None => return Err(".".to_owned()),
+        p => p,
+      }
+    }
+    _ => None,
+  };
+
+  let conversion = match at(*pos) {
+    None => return Err("%".to_owned()),
+    Some(c) if !CONVERSIONS.contains(c) => return Err(c.to_string()),
+    Some(c) => c,
+  };
+  *pos += 1;
+
+  Ok(Specifier {
+    index,
+    flags,
+    width,
+    precision,
+    conversion,
+    text: String::from_utf16_lossy(&pattern[start..*pos]),
+  })
+}
+
+/// Check the flags, width and precision make sense for the conversion
+fn check(spec: &Specifier, cs: &Rc<CallStack>, env: &Module) -> Result<(), InvokeResult> {
+  if (spec.has('-') || spec.has('0')) && spec.width.is_none() {
+    return Err(exception(
+      "Ljava/util/MissingFormatWidthException;",
+      Some(&spec.text),
+      cs,
+      env,
+    ));
+  }
+
+  match (spec.precision, spec.conversion.to_ascii_lowercase()) {
+    (Some(p), 'c') | (Some(p), 'd') | (Some(p), 'o') | (Some(p), 'x') | (Some(p), 'n') => {
+      Err(exception(
+        "Ljava/util/IllegalFormatPrecisionException;",
+        Some(&p.to_string()),
+        cs,
+        env,
+      ))
+    }
+    _ => Ok(()),
+  }
+}
+
+/// The text of an argument for a specifier, before it is justified to its width
+fn convert(
+  spec: &Specifier,
+  arg: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Vec<u16>, InvokeResult> {
+  let text: Vec<u16> = match spec.conversion.to_ascii_lowercase() {
+    'b' => {
+      let b = match boxed(arg, env) {
+        _ if arg.is_null() => false,
+        Some(("Ljava/lang/Boolean;", v)) => v.to_single() != 0,
+        _ => true,
+      };
+      truncate(units(&b.to_string()), spec)
+    }
+    'h' if arg.is_null() => truncate(units("null"), spec),
+    'h' => match env.invoke_virtual("hashCode()I", cs.clone(), vec![arg.clone()]) {
+      InvokeResult::Ok(h) => truncate(units(&format!("{:x}", h.to_single())), spec),
+      r => return Err(r),
+    },
+    's' => truncate(object_text(arg, cs, env)?, spec),
+    'c' if arg.is_null() => units("null"),
+    'c' => {
+      let c = match (arg, boxed(arg, env)) {
+        (runtime::Value::U32(c), _) => *c as u32,
+        (_, Some(("Ljava/lang/Character;", v)))
+        | (_, Some(("Ljava/lang/Byte;", v)))
+        | (_, Some(("Ljava/lang/Short;", v)))
+        | (_, Some(("Ljava/lang/Integer;", v))) => v.to_single() as u32,
+        _ => return Err(conversion_mismatch(spec, arg, cs, env)),
+      };
+      match char::from_u32(c) {
+        None => {
+          return Err(exception(
+            "Ljava/util/IllegalFormatCodePointException;",
+            Some(&format!("Code point = {:#x}", c)),
+            cs,
+            env,
+          ))
+        }
+        Some(c) => units(&c.to_string()),
+      }
+    }
+    conversion => match (conversion, number(arg, env)) {
+      _ if arg.is_null() => units("null"),
+      ('d', Some(Number::Integral(v, _))) => units(&decimal(v, spec)),
+      ('o', Some(Number::Integral(v, bits))) => units(&radix(v, bits, 8, spec)),
+      ('x', Some(Number::Integral(v, bits))) => units(&radix(v, bits, 16, spec)),
+      ('e', Some(Number::Floating(v, float)))
+      | ('f', Some(Number::Floating(v, float)))
+      | ('g', Some(Number::Floating(v, float))) => units(&floating(v, float, spec)),
+      _ => return Err(conversion_mismatch(spec, arg, cs, env)),
+    },
+  };
+
+  if spec.conversion.is_ascii_uppercase() {
+    Ok(units(&String::from_utf16_lossy(&text).to_uppercase()))
+  } else {
+    Ok(text)
+  }
+}
+
+/// The class and `value` of a boxed primitive
+fn boxed(arg: &runtime::Value, env: &Module) -> Option<(&'static str, runtime::Value)> {
+  const WRAPPERS: &[&str] = &[
+    "Ljava/lang/Boolean;",
+    "Ljava/lang/Character;",
+    "Ljava/lang/Byte;",
+    "Ljava/lang/Short;",
+    "Ljava/lang/Integer;",
+    "Ljava/lang/Long;",
+    "Ljava/lang/Float;",
+    "Ljava/lang/Double;",
+  ];
+
+  let class = arg.get_type()?;
+  let class = WRAPPERS.iter().find(|w| **w == class)?;
+  env.field(arg, "value").ok().map(|v| (*class, v))
+}
+
+/// An argument as a number. Unboxed ints and longs are accepted as well.
+fn number(arg: &runtime::Value, env: &Module) -> Option<Number> {
+  let long = |v: &runtime::Value| match v {
+    runtime::Value::U64(l) => *l,
+    v => v.to_single() as i64,
+  };
+
+  match arg {
+    runtime::Value::U32(i) => Some(Number::Integral(*i as i64, 32)),
+    runtime::Value::U64(l) => Some(Number::Integral(*l, 64)),
+    _ => match boxed(arg, env)? {
+      ("Ljava/lang/Byte;", v) => Some(Number::Integral(v.to_single() as i8 as i64, 8)),
+      ("Ljava/lang/Short;", v) => Some(Number::Integral(v.to_single() as i16 as i64, 16)),
+      ("Ljava/lang/Integer;", v) => Some(Number::Integral(v.to_single() as i64, 32)),
+      ("Ljava/lang/Long;", v) => Some(Number::Integral(long(&v), 64)),
+      ("Ljava/lang/Float;", v) => Some(Number::Floating(
+        f32::from_bits(v.to_single() as u32) as f64,
+        true,
+      )),
+      ("Ljava/lang/Double;", v) => Some(Number::Floating(f64::from_bits(long(&v) as u64), false)),
+      _ => None,
+    },
+  }
+}
+
+/// `%d`
+fn decimal(v: i64, spec: &Specifier) -> String {
+  let digits = (v as i128).abs().to_string();
+  let digits = if spec.has(',') {
+    group(&digits)
+  } else {
+    digits
+  };
+  signed(v < 0, digits, spec)
+}
+
+/// `%o` and `%x`: negative values are written as unsigned values of their type
+fn radix(v: i64, bits: u32, radix: u32, spec: &Specifier) -> String {
+  let unsigned = if v < 0 && bits < 64 {
+    (v + (1 << bits)) as u64
+  } else {
+    v as u64
+  };
+
+  let mut digits = match radix {
+    8 => format!("{:o}", unsigned),
+    _ => format!("{:x}", unsigned),
+  };
+  let prefix = match (spec.has('#'), radix) {
+    (false, _) => "",
+    (true, 8) => "0",
+    (true, _) => "0x",
+  };
+  if let (true, Some(width)) = (spec.has('0'), spec.width) {
+    while prefix.len() + digits.len() < width {
+      digits.insert(0, '0');
+    }
+  }
+
+  format!("{}{}", prefix, digits)
+}
+
+/// `%e`, `%f` and `%g`
+fn floating(v: f64, float: bool, spec: &Specifier) -> String {
+  if v.is_nan() {
+    return "NaN".to_owned();
+  }
+  if v.is_infinite() {
+    let sign = match () {
+      _ if v < 0.0 && spec.has('(') => return "(Infinity)".to_owned(),
+      _ if v < 0.0 => "-",
+      _ if spec.has('+') => "+",
+      _ if spec.has(' ') => " ",
+      _ => "",
+    };
+    return format!("{}Infinity", sign);
+  }
+
+  let d = Decimal::new(v.abs(), float);
+  let precision = spec.precision.unwrap_or(6) as i32;
+  let body = match spec.conversion.to_ascii_lowercase() {
+    'e' => d.scientific(precision),
+    'f' => d.fixed(precision, spec.has(',')),
+    _ => {
+      // The precision is the number of significant digits, and the magnitude after rounding
+      // decides between the notations
+      let precision = precision.max(1);
+      let rounded = d.round(precision);
+      let exponent = rounded.point - 1;
+      if rounded.is_zero() || (-4..precision).contains(&exponent) {
+        d.fixed(precision - exponent - 1, spec.has(','))
+      } else {
+        d.scientific(precision - 1)
+      }
+    }
+  };
+
+  signed(v.is_sign_negative() && v != 0.0, body, spec)
+}
+
+/// Add the sign of a number, and the leading zeros of the `0` flag after it
+fn signed(negative: bool, mut body: String, spec: &Specifier) -> String {
+  let (prefix, suffix) = match () {
+    _ if negative && spec.has('(') => ("(", ")"),
+    _ if negative => ("-", ""),
+    _ if spec.has('+') => ("+", ""),
+    _ if spec.has(' ') => (" ", ""),
+    _ => ("", ""),
+  };
+
+  if let (true, Some(width)) = (spec.has('0'), spec.width) {
+    while prefix.len() + body.len() + suffix.len() < width {
+      body.insert(0, '0');
+    }
+  }
+
+  format!("{}{}{}", prefix, body, suffix)
+}
+
+/// Separate the thousands of an integer with commas
+fn group(digits: &str) -> String {
+  let mut out = String::new();
+  for (i, c) in digits.chars().enumerate() {
+    if i > 0 && (digits.len() - i).is_multiple_of(3) {
+      out.push(',');
+    }
+    out.push(c);
+  }
+
+  out
+}
+
+/// Pad to the width of the specifier, with spaces on the left (or on the right with `-`)
+fn justify(mut text: Vec<u16>, spec: &Specifier) -> Vec<u16> {
+  let width = match spec.width {
+    Some(w) if w > text.len() => w,
+    _ => return text,
+  };
+
+  let padding = vec![' ' as u16; width - text.len()];
+  if spec.has('-') {
+    text.extend(padding);
+    text
+  } else {
+    padding.into_iter().chain(text).collect()
+  }
+}
+
+/// The precision of `%s`, `%b` and `%h` is the maximum length
+fn truncate(mut text: Vec<u16>, spec: &Specifier) -> Vec<u16> {
+  if let Some(p) = spec.precision {
+    text.truncate(p);
+  }
+
+  text
+}
+
+fn units(s: &str) -> Vec<u16> {
+  s.encode_utf16().collect()
+}
+
+/// A positive decimal number `0.d1d2d3... * 10^point`. Java rounds the shortest digits that
+/// identify a double (those of `Double.toString`) half-up, which isn't what rust's formatting
+/// does (`%.2f` of `0.125` is `0.13` in java), so the digits are rounded by hand.
+#[derive(Debug, Clone)]
+struct Decimal {
+  /// No digits is zero
+  digits: Vec<u8>,
+  point: i32,
+}
+
+impl Decimal {
+  fn new(v: f64, float: bool) -> Self {
+    // Rust's `{:e}` gives the shortest digits that round-trip, like `Double.toString`
+    let sci = if float {
+      format!("{:e}", v as f32)
+    } else {
+      format!("{:e}", v)
+    };
+    let (mantissa, exponent) = sci.split_at(sci.find('e').unwrap());
+    let exponent: i32 = exponent[1..].parse().unwrap();
+
+    let mut digits: Vec<u8> = mantissa
+      .bytes()
+      .filter(|b| b.is_ascii_digit())
+      .map(|b| b - b'0')
+      .collect();
+    while digits.last() == Some(&0) {
+      digits.pop();
+    }
+
+    Self {
+      digits,
+      point: exponent + 1,
+    }
+  }
+
+  fn is_zero(&self) -> bool {
+    self.digits.is_empty()
+  }
+
+  /// The digit at an index, which may be before or after the digits
+  fn digit(&self, idx: i32) -> u8 {
+    if idx < 0 {
+      0
+    } else {
+      self.digits.get(idx as usize).copied().unwrap_or(0)
+    }
+  }
+
+  /// Round half-up to a number of significant digits
+  fn round(&self, significant: i32) -> Decimal {
+    if significant < 0 || self.is_zero() {
+      return Decimal {
+        digits: Vec::new(),
+        point: 1,
+      };
+    }
+
+    let n = significant as usize;
+    if self.digits.len() <= n {
+      return self.clone();
+    }
+
+    let mut digits = self.digits[..n].to_vec();
+    let mut point = self.point;
+    if self.digits[n] >= 5 {
+      let mut carry = true;
+      for d in digits.iter_mut().rev() {
+        if *d == 9 {
+          *d = 0;
+        } else {
+          *d += 1;
+          carry = false;
+          break;
+        }
+      }
+      if carry {
+        digits.insert(0, 1);
+        point += 1;
+      }
+    }
+    while digits.last() == Some(&0) {
+      digits.pop();
+    }
+
+    if digits.is_empty() {
+      point = 1;
+    }
+    Decimal { digits, point }
+  }
+
+  /// `%f`: `precision` digits after the point
+  fn fixed(&self, precision: i32, grouping: bool) -> String {
+    let r = self.round(self.point + precision);
+
+    let integer: String = if r.point > 0 && !r.is_zero() {
+      (0..r.point).map(|i| (b'0' + r.digit(i)) as char).collect()
+    } else {
+      "0".to_owned()
+    };
+    let integer = if grouping { group(&integer) } else { integer };
+
+    if precision <= 0 {
+      return integer;
+    }
+    let fraction: String = (r.point..r.point + precision)
+      .map(|i| (b'0' + r.digit(i)) as char)
+      .collect();
+    format!("{}.{}", integer, fraction)
+  }
+
+  /// `%e`: one digit before the point and `precision` after it, then the exponent
+  fn scientific(&self, precision: i32) -> String {
+    let r = self.round(precision + 1);
+    let exponent = if r.is_zero() { 0 } else { r.point - 1 };
+
+    let mut out = ((b'0' + r.digit(0)) as char).to_string();
+    if precision > 0 {
+      out.push('.');
+      out.extend((1..=precision).map(|i| (b'0' + r.digit(i)) as char));
+    }
+    format!(
+      "{}e{}{:02}",
+      out,
+      if exponent < 0 { '-' } else { '+' },
+      exponent.abs()
+    )
+  }
+}
+
+fn exception(class: &str, message: Option<&str>, cs: &Rc<CallStack>, env: &Module) -> InvokeResult {
+  InvokeResult::Exception(env.new_exception(class, message, cs), cs.clone())
+}
+
+/// `IllegalFormatConversionException`, e.g. `d != java.lang.String`
+fn conversion_mismatch(
+  spec: &Specifier,
+  arg: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let class = match arg {
+    runtime::Value::U32(_) => "java.lang.Integer".to_owned(),
+    runtime::Value::U64(_) => "java.lang.Long".to_owned(),
+    _ => arg.get_type().map(|t| java_name(&t)).unwrap_or_default(),
+  };
+
+  exception(
+    "Ljava/util/IllegalFormatConversionException;",
+    Some(&format!("{} != {}", spec.conversion, class)),
+    cs,
+    env,
+  )
+}
+
+/// The `Appendable` a formatter writes to
+fn out(this: &runtime::Value) -> Result<runtime::Value, RuntimeError> {
+  match this.instance()?.lock().unwrap().get_field(OUT) {
+    None => Err(RuntimeError::FieldOutOfBounds(OUT)),
+    Some(o) => Ok(o.clone()),
+  }
+}
+
+/// `Formatter()` writes to a new `StringBuilder`, `Formatter(Appendable)` to the appendable.
+/// Other constructors (a `Locale`, a file name) aren't supported and also get a `StringBuilder`.
+fn java_util_formatter_init_(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = match params.first() {
+    None => return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(t) => t,
+  };
+
+  let appendable = params.get(1).filter(|a| {
+    a.get_type().is_some_and(|t| {
+      [
+        "Ljava/lang/StringBuilder;",
+        "Ljava/lang/StringBuffer;",
+        "Ljava/io/PrintStream;",
+      ]
+      .iter()
+      .any(|c| env.is_assignable(&t, c))
+    })
+  });
+  let out = match appendable {
+    Some(a) => a.clone(),
+    None => {
+      let sb = runtime::Value::from_instance(env.new_instance("Ljava/lang/StringBuilder;"));
+      match env.invoke_virtual("__init__()V", cs.clone(), vec![sb.clone()]) {
+        InvokeResult::Ok(_) => sb,
+        r => return r,
+      }
+    }
+  };
+
+  let this = unwrap_or_error!(this.instance(), cs);
+  unwrap_or_error!(this.lock().unwrap().set_field(OUT, out), cs);
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `format([locale,] format, args...)`, appending to `out()`. Returns the formatter.
+fn java_util_formatter_format(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  if params.is_empty() {
+    return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(3, 0), cs);
+  }
+
+  let text = match format_varargs(&params[1..], &cs, env) {
+    Err(r) => return r,
+    Ok(t) => t,
+  };
+  let out = unwrap_or_error!(out(&params[0]), cs);
+  let text = runtime::Value::String(runtime::JavaString::from_units(text));
+  match env.invoke_virtual(
+    "append(Ljava/lang/CharSequence;)Ljava/lang/Appendable;",
+    cs,
+    vec![out, text],
+  ) {
+    InvokeResult::Ok(_) => InvokeResult::Ok(params[0].clone()),
+    r => r,
+  }
+}
+
+fn java_util_formatter_out(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  match params.first() {
+    None => InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(this) => InvokeResult::Ok(unwrap_or_error!(out(this), cs)),
+  }
+}
+
+/// `toString()`: the text of `out()`
+fn java_util_formatter_to_string(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let out = match params.first() {
+    None => return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(this) => unwrap_or_error!(out(this), cs),
+  };
+
+  match object_text(&out, &cs, env) {
+    Err(r) => r,
+    Ok(t) => InvokeResult::Ok(runtime::Value::String(runtime::JavaString::from_units(t))),
+  }
+}
+
+/// `flush()` and `close()`: nothing is buffered
+fn java_util_formatter_flush(
+  _params: Vec<runtime::Value>,
+  _cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn spec(text: &str) -> Result<Specifier, String> {
+    let pattern: Vec<u16> = text.encode_utf16().collect();
+    let mut pos = 0;
+    let spec = parse(&pattern, &mut pos);
+    if spec.is_ok() {
+      assert_eq!(pos, pattern.len());
+    }
+    spec
+  }
+
+  /// The text of a format string, or the class of the exception it throws
+  fn format_str(pattern: &str, args: &[runtime::Value]) -> Result<String, String> {
+    let env = Module::new("test".to_owned());
+    let cs = Rc::new(CallStack::default());
+    let pattern: Vec<u16> = pattern.encode_utf16().collect();
+    match format(&pattern, args, &cs, &env) {
+      Ok(text) => Ok(String::from_utf16_lossy(&text)),
+      Err(InvokeResult::Exception(e, _)) => Err(e.get_type().unwrap_or_default()),
+      Err(_) => Err("a runtime error".to_owned()),
+    }
+  }
+
+  #[test]
+  fn specifiers() {
+    let s = spec("%-08.3f").unwrap();
+    assert!(matches!(s.index, Index::Next));
+    assert_eq!(
+      (s.flags.as_str(), s.width, s.precision, s.conversion),
+      ("-0", Some(8), Some(3), 'f')
+    );
+
+    let s = spec("%2$,d").unwrap();
+    assert!(matches!(s.index, Index::Explicit(2)));
+    assert_eq!((s.flags.as_str(), s.width), (",", None));
+
+    // Digits without a `$` are the width
+    let s = spec("%12s").unwrap();
+    assert!(matches!(s.index, Index::Next));
+    assert_eq!(s.width, Some(12));
+
+    assert!(matches!(spec("%<x").unwrap().index, Index::Previous));
+    assert_eq!(spec("%n").unwrap().conversion, 'n');
+    assert_eq!(spec("%%").unwrap().conversion, '%');
+  }
+
+  #[test]
+  fn bad_specifiers() {
+    assert_eq!(spec("%q").unwrap_err(), "q");
+    assert_eq!(spec("%").unwrap_err(), "%");
+    assert_eq!(spec("%5").unwrap_err(), "%");
+    assert_eq!(spec("%.f").unwrap_err(), ".");
+  }
+
+  #[test]
+  fn integers() {
+    let (i, l) = (runtime::Value::U32, runtime::Value::U64);
+    assert_eq!(
+      format_str("%d|%5d|%-5d|", &[i(42), i(-7), i(3)]).unwrap(),
+      "42|   -7|3    |"
+    );
+    assert_eq!(
+      format_str("%05d %+d % d", &[i(-42), i(1), i(2)]).unwrap(),
+      "-0042 +1  2"
+    );
+    assert_eq!(
+      format_str("%,d %(d", &[l(-1234567), i(-5)]).unwrap(),
+      "-1,234,567 (5)"
+    );
+    assert_eq!(
+      format_str("%x %X %o", &[i(-1), i(255), i(8)]).unwrap(),
+      "ffffffff FF 10"
+    );
+    assert_eq!(
+      format_str("%#x %#o %08x", &[l(-1), i(8), i(255)]).unwrap(),
+      "0xffffffffffffffff 010 000000ff"
+    );
+  }
+
+  #[test]
+  fn indices() {
+    let args = [
+      runtime::Value::new_string("a"),
+      runtime::Value::new_string("b"),
+    ];
+    assert_eq!(
+      format_str("%2$s %1$s %<s %s%n", &args).unwrap(),
+      "b a a a\n"
+    );
+    assert_eq!(format_str("%s %s %1$s%%", &args).unwrap(), "a b a%");
+    assert_eq!(
+      format_str("[%-3s][%3S][%.1s]", &vec![args[0].clone(); 3]).unwrap(),
+      "[a  ][  A][a]"
+    );
+  }
+
+  #[test]
+  fn floating_point() {
+    let f = |text: &str, v: f64| floating(v, false, &spec(text).unwrap());
+    assert_eq!(f("%f", 1.5), "1.500000");
+    assert_eq!(f("%.2f", 2.675), "2.68");
+    assert_eq!(f("%.0f", 0.5), "1");
+    assert_eq!(f("%,.1f", -12345.67), "-12,345.7");
+    assert_eq!(f("%e", 12345.678), "1.234568e+04");
+    assert_eq!(f("%.2e", 0.000123), "1.23e-04");
+    assert_eq!(f("%g", 0.0001), "0.000100000");
+    assert_eq!(f("%g", 1234567.0), "1.23457e+06");
+    assert_eq!(f("%+f", f64::INFINITY), "+Infinity");
+    assert_eq!(f("%(f", f64::NEG_INFINITY), "(Infinity)");
+    assert_eq!(f("%f", f64::NAN), "NaN");
+    // A float has the digits of its own shortest representation
+    assert_eq!(
+      floating(0.1f32 as f64, true, &spec("%.10f").unwrap()),
+      "0.1000000000"
+    );
+  }
+
+  #[test]
+  fn errors() {
+    let i = runtime::Value::U32;
+    let error = |pattern: &str, args: &[runtime::Value]| format_str(pattern, args).unwrap_err();
+    assert_eq!(
+      error("%y", &[]),
+      "Ljava/util/UnknownFormatConversionException;"
+    );
+    assert_eq!(
+      error("%d %d", &[i(1)]),
+      "Ljava/util/MissingFormatArgumentException;"
+    );
+    assert_eq!(
+      error("%<d", &[i(1)]),
+      "Ljava/util/MissingFormatArgumentException;"
+    );
+    assert_eq!(
+      error("%0$d", &[i(1)]),
+      "Ljava/util/MissingFormatArgumentException;"
+    );
+    assert_eq!(
+      error("%-d", &[i(1)]),
+      "Ljava/util/MissingFormatWidthException;"
+    );
+    assert_eq!(
+      error("%.2d", &[i(1)]),
+      "Ljava/util/IllegalFormatPrecisionException;"
+    );
+    assert_eq!(
+      error("%d", &[runtime::Value::new_string("a")]),
+      "Ljava/util/IllegalFormatConversionException;"
+    );
+  }
+}
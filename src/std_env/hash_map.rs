@@ -0,0 +1,1009 @@
+//! `HashMap` and `HashSet` (which is backed by a `HashMap`), with the same buckets as java so
+//! they iterate in the same order: a power of two buckets, indexed by the spread `hashCode` of
+//! the keys, each keeping its entries in insertion order and doubled when 3/4 full
+//TODO Java turns buckets with many collisions into trees, which changes their order
+
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use super::collection::{arg, boolean, call, elements, equals, exception, hash_code, non_null};
+use super::{get_field, runtime, CallStack, InvokeResult, Module, Native, RuntimeError};
+use crate::codegen::runtime::NativeData;
+
+const NODE: &str = "Ljava/util/HashMap$Node;";
+const ITERATOR: &str = "Ljava/util/HashMap$HashIterator;";
+/// The value of the keys of the map of a `HashSet`
+const PRESENT: &str = "CLASS_java__util__HashSet__PRESENT";
+
+const DEFAULT_CAPACITY: usize = 16;
+const DEFAULT_LOAD_FACTOR: f32 = 0.75;
+const MAXIMUM_CAPACITY: usize = 1 << 30;
+
+// Slot of the `table` field of a `HashMap`, holding the `Table` as native data
+const TABLE: usize = 0;
+// Slot of the `map` field of a `HashSet`, of the views of a map and of their iterators
+const MAP: usize = 0;
+// Slot of the `cursor` field of an iterator, holding the `Cursor` as native data
+const CURSOR: usize = 1;
+// Slots of the fields of a `HashMap$Node`
+const KEY: usize = 0;
+const VALUE: usize = 1;
+
+/// The buckets of a map, with the hash of the key and the `HashMap$Node` of each entry
+struct Table {
+  buckets: Vec<Vec<(i32, runtime::Value)>>,
+  size: usize,
+  /// The size above which the buckets are doubled, or the initial capacity before the first
+  /// entry is added
+  threshold: usize,
+  load_factor: f32,
+  /// The number of structural modifications, so iterators can detect concurrent ones
+  mod_count: u32,
+}
+
+/// Where an entry is in a `Table`: its bucket, and its index in the bucket
+type Position = (usize, usize);
+
+impl Table {
+  fn new(capacity: usize, load_factor: f32) -> Self {
+    Self {
+      buckets: Vec::new(),
+      size: 0,
+      threshold: capacity,
+      load_factor,
+      mod_count: 0,
+    }
+  }
+
+  fn index(&self, hash: i32) -> usize {
+    hash as u32 as usize & (self.buckets.len() - 1)
+  }
+
+  /// Create or double the buckets, like `HashMap.resize()`. Each bucket is split in two, keeping
+  /// the order of its entries.
+  fn resize(&mut self) {
+    let old = self.buckets.len();
+    let (capacity, mut threshold) = match old {
+      0 if self.threshold > 0 => (self.threshold, 0),
+      0 => (
+        DEFAULT_CAPACITY,
+        (DEFAULT_CAPACITY as f32 * DEFAULT_LOAD_FACTOR) as usize,
+      ),
+      _ if old >= MAXIMUM_CAPACITY => {
+        self.threshold = i32::MAX as usize;
+        return;
+      }
+      _ if old >= DEFAULT_CAPACITY => (old * 2, self.threshold * 2),
+      _ => (old * 2, 0),
+    };
+    if threshold == 0 {
+      threshold = match capacity < MAXIMUM_CAPACITY {
+        true => (capacity as f32 * self.load_factor) as usize,
+        false => i32::MAX as usize,
+      };
+    }
+
+    let mut buckets = vec![Vec::new(); capacity];
+    for (hash, node) in self.buckets.drain(..).flatten() {
+      buckets[hash as u32 as usize & (capacity - 1)].push((hash, node));
+    }
+    self.buckets = buckets;
+    self.threshold = threshold;
+  }
+
+  /// The entries in the order of iteration
+  fn nodes(&self) -> Vec<runtime::Value> {
+    self
+      .buckets
+      .iter()
+      .flatten()
+      .map(|(_, node)| node.clone())
+      .collect()
+  }
+}
+
+/// The position of an iterator over the entries of a map
+struct Cursor {
+  view: View,
+  /// Bucket and index in the bucket of the next entry to look at
+  next: Position,
+  /// Position of the entry last returned, which `remove()` removes
+  last: Option<Position>,
+  /// The `modCount` of the map when the iterator last saw it
+  expected: u32,
+}
+
+/// What a view of a map, and its iterator, contains
+#[derive(Clone, Copy, PartialEq)]
+enum View {
+  Keys,
+  Values,
+  Entries,
+}
+
+impl View {
+  fn class(self) -> &'static str {
+    match self {
+      View::Keys => "Ljava/util/HashMap$KeySet;",
+      View::Values => "Ljava/util/HashMap$Values;",
+      View::Entries => "Ljava/util/HashMap$EntrySet;",
+    }
+  }
+
+  fn of(class: &str) -> Option<View> {
+    [View::Keys, View::Values, View::Entries]
+      .iter()
+      .copied()
+      .find(|v| v.class() == class)
+  }
+}
+
+pub fn add_functions(m: &mut Module) {
+  let classes: &[(&str, &[(&str, Native)])] = &[
+    (
+      "HashMap",
+      &[
+        ("<init>()V", map_init_),
+        ("<init>(I)V", map_init_),
+        ("<init>(IF)V", map_init_),
+        ("<init>(Ljava/util/Map;)V", map_init_),
+        ("size()I", map_size),
+        ("get(Ljava/lang/Object;)Ljava/lang/Object;", |p, cs, env| {
+          map_get(false, p, cs, env)
+        }),
+        (
+          "getOrDefault(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+          |p, cs, env| map_get(false, p, cs, env),
+        ),
+        ("containsKey(Ljava/lang/Object;)Z", map_contains_key),
+        ("containsValue(Ljava/lang/Object;)Z", map_contains_value),
+        (
+          "put(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+          |p, cs, env| map_put(false, p, cs, env),
+        ),
+        (
+          "putIfAbsent(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+          |p, cs, env| map_put(true, p, cs, env),
+        ),
+        ("putAll(Ljava/util/Map;)V", map_put_all),
+        ("remove(Ljava/lang/Object;)Ljava/lang/Object;", map_remove),
+        ("remove(Ljava/lang/Object;Ljava/lang/Object;)Z", map_remove),
+        ("clear()V", map_clear),
+        ("keySet()Ljava/util/Set;", |p, cs, env| {
+          view(View::Keys, p, cs, env)
+        }),
+        ("values()Ljava/util/Collection;", |p, cs, env| {
+          view(View::Values, p, cs, env)
+        }),
+        ("entrySet()Ljava/util/Set;", |p, cs, env| {
+          view(View::Entries, p, cs, env)
+        }),
+        ("forEach(Ljava/util/function/BiConsumer;)V", map_for_each),
+        (
+          "computeIfAbsent(Ljava/lang/Object;Ljava/util/function/Function;)Ljava/lang/Object;",
+          map_compute_if_absent,
+        ),
+        (
+          "merge(Ljava/lang/Object;Ljava/lang/Object;Ljava/util/function/BiFunction;)Ljava/lang/Object;",
+          map_merge,
+        ),
+      ],
+    ),
+    (
+      "HashMap$Node",
+      &[
+        ("getKey()Ljava/lang/Object;", |p, cs, env| {
+          node_field(KEY, p, cs, env)
+        }),
+        ("getValue()Ljava/lang/Object;", |p, cs, env| {
+          node_field(VALUE, p, cs, env)
+        }),
+        (
+          "setValue(Ljava/lang/Object;)Ljava/lang/Object;",
+          node_set_value,
+        ),
+        ("equals(Ljava/lang/Object;)Z", node_equals),
+        ("hashCode()I", node_hash_code),
+        ("toString()Ljava/lang/String;", node_to_string),
+      ],
+    ),
+    (
+      "HashMap$KeySet",
+      &[
+        ("size()I", |p, cs, env| delegate(map_size, p, cs, env)),
+        ("contains(Ljava/lang/Object;)Z", |p, cs, env| {
+          delegate(map_contains_key, p, cs, env)
+        }),
+        ("remove(Ljava/lang/Object;)Z", view_remove),
+        ("clear()V", |p, cs, env| delegate(map_clear, p, cs, env)),
+        ("iterator()Ljava/util/Iterator;", view_iterator),
+      ],
+    ),
+    (
+      "HashMap$Values",
+      &[
+        ("size()I", |p, cs, env| delegate(map_size, p, cs, env)),
+        ("contains(Ljava/lang/Object;)Z", |p, cs, env| {
+          delegate(map_contains_value, p, cs, env)
+        }),
+        ("clear()V", |p, cs, env| delegate(map_clear, p, cs, env)),
+        ("iterator()Ljava/util/Iterator;", view_iterator),
+      ],
+    ),
+    (
+      "HashMap$EntrySet",
+      &[
+        ("size()I", |p, cs, env| delegate(map_size, p, cs, env)),
+        ("clear()V", |p, cs, env| delegate(map_clear, p, cs, env)),
+        ("iterator()Ljava/util/Iterator;", view_iterator),
+      ],
+    ),
+    (
+      "HashMap$HashIterator",
+      &[
+        ("hasNext()Z", iterator_has_next),
+        ("next()Ljava/lang/Object;", iterator_next),
+        ("remove()V", iterator_remove),
+      ],
+    ),
+    (
+      "HashSet",
+      &[
+        ("<init>()V", set_init_),
+        ("<init>(I)V", set_init_),
+        ("<init>(IF)V", set_init_),
+        ("<init>(Ljava/util/Collection;)V", set_init_),
+        ("size()I", |p, cs, env| delegate(map_size, p, cs, env)),
+        ("contains(Ljava/lang/Object;)Z", |p, cs, env| {
+          delegate(map_contains_key, p, cs, env)
+        }),
+        ("add(Ljava/lang/Object;)Z", set_add),
+        ("remove(Ljava/lang/Object;)Z", set_remove),
+        ("clear()V", |p, cs, env| delegate(map_clear, p, cs, env)),
+        ("iterator()Ljava/util/Iterator;", set_iterator),
+      ],
+    ),
+  ];
+
+  for (class, functions) in classes {
+    for (method, f) in functions.iter() {
+      m.natives()
+        .untyped_method(&format!("java/util/{}.{}", class, method), *f);
+    }
+  }
+
+  let present = m.new_instance("Ljava/lang/Object;");
+  m.set_static(PRESENT.to_owned(), runtime::Value::from_instance(present));
+}
+
+fn data(this: &runtime::Value, slot: usize) -> Result<Arc<Mutex<NativeData>>, RuntimeError> {
+  match this.instance()?.lock().unwrap().get_field(slot) {
+    None => Err(RuntimeError::FieldOutOfBounds(slot)),
+    Some(v) => Ok(v.native_data()?.clone()),
+  }
+}
+
+/// Run a function on the table of a map
+fn with_table<T>(
+  this: &runtime::Value,
+  f: impl FnOnce(&mut Table) -> T,
+) -> Result<T, RuntimeError> {
+  let data = data(this, TABLE)?;
+  let mut data = data.lock().unwrap();
+  match data.get_mut::<Table>() {
+    None => Err(RuntimeError::CastError(
+      "native data as hash table".to_owned(),
+    )),
+    Some(t) => Ok(f(t)),
+  }
+}
+
+/// `this`, throwing a `NullPointerException` for null
+fn this<'a>(
+  params: &'a [runtime::Value],
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<&'a runtime::Value, InvokeResult> {
+  non_null(params, 0, cs, env)
+}
+
+/// `HashMap.hash(key)`: the hash of the key, with its high bits spread to the low ones that
+/// index the buckets
+fn spread_hash(
+  key: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<i32, InvokeResult> {
+  let h = hash_code(key, cs, env)?;
+  Ok(h ^ ((h as u32) >> 16) as i32)
+}
+
+/// The smallest power of two at least as large as a capacity
+fn table_size_for(capacity: usize) -> usize {
+  capacity.max(1).next_power_of_two().min(MAXIMUM_CAPACITY)
+}
+
+fn new_map(table: Table, env: &Module) -> runtime::Value {
+  let mut map = env.new_instance("Ljava/util/HashMap;");
+  map.set_field(TABLE, runtime::Value::native(table)).unwrap();
+  runtime::Value::from_instance(map)
+}
+
+/// Find the entry of a key: its position, and its node
+fn find(
+  map: &runtime::Value,
+  key: &runtime::Value,
+  hash: i32,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Option<(Position, runtime::Value)>, InvokeResult> {
+  // The candidates are copied so `equals` can run without the table being locked
+  let candidates = with_table(map, |t| match t.buckets.len() {
+    0 => Vec::new(),
+    _ => {
+      let b = t.index(hash);
+      t.buckets[b]
+        .iter()
+        .enumerate()
+        .filter(|(_, (h, _))| *h == hash)
+        .map(|(i, (_, node))| ((b, i), node.clone()))
+        .collect()
+    }
+  })
+  .map_err(|e| InvokeResult::runtime(e, cs.clone()))?;
+
+  for (position, node) in candidates {
+    let k = get_field(&node, KEY).map_err(|e| InvokeResult::runtime(e, cs.clone()))?;
+    if equals(key, &k, cs, env)? {
+      return Ok(Some((position, node)));
+    }
+  }
+
+  Ok(None)
+}
+
+/// Map a key to a value, returning the node of the key if it was already in the map
+fn put(
+  map: &runtime::Value,
+  key: runtime::Value,
+  value: runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Option<runtime::Value>, InvokeResult> {
+  let hash = spread_hash(&key, cs, env)?;
+  if let Some((_, node)) = find(map, &key, hash, cs, env)? {
+    return Ok(Some(node));
+  }
+
+  let mut node = env.new_instance(NODE);
+  node.set_field(KEY, key).unwrap();
+  node.set_field(VALUE, value).unwrap();
+  let node = runtime::Value::from_instance(node);
+
+  with_table(map, |t| {
+    if t.buckets.is_empty() {
+      t.resize();
+    }
+    let b = t.index(hash);
+    t.buckets[b].push((hash, node));
+    t.mod_count += 1;
+    t.size += 1;
+    if t.size > t.threshold {
+      t.resize();
+    }
+  })
+  .map_err(|e| InvokeResult::runtime(e, cs.clone()))?;
+  Ok(None)
+}
+
+/// Remove the entry of a key, returning its node
+fn remove(
+  map: &runtime::Value,
+  key: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Option<runtime::Value>, InvokeResult> {
+  let hash = spread_hash(key, cs, env)?;
+  let (position, node) = match find(map, key, hash, cs, env)? {
+    None => return Ok(None),
+    Some(found) => found,
+  };
+
+  with_table(map, |t| remove_at(t, position)).map_err(|e| InvokeResult::runtime(e, cs.clone()))?;
+  Ok(Some(node))
+}
+
+fn remove_at(t: &mut Table, (b, i): Position) {
+  t.buckets[b].remove(i);
+  t.size -= 1;
+  t.mod_count += 1;
+}
+
+/// The constructors `()`, `(int initialCapacity)`, `(int initialCapacity, float loadFactor)`
+/// and `(Map m)`
+fn map_init_(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let (capacity, load_factor, entries) = match params.get(1) {
+    None => (0, DEFAULT_LOAD_FACTOR, None),
+    Some(runtime::Value::U32(capacity)) => {
+      let load_factor =
+        params.get(2).map_or(
+          DEFAULT_LOAD_FACTOR,
+          |f| f32::from_bits(f.to_single() as u32),
+        );
+      if *capacity < 0 {
+        let message = format!("Illegal initial capacity: {}", capacity);
+        return exception(
+          "Ljava/lang/IllegalArgumentException;",
+          Some(&message),
+          &cs,
+          env,
+        );
+      }
+      if load_factor.is_nan() || load_factor <= 0.0 {
+        let message = format!("Illegal load factor: {}", load_factor);
+        return exception(
+          "Ljava/lang/IllegalArgumentException;",
+          Some(&message),
+          &cs,
+          env,
+        );
+      }
+      (table_size_for(*capacity as usize), load_factor, None)
+    }
+    Some(m) => {
+      let entries = try_invoke!(super::collection::entries(m, &cs, env));
+      // Large enough for all the entries, like `putMapEntries`
+      let capacity = match entries.len() {
+        0 => 0,
+        n => table_size_for((n as f32 / DEFAULT_LOAD_FACTOR + 1.0) as usize),
+      };
+      (capacity, DEFAULT_LOAD_FACTOR, Some(entries))
+    }
+  };
+
+  {
+    let mut instance = unwrap_or_error!(this.instance(), cs).lock().unwrap();
+    let table = runtime::Value::native(Table::new(capacity, load_factor));
+    unwrap_or_error!(instance.set_field(TABLE, table), cs);
+  }
+  for (key, value) in entries.unwrap_or_default() {
+    try_invoke!(put_value(this, key, value, &cs, env));
+  }
+
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// Put a key and value, replacing the value of an existing key. Returns the previous value.
+fn put_value(
+  map: &runtime::Value,
+  key: runtime::Value,
+  value: runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<runtime::Value, InvokeResult> {
+  match put(map, key, value.clone(), cs, env)? {
+    None => Ok(runtime::Value::Void),
+    Some(node) => {
+      let mut node = node
+        .instance()
+        .map_err(|e| InvokeResult::runtime(e, cs.clone()))?
+        .lock()
+        .unwrap();
+      let old = node
+        .get_field(VALUE)
+        .cloned()
+        .unwrap_or(runtime::Value::Void);
+      node.set_field(VALUE, value).unwrap();
+      Ok(old)
+    }
+  }
+}
+
+fn map_size(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let size = unwrap_or_error!(with_table(this, |t| t.size), cs);
+  InvokeResult::Ok(runtime::Value::U32(size as i32))
+}
+
+/// `get(key)` and `getOrDefault(key, default)`, or `containsKey(key)`
+fn map_get(
+  contains: bool,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let key = try_invoke!(arg(&params, 1, &cs));
+  let hash = try_invoke!(spread_hash(key, &cs, env));
+
+  let found = try_invoke!(find(this, key, hash, &cs, env));
+  match (contains, found) {
+    (true, found) => InvokeResult::Ok(boolean(found.is_some())),
+    (false, Some((_, node))) => InvokeResult::Ok(unwrap_or_error!(get_field(&node, VALUE), cs)),
+    (false, None) => InvokeResult::Ok(params.get(2).cloned().unwrap_or(runtime::Value::Void)),
+  }
+}
+
+fn map_contains_key(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  map_get(true, params, cs, env)
+}
+
+fn map_contains_value(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let value = try_invoke!(arg(&params, 1, &cs));
+  for node in unwrap_or_error!(with_table(this, |t| t.nodes()), cs) {
+    let v = unwrap_or_error!(get_field(&node, VALUE), cs);
+    if try_invoke!(equals(value, &v, &cs, env)) {
+      return InvokeResult::Ok(boolean(true));
+    }
+  }
+
+  InvokeResult::Ok(boolean(false))
+}
+
+/// `put(key, value)`, or `putIfAbsent(key, value)` which only replaces a null value. Both return
+/// the previous value.
+fn map_put(
+  if_absent: bool,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let key = try_invoke!(arg(&params, 1, &cs)).clone();
+  let value = try_invoke!(arg(&params, 2, &cs)).clone();
+
+  if if_absent {
+    let hash = try_invoke!(spread_hash(&key, &cs, env));
+    if let Some((_, node)) = try_invoke!(find(this, &key, hash, &cs, env)) {
+      let old = unwrap_or_error!(get_field(&node, VALUE), cs);
+      if !old.is_null() {
+        return InvokeResult::Ok(old);
+      }
+    }
+  }
+
+  InvokeResult::Ok(try_invoke!(put_value(this, key, value, &cs, env)))
+}
+
+fn map_put_all(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let m = try_invoke!(non_null(&params, 1, &cs, env));
+  for (key, value) in try_invoke!(super::collection::entries(m, &cs, env)) {
+    try_invoke!(put_value(this, key, value, &cs, env));
+  }
+
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `remove(key)` returns the value of the key, `remove(key, value)` only removes the key if it
+/// has the value and returns whether it did
+fn map_remove(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let key = try_invoke!(arg(&params, 1, &cs));
+
+  if let Some(value) = params.get(2) {
+    let hash = try_invoke!(spread_hash(key, &cs, env));
+    let (position, node) = match try_invoke!(find(this, key, hash, &cs, env)) {
+      None => return InvokeResult::Ok(boolean(false)),
+      Some(found) => found,
+    };
+    let v = unwrap_or_error!(get_field(&node, VALUE), cs);
+    if !try_invoke!(equals(value, &v, &cs, env)) {
+      return InvokeResult::Ok(boolean(false));
+    }
+    unwrap_or_error!(with_table(this, |t| remove_at(t, position)), cs);
+    return InvokeResult::Ok(boolean(true));
+  }
+
+  match try_invoke!(remove(this, key, &cs, env)) {
+    None => InvokeResult::Ok(runtime::Value::Void),
+    Some(node) => InvokeResult::Ok(unwrap_or_error!(get_field(&node, VALUE), cs)),
+  }
+}
+
+fn map_clear(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  unwrap_or_error!(
+    with_table(this, |t| {
+      if t.size > 0 {
+        t.buckets.iter_mut().for_each(|b| b.clear());
+        t.size = 0;
+      }
+      t.mod_count += 1;
+    }),
+    cs
+  );
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `keySet()`, `values()` or `entrySet()`: a view of the map, which changes with it
+fn view(view: View, params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let mut v = env.new_instance(view.class());
+  unwrap_or_error!(v.set_field(MAP, this.clone()), cs);
+  InvokeResult::Ok(runtime::Value::from_instance(v))
+}
+
+/// `forEach(action)`: `action.accept(key, value)` for every entry
+fn map_for_each(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let action = try_invoke!(non_null(&params, 1, &cs, env));
+  let (nodes, mod_count) = unwrap_or_error!(with_table(this, |t| (t.nodes(), t.mod_count)), cs);
+
+  for node in nodes {
+    let key = unwrap_or_error!(get_field(&node, KEY), cs);
+    let value = unwrap_or_error!(get_field(&node, VALUE), cs);
+    try_invoke!(call(
+      "accept(Ljava/lang/Object;Ljava/lang/Object;)V",
+      vec![action.clone(), key, value],
+      &cs,
+      env
+    ));
+  }
+
+  if unwrap_or_error!(with_table(this, |t| t.mod_count), cs) != mod_count {
+    return exception(
+      "Ljava/util/ConcurrentModificationException;",
+      None,
+      &cs,
+      env,
+    );
+  }
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `computeIfAbsent(key, function)`: the value of the key, or if it has none the result of
+/// `function.apply(key)`, which is added unless it is null
+fn map_compute_if_absent(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let key = try_invoke!(arg(&params, 1, &cs));
+  let function = try_invoke!(non_null(&params, 2, &cs, env));
+
+  let hash = try_invoke!(spread_hash(key, &cs, env));
+  if let Some((_, node)) = try_invoke!(find(this, key, hash, &cs, env)) {
+    let value = unwrap_or_error!(get_field(&node, VALUE), cs);
+    if !value.is_null() {
+      return InvokeResult::Ok(value);
+    }
+  }
+
+  let value = try_invoke!(call(
+    "apply(Ljava/lang/Object;)Ljava/lang/Object;",
+    vec![function.clone(), key.clone()],
+    &cs,
+    env
+  ));
+  if !value.is_null() {
+    try_invoke!(put_value(this, key.clone(), value.clone(), &cs, env));
+  }
+  InvokeResult::Ok(value)
+}
+
+/// `merge(key, value, function)`: map the key to the value if it has none, or else to
+/// `function.apply(old, value)`, removing the key if that is null. Returns the new value.
+fn map_merge(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let key = try_invoke!(arg(&params, 1, &cs));
+  let value = try_invoke!(non_null(&params, 2, &cs, env));
+  let function = try_invoke!(non_null(&params, 3, &cs, env));
+
+  let hash = try_invoke!(spread_hash(key, &cs, env));
+  let old = match try_invoke!(find(this, key, hash, &cs, env)) {
+    None => runtime::Value::Void,
+    Some((_, node)) => unwrap_or_error!(get_field(&node, VALUE), cs),
+  };
+
+  let new = match old.is_null() {
+    true => value.clone(),
+    false => try_invoke!(call(
+      "apply(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+      vec![function.clone(), old, value.clone()],
+      &cs,
+      env
+    )),
+  };
+  match new.is_null() {
+    true => {
+      try_invoke!(remove(this, key, &cs, env));
+    }
+    false => {
+      try_invoke!(put_value(this, key.clone(), new.clone(), &cs, env));
+    }
+  }
+  InvokeResult::Ok(new)
+}
+
+/// `getKey()` or `getValue()` of an entry
+fn node_field(
+  slot: usize,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  InvokeResult::Ok(unwrap_or_error!(get_field(this, slot), cs))
+}
+
+/// `setValue(value)`, which changes the value in the map. Returns the previous value.
+fn node_set_value(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let value = try_invoke!(arg(&params, 1, &cs)).clone();
+  let mut node = unwrap_or_error!(this.instance(), cs).lock().unwrap();
+  let old = node
+    .get_field(VALUE)
+    .cloned()
+    .unwrap_or(runtime::Value::Void);
+  unwrap_or_error!(node.set_field(VALUE, value), cs);
+  InvokeResult::Ok(old)
+}
+
+/// `equals(o)`: another `Map.Entry` with an equal key and value
+fn node_equals(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let other = try_invoke!(arg(&params, 1, &cs));
+  if this.same(other) {
+    return InvokeResult::Ok(boolean(true));
+  }
+  if !super::collection::instance_of(other, "Ljava/util/Map$Entry;", env) {
+    return InvokeResult::Ok(boolean(false));
+  }
+
+  for (slot, getter) in &[
+    (KEY, "getKey()Ljava/lang/Object;"),
+    (VALUE, "getValue()Ljava/lang/Object;"),
+  ] {
+    let a = unwrap_or_error!(get_field(this, *slot), cs);
+    let b = try_invoke!(call(getter, vec![other.clone()], &cs, env));
+    if !try_invoke!(equals(&a, &b, &cs, env)) {
+      return InvokeResult::Ok(boolean(false));
+    }
+  }
+
+  InvokeResult::Ok(boolean(true))
+}
+
+/// `hashCode()`: the hash of the key xor the hash of the value
+fn node_hash_code(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let key = unwrap_or_error!(get_field(this, KEY), cs);
+  let value = unwrap_or_error!(get_field(this, VALUE), cs);
+  let h = try_invoke!(hash_code(&key, &cs, env)) ^ try_invoke!(hash_code(&value, &cs, env));
+  InvokeResult::Ok(runtime::Value::U32(h))
+}
+
+/// `toString()`: `key=value`
+fn node_to_string(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let mut text = Vec::new();
+  for (i, slot) in [KEY, VALUE].iter().enumerate() {
+    if i > 0 {
+      text.push('=' as u16);
+    }
+    let v = unwrap_or_error!(get_field(this, *slot), cs);
+    text.extend(try_invoke!(super::string_builder::object_text(
+      &v, &cs, env
+    )));
+  }
+
+  InvokeResult::Ok(runtime::Value::String(runtime::JavaString::from_units(
+    text,
+  )))
+}
+
+/// The map of a view, a `HashSet` or an iterator
+fn map_of(this: &runtime::Value, cs: &Rc<CallStack>) -> Result<runtime::Value, InvokeResult> {
+  get_field(this, MAP).map_err(|e| InvokeResult::runtime(e, cs.clone()))
+}
+
+/// Call a method of the map of a view or a `HashSet`, with the same arguments
+fn delegate(
+  f: Native,
+  mut params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  params[0] = try_invoke!(map_of(try_invoke!(this(&params, &cs, env)), &cs));
+  f(params, cs, env)
+}
+
+/// `remove(key)` of the keys, whether the map contained it
+fn view_remove(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let map = try_invoke!(map_of(try_invoke!(this(&params, &cs, env)), &cs));
+  let key = try_invoke!(arg(&params, 1, &cs));
+  InvokeResult::Ok(boolean(try_invoke!(remove(&map, key, &cs, env)).is_some()))
+}
+
+fn new_iterator(map: runtime::Value, view: View, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let expected = unwrap_or_error!(with_table(&map, |t| t.mod_count), cs);
+  let cursor = Cursor {
+    view,
+    next: (0, 0),
+    last: None,
+    expected,
+  };
+
+  let mut iterator = env.new_instance(ITERATOR);
+  unwrap_or_error!(iterator.set_field(MAP, map), cs);
+  unwrap_or_error!(
+    iterator.set_field(CURSOR, runtime::Value::native(cursor)),
+    cs
+  );
+  InvokeResult::Ok(runtime::Value::from_instance(iterator))
+}
+
+fn view_iterator(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let view = match this.get_type().and_then(|t| View::of(&t)) {
+    None => {
+      return InvokeResult::runtime(
+        RuntimeError::CastError(format!("{:?} as a view of a map", this)),
+        cs,
+      )
+    }
+    Some(v) => v,
+  };
+
+  let map = try_invoke!(map_of(this, &cs));
+  new_iterator(map, view, cs, env)
+}
+
+/// Run a function on the table and the cursor of an iterator
+fn with_cursor<T>(
+  this: &runtime::Value,
+  f: impl FnOnce(&mut Table, &mut Cursor) -> T,
+  cs: &Rc<CallStack>,
+) -> Result<T, InvokeResult> {
+  let error = |e| InvokeResult::runtime(e, cs.clone());
+  let map = get_field(this, MAP).map_err(error)?;
+  let cursor = data(this, CURSOR).map_err(error)?;
+
+  let mut cursor = cursor.lock().unwrap();
+  let cursor = cursor.get_mut::<Cursor>().ok_or_else(|| {
+    error(RuntimeError::CastError(
+      "native data as map iterator".to_owned(),
+    ))
+  })?;
+  with_table(&map, |t| f(t, cursor)).map_err(error)
+}
+
+/// The position of the first entry at or after a position
+fn next_entry(t: &Table, (mut b, mut i): Position) -> Option<Position> {
+  while b < t.buckets.len() {
+    if i < t.buckets[b].len() {
+      return Some((b, i));
+    }
+    b += 1;
+    i = 0;
+  }
+
+  None
+}
+
+fn iterator_has_next(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let has_next = try_invoke!(with_cursor(
+    this,
+    |t, cursor| next_entry(t, cursor.next).is_some(),
+    &cs
+  ));
+  InvokeResult::Ok(boolean(has_next))
+}
+
+fn iterator_next(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let next = try_invoke!(with_cursor(
+    this,
+    |t, cursor| {
+      if t.mod_count != cursor.expected {
+        return Err("Ljava/util/ConcurrentModificationException;");
+      }
+
+      let (b, i) = next_entry(t, cursor.next).ok_or("Ljava/util/NoSuchElementException;")?;
+      cursor.next = (b, i + 1);
+      cursor.last = Some((b, i));
+      Ok((cursor.view, t.buckets[b][i].1.clone()))
+    },
+    &cs
+  ));
+
+  match next {
+    Err(class) => exception(class, None, &cs, env),
+    Ok((View::Entries, node)) => InvokeResult::Ok(node),
+    Ok((View::Keys, node)) => InvokeResult::Ok(unwrap_or_error!(get_field(&node, KEY), cs)),
+    Ok((View::Values, node)) => InvokeResult::Ok(unwrap_or_error!(get_field(&node, VALUE), cs)),
+  }
+}
+
+/// `remove()`: remove the entry last returned by `next()`
+fn iterator_remove(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let removed = try_invoke!(with_cursor(
+    this,
+    |t, cursor| {
+      let (b, i) = cursor.last.ok_or("Ljava/lang/IllegalStateException;")?;
+      if t.mod_count != cursor.expected {
+        return Err("Ljava/util/ConcurrentModificationException;");
+      }
+
+      remove_at(t, (b, i));
+      // The next entry moved back into the place of the removed one
+      cursor.next = (b, i);
+      cursor.last = None;
+      cursor.expected = t.mod_count;
+      Ok(())
+    },
+    &cs
+  ));
+
+  match removed {
+    Err(class) => exception(class, None, &cs, env),
+    Ok(()) => InvokeResult::Ok(runtime::Value::Void),
+  }
+}
+
+/// The constructors `()`, `(int initialCapacity)`, `(int initialCapacity, float loadFactor)` and
+/// `(Collection c)`, which creates a map large enough for the elements
+fn set_init_(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let items = match params.get(1) {
+    Some(c) if !c.is_u32() => Some(try_invoke!(elements(c, &cs, env))),
+    _ => None,
+  };
+
+  let map = new_map(Table::new(0, DEFAULT_LOAD_FACTOR), env);
+  let mut map_params = vec![map.clone()];
+  match &items {
+    Some(items) => {
+      let capacity = ((items.len() as f32 / 0.75) as i32 + 1).max(DEFAULT_CAPACITY as i32);
+      map_params.push(runtime::Value::U32(capacity));
+    }
+    None => map_params.extend(params.iter().skip(1).cloned()),
+  }
+  if let r @ InvokeResult::Exception(..) | r @ InvokeResult::RuntimeError(_) =
+    map_init_(map_params, cs.clone(), env)
+  {
+    return r;
+  }
+
+  {
+    let mut instance = unwrap_or_error!(this.instance(), cs).lock().unwrap();
+    unwrap_or_error!(instance.set_field(MAP, map.clone()), cs);
+  }
+  let present = env.get_static(PRESENT).unwrap_or(runtime::Value::Void);
+  for item in items.unwrap_or_default() {
+    try_invoke!(put(&map, item, present.clone(), &cs, env));
+  }
+
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `add(e)`, whether the set didn't contain the element
+fn set_add(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let map = try_invoke!(map_of(try_invoke!(this(&params, &cs, env)), &cs));
+  let item = try_invoke!(arg(&params, 1, &cs)).clone();
+  let present = env.get_static(PRESENT).unwrap_or(runtime::Value::Void);
+  let existing = try_invoke!(put(&map, item, present, &cs, env));
+  InvokeResult::Ok(boolean(existing.is_none()))
+}
+
+/// `remove(o)`, whether the set contained the element
+fn set_remove(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let map = try_invoke!(map_of(try_invoke!(this(&params, &cs, env)), &cs));
+  let item = try_invoke!(arg(&params, 1, &cs));
+  InvokeResult::Ok(boolean(try_invoke!(remove(&map, item, &cs, env)).is_some()))
+}
+
+fn set_iterator(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let map = try_invoke!(map_of(try_invoke!(this(&params, &cs, env)), &cs));
+  new_iterator(map, View::Keys, cs, env)
+}
@@ -0,0 +1,233 @@
+use std::rc::Rc;
+
+use super::boxed;
+use super::{
+  descriptor, runtime, CallStack, Function, InvokeResult, Module, NativeFunction, RuntimeError,
+};
+use crate::codegen::runtime::{HandleTarget, MethodHandle};
+use crate::codegen::{demangle, format_method, parameter_types, ProxyClass};
+
+/// Flags of `altMetafactory`
+const FLAG_MARKERS: i32 = 1 << 1;
+const FLAG_BRIDGES: i32 = 1 << 2;
+
+/// Creates the lambda objects of a call site:
+/// `newLambda(class, implMethod, instantiatedMethodType, captured...)`
+const NEW_LAMBDA: &str = "CLASS_java__lang__invoke__LambdaMetafactory__newLambda";
+/// The methods of every lambda class
+const LAMBDA: &str = "CLASS_java__lang__invoke__LambdaMetafactory__lambda";
+
+// Slot of the implementation method handle in a lambda object, with the captured values bound
+const IMPL: usize = 0;
+// Slot of the descriptor of `instantiatedMethodType`, which the arguments are converted from
+const TYPE: usize = 1;
+
+pub fn add_functions(m: &mut Module) {
+  m.natives()
+    .untyped_static_method(
+      "java/lang/invoke/LambdaMetafactory.metafactory(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/invoke/MethodType;Ljava/lang/invoke/MethodType;Ljava/lang/invoke/MethodHandle;Ljava/lang/invoke/MethodType;)Ljava/lang/invoke/CallSite;",
+      metafactory,
+    )
+    .untyped_static_method(
+      "java/lang/invoke/LambdaMetafactory.altMetafactory(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/invoke/MethodType;[Ljava/lang/Object;)Ljava/lang/invoke/CallSite;",
+      metafactory,
+    );
+
+  m.add_function(
+    NEW_LAMBDA.to_owned(),
+    Function::Native(NativeFunction::new(new_lambda)),
+  );
+
+  m.add_function(
+    LAMBDA.to_owned(),
+    Function::Native(NativeFunction::new(lambda)),
+  );
+}
+
+/// Bootstrap method of lambdas and method references:
+/// `metafactory(lookup, name, factoryType, samMethodType, implMethod, instantiatedMethodType)`,
+/// or `altMetafactory(lookup, name, factoryType, samMethodType, implMethod,
+/// instantiatedMethodType, flags, ...)` which can add marker interfaces and bridge methods.
+///
+/// Each call site gets a class implementing the functional interface (the return type of
+/// `factoryType`), and its target creates an instance holding the captured values (the
+/// parameters of `factoryType`). The method `name` of the instance, with the descriptor of
+/// `samMethodType`, calls `implMethod` with the captured values followed by its own arguments.
+/// The arguments and the result are boxed or unboxed where `implMethod` uses primitives in place
+/// of the wrappers of `instantiatedMethodType` or the other way around, e.g. for
+/// `Integer::parseInt` as a `Function<String, Integer>`.
+fn metafactory(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  if params.len() < 6 {
+    return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(6, params.len()), cs);
+  }
+
+  let name = match params[1].string() {
+    None => {
+      return InvokeResult::runtime(
+        RuntimeError::CastError(format!("{:?} as string", params[1])),
+        cs,
+      )
+    }
+    Some(n) => n,
+  };
+  let factory_type = unwrap_or_error!(descriptor(&params[2], env), cs);
+  // The erased descriptor, which is the one of the interface method
+  let sam_type = unwrap_or_error!(descriptor(&params[3], env), cs);
+  let instantiated_type = unwrap_or_error!(descriptor(&params[5], env), cs);
+  let interface = match factory_type.find(')') {
+    None => {
+      return InvokeResult::runtime(
+        RuntimeError::CastError(format!("{} as method descriptor", factory_type)),
+        cs,
+      )
+    }
+    Some(i) => factory_type[i + 1..].to_owned(),
+  };
+
+  let mut interfaces = vec![interface.clone()];
+  let mut methods = vec![(format_method(&name, &sam_type), LAMBDA.to_owned())];
+  if let Some(runtime::Value::U32(flags)) = params.get(6) {
+    let mut extra = params.iter().skip(7);
+    if flags & FLAG_MARKERS != 0 {
+      let count = extra.next().map_or(0, |c| c.to_single().max(0) as usize);
+      for marker in extra.by_ref().take(count) {
+        interfaces.push(unwrap_or_error!(
+          env
+            .field(marker, "descriptor")
+            .map(|d| d.string().unwrap_or_default()),
+          cs
+        ));
+      }
+    }
+    // The bridges are the same method with other (erased) descriptors
+    if flags & FLAG_BRIDGES != 0 {
+      let count = extra.next().map_or(0, |c| c.to_single().max(0) as usize);
+      for bridge in extra.take(count) {
+        let bridge_type = unwrap_or_error!(descriptor(bridge, env), cs);
+        methods.push((format_method(&name, &bridge_type), LAMBDA.to_owned()));
+      }
+    }
+  }
+
+  // Lambda classes are named after the class that created them, like `Main$$Lambda$0`
+  let caller = cs
+    .frames()
+    .get(1)
+    .and_then(|f| demangle(&f.cur))
+    .map_or(interface, |(class, _)| class);
+  let class = env.define_proxy_class(
+    &format!("{}$$Lambda$", caller.trim_end_matches(';')),
+    |_| ProxyClass {
+      interfaces,
+      methods,
+    },
+  );
+
+  let factory = env.method_handle(MethodHandle {
+    target: HandleTarget::Function(NEW_LAMBDA.to_owned()),
+    bound: vec![
+      runtime::Value::new_string(&class),
+      params[4].clone(),
+      runtime::Value::new_string(&instantiated_type),
+    ],
+    descriptor: factory_type,
+  });
+
+  InvokeResult::Ok(env.constant_call_site(factory))
+}
+
+fn new_lambda(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  if params.len() < 3 {
+    return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(3, params.len()), cs);
+  }
+
+  let class = match params[0].string() {
+    None => {
+      return InvokeResult::runtime(
+        RuntimeError::CastError(format!("{:?} as string", params[0])),
+        cs,
+      )
+    }
+    Some(c) => c,
+  };
+  let implementation = unwrap_or_error!(env.bind_handle(&params[1], params[3..].to_vec()), cs);
+
+  let mut lambda = runtime::Instance::new(class, 2);
+  unwrap_or_error!(lambda.set_field(IMPL, implementation), cs);
+  unwrap_or_error!(lambda.set_field(TYPE, params[2].clone()), cs);
+
+  InvokeResult::Ok(runtime::Value::from_instance(lambda))
+}
+
+/// The method of a lambda object (`this`), calling its implementation with the arguments
+fn lambda(mut params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  if params.is_empty() {
+    return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs);
+  }
+
+  let this = params.remove(0);
+  let (implementation, instantiated_type) = {
+    let instance = unwrap_or_error!(this.instance(), cs).lock().unwrap();
+    match (instance.get_field(IMPL), instance.get_field(TYPE)) {
+      (None, _) => return InvokeResult::runtime(RuntimeError::FieldOutOfBounds(IMPL), cs),
+      (_, None) => return InvokeResult::runtime(RuntimeError::FieldOutOfBounds(TYPE), cs),
+      (Some(h), Some(t)) => (h.clone(), t.string().unwrap_or_default()),
+    }
+  };
+  let impl_type = unwrap_or_error!(env.method_handle_data(&implementation), cs).descriptor;
+
+  let from = parameter_types(&instantiated_type);
+  let to = parameter_types(&impl_type);
+  if from.len() != to.len() {
+    return InvokeResult::runtime(
+      RuntimeError::WrongNumberOfParameters(to.len(), from.len()),
+      cs,
+    );
+  }
+
+  // Longs and doubles take two registers
+  let registers = params.len();
+  let mut values = params.into_iter();
+  let mut args = Vec::new();
+  for (from, to) in from.into_iter().zip(to) {
+    let v = match values.next() {
+      None => {
+        return InvokeResult::runtime(
+          RuntimeError::WrongNumberOfParameters(args.len() + 1, registers),
+          cs,
+        )
+      }
+      Some(v) => v,
+    };
+    if is_wide(from) {
+      values.next();
+    }
+
+    match boxed::convert(from, to, v, &cs, env) {
+      Err(r) => return r,
+      Ok(v) => args.push(v),
+    }
+    if is_wide(to) {
+      args.push(runtime::Value::Void);
+    }
+  }
+
+  match env.invoke_handle(&implementation, cs.clone(), args) {
+    InvokeResult::Ok(v) => match return_type(&instantiated_type) {
+      "V" => InvokeResult::Ok(runtime::Value::Void),
+      to => match boxed::convert(return_type(&impl_type), to, v, &cs, env) {
+        Err(r) => r,
+        Ok(v) => InvokeResult::Ok(v),
+      },
+    },
+    r => r,
+  }
+}
+
+fn is_wide(type_: &str) -> bool {
+  type_ == "J" || type_ == "D"
+}
+
+fn return_type(descriptor: &str) -> &str {
+  descriptor.rfind(')').map_or("V", |i| &descriptor[i + 1..])
+}
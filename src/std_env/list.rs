@@ -0,0 +1,665 @@
+//! `ArrayList`, `LinkedList` and `ArrayDeque`, which keep their elements in a `VecDeque`, and
+//! `AbstractList$Itr`, the iterator of lists and deques
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use super::collection::{
+  arg, boolean, call, contains, elements, equals, exception, int_arg, non_null, size, sort,
+};
+use super::{runtime, CallStack, InvokeResult, Module, Native, RuntimeError};
+use crate::codegen::runtime::NativeData;
+
+// Slot of the `elements` field, holding the `Elements` as native data
+const ELEMENTS: usize = 0;
+// Slots of the fields of `AbstractList$Itr`: the list, and the `Cursor` as native data
+const LIST: usize = 0;
+const CURSOR: usize = 1;
+
+/// The elements of a list or deque, and the number of times it was structurally modified
+/// (`modCount`) so its iterators can detect concurrent modifications
+struct Elements {
+  items: VecDeque<runtime::Value>,
+  mod_count: u32,
+}
+
+/// The position of an iterator
+struct Cursor {
+  /// Index of the element `next()` returns
+  next: usize,
+  /// Index of the element last returned, which `remove()` removes
+  last: Option<usize>,
+  /// The `modCount` of a native list when the iterator last saw it
+  expected: Option<u32>,
+}
+
+pub fn add_functions(m: &mut Module) {
+  let lists: &[(&str, Native)] = &[
+    ("<init>()V", init_),
+    ("<init>(I)V", init_),
+    ("<init>(Ljava/util/Collection;)V", init_),
+    ("add(Ljava/lang/Object;)Z", add),
+    ("add(ILjava/lang/Object;)V", add),
+    ("addAll(Ljava/util/Collection;)Z", add_all),
+    ("addAll(ILjava/util/Collection;)Z", add_all),
+    ("get(I)Ljava/lang/Object;", get),
+    ("set(ILjava/lang/Object;)Ljava/lang/Object;", set),
+    ("remove(I)Ljava/lang/Object;", remove_index),
+    ("remove(Ljava/lang/Object;)Z", remove_object),
+    ("size()I", size_),
+    ("contains(Ljava/lang/Object;)Z", contains_),
+    ("clear()V", clear),
+    ("sort(Ljava/util/Comparator;)V", sort_),
+    ("ensureCapacity(I)V", nothing),
+    ("trimToSize()V", nothing),
+  ];
+  let deques: &[(&str, Native)] = &[
+    ("addFirst(Ljava/lang/Object;)V", |p, cs, env| {
+      insert(End::First, false, p, cs, env)
+    }),
+    ("addLast(Ljava/lang/Object;)V", |p, cs, env| {
+      insert(End::Last, false, p, cs, env)
+    }),
+    ("push(Ljava/lang/Object;)V", |p, cs, env| {
+      insert(End::First, false, p, cs, env)
+    }),
+    ("offerFirst(Ljava/lang/Object;)Z", |p, cs, env| {
+      insert(End::First, true, p, cs, env)
+    }),
+    ("offerLast(Ljava/lang/Object;)Z", |p, cs, env| {
+      insert(End::Last, true, p, cs, env)
+    }),
+    ("offer(Ljava/lang/Object;)Z", |p, cs, env| {
+      insert(End::Last, true, p, cs, env)
+    }),
+    ("remove()Ljava/lang/Object;", |p, cs, env| {
+      take(End::First, true, true, p, cs, env)
+    }),
+    ("removeFirst()Ljava/lang/Object;", |p, cs, env| {
+      take(End::First, true, true, p, cs, env)
+    }),
+    ("removeLast()Ljava/lang/Object;", |p, cs, env| {
+      take(End::Last, true, true, p, cs, env)
+    }),
+    ("pop()Ljava/lang/Object;", |p, cs, env| {
+      take(End::First, true, true, p, cs, env)
+    }),
+    ("pollFirst()Ljava/lang/Object;", |p, cs, env| {
+      take(End::First, true, false, p, cs, env)
+    }),
+    ("pollLast()Ljava/lang/Object;", |p, cs, env| {
+      take(End::Last, true, false, p, cs, env)
+    }),
+    ("poll()Ljava/lang/Object;", |p, cs, env| {
+      take(End::First, true, false, p, cs, env)
+    }),
+    ("getFirst()Ljava/lang/Object;", |p, cs, env| {
+      take(End::First, false, true, p, cs, env)
+    }),
+    ("getLast()Ljava/lang/Object;", |p, cs, env| {
+      take(End::Last, false, true, p, cs, env)
+    }),
+    ("element()Ljava/lang/Object;", |p, cs, env| {
+      take(End::First, false, true, p, cs, env)
+    }),
+    ("peekFirst()Ljava/lang/Object;", |p, cs, env| {
+      take(End::First, false, false, p, cs, env)
+    }),
+    ("peekLast()Ljava/lang/Object;", |p, cs, env| {
+      take(End::Last, false, false, p, cs, env)
+    }),
+    ("peek()Ljava/lang/Object;", |p, cs, env| {
+      take(End::First, false, false, p, cs, env)
+    }),
+  ];
+  let array_deque: &[(&str, Native)] = &[
+    ("<init>()V", init_),
+    ("<init>(I)V", init_),
+    ("<init>(Ljava/util/Collection;)V", init_),
+    ("add(Ljava/lang/Object;)Z", |p, cs, env| {
+      insert(End::Last, true, p, cs, env)
+    }),
+    ("remove(Ljava/lang/Object;)Z", remove_object),
+    ("size()I", size_),
+    ("contains(Ljava/lang/Object;)Z", contains_),
+    ("clear()V", clear),
+    ("iterator()Ljava/util/Iterator;", iterator),
+  ];
+  let iterator_: &[(&str, Native)] = &[
+    ("hasNext()Z", iterator_has_next),
+    ("next()Ljava/lang/Object;", iterator_next),
+    ("remove()V", iterator_remove),
+  ];
+
+  let classes: &[(&str, &[(&str, Native)])] = &[
+    ("ArrayList", lists),
+    ("LinkedList", lists),
+    ("LinkedList", deques),
+    ("ArrayDeque", deques),
+    ("ArrayDeque", array_deque),
+    ("AbstractList$Itr", iterator_),
+  ];
+  for (class, functions) in classes {
+    for (method, f) in functions.iter() {
+      m.natives()
+        .untyped_method(&format!("java/util/{}.{}", class, method), *f);
+    }
+  }
+}
+
+fn data(this: &runtime::Value, slot: usize) -> Result<Arc<Mutex<NativeData>>, RuntimeError> {
+  match this.instance()?.lock().unwrap().get_field(slot) {
+    None => Err(RuntimeError::FieldOutOfBounds(slot)),
+    Some(v) => Ok(v.native_data()?.clone()),
+  }
+}
+
+/// Run a function on the elements of a list or deque
+fn with_elements<T>(
+  this: &runtime::Value,
+  f: impl FnOnce(&mut Elements) -> T,
+) -> Result<T, RuntimeError> {
+  let data = data(this, ELEMENTS)?;
+  let mut data = data.lock().unwrap();
+  match data.get_mut::<Elements>() {
+    None => Err(RuntimeError::CastError("native data as list".to_owned())),
+    Some(e) => Ok(f(e)),
+  }
+}
+
+/// A copy of the elements of a native list or deque, or `None` for any other object
+pub(super) fn snapshot(c: &runtime::Value) -> Option<Vec<runtime::Value>> {
+  if !c.is_instance() {
+    return None;
+  }
+
+  with_elements(c, |e| e.items.iter().cloned().collect()).ok()
+}
+
+/// `this`, throwing a `NullPointerException` for null
+fn this<'a>(
+  params: &'a [runtime::Value],
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<&'a runtime::Value, InvokeResult> {
+  non_null(params, 0, cs, env)
+}
+
+/// Whether the deque is an `ArrayDeque`, which doesn't allow nulls
+fn is_array_deque(this: &runtime::Value, env: &Module) -> bool {
+  this
+    .get_type()
+    .is_some_and(|t| env.is_assignable(&t, "Ljava/util/ArrayDeque;"))
+}
+
+/// An `IndexOutOfBoundsException` for an index of an element: `ArrayList` checks the index
+/// like `Objects.checkIndex`, and `LinkedList` (and inserting into any list) with its own message
+fn index_error(
+  this: &runtime::Value,
+  insert: bool,
+  index: i32,
+  len: usize,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let linked = this
+    .get_type()
+    .is_some_and(|t| env.is_assignable(&t, "Ljava/util/LinkedList;"));
+  let message = match insert || linked {
+    true => format!("Index: {}, Size: {}", index, len),
+    false => format!("Index {} out of bounds for length {}", index, len),
+  };
+
+  exception(
+    "Ljava/lang/IndexOutOfBoundsException;",
+    Some(&message),
+    cs,
+    env,
+  )
+}
+
+/// Check an index of an element (or where one is inserted), and get it as an index of the
+/// `VecDeque`
+fn check_index(
+  this: &runtime::Value,
+  index: i32,
+  insert: bool,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<usize, InvokeResult> {
+  let len =
+    with_elements(this, |e| e.items.len()).map_err(|e| InvokeResult::runtime(e, cs.clone()))?;
+  let end = if insert { len + 1 } else { len };
+  if index < 0 || index as usize >= end {
+    return Err(index_error(this, insert, index, len, cs, env));
+  }
+
+  Ok(index as usize)
+}
+
+/// The constructors `()`, `(int initialCapacity)` and `(Collection c)`
+fn init_(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let items = match params.get(1) {
+    None => VecDeque::new(),
+    Some(runtime::Value::U32(capacity)) if *capacity < 0 => {
+      let message = format!("Illegal Capacity: {}", capacity);
+      return exception(
+        "Ljava/lang/IllegalArgumentException;",
+        Some(&message),
+        &cs,
+        env,
+      );
+    }
+    Some(runtime::Value::U32(capacity)) => VecDeque::with_capacity(*capacity as usize),
+    Some(c) => try_invoke!(elements(c, &cs, env)).into_iter().collect(),
+  };
+
+  if is_array_deque(this, env) && items.iter().any(|i| i.is_null()) {
+    return exception("Ljava/lang/NullPointerException;", None, &cs, env);
+  }
+
+  let data = runtime::Value::native(Elements {
+    items,
+    mod_count: 0,
+  });
+  let mut this = unwrap_or_error!(this.instance(), cs).lock().unwrap();
+  unwrap_or_error!(this.set_field(ELEMENTS, data), cs);
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+fn nothing(_params: Vec<runtime::Value>, _cs: Rc<CallStack>, _env: &Module) -> InvokeResult {
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `add(e)`, or `add(index, e)` which inserts it
+fn add(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  if params.len() < 3 {
+    return insert(End::Last, true, params, cs, env);
+  }
+
+  let index = try_invoke!(int_arg(&params, 1, &cs));
+  let item = try_invoke!(arg(&params, 2, &cs)).clone();
+  let index = try_invoke!(check_index(this, index, true, &cs, env));
+  unwrap_or_error!(
+    with_elements(this, |e| {
+      e.items.insert(index, item);
+      e.mod_count += 1;
+    }),
+    cs
+  );
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `addAll(c)`, or `addAll(index, c)` which inserts the elements. Whether the list changed.
+fn add_all(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let (index, c) = match params.len() {
+    n if n < 3 => (None, try_invoke!(non_null(&params, 1, &cs, env))),
+    _ => {
+      let index = try_invoke!(int_arg(&params, 1, &cs));
+      let index = try_invoke!(check_index(this, index, true, &cs, env));
+      (Some(index), try_invoke!(non_null(&params, 2, &cs, env)))
+    }
+  };
+
+  let items = try_invoke!(elements(c, &cs, env));
+  let changed = !items.is_empty();
+  unwrap_or_error!(
+    with_elements(this, |e| {
+      let index = index.unwrap_or(e.items.len());
+      for (i, item) in items.into_iter().enumerate() {
+        e.items.insert(index + i, item);
+      }
+      e.mod_count += 1;
+    }),
+    cs
+  );
+  InvokeResult::Ok(boolean(changed))
+}
+
+fn get(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let index = try_invoke!(int_arg(&params, 1, &cs));
+  let index = try_invoke!(check_index(this, index, false, &cs, env));
+  let item = unwrap_or_error!(with_elements(this, |e| e.items[index].clone()), cs);
+  InvokeResult::Ok(item)
+}
+
+/// `set(index, e)`, returning the element it replaced
+fn set(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let index = try_invoke!(int_arg(&params, 1, &cs));
+  let item = try_invoke!(arg(&params, 2, &cs)).clone();
+  let index = try_invoke!(check_index(this, index, false, &cs, env));
+  let old = unwrap_or_error!(
+    with_elements(this, |e| std::mem::replace(&mut e.items[index], item)),
+    cs
+  );
+  InvokeResult::Ok(old)
+}
+
+/// `remove(index)`: remove the element at an index, returning it
+fn remove_index(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let index = try_invoke!(int_arg(&params, 1, &cs));
+  let index = try_invoke!(check_index(this, index, false, &cs, env));
+  let item = unwrap_or_error!(
+    with_elements(this, |e| {
+      e.mod_count += 1;
+      e.items.remove(index)
+    }),
+    cs
+  );
+  InvokeResult::Ok(item.unwrap_or(runtime::Value::Void))
+}
+
+/// `remove(Object)`: remove the first element equal to the object, returning whether there was one
+fn remove_object(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let o = try_invoke!(arg(&params, 1, &cs));
+
+  let items = unwrap_or_error!(with_elements(this, |e| e.items.clone()), cs);
+  for (i, item) in items.iter().enumerate() {
+    if try_invoke!(equals(o, item, &cs, env)) {
+      unwrap_or_error!(
+        with_elements(this, |e| {
+          e.items.remove(i);
+          e.mod_count += 1;
+        }),
+        cs
+      );
+      return InvokeResult::Ok(boolean(true));
+    }
+  }
+
+  InvokeResult::Ok(boolean(false))
+}
+
+fn size_(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let len = unwrap_or_error!(with_elements(this, |e| e.items.len()), cs);
+  InvokeResult::Ok(runtime::Value::U32(len as i32))
+}
+
+fn contains_(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let o = try_invoke!(arg(&params, 1, &cs));
+  let items: Vec<_> = unwrap_or_error!(with_elements(this, |e| e.items.clone()), cs).into();
+  InvokeResult::Ok(boolean(try_invoke!(contains(&items, o, &cs, env))))
+}
+
+fn clear(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  unwrap_or_error!(
+    with_elements(this, |e| {
+      e.items.clear();
+      e.mod_count += 1;
+    }),
+    cs
+  );
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// `sort(comparator)`, with the natural ordering of the elements if the comparator is null
+fn sort_(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let comparator = params.get(1).cloned().unwrap_or(runtime::Value::Void);
+
+  let mut items: Vec<_> = unwrap_or_error!(with_elements(this, |e| e.items.clone()), cs).into();
+  try_invoke!(sort(&mut items, &comparator, &cs, env));
+  unwrap_or_error!(
+    with_elements(this, |e| {
+      e.items = items.into();
+      e.mod_count += 1;
+    }),
+    cs
+  );
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+/// An end of a deque
+#[derive(Clone, Copy)]
+enum End {
+  First,
+  Last,
+}
+
+/// Add an element at an end of a deque: `addFirst`, `offerLast`, ... The `offer` methods (and
+/// `add`) return true.
+fn insert(
+  end: End,
+  returns: bool,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let item = match is_array_deque(this, env) {
+    true => try_invoke!(non_null(&params, 1, &cs, env)),
+    false => try_invoke!(arg(&params, 1, &cs)),
+  };
+
+  unwrap_or_error!(
+    with_elements(this, |e| {
+      match end {
+        End::First => e.items.push_front(item.clone()),
+        End::Last => e.items.push_back(item.clone()),
+      }
+      e.mod_count += 1;
+    }),
+    cs
+  );
+  InvokeResult::Ok(match returns {
+    true => boolean(true),
+    false => runtime::Value::Void,
+  })
+}
+
+/// Get (and maybe remove) the element at an end of a deque. When the deque is empty, the
+/// `remove` and `get` methods throw a `NoSuchElementException`, `poll` and `peek` return null.
+fn take(
+  end: End,
+  remove: bool,
+  throws: bool,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let item = unwrap_or_error!(
+    with_elements(this, |e| match (remove, end) {
+      (false, End::First) => e.items.front().cloned(),
+      (false, End::Last) => e.items.back().cloned(),
+      (true, end) => {
+        let item = match end {
+          End::First => e.items.pop_front(),
+          End::Last => e.items.pop_back(),
+        };
+        if item.is_some() {
+          e.mod_count += 1;
+        }
+        item
+      }
+    }),
+    cs
+  );
+
+  match item {
+    Some(item) => InvokeResult::Ok(item),
+    None if throws => exception("Ljava/util/NoSuchElementException;", None, &cs, env),
+    None => InvokeResult::Ok(runtime::Value::Void),
+  }
+}
+
+/// `iterator()` of a list or deque: an `AbstractList$Itr`. Native lists are read directly, other
+/// lists with their `size()`, `get(index)` and `remove(index)`.
+pub(super) fn iterator(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let expected = with_elements(this, |e| e.mod_count).ok();
+
+  let mut iterator = env.new_instance("Ljava/util/AbstractList$Itr;");
+  let cursor = Cursor {
+    next: 0,
+    last: None,
+    expected,
+  };
+  unwrap_or_error!(iterator.set_field(LIST, this.clone()), cs);
+  unwrap_or_error!(
+    iterator.set_field(CURSOR, runtime::Value::native(cursor)),
+    cs
+  );
+  InvokeResult::Ok(runtime::Value::from_instance(iterator))
+}
+
+/// Run a function on the list and the cursor of an iterator
+fn with_cursor<T>(
+  this: &runtime::Value,
+  f: impl FnOnce(&runtime::Value, &mut Cursor) -> Result<T, InvokeResult>,
+  cs: &Rc<CallStack>,
+) -> Result<T, InvokeResult> {
+  let fields = this.instance().map(|i| {
+    let i = i.lock().unwrap();
+    (i.get_field(LIST).cloned(), i.get_field(CURSOR).cloned())
+  });
+  let (list, cursor) = match fields {
+    Ok((Some(list), Some(runtime::Value::Native(cursor)))) => (list, cursor),
+    Ok(_) => {
+      return Err(InvokeResult::runtime(
+        RuntimeError::CastError(format!("{:?} as list iterator", this)),
+        cs.clone(),
+      ))
+    }
+    Err(e) => return Err(InvokeResult::runtime(e, cs.clone())),
+  };
+
+  let mut cursor = cursor.lock().unwrap();
+  match cursor.get_mut::<Cursor>() {
+    None => Err(InvokeResult::runtime(
+      RuntimeError::CastError("native data as list iterator".to_owned()),
+      cs.clone(),
+    )),
+    Some(c) => f(&list, c),
+  }
+}
+
+/// Throw a `ConcurrentModificationException` if a native list was changed other than through
+/// the iterator
+fn check_modification(
+  list: &runtime::Value,
+  cursor: &Cursor,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<(), InvokeResult> {
+  match (cursor.expected, with_elements(list, |e| e.mod_count).ok()) {
+    (Some(expected), Some(mod_count)) if expected != mod_count => Err(exception(
+      "Ljava/util/ConcurrentModificationException;",
+      None,
+      cs,
+      env,
+    )),
+    _ => Ok(()),
+  }
+}
+
+fn list_size(
+  list: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<usize, InvokeResult> {
+  match with_elements(list, |e| e.items.len()) {
+    Ok(len) => Ok(len),
+    Err(_) => size(list, cs, env),
+  }
+}
+
+fn iterator_has_next(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let has_next = with_cursor(
+    this,
+    |list, cursor| Ok(cursor.next != list_size(list, &cs, env)?),
+    &cs,
+  );
+  InvokeResult::Ok(boolean(try_invoke!(has_next)))
+}
+
+fn iterator_next(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let item = with_cursor(
+    this,
+    |list, cursor| {
+      check_modification(list, cursor, &cs, env)?;
+      let index = cursor.next;
+      if index >= list_size(list, &cs, env)? {
+        return Err(exception(
+          "Ljava/util/NoSuchElementException;",
+          None,
+          &cs,
+          env,
+        ));
+      }
+
+      let item = match with_elements(list, |e| e.items[index].clone()) {
+        Ok(item) => item,
+        Err(_) => call(
+          "get(I)Ljava/lang/Object;",
+          vec![list.clone(), runtime::Value::U32(index as i32)],
+          &cs,
+          env,
+        )?,
+      };
+      cursor.next = index + 1;
+      cursor.last = Some(index);
+      Ok(item)
+    },
+    &cs,
+  );
+  InvokeResult::Ok(try_invoke!(item))
+}
+
+/// `remove()`: remove the element last returned by `next()`
+fn iterator_remove(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let this = try_invoke!(this(&params, &cs, env));
+  let removed = with_cursor(
+    this,
+    |list, cursor| {
+      let last = match cursor.last {
+        None => {
+          return Err(exception(
+            "Ljava/lang/IllegalStateException;",
+            None,
+            &cs,
+            env,
+          ))
+        }
+        Some(last) => last,
+      };
+      check_modification(list, cursor, &cs, env)?;
+
+      let mod_count = with_elements(list, |e| {
+        e.items.remove(last);
+        e.mod_count += 1;
+        e.mod_count
+      });
+      if mod_count.is_err() {
+        let params = vec![list.clone(), runtime::Value::U32(last as i32)];
+        call("remove(I)Ljava/lang/Object;", params, &cs, env)?;
+      }
+
+      cursor.next = last;
+      cursor.last = None;
+      cursor.expected = mod_count.ok();
+      Ok(())
+    },
+    &cs,
+  );
+
+  try_invoke!(removed);
+  InvokeResult::Ok(runtime::Value::Void)
+}
@@ -0,0 +1,545 @@
+//! `java.lang.Math` and `java.lang.StrictMath`, which share the same natives
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{runtime, CallStack, InvokeResult, Module, Native, RuntimeError};
+use crate::codegen::native::{Context, TypedNative};
+
+/// The hidden static holding the seed of `Math.random()`, shared by `Math` and `StrictMath`
+const SEED: &str = "CLASS_java__lang__Math__seed";
+/// Multiplier, increment and mask of the linear congruential generator of `java.util.Random`
+const MULTIPLIER: i64 = 0x5_deec_e66d;
+const INCREMENT: i64 = 0xb;
+const MASK: i64 = (1 << 48) - 1;
+
+pub fn add_functions(m: &mut Module) {
+  const DOUBLE: &[&str] = &["(D)D"];
+  const DOUBLE2: &[&str] = &["(DD)D"];
+  let functions: &[(&str, &[&str], Native)] = &[
+    ("sqrt", DOUBLE, |p, cs, _env| floating(f64::sqrt, p, cs)),
+    ("cbrt", DOUBLE, |p, cs, _env| floating(f64::cbrt, p, cs)),
+    ("floor", DOUBLE, |p, cs, _env| floating(f64::floor, p, cs)),
+    ("ceil", DOUBLE, |p, cs, _env| floating(f64::ceil, p, cs)),
+    ("rint", DOUBLE, |p, cs, _env| floating(rint, p, cs)),
+    ("sin", DOUBLE, |p, cs, _env| floating(f64::sin, p, cs)),
+    ("cos", DOUBLE, |p, cs, _env| floating(f64::cos, p, cs)),
+    ("tan", DOUBLE, |p, cs, _env| floating(f64::tan, p, cs)),
+    ("asin", DOUBLE, |p, cs, _env| floating(f64::asin, p, cs)),
+    ("acos", DOUBLE, |p, cs, _env| floating(f64::acos, p, cs)),
+    ("atan", DOUBLE, |p, cs, _env| floating(f64::atan, p, cs)),
+    ("sinh", DOUBLE, |p, cs, _env| floating(f64::sinh, p, cs)),
+    ("cosh", DOUBLE, |p, cs, _env| floating(f64::cosh, p, cs)),
+    ("tanh", DOUBLE, |p, cs, _env| floating(f64::tanh, p, cs)),
+    ("exp", DOUBLE, |p, cs, _env| floating(f64::exp, p, cs)),
+    ("expm1", DOUBLE, |p, cs, _env| floating(f64::exp_m1, p, cs)),
+    ("log", DOUBLE, |p, cs, _env| floating(f64::ln, p, cs)),
+    ("log10", DOUBLE, |p, cs, _env| floating(f64::log10, p, cs)),
+    ("log1p", DOUBLE, |p, cs, _env| floating(f64::ln_1p, p, cs)),
+    ("toRadians", DOUBLE, |p, cs, _env| {
+      floating(f64::to_radians, p, cs)
+    }),
+    ("toDegrees", DOUBLE, |p, cs, _env| {
+      floating(f64::to_degrees, p, cs)
+    }),
+    ("atan2", DOUBLE2, |p, cs, _env| floating2(f64::atan2, p, cs)),
+    ("hypot", DOUBLE2, |p, cs, _env| floating2(f64::hypot, p, cs)),
+    ("pow", DOUBLE2, |p, cs, _env| floating2(pow, p, cs)),
+    ("random", &["()D"], java_lang_math_random),
+  ];
+
+  for class in &["Math", "StrictMath"] {
+    let method = |descriptor: &str| format!("java/lang/{}.{}", class, descriptor);
+    for (name, descriptors, f) in functions {
+      for descriptor in descriptors.iter() {
+        m.natives()
+          .untyped_static_method(&method(&format!("{}{}", name, descriptor)), *f);
+      }
+    }
+
+    for (descriptor, native) in overloads() {
+      m.natives().static_method(&method(descriptor), native);
+    }
+
+    let constants = [("PI", std::f64::consts::PI), ("E", std::f64::consts::E)];
+    for (name, c) in &constants {
+      m.set_static(
+        format!("CLASS_java__lang__{}__{}", class, name),
+        runtime::Value::U64(c.to_bits() as i64),
+      );
+    }
+  }
+}
+
+/// The natives of the methods with overloads for ints and longs, or floats and doubles. Their
+/// values look the same in the registers, so each overload gets its own typed native.
+fn overloads() -> Vec<(&'static str, TypedNative)> {
+  vec![
+    (
+      "abs(I)I",
+      crate::native!(|ctx, a: i32| -> i32 { a.wrapping_abs() }),
+    ),
+    (
+      "abs(J)J",
+      crate::native!(|ctx, a: i64| -> i64 { a.wrapping_abs() }),
+    ),
+    ("abs(F)F", crate::native!(|ctx, a: f32| -> f32 { a.abs() })),
+    ("abs(D)D", crate::native!(|ctx, a: f64| -> f64 { a.abs() })),
+    (
+      "min(II)I",
+      crate::native!(|ctx, a: i32, b: i32| -> i32 { a.min(b) }),
+    ),
+    (
+      "min(JJ)J",
+      crate::native!(|ctx, a: i64, b: i64| -> i64 { a.min(b) }),
+    ),
+    (
+      "min(FF)F",
+      crate::native!(|ctx, a: f32, b: f32| -> f32 {
+        min_max_floating(Ordering::Less, a as f64, b as f64) as f32
+      }),
+    ),
+    (
+      "min(DD)D",
+      crate::native!(|ctx, a: f64, b: f64| -> f64 { min_max_floating(Ordering::Less, a, b) }),
+    ),
+    (
+      "max(II)I",
+      crate::native!(|ctx, a: i32, b: i32| -> i32 { a.max(b) }),
+    ),
+    (
+      "max(JJ)J",
+      crate::native!(|ctx, a: i64, b: i64| -> i64 { a.max(b) }),
+    ),
+    (
+      "max(FF)F",
+      crate::native!(|ctx, a: f32, b: f32| -> f32 {
+        min_max_floating(Ordering::Greater, a as f64, b as f64) as f32
+      }),
+    ),
+    (
+      "max(DD)D",
+      crate::native!(|ctx, a: f64, b: f64| -> f64 { min_max_floating(Ordering::Greater, a, b) }),
+    ),
+    // Casts saturate and take NaN to 0, like java
+    (
+      "round(F)I",
+      crate::native!(|ctx, a: f32| -> i32 { round(a as f64) as i32 }),
+    ),
+    (
+      "round(D)J",
+      crate::native!(|ctx, a: f64| -> i64 { round(a) as i64 }),
+    ),
+    (
+      "signum(F)F",
+      crate::native!(|ctx, a: f32| -> f32 { signum(a as f64) as f32 }),
+    ),
+    (
+      "signum(D)D",
+      crate::native!(|ctx, a: f64| -> f64 { signum(a) }),
+    ),
+    (
+      "addExact(II)I",
+      crate::native!(|ctx, a: i32, b: i32| -> Result<i32, InvokeResult> {
+        int_exact(a.checked_add(b), ctx)
+      }),
+    ),
+    (
+      "addExact(JJ)J",
+      crate::native!(|ctx, a: i64, b: i64| -> Result<i64, InvokeResult> {
+        long_exact(a.checked_add(b), ctx)
+      }),
+    ),
+    (
+      "subtractExact(II)I",
+      crate::native!(|ctx, a: i32, b: i32| -> Result<i32, InvokeResult> {
+        int_exact(a.checked_sub(b), ctx)
+      }),
+    ),
+    (
+      "subtractExact(JJ)J",
+      crate::native!(|ctx, a: i64, b: i64| -> Result<i64, InvokeResult> {
+        long_exact(a.checked_sub(b), ctx)
+      }),
+    ),
+    (
+      "multiplyExact(II)I",
+      crate::native!(|ctx, a: i32, b: i32| -> Result<i32, InvokeResult> {
+        int_exact(a.checked_mul(b), ctx)
+      }),
+    ),
+    (
+      "multiplyExact(JJ)J",
+      crate::native!(|ctx, a: i64, b: i64| -> Result<i64, InvokeResult> {
+        long_exact(a.checked_mul(b), ctx)
+      }),
+    ),
+    (
+      "incrementExact(I)I",
+      crate::native!(|ctx, a: i32| -> Result<i32, InvokeResult> {
+        int_exact(a.checked_add(1), ctx)
+      }),
+    ),
+    (
+      "incrementExact(J)J",
+      crate::native!(|ctx, a: i64| -> Result<i64, InvokeResult> {
+        long_exact(a.checked_add(1), ctx)
+      }),
+    ),
+    (
+      "decrementExact(I)I",
+      crate::native!(|ctx, a: i32| -> Result<i32, InvokeResult> {
+        int_exact(a.checked_sub(1), ctx)
+      }),
+    ),
+    (
+      "decrementExact(J)J",
+      crate::native!(|ctx, a: i64| -> Result<i64, InvokeResult> {
+        long_exact(a.checked_sub(1), ctx)
+      }),
+    ),
+    (
+      "negateExact(I)I",
+      crate::native!(|ctx, a: i32| -> Result<i32, InvokeResult> {
+        int_exact(a.checked_neg(), ctx)
+      }),
+    ),
+    (
+      "negateExact(J)J",
+      crate::native!(|ctx, a: i64| -> Result<i64, InvokeResult> {
+        long_exact(a.checked_neg(), ctx)
+      }),
+    ),
+    (
+      "toIntExact(J)I",
+      crate::native!(|ctx, a: i64| -> Result<i32, InvokeResult> {
+        int_exact(i32::try_from(a).ok(), ctx)
+      }),
+    ),
+    (
+      "floorDiv(II)I",
+      crate::native!(|ctx, a: i32, b: i32| -> Result<i32, InvokeResult> {
+        floor_div_mod(false, a as i64, b as i64, ctx).map(|r| r as i32)
+      }),
+    ),
+    (
+      "floorDiv(JJ)J",
+      crate::native!(|ctx, a: i64, b: i64| -> Result<i64, InvokeResult> {
+        floor_div_mod(false, a, b, ctx).map(|r| r as i64)
+      }),
+    ),
+    (
+      "floorMod(II)I",
+      crate::native!(|ctx, a: i32, b: i32| -> Result<i32, InvokeResult> {
+        floor_div_mod(true, a as i64, b as i64, ctx).map(|r| r as i32)
+      }),
+    ),
+    (
+      "floorMod(JJ)J",
+      crate::native!(|ctx, a: i64, b: i64| -> Result<i64, InvokeResult> {
+        floor_div_mod(true, a, b, ctx).map(|r| r as i64)
+      }),
+    ),
+  ]
+}
+
+/// The arguments of a call, with the second register of longs and doubles skipped
+pub(super) fn args(params: &[runtime::Value]) -> Vec<&runtime::Value> {
+  let mut args = Vec::new();
+  let mut iter = params.iter();
+  while let Some(v) = iter.next() {
+    args.push(v);
+    if v.is_u64() {
+      iter.next();
+    }
+  }
+
+  args
+}
+
+/// The value of a float (in one register) or a double (in two), and whether it is a float
+fn float_value(v: &runtime::Value) -> (f64, bool) {
+  match v {
+    runtime::Value::U64(d) => (f64::from_bits(*d as u64), false),
+    v => (f32::from_bits(v.to_single() as u32) as f64, true),
+  }
+}
+
+fn float_result(d: f64, is_float: bool) -> runtime::Value {
+  match is_float {
+    true => runtime::Value::U32((d as f32).to_bits() as i32),
+    false => runtime::Value::U64(d.to_bits() as i64),
+  }
+}
+
+/// Apply a function of doubles to a float or double argument, keeping its type
+fn floating(f: fn(f64) -> f64, params: Vec<runtime::Value>, cs: Rc<CallStack>) -> InvokeResult {
+  match args(&params).first() {
+    None => InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(1, 0), cs),
+    Some(v) => {
+      let (d, is_float) = float_value(v);
+      InvokeResult::Ok(float_result(f(d), is_float))
+    }
+  }
+}
+
+fn floating2(
+  f: fn(f64, f64) -> f64,
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+) -> InvokeResult {
+  match args(&params)[..] {
+    [a, b, ..] => {
+      let ((a, is_float), (b, _)) = (float_value(a), float_value(b));
+      InvokeResult::Ok(float_result(f(a, b), is_float))
+    }
+    _ => InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(2, params.len()), cs),
+  }
+}
+
+/// `Math.signum`: zeros and NaN are returned as they are, unlike rust's `signum`
+fn signum(d: f64) -> f64 {
+  if d == 0.0 || d.is_nan() {
+    d
+  } else {
+    d.signum()
+  }
+}
+
+/// `Math.rint`: the closest integer, with ties going to the even one
+fn rint(d: f64) -> f64 {
+  let r = d.round();
+  let r = if (r - d).abs() == 0.5 && r % 2.0 != 0.0 {
+    r - d.signum()
+  } else {
+    r
+  };
+
+  // `-0.5` rounds to `-0.0`
+  r.copysign(d)
+}
+
+/// `Math.pow`, where unlike C a NaN exponent always gives NaN, and so does `±1` to an infinite
+/// power
+fn pow(x: f64, y: f64) -> f64 {
+  if y.is_nan() || (x.abs() == 1.0 && y.is_infinite()) {
+    f64::NAN
+  } else {
+    x.powf(y)
+  }
+}
+
+/// `Math.min` and `Math.max` of floats and doubles: NaN if either is NaN, and `-0.0` is less
+/// than `0.0`
+fn min_max_floating(keep: Ordering, a: f64, b: f64) -> f64 {
+  if a.is_nan() || b.is_nan() {
+    return f64::NAN;
+  }
+
+  let order = a
+    .partial_cmp(&b)
+    .unwrap()
+    .then(b.is_sign_negative().cmp(&a.is_sign_negative()));
+  if order == keep {
+    a
+  } else {
+    b
+  }
+}
+
+/// `Math.round`: the closest integer, with ties rounded up
+fn round(d: f64) -> f64 {
+  // The difference to the floor is exact, unlike `d + 0.5` which can round up
+  let floor = d.floor();
+  if d - floor >= 0.5 {
+    floor + 1.0
+  } else {
+    floor
+  }
+}
+
+/// `Math.random()`: a double in `[0, 1)`, from the same generator as `java.util.Random`
+fn java_lang_math_random(
+  _params: Vec<runtime::Value>,
+  _cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let mut seed = match env.get_static(SEED) {
+    Some(runtime::Value::U64(s)) => s,
+    _ => {
+      let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as i64);
+      (nanos ^ MULTIPLIER) & MASK
+    }
+  };
+
+  let mut next = |bits: u32| {
+    seed = seed.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT) & MASK;
+    seed >> (48 - bits)
+  };
+  let d = ((next(26) << 27) + next(27)) as f64 / (1u64 << 53) as f64;
+
+  env.set_static(SEED.to_owned(), runtime::Value::U64(seed));
+  InvokeResult::Ok(runtime::Value::U64(d.to_bits() as i64))
+}
+
+/// The result of an `*Exact` operation on ints, throwing an `ArithmeticException` on overflow
+fn int_exact(r: Option<i32>, ctx: &Context) -> Result<i32, InvokeResult> {
+  r.ok_or_else(|| ctx.throw("Ljava/lang/ArithmeticException;", Some("integer overflow")))
+}
+
+fn long_exact(r: Option<i64>, ctx: &Context) -> Result<i64, InvokeResult> {
+  r.ok_or_else(|| ctx.throw("Ljava/lang/ArithmeticException;", Some("long overflow")))
+}
+
+/// `Math.floorDiv` (rounding the quotient down instead of towards zero) and `Math.floorMod`
+/// (whose result has the sign of the divisor), in 128 bits so `MIN_VALUE / -1` overflows (to
+/// `MIN_VALUE`) only when truncated to an int or long, like java
+fn floor_div_mod(modulo: bool, a: i64, b: i64, ctx: &Context) -> Result<i128, InvokeResult> {
+  if b == 0 {
+    return Err(ctx.throw("Ljava/lang/ArithmeticException;", Some("/ by zero")));
+  }
+
+  let (a, b) = (a as i128, b as i128);
+  let mut q = a / b;
+  if a % b != 0 && (a < 0) != (b < 0) {
+    q -= 1;
+  }
+  Ok(if modulo { a - q * b } else { q })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Call the native of an overload, like `round(D)J`
+  fn call(descriptor: &str, params: Vec<runtime::Value>) -> Result<runtime::Value, String> {
+    let (_, native) = overloads()
+      .into_iter()
+      .find(|(d, _)| *d == descriptor)
+      .unwrap();
+    let env = Module::new("test".to_owned());
+    match (native.function.function)(params, Rc::new(CallStack::default()), &env) {
+      InvokeResult::Ok(v) => Ok(v),
+      InvokeResult::Exception(e, _) => Err(e.get_type().unwrap_or_default()),
+      InvokeResult::RuntimeError(e) => Err(format!("{:?}", e.error)),
+    }
+  }
+
+  fn int(descriptor: &str, args: &[i32]) -> Result<i32, String> {
+    let params = args.iter().map(|a| runtime::Value::U32(*a)).collect();
+    call(descriptor, params).map(|v| v.to_single())
+  }
+
+  fn long(descriptor: &str, args: &[i64]) -> Result<i64, String> {
+    let params = args
+      .iter()
+      .flat_map(|a| vec![runtime::Value::U64(*a), runtime::Value::Void])
+      .collect();
+    match call(descriptor, params)? {
+      runtime::Value::U64(l) => Ok(l),
+      v => Err(format!("{:?}", v)),
+    }
+  }
+
+  fn round_double(d: f64) -> i64 {
+    let d = runtime::Value::U64(d.to_bits() as i64);
+    match call("round(D)J", vec![d, runtime::Value::Void]) {
+      Ok(runtime::Value::U64(l)) => l,
+      r => panic!("{:?}", r),
+    }
+  }
+
+  fn round_float(f: f32) -> i32 {
+    let f = runtime::Value::U32(f.to_bits() as i32);
+    match call("round(F)I", vec![f]) {
+      Ok(runtime::Value::U32(i)) => i,
+      r => panic!("{:?}", r),
+    }
+  }
+
+  #[test]
+  fn round() {
+    assert_eq!(round_double(2.5), 3);
+    assert_eq!(round_double(-2.5), -2);
+    assert_eq!(round_double(-0.5), 0);
+    assert_eq!(round_double(-0.6), -1);
+    // Adding 0.5 would round this up to 1
+    assert_eq!(round_double(0.49999999999999994), 0);
+    assert_eq!(round_double(4503599627370497.0), 4503599627370497);
+    assert_eq!(round_double(f64::NAN), 0);
+    assert_eq!(round_double(1e300), i64::MAX);
+    assert_eq!(round_double(f64::NEG_INFINITY), i64::MIN);
+
+    assert_eq!(round_float(2.5), 3);
+    assert_eq!(round_float(-2.5), -2);
+    assert_eq!(round_float(0.49999997), 0);
+    assert_eq!(round_float(f32::NAN), 0);
+    assert_eq!(round_float(1e20), i32::MAX);
+    assert_eq!(round_float(-1e20), i32::MIN);
+  }
+
+  #[test]
+  fn floor_mod() {
+    assert_eq!(int("floorMod(II)I", &[7, 2]), Ok(1));
+    assert_eq!(int("floorMod(II)I", &[-7, 2]), Ok(1));
+    assert_eq!(int("floorMod(II)I", &[7, -2]), Ok(-1));
+    assert_eq!(int("floorMod(II)I", &[-7, -2]), Ok(-1));
+    assert_eq!(int("floorMod(II)I", &[-8, 2]), Ok(0));
+    assert_eq!(int("floorMod(II)I", &[i32::MIN, -1]), Ok(0));
+    assert_eq!(
+      int("floorMod(II)I", &[i32::MIN, i32::MAX]),
+      Ok(i32::MAX - 1)
+    );
+    assert_eq!(
+      int("floorMod(II)I", &[1, 0]),
+      Err("Ljava/lang/ArithmeticException;".to_owned())
+    );
+    assert_eq!(long("floorMod(JJ)J", &[-7, 3]), Ok(2));
+    assert_eq!(long("floorMod(JJ)J", &[i64::MIN, -1]), Ok(0));
+  }
+
+  #[test]
+  fn floor_div() {
+    assert_eq!(int("floorDiv(II)I", &[7, 2]), Ok(3));
+    assert_eq!(int("floorDiv(II)I", &[-7, 2]), Ok(-4));
+    assert_eq!(int("floorDiv(II)I", &[7, -2]), Ok(-4));
+    assert_eq!(int("floorDiv(II)I", &[-8, 2]), Ok(-4));
+    // Overflows like the division
+    assert_eq!(int("floorDiv(II)I", &[i32::MIN, -1]), Ok(i32::MIN));
+    assert_eq!(long("floorDiv(JJ)J", &[i64::MIN, -1]), Ok(i64::MIN));
+    assert_eq!(long("floorDiv(JJ)J", &[-1, i64::MAX]), Ok(-1));
+  }
+
+  #[test]
+  fn widths() {
+    assert_eq!(int("abs(I)I", &[i32::MIN]), Ok(i32::MIN));
+    assert_eq!(long("abs(J)J", &[i32::MIN as i64]), Ok(2147483648));
+    assert_eq!(long("abs(J)J", &[i64::MIN]), Ok(i64::MIN));
+    assert_eq!(int("max(II)I", &[-3, 2]), Ok(2));
+    assert_eq!(long("min(JJ)J", &[1 << 40, -(1 << 40)]), Ok(-(1 << 40)));
+
+    let overflow = "Ljava/lang/ArithmeticException;".to_owned();
+    assert_eq!(int("addExact(II)I", &[i32::MAX, 1]), Err(overflow.clone()));
+    assert_eq!(long("addExact(JJ)J", &[i32::MAX as i64, 1]), Ok(1 << 31));
+    assert_eq!(
+      long("multiplyExact(JJ)J", &[1 << 32, 1 << 31]),
+      Err(overflow.clone())
+    );
+    assert_eq!(int("negateExact(I)I", &[i32::MIN]), Err(overflow.clone()));
+    assert_eq!(
+      long("incrementExact(J)J", &[i64::MAX]),
+      Err(overflow.clone())
+    );
+
+    let to_int = |l: i64| {
+      let params = vec![runtime::Value::U64(l), runtime::Value::Void];
+      call("toIntExact(J)I", params).map(|v| v.to_single())
+    };
+    assert_eq!(to_int(-5), Ok(-5));
+    assert_eq!(to_int(1 << 31), Err(overflow));
+  }
+}
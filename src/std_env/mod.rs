@@ -1,87 +1,528 @@
-use log::{debug, error, info};
 use std::rc::Rc;
 
+use log::info;
+
 use super::codegen::{
   function::{CallStack, Function, NativeFunction},
   runtime, InvokeResult, RuntimeError,
 };
 use super::Module;
 
+/// Unwrap a result, or return the exception (or error) it holds
+macro_rules! try_invoke {
+  ($e:expr) => {
+    match $e {
+      Err(r) => return r,
+      Ok(v) => v,
+    }
+  };
+}
+
+/// Unwrap a result, or return its error as a runtime error
+macro_rules! unwrap_or_error {
+  ($e:expr, $cs:ident) => {
+    match $e {
+      Err(e) => return InvokeResult::runtime(e, $cs),
+      Ok(v) => v,
+    }
+  };
+}
+
+mod arrays;
+mod boxed;
+mod class;
+mod collection;
+mod collections;
+mod formatter;
+mod hash_map;
+mod lambda;
+mod list;
+mod math;
+mod number;
+mod object;
+mod print_stream;
+mod regex;
+mod stack_trace_element;
+mod string;
 mod string_builder;
+mod string_concat;
+mod system;
+mod thread;
+mod throwable;
 
-fn java_lang_object_init(
-  _params: Vec<runtime::Value>,
-  _cs: Rc<CallStack>,
-  _env: &Module,
-) -> InvokeResult {
-  InvokeResult::Ok(runtime::Value::Void)
+pub use throwable::format_uncaught;
+
+type Native = fn(Vec<runtime::Value>, Rc<CallStack>, &Module) -> InvokeResult;
+
+/// A field of an object by its slot
+fn get_field(object: &runtime::Value, slot: usize) -> Result<runtime::Value, RuntimeError> {
+  match object.instance()?.lock().unwrap().get_field(slot) {
+    None => Err(RuntimeError::FieldOutOfBounds(slot)),
+    Some(v) => Ok(v.clone()),
+  }
 }
 
-fn java_io_print_stream_println(
-  params: Vec<runtime::Value>,
-  _cs: Rc<CallStack>,
-  _env: &Module,
-) -> InvokeResult {
-  debug!("Println: {:?}", params);
-  if params.len() == 2 {
-    match &params[1] {
-      runtime::Value::U32(v) => println!("{}", v),
-      runtime::Value::Instance(v) => {
-        let i = v.lock().unwrap();
-        if i.get_class_type() == "java_lang_string" {
-          let a = match i.get_field("data") {
-            None => unimplemented!(),
-            Some(v) => match v {
-              runtime::Value::Array(arr) => arr,
-              _ => unimplemented!(),
-            },
-          };
-
-          let string = a
-            .iter()
-            .map(|x| match x {
-              runtime::Value::Char(v) => *v,
-              _ => unimplemented!(),
-            })
-            .collect::<String>();
-
-          println!("{}", string);
-        } else {
-          println!("{:?}", i);
-        }
-      }
-      _ => println!("{:?}", params[1]),
-    }
-  } else {
-    error!(
-      "Unsupported number of parameters in print stream println: {}",
-      params.len()
+/// The descriptor of a `java.lang.invoke.MethodType`
+fn descriptor(method_type: &runtime::Value, env: &Module) -> Result<String, RuntimeError> {
+  let d = env.field(method_type, "descriptor")?;
+  d.string()
+    .ok_or_else(|| RuntimeError::CastError(format!("{:?} as string", d)))
+}
+
+/// Classes whose objects the runtime represents its own way (strings aren't instances, and the
+/// natives of the others rely on their fields), or which connect the program to the host like
+/// `System`. The runtime's versions are kept when a core library is loaded.
+const INTRINSIC_CLASSES: &[&str] = &[
+  "Ljava/lang/Object;",
+  "Ljava/lang/String;",
+  "Ljava/lang/Class;",
+  "Ljava/lang/System;",
+  "Ljava/lang/Throwable;",
+  "Ljava/lang/StackTraceElement;",
+  "Ljava/lang/invoke/MethodType;",
+  "Ljava/lang/invoke/MethodHandle;",
+  "Ljava/lang/invoke/CallSite;",
+  "Ljava/lang/invoke/ConstantCallSite;",
+  "Ljava/lang/StringBuilder;",
+  "Ljava/lang/StringBuffer;",
+  "Ljava/lang/Thread;",
+  "Ljava/lang/Number;",
+  "Ljava/lang/Boolean;",
+  "Ljava/lang/Character;",
+  "Ljava/lang/Byte;",
+  "Ljava/lang/Short;",
+  "Ljava/lang/Integer;",
+  "Ljava/lang/Long;",
+  "Ljava/lang/Float;",
+  "Ljava/lang/Double;",
+  "Ljava/io/PrintStream;",
+  "Ljava/util/Formatter;",
+  "Ljava/util/ArrayList;",
+  "Ljava/util/LinkedList;",
+  "Ljava/util/ArrayDeque;",
+  "Ljava/util/Arrays$ArrayList;",
+  "Ljava/util/AbstractList$Itr;",
+  "Ljava/util/HashSet;",
+  "Ljava/util/HashMap;",
+  "Ljava/util/HashMap$Node;",
+  "Ljava/util/HashMap$KeySet;",
+  "Ljava/util/HashMap$Values;",
+  "Ljava/util/HashMap$EntrySet;",
+  "Ljava/util/HashMap$HashIterator;",
+];
+
+/// Whether the bytecode of a class of the core library is ignored in favor of the runtime's own
+/// implementation
+pub fn is_intrinsic(class: &str) -> bool {
+  INTRINSIC_CLASSES.contains(&class)
+}
+
+/// Declare the classes (and instance fields) the environment relies on.
+/// Superclasses have to come before their subclasses.
+fn add_classes(m: &mut Module) {
+  let classes: &[(&str, Option<&str>, &[&str])] = &[
+    ("Ljava/lang/Object;", None, &[]),
+    ("Ljava/lang/String;", Some("Ljava/lang/Object;"), &[]),
+    (
+      "Ljava/lang/Class;",
+      Some("Ljava/lang/Object;"),
+      &["descriptor"],
+    ),
+    (
+      "Ljava/lang/StringBuilder;",
+      Some("Ljava/lang/Object;"),
+      &["value"],
+    ),
+    (
+      "Ljava/lang/StringBuffer;",
+      Some("Ljava/lang/Object;"),
+      &["value"],
+    ),
+    ("Ljava/lang/Number;", Some("Ljava/lang/Object;"), &[]),
+    (
+      "Ljava/lang/Boolean;",
+      Some("Ljava/lang/Object;"),
+      &["value"],
+    ),
+    (
+      "Ljava/lang/Character;",
+      Some("Ljava/lang/Object;"),
+      &["value"],
+    ),
+    ("Ljava/lang/Byte;", Some("Ljava/lang/Number;"), &["value"]),
+    ("Ljava/lang/Short;", Some("Ljava/lang/Number;"), &["value"]),
+    (
+      "Ljava/lang/Integer;",
+      Some("Ljava/lang/Number;"),
+      &["value"],
+    ),
+    ("Ljava/lang/Long;", Some("Ljava/lang/Number;"), &["value"]),
+    ("Ljava/lang/Float;", Some("Ljava/lang/Number;"), &["value"]),
+    ("Ljava/lang/Double;", Some("Ljava/lang/Number;"), &["value"]),
+    (
+      "Ljava/lang/Thread;",
+      Some("Ljava/lang/Object;"),
+      &["name", "target", "control"],
+    ),
+    ("Ljava/io/PrintStream;", Some("Ljava/lang/Object;"), &["fd"]),
+    (
+      "Ljava/util/Formatter;",
+      Some("Ljava/lang/Object;"),
+      &["out"],
+    ),
+    (
+      "Ljava/lang/Throwable;",
+      Some("Ljava/lang/Object;"),
+      &["detailMessage", "cause", "stackTrace"],
+    ),
+    (
+      "Ljava/lang/StackTraceElement;",
+      Some("Ljava/lang/Object;"),
+      &["declaringClass", "methodName", "fileName", "lineNumber"],
+    ),
+    ("Ljava/lang/Exception;", Some("Ljava/lang/Throwable;"), &[]),
+    (
+      "Ljava/lang/InterruptedException;",
+      Some("Ljava/lang/Exception;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/RuntimeException;",
+      Some("Ljava/lang/Exception;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/ArithmeticException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/ClassCastException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/IllegalArgumentException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/NumberFormatException;",
+      Some("Ljava/lang/IllegalArgumentException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/IllegalThreadStateException;",
+      Some("Ljava/lang/IllegalArgumentException;"),
+      &[],
+    ),
+    (
+      "Ljava/util/regex/PatternSyntaxException;",
+      Some("Ljava/lang/IllegalArgumentException;"),
+      &[],
+    ),
+    (
+      "Ljava/util/IllegalFormatException;",
+      Some("Ljava/lang/IllegalArgumentException;"),
+      &[],
+    ),
+    (
+      "Ljava/util/UnknownFormatConversionException;",
+      Some("Ljava/util/IllegalFormatException;"),
+      &[],
+    ),
+    (
+      "Ljava/util/MissingFormatArgumentException;",
+      Some("Ljava/util/IllegalFormatException;"),
+      &[],
+    ),
+    (
+      "Ljava/util/MissingFormatWidthException;",
+      Some("Ljava/util/IllegalFormatException;"),
+      &[],
+    ),
+    (
+      "Ljava/util/IllegalFormatConversionException;",
+      Some("Ljava/util/IllegalFormatException;"),
+      &[],
+    ),
+    (
+      "Ljava/util/IllegalFormatPrecisionException;",
+      Some("Ljava/util/IllegalFormatException;"),
+      &[],
+    ),
+    (
+      "Ljava/util/IllegalFormatCodePointException;",
+      Some("Ljava/util/IllegalFormatException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/NullPointerException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/NegativeArraySizeException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/IllegalMonitorStateException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/IndexOutOfBoundsException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/StringIndexOutOfBoundsException;",
+      Some("Ljava/lang/IndexOutOfBoundsException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/ArrayIndexOutOfBoundsException;",
+      Some("Ljava/lang/IndexOutOfBoundsException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/ArrayStoreException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/IllegalStateException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/UnsupportedOperationException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/util/NoSuchElementException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    (
+      "Ljava/util/ConcurrentModificationException;",
+      Some("Ljava/lang/RuntimeException;"),
+      &[],
+    ),
+    ("Ljava/lang/Error;", Some("Ljava/lang/Throwable;"), &[]),
+    ("Ljava/lang/LinkageError;", Some("Ljava/lang/Error;"), &[]),
+    (
+      "Ljava/lang/BootstrapMethodError;",
+      Some("Ljava/lang/LinkageError;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/ExceptionInInitializerError;",
+      Some("Ljava/lang/LinkageError;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/NoClassDefFoundError;",
+      Some("Ljava/lang/LinkageError;"),
+      &[],
+    ),
+    (
+      "Ljava/lang/invoke/MethodType;",
+      Some("Ljava/lang/Object;"),
+      &["descriptor"],
+    ),
+    (
+      "Ljava/lang/invoke/MethodHandle;",
+      Some("Ljava/lang/Object;"),
+      &["handle"],
+    ),
+    (
+      "Ljava/lang/invoke/CallSite;",
+      Some("Ljava/lang/Object;"),
+      &["target"],
+    ),
+    (
+      "Ljava/lang/invoke/ConstantCallSite;",
+      Some("Ljava/lang/invoke/CallSite;"),
+      &[],
+    ),
+  ];
+
+  for (name, superclass, fields) in classes {
+    m.add_class(
+      name.to_string(),
+      false,
+      superclass.map(|s| s.to_owned()),
+      Vec::new(),
+      fields.iter().map(|f| f.to_string()).collect(),
+    );
+  }
+
+  // The collection interfaces, each with the interfaces it extends
+  let interfaces: &[(&str, &[&str])] = &[
+    ("Ljava/lang/Iterable;", &[]),
+    ("Ljava/util/Collection;", &["Ljava/lang/Iterable;"]),
+    ("Ljava/util/List;", &["Ljava/util/Collection;"]),
+    ("Ljava/util/Set;", &["Ljava/util/Collection;"]),
+    ("Ljava/util/Queue;", &["Ljava/util/Collection;"]),
+    ("Ljava/util/Deque;", &["Ljava/util/Queue;"]),
+    ("Ljava/util/RandomAccess;", &[]),
+    ("Ljava/util/Iterator;", &[]),
+    ("Ljava/util/Map;", &[]),
+    ("Ljava/util/Map$Entry;", &[]),
+  ];
+
+  for (name, interfaces) in interfaces {
+    m.add_class(
+      name.to_string(),
+      true,
+      Some("Ljava/lang/Object;".to_owned()),
+      interfaces.iter().map(|i| i.to_string()).collect(),
+      Vec::new(),
     );
   }
 
-  InvokeResult::Ok(runtime::Value::Void)
+  // The collections, with the interfaces they implement
+  let collections: &[(&str, &str, &[&str], &[&str])] = &[
+    (
+      "Ljava/util/AbstractCollection;",
+      "Ljava/lang/Object;",
+      &["Ljava/util/Collection;"],
+      &[],
+    ),
+    (
+      "Ljava/util/AbstractList;",
+      "Ljava/util/AbstractCollection;",
+      &["Ljava/util/List;"],
+      &[],
+    ),
+    (
+      "Ljava/util/AbstractSequentialList;",
+      "Ljava/util/AbstractList;",
+      &[],
+      &[],
+    ),
+    (
+      "Ljava/util/ArrayList;",
+      "Ljava/util/AbstractList;",
+      &["Ljava/util/List;", "Ljava/util/RandomAccess;"],
+      &["elements"],
+    ),
+    (
+      "Ljava/util/LinkedList;",
+      "Ljava/util/AbstractSequentialList;",
+      &["Ljava/util/List;", "Ljava/util/Deque;"],
+      &["elements"],
+    ),
+    (
+      "Ljava/util/ArrayDeque;",
+      "Ljava/util/AbstractCollection;",
+      &["Ljava/util/Deque;"],
+      &["elements"],
+    ),
+    (
+      "Ljava/util/Arrays$ArrayList;",
+      "Ljava/util/AbstractList;",
+      &["Ljava/util/RandomAccess;"],
+      &["a"],
+    ),
+    (
+      "Ljava/util/AbstractList$Itr;",
+      "Ljava/lang/Object;",
+      &["Ljava/util/Iterator;"],
+      &["list", "cursor"],
+    ),
+    (
+      "Ljava/util/AbstractSet;",
+      "Ljava/util/AbstractCollection;",
+      &["Ljava/util/Set;"],
+      &[],
+    ),
+    (
+      "Ljava/util/HashSet;",
+      "Ljava/util/AbstractSet;",
+      &["Ljava/util/Set;"],
+      &["map"],
+    ),
+    (
+      "Ljava/util/AbstractMap;",
+      "Ljava/lang/Object;",
+      &["Ljava/util/Map;"],
+      &[],
+    ),
+    (
+      "Ljava/util/HashMap;",
+      "Ljava/util/AbstractMap;",
+      &["Ljava/util/Map;"],
+      &["table"],
+    ),
+    (
+      "Ljava/util/HashMap$Node;",
+      "Ljava/lang/Object;",
+      &["Ljava/util/Map$Entry;"],
+      &["key", "value"],
+    ),
+    (
+      "Ljava/util/HashMap$KeySet;",
+      "Ljava/util/AbstractSet;",
+      &[],
+      &["map"],
+    ),
+    (
+      "Ljava/util/HashMap$Values;",
+      "Ljava/util/AbstractCollection;",
+      &[],
+      &["map"],
+    ),
+    (
+      "Ljava/util/HashMap$EntrySet;",
+      "Ljava/util/AbstractSet;",
+      &[],
+      &["map"],
+    ),
+    (
+      "Ljava/util/HashMap$HashIterator;",
+      "Ljava/lang/Object;",
+      &["Ljava/util/Iterator;"],
+      &["map", "cursor"],
+    ),
+  ];
+
+  for (name, superclass, interfaces, fields) in collections {
+    m.add_class(
+      name.to_string(),
+      false,
+      Some(superclass.to_string()),
+      interfaces.iter().map(|i| i.to_string()).collect(),
+      fields.iter().map(|f| f.to_string()).collect(),
+    );
+  }
 }
 
 pub fn add_all(m: &mut Module) {
   info!("Initializing java environment");
 
-  m.add_static("CLASS_java__lang__System__out".to_owned());
-
-  m.add_function(
-    "CLASS_java__lang__Object____init__".to_owned(),
-    Function::Native(NativeFunction(java_lang_object_init)),
-  );
-
-  m.add_function(
-    "CLASS_java__io__PrintStream__println".to_owned(),
-    Function::Native(NativeFunction(java_io_print_stream_println)),
-  );
+  add_classes(m);
 
+  arrays::add_functions(m);
+  boxed::add_functions(m);
+  class::add_functions(m);
+  collection::add_functions(m);
+  collections::add_functions(m);
+  formatter::add_functions(m);
+  hash_map::add_functions(m);
+  lambda::add_functions(m);
+  list::add_functions(m);
+  math::add_functions(m);
+  object::add_functions(m);
+  print_stream::add_functions(m);
+  stack_trace_element::add_functions(m);
+  string::add_functions(m);
   string_builder::add_functions(m);
-
-  // "CLASS_java__lang__Object____init__" => Ok(runtime::Value::Void),
-  // "CLASS_java__io__PrintStream__println" => {
-  // 	println!("Printstream: {:?}", params);
-  // 	Ok(runtime::Value::Void)
-  // }
+  string_concat::add_functions(m);
+  system::add_functions(m);
+  thread::add_functions(m);
+  throwable::add_functions(m);
 }
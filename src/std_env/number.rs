@@ -0,0 +1,52 @@
+/// `Double.toString(d)`: the shortest digits that identify the value, in plain notation
+/// for `10^-3 <= |d| < 10^7` and in computerized scientific notation (`1.0E10`) otherwise
+pub fn double_to_string(d: f64) -> String {
+  if d.is_nan() {
+    return "NaN".to_owned();
+  }
+  if d.is_infinite() {
+    return if d > 0.0 { "Infinity" } else { "-Infinity" }.to_owned();
+  }
+  if d == 0.0 {
+    return if d.is_sign_negative() { "-0.0" } else { "0.0" }.to_owned();
+  }
+
+  // Rust's `{:e}` also gives the shortest digits that round-trip
+  java_notation(&format!("{:e}", d))
+}
+
+/// `Float.toString(f)`, like `double_to_string` but with the digits of a float
+pub fn float_to_string(f: f32) -> String {
+  if f.is_nan() || f.is_infinite() || f == 0.0 {
+    return double_to_string(f as f64);
+  }
+
+  java_notation(&format!("{:e}", f))
+}
+
+/// Turn rust's scientific notation (`-1.2345e-5`) into java's notation
+fn java_notation(sci: &str) -> String {
+  let (mantissa, exp) = sci.split_at(sci.find('e').unwrap());
+  let exp: i32 = exp[1..].parse().unwrap();
+  let (sign, mantissa) = match mantissa.strip_prefix('-') {
+    Some(m) => ("-", m),
+    None => ("", mantissa),
+  };
+  let digits = mantissa.replace('.', "");
+
+  if !(-3..7).contains(&exp) {
+    let fraction = if digits.len() > 1 { &digits[1..] } else { "0" };
+    return format!("{}{}.{}E{}", sign, &digits[..1], fraction, exp);
+  }
+
+  if exp < 0 {
+    return format!("{}0.{}{}", sign, "0".repeat((-exp - 1) as usize), digits);
+  }
+
+  let int_len = exp as usize + 1;
+  if digits.len() > int_len {
+    format!("{}{}.{}", sign, &digits[..int_len], &digits[int_len..])
+  } else {
+    format!("{}{}{}.0", sign, digits, "0".repeat(int_len - digits.len()))
+  }
+}
@@ -0,0 +1,119 @@
+use super::{runtime, InvokeResult, Module, RuntimeError};
+use crate::codegen::java_name;
+use crate::codegen::native::Context;
+
+pub fn add_functions(m: &mut Module) {
+  m.natives()
+    .method(
+      "java/lang/Object.<init>()V",
+      crate::native!(|ctx, _this: runtime::Value| {}),
+    )
+    .method(
+      "java/lang/Object.toString()Ljava/lang/String;",
+      crate::native!(
+        |ctx, this: runtime::Value| -> Result<String, InvokeResult> { to_string(this, ctx) }
+      ),
+    )
+    .method(
+      "java/lang/Object.hashCode()I",
+      crate::native!(|ctx, this: runtime::Value| -> Result<i32, InvokeResult> {
+        Ok(non_null(this, ctx)?.identity_hash())
+      }),
+    )
+    // Reference equality
+    .method(
+      "java/lang/Object.equals(Ljava/lang/Object;)Z",
+      crate::native!(|ctx,
+                      this: runtime::Value,
+                      other: runtime::Value|
+       -> Result<bool, InvokeResult> {
+        Ok(non_null(this, ctx)?.same(&other))
+      }),
+    )
+    .method(
+      "java/lang/Object.getClass()Ljava/lang/Class;",
+      crate::native!(
+        |ctx, this: runtime::Value| -> Result<runtime::Value, InvokeResult> {
+          let this = non_null(this, ctx)?;
+          match this.get_type() {
+            None => Err(ctx.error(RuntimeError::CastError(format!("{:?} as object", this)))),
+            Some(class) => Ok(ctx.module.class_object(&class)),
+          }
+        }
+      ),
+    )
+    .method(
+      "java/lang/Object.wait()V",
+      crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> {
+        super::thread::wait(&non_null(this, ctx)?, 0, 0, ctx)
+      }),
+    )
+    .method(
+      "java/lang/Object.wait(J)V",
+      crate::native!(
+        |ctx, this: runtime::Value, millis: i64| -> Result<(), InvokeResult> {
+          super::thread::wait(&non_null(this, ctx)?, millis, 0, ctx)
+        }
+      ),
+    )
+    .method(
+      "java/lang/Object.wait(JI)V",
+      crate::native!(|ctx,
+                      this: runtime::Value,
+                      millis: i64,
+                      nanos: i32|
+       -> Result<(), InvokeResult> {
+        super::thread::wait(&non_null(this, ctx)?, millis, nanos, ctx)
+      }),
+    )
+    .method(
+      "java/lang/Object.notify()V",
+      crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> {
+        notify(this, false, ctx)
+      }),
+    )
+    .method(
+      "java/lang/Object.notifyAll()V",
+      crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> {
+        notify(this, true, ctx)
+      }),
+    )
+    .static_method(
+      "java/lang/System.identityHashCode(Ljava/lang/Object;)I",
+      crate::native!(|ctx, v: runtime::Value| -> i32 { v.identity_hash() }),
+    );
+}
+
+/// `this`, throwing a `NullPointerException` if it is null
+fn non_null(this: runtime::Value, ctx: &Context) -> Result<runtime::Value, InvokeResult> {
+  match this {
+    v if v.is_null() => Err(ctx.throw("Ljava/lang/NullPointerException;", None)),
+    v => Ok(v),
+  }
+}
+
+/// `notify()` and `notifyAll()`, which need the current thread to hold the monitor of the object
+fn notify(this: runtime::Value, all: bool, ctx: &Context) -> Result<(), InvokeResult> {
+  match ctx.module.monitors().notify(&non_null(this, ctx)?, all) {
+    true => Ok(()),
+    false => Err(ctx.throw(
+      "Ljava/lang/IllegalMonitorStateException;",
+      Some("current thread is not owner"),
+    )),
+  }
+}
+
+/// `Object.toString()`: the class name and the hex `hashCode()`, e.g. `Foo@2c7b84de`
+fn to_string(this: runtime::Value, ctx: &Context) -> Result<String, InvokeResult> {
+  let this = non_null(this, ctx)?;
+  let class = this.get_type().unwrap_or_default();
+
+  // Subclasses may override hashCode
+  match ctx
+    .module
+    .invoke_virtual("hashCode()I", ctx.cs.clone(), vec![this])
+  {
+    InvokeResult::Ok(h) => Ok(format!("{}@{:x}", java_name(&class), h.to_single())),
+    r => Err(r),
+  }
+}
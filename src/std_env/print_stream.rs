@@ -0,0 +1,203 @@
+use log::warn;
+
+use super::formatter;
+use super::number::{double_to_string, float_to_string};
+use super::string_builder::object_text;
+use super::{runtime, InvokeResult, Module, RuntimeError};
+use crate::codegen::native::Context;
+use crate::codegen::StandardStream;
+
+// Slot of the `fd` field: the standard stream the print stream writes to
+const FD: usize = 0;
+
+/// The `print` and `println` overloads of a parameter type, with the text of their argument
+macro_rules! print_overloads {
+  ($m:ident, $type_:literal, |$ctx:ident, $v:ident: $t:ty| $text:expr) => {
+    $m.natives()
+      .method(
+        concat!("java/io/PrintStream.print(", $type_, ")V"),
+        crate::native!(
+          |$ctx, this: runtime::Value, $v: $t| -> Result<(), InvokeResult> {
+            print(&this, $text, false, $ctx)
+          }
+        ),
+      )
+      .method(
+        concat!("java/io/PrintStream.println(", $type_, ")V"),
+        crate::native!(
+          |$ctx, this: runtime::Value, $v: $t| -> Result<(), InvokeResult> {
+            print(&this, $text, true, $ctx)
+          }
+        ),
+      );
+  };
+}
+
+pub fn add_functions(m: &mut Module) {
+  print_overloads!(m, "Z", |ctx, b: bool| units(if b {
+    "true"
+  } else {
+    "false"
+  }));
+  print_overloads!(m, "C", |ctx, c: u16| vec![c]);
+  print_overloads!(m, "I", |ctx, i: i32| units(&i.to_string()));
+  print_overloads!(m, "J", |ctx, l: i64| units(&l.to_string()));
+  print_overloads!(m, "F", |ctx, f: f32| units(&float_to_string(f)));
+  print_overloads!(m, "D", |ctx, d: f64| units(&double_to_string(d)));
+  print_overloads!(m, "[C", |ctx, chars: Vec<u16>| chars);
+  print_overloads!(m, "Ljava/lang/String;", |ctx, s: runtime::Value| {
+    object_text(&s, ctx.cs, ctx.module)?
+  });
+  print_overloads!(m, "Ljava/lang/Object;", |ctx, o: runtime::Value| {
+    object_text(&o, ctx.cs, ctx.module)?
+  });
+
+  m.natives()
+    .method(
+      "java/io/PrintStream.println()V",
+      crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> {
+        print(&this, Vec::new(), true, ctx)
+      }),
+    )
+    .method(
+      "java/io/PrintStream.write(I)V",
+      crate::native!(
+        |ctx, this: runtime::Value, b: i32| -> Result<(), InvokeResult> {
+          write_bytes(&this, &[b as u8], ctx)
+        }
+      ),
+    )
+    .method(
+      "java/io/PrintStream.flush()V",
+      crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> {
+        let stream = stream(&this).map_err(|e| ctx.error(e))?;
+        if let Err(e) = ctx.module.flush_output(stream) {
+          warn!("Could not flush {:?}: {:?}", stream, e);
+        }
+        Ok(())
+      }),
+    );
+
+  // `printf` and `format` return the stream. The locale is ignored.
+  for name in &["printf", "format"] {
+    m.natives()
+      .method(
+        &format!(
+          "java/io/PrintStream.{}(Ljava/lang/String;[Ljava/lang/Object;)Ljava/io/PrintStream;",
+          name
+        ),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        format: runtime::Value,
+                        args: runtime::Value|
+         -> Result<runtime::Value, InvokeResult> {
+          printf(this, &[format, args], ctx)
+        }),
+      )
+      .method(
+        &format!(
+          "java/io/PrintStream.{}(Ljava/util/Locale;Ljava/lang/String;[Ljava/lang/Object;)Ljava/io/PrintStream;",
+          name
+        ),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        locale: runtime::Value,
+                        format: runtime::Value,
+                        args: runtime::Value|
+         -> Result<runtime::Value, InvokeResult> {
+          printf(this, &[locale, format, args], ctx)
+        }),
+      );
+  }
+
+  // `Appendable`, also called through the interface (like by a `Formatter`)
+  for return_type in &["Ljava/io/PrintStream;", "Ljava/lang/Appendable;"] {
+    m.natives()
+      .method(
+        &format!("java/io/PrintStream.append(C){}", return_type),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        c: u16|
+         -> Result<runtime::Value, InvokeResult> {
+          print(&this, vec![c], false, ctx)?;
+          Ok(this)
+        }),
+      )
+      .method(
+        &format!(
+          "java/io/PrintStream.append(Ljava/lang/CharSequence;){}",
+          return_type
+        ),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        s: runtime::Value|
+         -> Result<runtime::Value, InvokeResult> {
+          print(&this, object_text(&s, ctx.cs, ctx.module)?, false, ctx)?;
+          Ok(this)
+        }),
+      );
+  }
+
+  for (name, stream) in &[("out", StandardStream::Out), ("err", StandardStream::Err)] {
+    m.set_static(
+      format!("CLASS_java__lang__System__{}", name),
+      new_print_stream(*stream, m),
+    );
+  }
+}
+
+/// A `PrintStream` writing to a standard stream, like `System.out`
+fn new_print_stream(stream: StandardStream, env: &Module) -> runtime::Value {
+  let mut o = env.new_instance("Ljava/io/PrintStream;");
+  o.set_field(FD, runtime::Value::U32(stream as i32)).unwrap();
+  runtime::Value::from_instance(o)
+}
+
+/// The standard stream of the `PrintStream` passed as `this`
+pub(super) fn stream(this: &runtime::Value) -> Result<StandardStream, RuntimeError> {
+  let fd = match this.instance()?.lock().unwrap().get_field(FD) {
+    None => return Err(RuntimeError::FieldOutOfBounds(FD)),
+    Some(fd) => fd.to_single(),
+  };
+
+  StandardStream::from_fd(fd)
+    .ok_or_else(|| RuntimeError::CastError(format!("{} as standard stream", fd)))
+}
+
+fn units(s: &str) -> Vec<u16> {
+  s.encode_utf16().collect()
+}
+
+/// Write bytes to the stream of `this`. Like java, a `PrintStream` never throws on I/O errors.
+fn write_bytes(this: &runtime::Value, bytes: &[u8], ctx: &Context) -> Result<(), InvokeResult> {
+  let stream = stream(this).map_err(|e| ctx.error(e))?;
+  if let Err(e) = ctx.module.write_output(stream, bytes) {
+    warn!("Could not write to {:?}: {:?}", stream, e);
+  }
+
+  Ok(())
+}
+
+/// Print text to the stream of `this`, optionally followed by a line separator
+fn print(
+  this: &runtime::Value,
+  mut text: Vec<u16>,
+  newline: bool,
+  ctx: &Context,
+) -> Result<(), InvokeResult> {
+  if newline {
+    text.push('\n' as u16);
+  }
+  write_bytes(this, String::from_utf16_lossy(&text).as_bytes(), ctx)
+}
+
+/// `printf([locale,] format, args)`, returning the stream
+fn printf(
+  this: runtime::Value,
+  params: &[runtime::Value],
+  ctx: &Context,
+) -> Result<runtime::Value, InvokeResult> {
+  let text = formatter::format_varargs(params, ctx.cs, ctx.module)?;
+  print(&this, text, false, ctx)?;
+  Ok(this)
+}
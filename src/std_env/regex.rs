@@ -0,0 +1,519 @@
+//! The regular expressions of `java.util.regex.Pattern` that `String.split` needs: literals,
+//! `.`, character classes (`[a-z]`, `[^,;]`, `\d`, `\s`, `\w`), groups, alternation, anchors
+//! and greedy or lazy quantifiers. Other constructs, like back references or lookarounds,
+//! aren't supported.
+
+/// Why a pattern can't be compiled
+#[derive(Debug, PartialEq)]
+pub enum Error {
+  /// The pattern is invalid, like `*a`: a `PatternSyntaxException`, with the index of the error
+  Syntax(String, usize),
+  /// The pattern is valid, but uses a construct which isn't supported
+  Unsupported(String),
+}
+
+#[derive(Debug)]
+pub struct Regex {
+  alternatives: Vec<Vec<Node>>,
+}
+
+#[derive(Debug)]
+enum Node {
+  Unit(u16),
+  /// `.`, any char except line terminators
+  Any,
+  Class(Class),
+  /// `^`
+  Start,
+  /// `$`
+  End,
+  Group(Vec<Vec<Node>>),
+  Repeat(Repeat),
+}
+
+/// A quantified node, like `a{2,3}?`
+#[derive(Debug)]
+struct Repeat {
+  node: Box<Node>,
+  min: usize,
+  max: usize,
+  greedy: bool,
+}
+
+#[derive(Debug, Default)]
+struct Class {
+  negated: bool,
+  ranges: Vec<(u16, u16)>,
+  /// Predefined classes like `\d`, and if they are negated (`\D`)
+  predefined: Vec<(Predefined, bool)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Predefined {
+  Digit,
+  Space,
+  Word,
+}
+
+impl Predefined {
+  fn contains(self, c: u16) -> bool {
+    let ascii = |f: fn(&u8) -> bool| c < 0x80 && f(&(c as u8));
+    match self {
+      Predefined::Digit => ascii(u8::is_ascii_digit),
+      // `[ \t\n\x0B\f\r]`
+      Predefined::Space => [0x20, 0x09, 0x0a, 0x0b, 0x0c, 0x0d].contains(&c),
+      Predefined::Word => ascii(u8::is_ascii_alphanumeric) || c == '_' as u16,
+    }
+  }
+}
+
+impl Class {
+  fn contains(&self, c: u16) -> bool {
+    let found = self.ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c))
+      || self
+        .predefined
+        .iter()
+        .any(|(p, negated)| p.contains(c) != *negated);
+    found != self.negated
+  }
+}
+
+fn is_line_terminator(c: u16) -> bool {
+  [0x0a, 0x0d, 0x85, 0x2028, 0x2029].contains(&c)
+}
+
+impl Regex {
+  pub fn new(pattern: &[u16]) -> Result<Self, Error> {
+    let mut parser = Parser { pattern, pos: 0 };
+    let alternatives = parser.alternatives()?;
+    match parser.peek() {
+      None => Ok(Self { alternatives }),
+      Some(_) => Err(Error::Syntax(
+        "Unmatched closing ')'".to_owned(),
+        parser.pos.saturating_sub(1),
+      )),
+    }
+  }
+
+  /// Find the first match in `input` at or after `from`, as its start and end
+  pub fn find(&self, input: &[u16], from: usize) -> Option<(usize, usize)> {
+    let matcher = Matcher { input };
+    (from..=input.len()).find_map(|start| {
+      let mut end = None;
+      let matched = self.alternatives.iter().any(|seq| {
+        matcher.sequence(seq, start, &mut |e| {
+          end = Some(e);
+          true
+        })
+      });
+      match (matched, end) {
+        (true, Some(end)) => Some((start, end)),
+        _ => None,
+      }
+    })
+  }
+}
+
+/// Backtracking matcher: every match function gets a continuation, called with the end of the
+/// match, which says if the rest of the pattern matches from there
+struct Matcher<'a> {
+  input: &'a [u16],
+}
+
+type Continuation<'c> = &'c mut dyn FnMut(usize) -> bool;
+
+impl<'a> Matcher<'a> {
+  fn sequence(&self, seq: &[Node], i: usize, k: Continuation) -> bool {
+    match seq.split_first() {
+      None => k(i),
+      Some((node, rest)) => self.node(node, i, &mut |j| self.sequence(rest, j, k)),
+    }
+  }
+
+  fn node(&self, node: &Node, i: usize, k: Continuation) -> bool {
+    let unit = self.input.get(i).copied();
+    match node {
+      Node::Unit(c) => unit == Some(*c) && k(i + 1),
+      Node::Any => unit.is_some_and(|c| !is_line_terminator(c)) && k(i + 1),
+      Node::Class(class) => unit.is_some_and(|c| class.contains(c)) && k(i + 1),
+      Node::Start => i == 0 && k(i),
+      Node::End => i == self.input.len() && k(i),
+      Node::Group(alternatives) => alternatives
+        .iter()
+        .any(|seq| self.sequence(seq, i, &mut *k)),
+      Node::Repeat(repeat) => self.repeat(repeat, 0, i, k),
+    }
+  }
+
+  /// Match the node of `repeat` again after `count` matches
+  fn repeat(&self, repeat: &Repeat, count: usize, i: usize, k: Continuation) -> bool {
+    let again = |k: Continuation| {
+      count < repeat.max
+        && self.node(&repeat.node, i, &mut |j| {
+          // Stop repeating a match of nothing, which would repeat forever
+          (j != i || count < repeat.min) && self.repeat(repeat, count + 1, j, &mut *k)
+        })
+    };
+
+    if repeat.greedy {
+      again(&mut *k) || count >= repeat.min && k(i)
+    } else {
+      count >= repeat.min && k(i) || again(k)
+    }
+  }
+}
+
+struct Parser<'p> {
+  pattern: &'p [u16],
+  pos: usize,
+}
+
+impl<'p> Parser<'p> {
+  fn peek(&self) -> Option<char> {
+    self
+      .pattern
+      .get(self.pos)
+      .map(|c| std::char::from_u32(*c as u32).unwrap_or('\u{fffd}'))
+  }
+
+  fn next(&mut self) -> Option<char> {
+    let c = self.peek();
+    self.pos += 1;
+    c
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    let found = self.peek() == Some(c);
+    if found {
+      self.pos += 1;
+    }
+    found
+  }
+
+  /// Alternatives separated by `|`, until the end of the pattern or a `)`
+  fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, Error> {
+    let mut alternatives = vec![self.sequence()?];
+    while self.eat('|') {
+      alternatives.push(self.sequence()?);
+    }
+    Ok(alternatives)
+  }
+
+  fn sequence(&mut self) -> Result<Vec<Node>, Error> {
+    let mut seq = Vec::new();
+    while let Some(c) = self.peek() {
+      if c == '|' || c == ')' {
+        break;
+      }
+      let atom = self.atom()?;
+      seq.push(self.quantifier(atom)?);
+    }
+    Ok(seq)
+  }
+
+  fn atom(&mut self) -> Result<Node, Error> {
+    let start = self.pos;
+    Ok(match self.next() {
+      None => unreachable!("atom at the end of the pattern"),
+      Some('.') => Node::Any,
+      Some('^') => Node::Start,
+      Some('$') => Node::End,
+      Some('[') => Node::Class(self.class()?),
+      Some('(') => {
+        if self.eat('?') && !self.eat(':') {
+          return Err(Error::Unsupported(format!(
+            "the group construct at index {}",
+            start
+          )));
+        }
+        let alternatives = self.alternatives()?;
+        if !self.eat(')') {
+          return Err(Error::Syntax(
+            "Unclosed group".to_owned(),
+            self.pattern.len(),
+          ));
+        }
+        Node::Group(alternatives)
+      }
+      Some(c @ '*') | Some(c @ '+') | Some(c @ '?') => {
+        return Err(Error::Syntax(
+          format!("Dangling meta character '{}'", c),
+          start,
+        ))
+      }
+      Some('{') => return Err(Error::Syntax("Illegal repetition".to_owned(), start)),
+      Some('\\') => match self.escape()? {
+        Escaped::Unit(c) => Node::Unit(c),
+        Escaped::Predefined(p, negated) => Node::Class(Class {
+          predefined: vec![(p, negated)],
+          ..Class::default()
+        }),
+      },
+      Some(_) => Node::Unit(self.pattern[start]),
+    })
+  }
+
+  fn quantifier(&mut self, node: Node) -> Result<Node, Error> {
+    let start = self.pos;
+    let (min, max) = match self.peek() {
+      Some('*') => (0, usize::MAX),
+      Some('+') => (1, usize::MAX),
+      Some('?') => (0, 1),
+      Some('{') => {
+        self.pos += 1;
+        let min = self
+          .number()
+          .ok_or(Error::Syntax("Illegal repetition".to_owned(), start))?;
+        let max = if self.eat(',') {
+          self.number().unwrap_or(usize::MAX)
+        } else {
+          min
+        };
+        if self.peek() != Some('}') {
+          return Err(Error::Syntax(
+            "Unclosed counted closure".to_owned(),
+            self.pos,
+          ));
+        }
+        if max < min {
+          return Err(Error::Syntax("Illegal repetition range".to_owned(), start));
+        }
+        (min, max)
+      }
+      _ => return Ok(node),
+    };
+    self.pos += 1;
+
+    let greedy = !self.eat('?');
+    if self.peek() == Some('+') {
+      return Err(Error::Unsupported(format!(
+        "the possessive quantifier at index {}",
+        self.pos
+      )));
+    }
+    Ok(Node::Repeat(Repeat {
+      node: Box::new(node),
+      min,
+      max,
+      greedy,
+    }))
+  }
+
+  fn number(&mut self) -> Option<usize> {
+    let start = self.pos;
+    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+      self.pos += 1;
+    }
+    String::from_utf16_lossy(&self.pattern[start..self.pos])
+      .parse()
+      .ok()
+  }
+
+  /// A character class, after its `[`
+  fn class(&mut self) -> Result<Class, Error> {
+    let start = self.pos - 1;
+    let mut class = Class {
+      negated: self.eat('^'),
+      ..Class::default()
+    };
+
+    let mut first = true;
+    loop {
+      let lo = match self.peek() {
+        None => {
+          return Err(Error::Syntax(
+            "Unclosed character class".to_owned(),
+            self.pattern.len() - 1,
+          ))
+        }
+        // A `]` right after the `[` is a literal
+        Some(']') if !first => {
+          self.pos += 1;
+          return Ok(class);
+        }
+        Some('[') => {
+          return Err(Error::Unsupported(format!(
+            "the nested character class at index {}",
+            self.pos
+          )))
+        }
+        Some('&') if self.pattern.get(self.pos + 1) == Some(&('&' as u16)) => {
+          return Err(Error::Unsupported(format!(
+            "the intersection at index {}",
+            self.pos
+          )))
+        }
+        Some(_) => self.class_unit()?,
+      };
+      first = false;
+
+      let lo = match lo {
+        Escaped::Predefined(p, negated) => {
+          class.predefined.push((p, negated));
+          continue;
+        }
+        Escaped::Unit(lo) => lo,
+      };
+
+      // A range, unless the `-` is the last char of the class
+      let is_range = self.peek() == Some('-')
+        && !matches!(self.pattern.get(self.pos + 1), Some(c) if *c == ']' as u16);
+      if !is_range || self.pos + 1 >= self.pattern.len() {
+        class.ranges.push((lo, lo));
+        continue;
+      }
+      self.pos += 1;
+      match self.class_unit()? {
+        Escaped::Unit(hi) if hi >= lo => class.ranges.push((lo, hi)),
+        _ => {
+          return Err(Error::Syntax(
+            "Illegal character range".to_owned(),
+            start.max(self.pos - 1),
+          ))
+        }
+      }
+    }
+  }
+
+  fn class_unit(&mut self) -> Result<Escaped, Error> {
+    let c = self.pattern[self.pos];
+    self.pos += 1;
+    if c == '\\' as u16 {
+      self.escape()
+    } else {
+      Ok(Escaped::Unit(c))
+    }
+  }
+
+  /// An escape sequence, after its `\`
+  fn escape(&mut self) -> Result<Escaped, Error> {
+    let start = self.pos - 1;
+    let c = match self.next() {
+      None => {
+        return Err(Error::Syntax(
+          "Unexpected internal error".to_owned(),
+          start + 1,
+        ))
+      }
+      Some(c) => c,
+    };
+
+    let hex = |parser: &mut Self, digits: usize| {
+      let end = parser.pos + digits;
+      let value = parser
+        .pattern
+        .get(parser.pos..end)
+        .map(String::from_utf16_lossy)
+        .and_then(|h| u16::from_str_radix(&h, 16).ok());
+      parser.pos = end;
+      value.map(Escaped::Unit)
+    };
+
+    Ok(match c {
+      'd' => Escaped::Predefined(Predefined::Digit, false),
+      'D' => Escaped::Predefined(Predefined::Digit, true),
+      's' => Escaped::Predefined(Predefined::Space, false),
+      'S' => Escaped::Predefined(Predefined::Space, true),
+      'w' => Escaped::Predefined(Predefined::Word, false),
+      'W' => Escaped::Predefined(Predefined::Word, true),
+      't' => Escaped::Unit(0x09),
+      'n' => Escaped::Unit(0x0a),
+      'r' => Escaped::Unit(0x0d),
+      'f' => Escaped::Unit(0x0c),
+      'a' => Escaped::Unit(0x07),
+      'e' => Escaped::Unit(0x1b),
+      'x' => hex(self, 2).ok_or(Error::Syntax(
+        "Illegal hexadecimal escape sequence".to_owned(),
+        self.pos,
+      ))?,
+      'u' => hex(self, 4).ok_or(Error::Syntax(
+        "Illegal Unicode escape sequence".to_owned(),
+        self.pos,
+      ))?,
+      c if c.is_ascii_alphanumeric() => {
+        return Err(Error::Unsupported(format!(
+          "the escape \\{} at index {}",
+          c, start
+        )))
+      }
+      _ => Escaped::Unit(self.pattern[self.pos - 1]),
+    })
+  }
+}
+
+enum Escaped {
+  Unit(u16),
+  Predefined(Predefined, bool),
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn units(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+  }
+
+  fn find(pattern: &str, input: &str) -> Option<(usize, usize)> {
+    Regex::new(&units(pattern)).unwrap().find(&units(input), 0)
+  }
+
+  #[test]
+  fn literals_and_classes() {
+    assert_eq!(find("b", "abc"), Some((1, 2)));
+    assert_eq!(find("\\.", "a.b"), Some((1, 2)));
+    assert_eq!(find("[,;]", "a;b"), Some((1, 2)));
+    assert_eq!(find("[^a-c]", "abcd"), Some((3, 4)));
+    assert_eq!(find("[a-]", "x-"), Some((1, 2)));
+    assert_eq!(find("\\d+", "ab123c"), Some((2, 5)));
+    assert_eq!(find("\\W", "ab c"), Some((2, 3)));
+    assert_eq!(find(".", "\n"), None);
+    assert_eq!(find("\\u0041", "zA"), Some((1, 2)));
+  }
+
+  #[test]
+  fn quantifiers() {
+    assert_eq!(find("\\s*,\\s*", "a  , b"), Some((1, 5)));
+    assert_eq!(find("a{2,3}", "aaaa"), Some((0, 3)));
+    assert_eq!(find("a{2}", "a aa"), Some((2, 4)));
+    assert_eq!(find("a+?", "aaa"), Some((0, 1)));
+    assert_eq!(find("x*", "abc"), Some((0, 0)));
+    assert_eq!(find("(a|)*b", "aab"), Some((0, 3)));
+  }
+
+  #[test]
+  fn groups_and_anchors() {
+    assert_eq!(find("(?:ab)+", "xababy"), Some((1, 5)));
+    assert_eq!(find("cat|dog", "hotdog"), Some((3, 6)));
+    assert_eq!(find("^a", "ba"), None);
+    assert_eq!(find("a$", "aba"), Some((2, 3)));
+  }
+
+  #[test]
+  fn errors() {
+    let error = |p: &str| Regex::new(&units(p)).unwrap_err();
+    assert_eq!(
+      error("*a"),
+      Error::Syntax("Dangling meta character '*'".to_owned(), 0)
+    );
+    assert_eq!(error("(a"), Error::Syntax("Unclosed group".to_owned(), 2));
+    assert_eq!(
+      error("a)"),
+      Error::Syntax("Unmatched closing ')'".to_owned(), 0)
+    );
+    assert_eq!(
+      error("a{2"),
+      Error::Syntax("Unclosed counted closure".to_owned(), 3)
+    );
+    assert_eq!(
+      error("[b-a]"),
+      Error::Syntax("Illegal character range".to_owned(), 3)
+    );
+    assert_eq!(
+      error("[ab"),
+      Error::Syntax("Unclosed character class".to_owned(), 2)
+    );
+    assert!(matches!(error("(?=a)"), Error::Unsupported(_)));
+    assert!(matches!(error("\\1"), Error::Unsupported(_)));
+  }
+}
@@ -0,0 +1,79 @@
+use super::{get_field, runtime, InvokeResult, Module, RuntimeError};
+use crate::codegen::StackFrame;
+
+// Slots of the fields declared by `java.lang.StackTraceElement`
+const DECLARING_CLASS: usize = 0;
+const METHOD_NAME: usize = 1;
+const FILE_NAME: usize = 2;
+const LINE_NUMBER: usize = 3;
+
+/// Line number java uses for native methods
+const NATIVE_LINE: i32 = -2;
+
+pub fn add_functions(m: &mut Module) {
+  m.natives()
+    .method(
+      "java/lang/StackTraceElement.toString()Ljava/lang/String;",
+      crate::native!(
+        |ctx, this: runtime::Value| -> Result<String, InvokeResult> {
+          Ok(frame(&this).map_err(|e| ctx.error(e))?.to_string())
+        }
+      ),
+    )
+    .method(
+      "java/lang/StackTraceElement.getClassName()Ljava/lang/String;",
+      crate::native!(
+        |ctx, this: runtime::Value| -> Result<runtime::Value, InvokeResult> {
+          get_field(&this, DECLARING_CLASS).map_err(|e| ctx.error(e))
+        }
+      ),
+    )
+    .method(
+      "java/lang/StackTraceElement.getMethodName()Ljava/lang/String;",
+      crate::native!(
+        |ctx, this: runtime::Value| -> Result<runtime::Value, InvokeResult> {
+          get_field(&this, METHOD_NAME).map_err(|e| ctx.error(e))
+        }
+      ),
+    )
+    .method(
+      "java/lang/StackTraceElement.getFileName()Ljava/lang/String;",
+      crate::native!(
+        |ctx, this: runtime::Value| -> Result<runtime::Value, InvokeResult> {
+          get_field(&this, FILE_NAME).map_err(|e| ctx.error(e))
+        }
+      ),
+    )
+    .method(
+      "java/lang/StackTraceElement.getLineNumber()I",
+      crate::native!(|ctx, this: runtime::Value| -> Result<i32, InvokeResult> {
+        Ok(
+          get_field(&this, LINE_NUMBER)
+            .map_err(|e| ctx.error(e))?
+            .to_single(),
+        )
+      }),
+    )
+    .method(
+      "java/lang/StackTraceElement.isNativeMethod()Z",
+      crate::native!(|ctx, this: runtime::Value| -> Result<bool, InvokeResult> {
+        Ok(frame(&this).map_err(|e| ctx.error(e))?.native)
+      }),
+    );
+}
+
+/// Read a `StackTraceElement` back into a frame
+pub fn frame(e: &runtime::Value) -> Result<StackFrame, RuntimeError> {
+  let line = match get_field(e, LINE_NUMBER)? {
+    runtime::Value::U32(l) => l,
+    v => return Err(RuntimeError::CastError(format!("{:?} as line number", v))),
+  };
+
+  Ok(StackFrame {
+    class: get_field(e, DECLARING_CLASS)?.string().unwrap_or_default(),
+    method: get_field(e, METHOD_NAME)?.string().unwrap_or_default(),
+    source_file: get_field(e, FILE_NAME)?.string(),
+    line: if line >= 0 { Some(line as u32) } else { None },
+    native: line == NATIVE_LINE,
+  })
+}
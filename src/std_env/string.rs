@@ -0,0 +1,474 @@
+use super::formatter;
+use super::number::{double_to_string, float_to_string};
+use super::regex::{self, Regex};
+use super::string_builder::object_text;
+use super::{runtime, InvokeResult, Module};
+use crate::codegen::native::Context;
+use crate::codegen::runtime::JavaString;
+
+/// The `indexOf` and `lastIndexOf` overloads of a needle type, with the code units of the needle
+macro_rules! index_overloads {
+  ($m:ident, $type_:literal, |$v:ident: $t:ty| $needle:expr) => {
+    $m.natives()
+      .method(
+        concat!("java/lang/String.indexOf(", $type_, ")I"),
+        crate::native!(|ctx, this: JavaString, $v: $t| -> i32 {
+          index_of(this.units(), &$needle, 0)
+        }),
+      )
+      .method(
+        concat!("java/lang/String.indexOf(", $type_, "I)I"),
+        crate::native!(|ctx, this: JavaString, $v: $t, from: i32| -> i32 {
+          index_of(this.units(), &$needle, from)
+        }),
+      )
+      .method(
+        concat!("java/lang/String.lastIndexOf(", $type_, ")I"),
+        crate::native!(|ctx, this: JavaString, $v: $t| -> i32 {
+          last_index_of(this.units(), &$needle, this.len() as i32)
+        }),
+      )
+      .method(
+        concat!("java/lang/String.lastIndexOf(", $type_, "I)I"),
+        crate::native!(|ctx, this: JavaString, $v: $t, from: i32| -> i32 {
+          last_index_of(this.units(), &$needle, from)
+        }),
+      );
+  };
+}
+
+pub fn add_functions(m: &mut Module) {
+  // The constructors return the string, see `Natives::method`
+  m.natives()
+    .method(
+      "java/lang/String.<init>()V",
+      crate::native!(|ctx, _this: runtime::Value| -> JavaString {
+        JavaString::from_units(Vec::new())
+      }),
+    )
+    .method(
+      "java/lang/String.<init>(Ljava/lang/String;)V",
+      crate::native!(|ctx, _this: runtime::Value, s: JavaString| -> JavaString { s }),
+    )
+    .method(
+      "java/lang/String.<init>([C)V",
+      crate::native!(|ctx, _this: runtime::Value, data: Vec<u16>| -> JavaString {
+        JavaString::from_units(data)
+      }),
+    )
+    .method(
+      "java/lang/String.<init>([CII)V",
+      crate::native!(|ctx,
+                      _this: runtime::Value,
+                      data: Vec<u16>,
+                      offset: i32,
+                      count: i32|
+       -> Result<JavaString, InvokeResult> {
+        region(&data, offset, count, ctx)
+      }),
+    )
+    .method(
+      "java/lang/String.length()I",
+      crate::native!(|ctx, this: JavaString| -> i32 { this.len() as i32 }),
+    )
+    .method(
+      "java/lang/String.isEmpty()Z",
+      crate::native!(|ctx, this: JavaString| -> bool { this.is_empty() }),
+    )
+    .method(
+      "java/lang/String.charAt(I)C",
+      crate::native!(
+        |ctx, this: JavaString, idx: i32| -> Result<u16, InvokeResult> {
+          match this.char_at(idx as usize).filter(|_| idx >= 0) {
+            None => Err(out_of_bounds(
+              format!("length={}; index={}", this.len(), idx),
+              ctx,
+            )),
+            Some(c) => Ok(c),
+          }
+        }
+      ),
+    )
+    .method(
+      "java/lang/String.equals(Ljava/lang/Object;)Z",
+      crate::native!(|ctx, this: JavaString, other: runtime::Value| -> bool {
+        match other {
+          runtime::Value::String(other) => this == other,
+          _ => false,
+        }
+      }),
+    )
+    .method(
+      "java/lang/String.hashCode()I",
+      crate::native!(|ctx, this: JavaString| -> i32 { this.hash_code() }),
+    )
+    .method(
+      "java/lang/String.substring(I)Ljava/lang/String;",
+      crate::native!(
+        |ctx, this: JavaString, begin: i32| -> Result<JavaString, InvokeResult> {
+          let end = this.len() as i32;
+          substring(this, begin, end, ctx)
+        }
+      ),
+    )
+    .method(
+      "java/lang/String.substring(II)Ljava/lang/String;",
+      crate::native!(|ctx,
+                      this: JavaString,
+                      begin: i32,
+                      end: i32|
+       -> Result<JavaString, InvokeResult> {
+        substring(this, begin, end, ctx)
+      }),
+    )
+    .method(
+      "java/lang/String.concat(Ljava/lang/String;)Ljava/lang/String;",
+      crate::native!(|ctx, this: JavaString, other: JavaString| -> JavaString {
+        if other.is_empty() {
+          return this;
+        }
+        JavaString::from_units([this.units(), other.units()].concat())
+      }),
+    )
+    .method(
+      "java/lang/String.compareTo(Ljava/lang/String;)I",
+      crate::native!(|ctx, this: JavaString, other: JavaString| -> i32 {
+        compare_to(&this, &other)
+      }),
+    )
+    // The bridge of `Comparable`
+    .method(
+      "java/lang/String.compareTo(Ljava/lang/Object;)I",
+      crate::native!(
+        |ctx, this: JavaString, other: runtime::Value| -> Result<i32, InvokeResult> {
+          match other {
+            runtime::Value::String(other) => Ok(compare_to(&this, &other)),
+            o if o.is_null() => Err(ctx.throw("Ljava/lang/NullPointerException;", None)),
+            _ => Err(ctx.throw("Ljava/lang/ClassCastException;", None)),
+          }
+        }
+      ),
+    )
+    .method(
+      "java/lang/String.split(Ljava/lang/String;)[Ljava/lang/String;",
+      crate::native!(|ctx,
+                      this: JavaString,
+                      regex: JavaString|
+       -> Result<runtime::Value, InvokeResult> { split(this, regex, 0, ctx) }),
+    )
+    .method(
+      "java/lang/String.split(Ljava/lang/String;I)[Ljava/lang/String;",
+      crate::native!(|ctx,
+                      this: JavaString,
+                      regex: JavaString,
+                      limit: i32|
+       -> Result<runtime::Value, InvokeResult> {
+        split(this, regex, limit, ctx)
+      }),
+    )
+    .method(
+      "java/lang/String.trim()Ljava/lang/String;",
+      crate::native!(|ctx, this: JavaString| -> JavaString { trim(this) }),
+    )
+    .method(
+      "java/lang/String.toUpperCase()Ljava/lang/String;",
+      crate::native!(|ctx, this: JavaString| -> JavaString { map_string(this, str::to_uppercase) }),
+    )
+    .method(
+      "java/lang/String.toLowerCase()Ljava/lang/String;",
+      crate::native!(|ctx, this: JavaString| -> JavaString { map_string(this, str::to_lowercase) }),
+    )
+    .method(
+      "java/lang/String.toCharArray()[C",
+      crate::native!(|ctx, this: JavaString| -> runtime::Value {
+        let data = this
+          .units()
+          .iter()
+          .map(|c| runtime::Value::U32(*c as i32))
+          .collect();
+        runtime::Value::from_array("[C".to_owned(), data)
+      }),
+    )
+    .method(
+      "java/lang/String.toString()Ljava/lang/String;",
+      crate::native!(|ctx, this: JavaString| -> JavaString { this }),
+    )
+    .method(
+      "java/lang/String.intern()Ljava/lang/String;",
+      crate::native!(|ctx, this: JavaString| -> JavaString { ctx.module.intern(&this) }),
+    );
+
+  index_overloads!(m, "I", |c: i32| code_point(c));
+  index_overloads!(m, "Ljava/lang/String;", |s: JavaString| s.units());
+
+  let value_of =
+    |descriptor: &str| format!("java/lang/String.valueOf({})Ljava/lang/String;", descriptor);
+  m.natives()
+    .static_method(
+      &value_of("Z"),
+      crate::native!(|ctx, b: bool| -> String {
+        (if b { "true" } else { "false" }).to_owned()
+      }),
+    )
+    .static_method(
+      &value_of("C"),
+      crate::native!(|ctx, c: u16| -> JavaString { JavaString::from_units(vec![c]) }),
+    )
+    .static_method(
+      &value_of("I"),
+      crate::native!(|ctx, i: i32| -> String { i.to_string() }),
+    )
+    .static_method(
+      &value_of("J"),
+      crate::native!(|ctx, l: i64| -> String { l.to_string() }),
+    )
+    .static_method(
+      &value_of("F"),
+      crate::native!(|ctx, f: f32| -> String { float_to_string(f) }),
+    )
+    .static_method(
+      &value_of("D"),
+      crate::native!(|ctx, d: f64| -> String { double_to_string(d) }),
+    )
+    .static_method(
+      &value_of("[C"),
+      crate::native!(|ctx, data: Vec<u16>| -> JavaString { JavaString::from_units(data) }),
+    )
+    .static_method(
+      &value_of("[CII"),
+      crate::native!(|ctx,
+                      data: Vec<u16>,
+                      offset: i32,
+                      count: i32|
+       -> Result<JavaString, InvokeResult> { region(&data, offset, count, ctx) }),
+    )
+    .static_method(
+      &value_of("Ljava/lang/Object;"),
+      crate::native!(|ctx, o: runtime::Value| -> Result<JavaString, InvokeResult> {
+        match o {
+          runtime::Value::String(s) => Ok(s),
+          o => Ok(JavaString::from_units(object_text(&o, ctx.cs, ctx.module)?)),
+        }
+      }),
+    )
+    // The locale is ignored
+    .static_method(
+      "java/lang/String.format(Ljava/lang/String;[Ljava/lang/Object;)Ljava/lang/String;",
+      crate::native!(|ctx,
+                      format: runtime::Value,
+                      args: runtime::Value|
+       -> Result<JavaString, InvokeResult> {
+        let units = formatter::format_varargs(&[format, args], ctx.cs, ctx.module)?;
+        Ok(JavaString::from_units(units))
+      }),
+    )
+    .static_method(
+      "java/lang/String.format(Ljava/util/Locale;Ljava/lang/String;[Ljava/lang/Object;)Ljava/lang/String;",
+      crate::native!(|ctx,
+                      locale: runtime::Value,
+                      format: runtime::Value,
+                      args: runtime::Value|
+       -> Result<JavaString, InvokeResult> {
+        let units = formatter::format_varargs(&[locale, format, args], ctx.cs, ctx.module)?;
+        Ok(JavaString::from_units(units))
+      }),
+    );
+}
+
+fn out_of_bounds(message: String, ctx: &Context) -> InvokeResult {
+  ctx.throw(
+    "Ljava/lang/StringIndexOutOfBoundsException;",
+    Some(&message),
+  )
+}
+
+/// The string of `count` units starting at `offset`, if they are all inside the data
+fn region(
+  data: &[u16],
+  offset: i32,
+  count: i32,
+  ctx: &Context,
+) -> Result<JavaString, InvokeResult> {
+  let units = match offset < 0 || count < 0 {
+    true => None,
+    false => data.get(offset as usize..offset as usize + count as usize),
+  };
+
+  match units {
+    None => Err(out_of_bounds(
+      format!(
+        "length={}; regionStart={}; regionLength={}",
+        data.len(),
+        offset,
+        count
+      ),
+      ctx,
+    )),
+    Some(units) => Ok(JavaString::from_units(units.to_vec())),
+  }
+}
+
+fn substring(
+  this: JavaString,
+  begin: i32,
+  end: i32,
+  ctx: &Context,
+) -> Result<JavaString, InvokeResult> {
+  if begin < 0 || end > this.len() as i32 || begin > end {
+    return Err(out_of_bounds(
+      format!(
+        "length={}; regionStart={}; regionLength={}",
+        this.len(),
+        begin,
+        end - begin
+      ),
+      ctx,
+    ));
+  }
+
+  if begin == 0 && end == this.len() as i32 {
+    return Ok(this);
+  }
+  Ok(JavaString::from_units(
+    this.units()[begin as usize..end as usize].to_vec(),
+  ))
+}
+
+/// The code units of a code point, searched for by `indexOf(int)`
+fn code_point(c: i32) -> Vec<u16> {
+  match std::char::from_u32(c as u32) {
+    Some(c) => c.encode_utf16(&mut [0; 2]).to_vec(),
+    // A lone surrogate
+    None => vec![c as u16],
+  }
+}
+
+fn index_of(s: &[u16], needle: &[u16], from: i32) -> i32 {
+  let from = from.max(0) as usize;
+  let found =
+    (from.min(s.len())..=s.len() - needle.len().min(s.len())).find(|&i| s[i..].starts_with(needle));
+  found.map_or(-1, |i| i as i32)
+}
+
+fn last_index_of(s: &[u16], needle: &[u16], from: i32) -> i32 {
+  let right = s.len() as i64 - needle.len() as i64;
+  let from = (from as i64).min(right);
+  if from < 0 {
+    return -1;
+  }
+
+  let found = (0..=from as usize)
+    .rev()
+    .find(|&i| s[i..].starts_with(needle));
+  found.map_or(-1, |i| i as i32)
+}
+
+fn compare_to(s: &JavaString, other: &JavaString) -> i32 {
+  match s.units().iter().zip(other.units()).find(|(a, b)| a != b) {
+    Some((a, b)) => *a as i32 - *b as i32,
+    None => s.len() as i32 - other.len() as i32,
+  }
+}
+
+/// `split(regex)` and `split(regex, limit)`, which work like `Pattern.split`
+fn split(
+  s: JavaString,
+  regex: JavaString,
+  limit: i32,
+  ctx: &Context,
+) -> Result<runtime::Value, InvokeResult> {
+  let pattern = match Regex::new(regex.units()) {
+    Ok(p) => p,
+    Err(e) => return Err(regex_error(e, &regex, ctx)),
+  };
+
+  let units = s.units();
+  let mut parts: Vec<&[u16]> = Vec::new();
+  let mut off = 0;
+  let mut from = 0;
+  while let Some((start, end)) = pattern.find(units, from) {
+    if limit > 0 && parts.len() as i32 >= limit - 1 {
+      break;
+    }
+    // The next search starts after the match, or the char after an empty match
+    from = if end == start { end + 1 } else { end };
+    // An empty match at the start doesn't give an empty leading part
+    if end == 0 {
+      continue;
+    }
+    parts.push(&units[off..start]);
+    off = end;
+  }
+
+  let parts = if off == 0 {
+    // No match
+    vec![runtime::Value::String(s.clone())]
+  } else {
+    parts.push(&units[off..]);
+    if limit == 0 {
+      while parts.last().is_some_and(|p| p.is_empty()) {
+        parts.pop();
+      }
+    }
+    parts
+      .into_iter()
+      .map(|p| runtime::Value::String(JavaString::from_units(p.to_vec())))
+      .collect()
+  };
+
+  Ok(runtime::Value::from_array(
+    "[Ljava/lang/String;".to_owned(),
+    parts,
+  ))
+}
+
+/// Throw the exception of a regex which can't be compiled: a `PatternSyntaxException` if it is
+/// invalid, with the message java gives, and an `UnsupportedOperationException` otherwise
+fn regex_error(e: regex::Error, regex: &JavaString, ctx: &Context) -> InvokeResult {
+  let (class, message) = match e {
+    regex::Error::Syntax(description, index) => (
+      "Ljava/util/regex/PatternSyntaxException;",
+      format!(
+        "{} near index {}\n{}\n{}^",
+        description,
+        index,
+        regex,
+        " ".repeat(index)
+      ),
+    ),
+    regex::Error::Unsupported(what) => (
+      "Ljava/lang/UnsupportedOperationException;",
+      format!("{} in the regex {} is not supported", what, regex),
+    ),
+  };
+
+  ctx.throw(class, Some(&message))
+}
+
+fn trim(this: JavaString) -> JavaString {
+  let units = this.units();
+  let start = units
+    .iter()
+    .position(|c| *c > ' ' as u16)
+    .unwrap_or(units.len());
+  let end = units
+    .iter()
+    .rposition(|c| *c > ' ' as u16)
+    .map_or(start, |e| e + 1);
+
+  if start == 0 && end == units.len() {
+    return this;
+  }
+  JavaString::from_units(units[start..end].to_vec())
+}
+
+/// Map a string, returning the string itself if nothing changed (like java does)
+fn map_string(this: JavaString, f: fn(&str) -> String) -> JavaString {
+  let mapped = JavaString::from(f(&this.to_string()).as_str());
+
+  if mapped == this {
+    this
+  } else {
+    mapped
+  }
+}
@@ -1,105 +1,388 @@
-use log::debug;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
-use super::{runtime, CallStack, Function, InvokeResult, Module, NativeFunction, RuntimeError};
+use super::number::{double_to_string, float_to_string};
+use super::{runtime, CallStack, InvokeResult, Module, RuntimeError};
+use crate::codegen::native::Context;
+use crate::codegen::runtime::{JavaString, NativeData};
 
-pub fn add_functions(m: &mut Module) {
-  m.add_function(
-    "CLASS_java__lang__StringBuilder____init__".to_owned(),
-    Function::Native(NativeFunction(java_lang_string_builder_init_)),
-  );
-
-  m.add_function(
-    "CLASS_java__lang__StringBuilder__append".to_owned(),
-    Function::Native(NativeFunction(java_lang_string_builder_append)),
-  );
-
-  m.add_function(
-    "CLASS_java__lang__StringBuilder__toString".to_owned(),
-    Function::Native(NativeFunction(java_lang_string_builder_to_string)),
-  );
+// Slot of the `value` field, holding the buffer as native data (a `Vec<u16>` of UTF-16 code units)
+const VALUE: usize = 0;
+
+/// The `append` and `insert` overloads of a parameter type, with the text of their argument
+macro_rules! text_overloads {
+  ($m:ident, $class:ident, $type_:literal, |$ctx:ident, $v:ident: $t:ty| $text:expr) => {
+    $m.natives()
+      .method(
+        &format!("java/lang/{0}.append({1})Ljava/lang/{0};", $class, $type_),
+        crate::native!(|$ctx,
+                        this: runtime::Value,
+                        $v: $t|
+         -> Result<runtime::Value, InvokeResult> {
+          append(this, $text, $ctx)
+        }),
+      )
+      .method(
+        &format!("java/lang/{0}.insert(I{1})Ljava/lang/{0};", $class, $type_),
+        crate::native!(|$ctx,
+                        this: runtime::Value,
+                        offset: i32,
+                        $v: $t|
+         -> Result<runtime::Value, InvokeResult> {
+          insert(this, offset, $text, $ctx)
+        }),
+      );
+  };
 }
 
-fn java_lang_string_builder_init_(
-  params: Vec<runtime::Value>,
-  cs: Rc<CallStack>,
-  _env: &Module,
-) -> InvokeResult {
-  if params.len() != 1 {
-    return InvokeResult::runtime(
-      RuntimeError::Unimplemented(format!(
-        "multiple init arguments ({}): {:?}",
-        params.len(),
-        params
-      )),
-      cs,
+pub fn add_functions(m: &mut Module) {
+  for class in &["StringBuilder", "StringBuffer"] {
+    let method = |descriptor: &str| format!("java/lang/{}.{}", class, descriptor);
+    let chaining =
+      |descriptor: &str| format!("java/lang/{0}.{1}Ljava/lang/{0};", class, descriptor);
+
+    m.natives()
+      .method(
+        &method("<init>()V"),
+        crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> {
+          init(this, Vec::new(), ctx)
+        }),
+      )
+      .method(
+        &method("<init>(I)V"),
+        crate::native!(
+          |ctx, this: runtime::Value, capacity: i32| -> Result<(), InvokeResult> {
+            if capacity < 0 {
+              let capacity = capacity.to_string();
+              return Err(ctx.throw("Ljava/lang/NegativeArraySizeException;", Some(&capacity)));
+            }
+            init(this, Vec::with_capacity(capacity as usize), ctx)
+          }
+        ),
+      )
+      .method(
+        &method("<init>(Ljava/lang/String;)V"),
+        crate::native!(
+          |ctx, this: runtime::Value, s: JavaString| -> Result<(), InvokeResult> {
+            init(this, s.units().to_vec(), ctx)
+          }
+        ),
+      )
+      .method(
+        &method("<init>(Ljava/lang/CharSequence;)V"),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        s: runtime::Value|
+         -> Result<(), InvokeResult> {
+          if s.is_null() {
+            return Err(ctx.throw("Ljava/lang/NullPointerException;", None));
+          }
+          init(this, object_text(&s, ctx.cs, ctx.module)?, ctx)
+        }),
+      );
+
+    text_overloads!(m, class, "Z", |ctx, b: bool| units(if b {
+      "true"
+    } else {
+      "false"
+    }));
+    text_overloads!(m, class, "C", |ctx, c: u16| vec![c]);
+    text_overloads!(m, class, "I", |ctx, i: i32| units(&i.to_string()));
+    text_overloads!(m, class, "J", |ctx, l: i64| units(&l.to_string()));
+    text_overloads!(m, class, "F", |ctx, f: f32| units(&float_to_string(f)));
+    text_overloads!(m, class, "D", |ctx, d: f64| units(&double_to_string(d)));
+    text_overloads!(m, class, "[C", |ctx, chars: Vec<u16>| chars);
+    text_overloads!(m, class, "Ljava/lang/String;", |ctx, s: runtime::Value| {
+      object_text(&s, ctx.cs, ctx.module)?
+    });
+    text_overloads!(
+      m,
+      class,
+      "Ljava/lang/CharSequence;",
+      |ctx, s: runtime::Value| object_text(&s, ctx.cs, ctx.module)?
     );
+    text_overloads!(m, class, "Ljava/lang/Object;", |ctx, o: runtime::Value| {
+      object_text(&o, ctx.cs, ctx.module)?
+    });
+
+    m.natives()
+      .method(
+        &chaining("append(Ljava/lang/StringBuffer;)"),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        sb: runtime::Value|
+         -> Result<runtime::Value, InvokeResult> {
+          append(this, object_text(&sb, ctx.cs, ctx.module)?, ctx)
+        }),
+      )
+      .method(
+        &chaining("append([CII)"),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        chars: Vec<u16>,
+                        offset: i32,
+                        count: i32|
+         -> Result<runtime::Value, InvokeResult> {
+          if offset < 0 || count < 0 || offset as usize + count as usize > chars.len() {
+            let message = format!(
+              "length={}; regionStart={}; regionLength={}",
+              chars.len(),
+              offset,
+              count
+            );
+            return Err(ctx.throw("Ljava/lang/IndexOutOfBoundsException;", Some(&message)));
+          }
+          append(
+            this,
+            chars[offset as usize..(offset + count) as usize].to_vec(),
+            ctx,
+          )
+        }),
+      )
+      .method(
+        &chaining("reverse()"),
+        crate::native!(
+          |ctx, this: runtime::Value| -> Result<runtime::Value, InvokeResult> {
+            reverse(this, ctx)
+          }
+        ),
+      )
+      .method(
+        &method("setLength(I)V"),
+        crate::native!(
+          |ctx, this: runtime::Value, new_length: i32| -> Result<(), InvokeResult> {
+            if new_length < 0 {
+              return Err(index_out_of_bounds(new_length, ctx));
+            }
+            with_buffer(&this, |b| b.resize(new_length as usize, 0), ctx)
+          }
+        ),
+      )
+      .method(
+        &method("length()I"),
+        crate::native!(|ctx, this: runtime::Value| -> Result<i32, InvokeResult> {
+          with_buffer(&this, |b| b.len() as i32, ctx)
+        }),
+      )
+      .method(
+        &method("charAt(I)C"),
+        crate::native!(
+          |ctx, this: runtime::Value, index: i32| -> Result<u16, InvokeResult> {
+            match with_buffer(&this, |b| b.get(index as usize).copied(), ctx)? {
+              Some(c) if index >= 0 => Ok(c),
+              _ => Err(index_out_of_bounds(index, ctx)),
+            }
+          }
+        ),
+      )
+      .method(
+        &chaining("deleteCharAt(I)"),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        index: i32|
+         -> Result<runtime::Value, InvokeResult> {
+          let deleted = with_buffer(
+            &this,
+            |b| {
+              if index < 0 || index as usize >= b.len() {
+                return false;
+              }
+              b.remove(index as usize);
+              true
+            },
+            ctx,
+          )?;
+          match deleted {
+            false => Err(index_out_of_bounds(index, ctx)),
+            true => Ok(this),
+          }
+        }),
+      )
+      .method(
+        &method("toString()Ljava/lang/String;"),
+        crate::native!(
+          |ctx, this: runtime::Value| -> Result<JavaString, InvokeResult> {
+            with_buffer(&this, |b| JavaString::from_units(b.clone()), ctx)
+          }
+        ),
+      );
+
+    // `append(CharSequence, int, int)`, and the bridges of `Appendable`, which return the builder
+    for return_type in &[
+      format!("Ljava/lang/{};", class),
+      "Ljava/lang/Appendable;".to_owned(),
+    ] {
+      m.natives().method(
+        &method(&format!(
+          "append(Ljava/lang/CharSequence;II){}",
+          return_type
+        )),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        s: runtime::Value,
+                        start: i32,
+                        end: i32|
+         -> Result<runtime::Value, InvokeResult> {
+          let text = object_text(&s, ctx.cs, ctx.module)?;
+          if start < 0 || start > end || end > text.len() as i32 {
+            let message = format!("start {}, end {}, length {}", start, end, text.len());
+            return Err(ctx.throw("Ljava/lang/IndexOutOfBoundsException;", Some(&message)));
+          }
+          append(this, text[start as usize..end as usize].to_vec(), ctx)
+        }),
+      );
+    }
+    m.natives()
+      .method(
+        &method("append(C)Ljava/lang/Appendable;"),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        c: u16|
+         -> Result<runtime::Value, InvokeResult> {
+          append(this, vec![c], ctx)
+        }),
+      )
+      .method(
+        &method("append(Ljava/lang/CharSequence;)Ljava/lang/Appendable;"),
+        crate::native!(|ctx,
+                        this: runtime::Value,
+                        s: runtime::Value|
+         -> Result<runtime::Value, InvokeResult> {
+          append(this, object_text(&s, ctx.cs, ctx.module)?, ctx)
+        }),
+      );
   }
+}
 
-  let mut string_instance = match params[0].instance() {
-    Err(e) => return InvokeResult::runtime(e, cs),
-    Ok(i) => i.lock().unwrap(),
-  };
+fn units(s: &str) -> Vec<u16> {
+  s.encode_utf16().collect()
+}
 
-  string_instance.set_class_type("java_lang_StringBuilder".to_owned());
-  string_instance.set_field("value".to_owned(), runtime::Value::Array(Vec::new()));
+fn buffer(this: &runtime::Value) -> Result<Arc<Mutex<NativeData>>, RuntimeError> {
+  match this.instance()?.lock().unwrap().get_field(VALUE) {
+    None => Err(RuntimeError::FieldOutOfBounds(VALUE)),
+    Some(v) => Ok(v.native_data()?.clone()),
+  }
+}
 
-  InvokeResult::Ok(runtime::Value::Void)
+/// Run a function on the buffer of a builder
+fn with_buffer<T>(
+  this: &runtime::Value,
+  f: impl FnOnce(&mut Vec<u16>) -> T,
+  ctx: &Context,
+) -> Result<T, InvokeResult> {
+  let data = buffer(this).map_err(|e| ctx.error(e))?;
+  let mut data = data.lock().unwrap();
+  match data.get_mut::<Vec<u16>>() {
+    None => Err(ctx.error(RuntimeError::CastError(
+      "native data as string buffer".to_owned(),
+    ))),
+    Some(b) => Ok(f(b)),
+  }
 }
 
-fn java_lang_string_builder_append(
-  params: Vec<runtime::Value>,
-  cs: Rc<CallStack>,
-  _env: &Module,
-) -> InvokeResult {
-  //TODO if params.len() != 2
+/// The text of an object: `"null"`, or the result of its `toString()`
+pub(super) fn object_text(
+  v: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Vec<u16>, InvokeResult> {
+  if v.is_null() {
+    return Ok(units("null"));
+  }
+  if let runtime::Value::String(s) = v {
+    return Ok(s.units().to_vec());
+  }
 
-  let string_instance = match params[0].instance() {
-    Err(e) => return InvokeResult::runtime(e, cs),
-    Ok(i) => i.lock().unwrap(),
-  };
+  match env.invoke_virtual("toString()Ljava/lang/String;", cs.clone(), vec![v.clone()]) {
+    InvokeResult::Ok(runtime::Value::String(s)) => Ok(s.units().to_vec()),
+    InvokeResult::Ok(s) if s.is_null() => Ok(units("null")),
+    InvokeResult::Ok(s) => Err(InvokeResult::runtime(
+      RuntimeError::CastError(format!("{:?} as string", s)),
+      cs.clone(),
+    )),
+    r => Err(r),
+  }
+}
 
-  let to_append: Vec<char> = match params.get(1) {
-    None => {
-      return InvokeResult::runtime(
-        RuntimeError::Unimplemented("no params to append function".to_owned()),
-        cs,
-      )
-    }
-    Some(v) => match v {
-      runtime::Value::Char(v) => vec![*v],
-      runtime::Value::U32(v) => format!("{}", v).chars().collect(),
-      runtime::Value::Instance(i) => format!("{:?}", i.lock().unwrap()).chars().collect(),
-      _ => {
-        return InvokeResult::runtime(
-          RuntimeError::Unimplemented(format!("Unimplemented append type: {:?}", v)),
-          cs,
-        );
+/// `StringIndexOutOfBoundsException(index)`
+fn index_out_of_bounds(index: i32, ctx: &Context) -> InvokeResult {
+  ctx.throw(
+    "Ljava/lang/StringIndexOutOfBoundsException;",
+    Some(&format!("String index out of range: {}", index)),
+  )
+}
+
+fn init(this: runtime::Value, data: Vec<u16>, ctx: &Context) -> Result<(), InvokeResult> {
+  let this = this.instance().map_err(|e| ctx.error(e))?;
+  let result = this
+    .lock()
+    .unwrap()
+    .set_field(VALUE, runtime::Value::native(data));
+  result.map_err(|e| ctx.error(e))
+}
+
+/// Append text to the builder, returning the builder for chaining
+fn append(
+  this: runtime::Value,
+  text: Vec<u16>,
+  ctx: &Context,
+) -> Result<runtime::Value, InvokeResult> {
+  with_buffer(&this, |b| b.extend(text), ctx)?;
+  Ok(this)
+}
+
+/// Insert text at an offset, returning the builder for chaining
+fn insert(
+  this: runtime::Value,
+  offset: i32,
+  text: Vec<u16>,
+  ctx: &Context,
+) -> Result<runtime::Value, InvokeResult> {
+  let inserted = with_buffer(
+    &this,
+    |b| {
+      if offset < 0 || offset as usize > b.len() {
+        return false;
       }
+      b.splice(offset as usize..offset as usize, text);
+      true
     },
-  };
+    ctx,
+  )?;
 
-  debug!("STRINGBUILDER APPEND: {:?}", to_append);
-  debug!(
-    "STRINGBUILDER APPEND: string instance: {:?}",
-    string_instance
-  );
+  match inserted {
+    false => Err(index_out_of_bounds(offset, ctx)),
+    true => Ok(this),
+  }
+}
 
-  InvokeResult::runtime(
-    RuntimeError::Unimplemented("Java.lang.stringbuilder.append".to_owned()),
-    cs,
-  )
+fn is_high_surrogate(c: u16) -> bool {
+  (0xd800..0xdc00).contains(&c)
 }
 
-fn java_lang_string_builder_to_string(
-  params: Vec<runtime::Value>,
-  cs: Rc<CallStack>,
-  _env: &Module,
-) -> InvokeResult {
-  debug!("STRINGBUILDER TO STRING: {:?}", params);
+fn is_low_surrogate(c: u16) -> bool {
+  (0xdc00..0xe000).contains(&c)
+}
 
-  InvokeResult::runtime(
-    RuntimeError::Unimplemented("Java.lang.stringbuilder.to_string".to_owned()),
-    cs,
-  )
+fn reverse(this: runtime::Value, ctx: &Context) -> Result<runtime::Value, InvokeResult> {
+  with_buffer(
+    &this,
+    |b| {
+      b.reverse();
+
+      // Surrogate pairs stay in their original order
+      let mut i = 0;
+      while i + 1 < b.len() {
+        if is_low_surrogate(b[i]) && is_high_surrogate(b[i + 1]) {
+          b.swap(i, i + 1);
+          i += 1;
+        }
+        i += 1;
+      }
+    },
+    ctx,
+  )?;
+
+  Ok(this)
 }
@@ -0,0 +1,186 @@
+use std::rc::Rc;
+
+use super::number::{double_to_string, float_to_string};
+use super::string_builder::object_text;
+use super::{
+  descriptor, runtime, CallStack, Function, InvokeResult, Module, NativeFunction, RuntimeError,
+};
+use crate::codegen::parameter_types;
+use crate::codegen::runtime::{HandleTarget, JavaString, MethodHandle};
+
+/// Tag of the recipe for the next argument
+const TAG_ARG: u16 = 0x0001;
+/// Tag of the recipe for the next constant
+const TAG_CONST: u16 = 0x0002;
+
+const CONCAT: &str = "CLASS_java__lang__invoke__StringConcatFactory__concat";
+
+pub fn add_functions(m: &mut Module) {
+  m.natives()
+    .untyped_static_method(
+      "java/lang/invoke/StringConcatFactory.makeConcatWithConstants(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/invoke/MethodType;Ljava/lang/String;[Ljava/lang/Object;)Ljava/lang/invoke/CallSite;",
+      make_concat_with_constants,
+    )
+    .untyped_static_method(
+      "java/lang/invoke/StringConcatFactory.makeConcat(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/invoke/MethodType;)Ljava/lang/invoke/CallSite;",
+      make_concat,
+    );
+
+  m.add_function(
+    CONCAT.to_owned(),
+    Function::Native(NativeFunction::new(concat)),
+  );
+}
+
+/// A call site for a recipe, bound to `concat`
+fn call_site(
+  recipe: runtime::Value,
+  constants: Vec<runtime::Value>,
+  method_type: runtime::Value,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let descriptor = unwrap_or_error!(descriptor(&method_type, env), cs);
+  let handle = env.method_handle(MethodHandle {
+    target: HandleTarget::Function(CONCAT.to_owned()),
+    bound: vec![
+      recipe,
+      runtime::Value::from_array("[Ljava/lang/Object;".to_owned(), constants),
+      method_type,
+    ],
+    descriptor,
+  });
+
+  InvokeResult::Ok(env.constant_call_site(handle))
+}
+
+/// Bootstrap method `makeConcatWithConstants(lookup, name, type, recipe, constants...)`, used
+/// by javac (and d8) for `+` on strings. In the recipe `\1` stands for the next argument and
+/// `\2` for the next constant, everything else is copied as is.
+fn make_concat_with_constants(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  if params.len() < 4 {
+    return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(4, params.len()), cs);
+  }
+
+  let mut params = params.into_iter().skip(2);
+  let method_type = params.next().unwrap();
+  let recipe = params.next().unwrap();
+
+  call_site(recipe, params.collect(), method_type, cs, env)
+}
+
+/// Bootstrap method `makeConcat(lookup, name, type)`: all arguments, one after the other
+fn make_concat(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  let method_type = match params.get(2) {
+    None => {
+      return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(3, params.len()), cs)
+    }
+    Some(t) => t.clone(),
+  };
+  let descriptor = unwrap_or_error!(descriptor(&method_type, env), cs);
+
+  let recipe = vec![TAG_ARG; parameter_types(&descriptor).len()];
+  call_site(
+    runtime::Value::String(JavaString::from_units(recipe)),
+    Vec::new(),
+    method_type,
+    cs,
+    env,
+  )
+}
+
+/// Target of the concat call sites: `concat(recipe, constants, type, args...)`
+fn concat(params: Vec<runtime::Value>, cs: Rc<CallStack>, env: &Module) -> InvokeResult {
+  if params.len() < 3 {
+    return InvokeResult::runtime(RuntimeError::WrongNumberOfParameters(3, params.len()), cs);
+  }
+
+  let recipe = unwrap_or_error!(params[0].java_string(), cs);
+  let constants = unwrap_or_error!(params[1].array(), cs)
+    .lock()
+    .unwrap()
+    .data()
+    .clone();
+  let descriptor = unwrap_or_error!(descriptor(&params[2], env), cs);
+
+  // Longs and doubles take two registers
+  let mut args = Vec::new();
+  let mut values = params[3..].iter();
+  for type_ in parameter_types(&descriptor) {
+    match values.next() {
+      None => break,
+      Some(v) => args.push((type_, v)),
+    }
+    if type_ == "J" || type_ == "D" {
+      values.next();
+    }
+  }
+
+  let mut args = args.into_iter();
+  let mut constants = constants.iter();
+  let mut out = Vec::new();
+  for c in recipe.units() {
+    match *c {
+      TAG_ARG => match args.next() {
+        None => continue,
+        Some((type_, v)) => match text(v, type_, &cs, env) {
+          Err(r) => return r,
+          Ok(t) => out.extend(t),
+        },
+      },
+      TAG_CONST => match constants.next() {
+        None => continue,
+        Some(v) => match constant_text(v, &cs, env) {
+          Err(r) => return r,
+          Ok(t) => out.extend(t),
+        },
+      },
+      c => out.push(c),
+    }
+  }
+
+  InvokeResult::Ok(runtime::Value::String(JavaString::from_units(out)))
+}
+
+/// The text of a constant of the recipe. Constants come from the dex file, so they are
+/// strings or unboxed numbers.
+fn constant_text(
+  v: &runtime::Value,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Vec<u16>, InvokeResult> {
+  match v {
+    runtime::Value::U32(_) => text(v, "I", cs, env),
+    runtime::Value::U64(_) => text(v, "J", cs, env),
+    _ => object_text(v, cs, env),
+  }
+}
+
+/// The text of a value of a type, like `String.valueOf`
+pub(super) fn text(
+  v: &runtime::Value,
+  type_: &str,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<Vec<u16>, InvokeResult> {
+  let long = || match v {
+    runtime::Value::U64(l) => *l,
+    v => v.to_single() as i64,
+  };
+
+  let s = match type_ {
+    "Z" => if v.to_single() != 0 { "true" } else { "false" }.to_owned(),
+    "C" => return Ok(vec![v.to_single() as u16]),
+    "B" | "S" | "I" => v.to_single().to_string(),
+    "J" => long().to_string(),
+    "F" => float_to_string(f32::from_bits(v.to_single() as u32)),
+    "D" => double_to_string(f64::from_bits(long() as u64)),
+    _ => return object_text(v, cs, env),
+  };
+
+  Ok(s.encode_utf16().collect())
+}
@@ -0,0 +1,189 @@
+//! `java.lang.System` intrinsics which the core library can't implement in bytecode
+
+use std::rc::Rc;
+
+use super::collection::{exception, int_arg, non_null};
+use super::{runtime, CallStack, InvokeResult, Module};
+use crate::codegen::{is_reference, java_name};
+
+pub fn add_functions(m: &mut Module) {
+  m.natives().untyped_static_method(
+    "java/lang/System.arraycopy(Ljava/lang/Object;ILjava/lang/Object;II)V",
+    java_lang_system_arraycopy,
+  );
+}
+
+/// The name of a type in the messages of `arraycopy`, e.g. `int[]` or `java.lang.String[]`
+fn type_name(descriptor: &str) -> String {
+  match descriptor.strip_prefix('[') {
+    Some(component) => format!("{}[]", type_name(component)),
+    None => java_name(descriptor),
+  }
+}
+
+/// How the range and type checks of `arraycopy` name an array: `int[10]`, or `object array[10]`
+/// for any array of references
+fn array_name(descriptor: &str, len: Option<usize>) -> String {
+  let component = match &descriptor[1..] {
+    c if is_reference(c) => "object array".to_owned(),
+    c => java_name(c),
+  };
+  match len {
+    Some(len) => format!("{}[{}]", component, len),
+    None => format!("{}[]", component),
+  }
+}
+
+/// The type and length of an array, or an `ArrayStoreException` if it isn't one
+fn array_info(
+  v: &runtime::Value,
+  which: &str,
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<(String, usize), InvokeResult> {
+  match v.array() {
+    Ok(a) => {
+      let a = a.lock().unwrap();
+      Ok((a.get_array_type().clone(), a.len()))
+    }
+    Err(_) => {
+      let type_ = v.get_type().unwrap_or_default();
+      let message = format!(
+        "arraycopy: {} type {} is not an array",
+        which,
+        type_name(&type_)
+      );
+      Err(exception(
+        "Ljava/lang/ArrayStoreException;",
+        Some(&message),
+        cs,
+        env,
+      ))
+    }
+  }
+}
+
+/// Check the arguments of `arraycopy` in the order the JVM does, and throw what it throws
+fn check_arraycopy(
+  params: &[runtime::Value],
+  cs: &Rc<CallStack>,
+  env: &Module,
+) -> Result<(usize, usize, usize), InvokeResult> {
+  let src = non_null(params, 0, cs, env)?;
+  let dest = non_null(params, 2, cs, env)?;
+  let (src_pos, dest_pos, length) = (
+    int_arg(params, 1, cs)?,
+    int_arg(params, 3, cs)?,
+    int_arg(params, 4, cs)?,
+  );
+
+  let (src_type, src_len) = array_info(src, "source", cs, env)?;
+  let (dest_type, dest_len) = array_info(dest, "destination", cs, env)?;
+  let (src_component, dest_component) = (&src_type[1..], &dest_type[1..]);
+  if src_component != dest_component
+    && !(is_reference(src_component) && is_reference(dest_component))
+  {
+    let message = format!(
+      "arraycopy: type mismatch: can not copy {} into {}",
+      array_name(&src_type, None),
+      array_name(&dest_type, None)
+    );
+    return Err(exception(
+      "Ljava/lang/ArrayStoreException;",
+      Some(&message),
+      cs,
+      env,
+    ));
+  }
+
+  let (src_end, dest_end) = (
+    src_pos as i64 + length as i64,
+    dest_pos as i64 + length as i64,
+  );
+  let message = if src_pos < 0 {
+    format!(
+      "source index {} out of bounds for {}",
+      src_pos,
+      array_name(&src_type, Some(src_len))
+    )
+  } else if dest_pos < 0 {
+    format!(
+      "destination index {} out of bounds for {}",
+      dest_pos,
+      array_name(&dest_type, Some(dest_len))
+    )
+  } else if length < 0 {
+    format!("length {} is negative", length)
+  } else if src_end > src_len as i64 {
+    format!(
+      "last source index {} out of bounds for {}",
+      src_end,
+      array_name(&src_type, Some(src_len))
+    )
+  } else if dest_end > dest_len as i64 {
+    format!(
+      "last destination index {} out of bounds for {}",
+      dest_end,
+      array_name(&dest_type, Some(dest_len))
+    )
+  } else {
+    return Ok((src_pos as usize, dest_pos as usize, length as usize));
+  };
+
+  Err(exception(
+    "Ljava/lang/ArrayIndexOutOfBoundsException;",
+    Some(&format!("arraycopy: {}", message)),
+    cs,
+    env,
+  ))
+}
+
+/// `System.arraycopy(src, srcPos, dest, destPos, length)`. Overlapping ranges of the same array
+/// are copied as if through a temporary array. When an element can't be stored in `dest`, the
+/// elements before it are copied and an `ArrayStoreException` is thrown.
+fn java_lang_system_arraycopy(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let (src_pos, dest_pos, length) = match check_arraycopy(&params, &cs, env) {
+    Err(r) => return r,
+    Ok(c) => c,
+  };
+  let (src, dest) = (&params[0], &params[2]);
+
+  let (src_type, items) = match src.array() {
+    Err(e) => return InvokeResult::runtime(e, cs),
+    Ok(a) => {
+      let a = a.lock().unwrap();
+      let items = a.data()[src_pos..src_pos + length].to_vec();
+      (a.get_array_type().clone(), items)
+    }
+  };
+  let dest = match dest.array() {
+    Err(e) => return InvokeResult::runtime(e, cs),
+    Ok(a) => a,
+  };
+  let dest_type = dest.lock().unwrap().get_array_type().clone();
+
+  // Elements of references are checked one by one, unless every one of them fits
+  let component = &dest_type[1..];
+  let check_elements = is_reference(component) && !env.is_assignable(&src_type, &dest_type);
+  for (i, item) in items.into_iter().enumerate() {
+    let fits = !check_elements
+      || item.is_null()
+      || env.is_assignable(&item.get_type().unwrap_or_default(), component);
+    if !fits {
+      let message = format!(
+        "arraycopy: element type mismatch: can not cast one of the elements of {} to the type \
+         of the destination array, {}",
+        type_name(&src_type),
+        java_name(component)
+      );
+      return exception("Ljava/lang/ArrayStoreException;", Some(&message), &cs, env);
+    }
+    dest.lock().unwrap().set(dest_pos + i, item);
+  }
+
+  InvokeResult::Ok(runtime::Value::Void)
+}
@@ -0,0 +1,467 @@
+//! `java.lang.Thread`. A shared module (`Module::into_shared`) runs each thread on a thread of
+//! its own, while `start()` throws an `UnsupportedOperationException` in any other module. The
+//! object of the main thread is created the first time it is asked for.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use super::{runtime, CallStack, InvokeResult, Module, RuntimeError};
+use crate::codegen::monitor::Wait;
+use crate::codegen::native::Context;
+use crate::codegen::StandardStream;
+
+const THREAD: &str = "Ljava/lang/Thread;";
+/// Static holding the object of the main thread
+const MAIN: &str = "CLASS_java__lang__Thread__main";
+/// How often a thread joining another one checks if it is interrupted
+const JOIN_POLL: Duration = Duration::from_millis(10);
+
+/// Number of the next thread named by default, `Thread-{n}`
+static NEXT_NUMBER: AtomicU32 = AtomicU32::new(0);
+
+thread_local! {
+  /// The object of the thread running, unless it is the main thread
+  static CURRENT: RefCell<Option<runtime::Value>> = const { RefCell::new(None) };
+}
+
+/// The state of a thread, which the threads waiting for it are notified of when it changes
+#[derive(Default)]
+struct Control {
+  state: Mutex<State>,
+  changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+  started: bool,
+  finished: bool,
+  interrupted: bool,
+  daemon: bool,
+}
+
+pub fn add_functions(m: &mut Module) {
+  m.natives()
+    .method(
+      "java/lang/Thread.<init>()V",
+      crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> {
+        init(this, runtime::Value::Void, None, ctx)
+      }),
+    )
+    .method(
+      "java/lang/Thread.<init>(Ljava/lang/Runnable;)V",
+      crate::native!(|ctx,
+                      this: runtime::Value,
+                      target: runtime::Value|
+       -> Result<(), InvokeResult> { init(this, target, None, ctx) }),
+    )
+    .method(
+      "java/lang/Thread.<init>(Ljava/lang/String;)V",
+      crate::native!(|ctx,
+                      this: runtime::Value,
+                      name: runtime::JavaString|
+       -> Result<(), InvokeResult> {
+        init(this, runtime::Value::Void, Some(name), ctx)
+      }),
+    )
+    .method(
+      "java/lang/Thread.<init>(Ljava/lang/Runnable;Ljava/lang/String;)V",
+      crate::native!(|ctx,
+                      this: runtime::Value,
+                      target: runtime::Value,
+                      name: runtime::JavaString|
+       -> Result<(), InvokeResult> { init(this, target, Some(name), ctx) }),
+    )
+    .static_method(
+      "java/lang/Thread.currentThread()Ljava/lang/Thread;",
+      crate::native!(|ctx| -> Result<runtime::Value, InvokeResult> { current_thread(ctx) }),
+    )
+    .method(
+      "java/lang/Thread.getName()Ljava/lang/String;",
+      crate::native!(
+        |ctx, this: runtime::Value| -> Result<runtime::Value, InvokeResult> { get_name(this, ctx) }
+      ),
+    )
+    .method(
+      "java/lang/Thread.setName(Ljava/lang/String;)V",
+      crate::native!(|ctx,
+                      this: runtime::Value,
+                      name: runtime::JavaString|
+       -> Result<(), InvokeResult> {
+        set_field(&this, "name", runtime::Value::String(name), ctx)
+      }),
+    )
+    .method(
+      "java/lang/Thread.run()V",
+      crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> { run(this, ctx) }),
+    )
+    .method(
+      "java/lang/Thread.start()V",
+      crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> { start(this, ctx) }),
+    )
+    .method(
+      "java/lang/Thread.join()V",
+      crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> {
+        join(this, 0, ctx)
+      }),
+    )
+    .method(
+      "java/lang/Thread.join(J)V",
+      crate::native!(
+        |ctx, this: runtime::Value, millis: i64| -> Result<(), InvokeResult> {
+          join(this, millis, ctx)
+        }
+      ),
+    )
+    .static_method(
+      "java/lang/Thread.sleep(J)V",
+      crate::native!(|ctx, millis: i64| -> Result<(), InvokeResult> { sleep(millis, ctx) }),
+    )
+    .method(
+      "java/lang/Thread.interrupt()V",
+      crate::native!(|ctx, this: runtime::Value| -> Result<(), InvokeResult> {
+        let control = control(&this, ctx)?;
+        control.state.lock().unwrap().interrupted = true;
+        control.changed.notify_all();
+        Ok(())
+      }),
+    )
+    .method(
+      "java/lang/Thread.isInterrupted()Z",
+      crate::native!(|ctx, this: runtime::Value| -> Result<bool, InvokeResult> {
+        Ok(control(&this, ctx)?.state.lock().unwrap().interrupted)
+      }),
+    )
+    .static_method(
+      "java/lang/Thread.interrupted()Z",
+      crate::native!(|ctx| -> Result<bool, InvokeResult> {
+        let control = control(&current_thread(ctx)?, ctx)?;
+        let mut state = control.state.lock().unwrap();
+        Ok(std::mem::replace(&mut state.interrupted, false))
+      }),
+    )
+    .method(
+      "java/lang/Thread.isAlive()Z",
+      crate::native!(|ctx, this: runtime::Value| -> Result<bool, InvokeResult> {
+        let control = control(&this, ctx)?;
+        let state = control.state.lock().unwrap();
+        Ok(state.started && !state.finished)
+      }),
+    )
+    .method(
+      "java/lang/Thread.setDaemon(Z)V",
+      crate::native!(
+        |ctx, this: runtime::Value, on: bool| -> Result<(), InvokeResult> {
+          let control = control(&this, ctx)?;
+          let mut state = control.state.lock().unwrap();
+          if state.started {
+            return Err(ctx.throw("Ljava/lang/IllegalThreadStateException;", None));
+          }
+          state.daemon = on;
+          Ok(())
+        }
+      ),
+    )
+    .method(
+      "java/lang/Thread.isDaemon()Z",
+      crate::native!(|ctx, this: runtime::Value| -> Result<bool, InvokeResult> {
+        Ok(control(&this, ctx)?.state.lock().unwrap().daemon)
+      }),
+    );
+}
+
+/// The slot of a field of `Thread`. The core library's `Thread` has `name` and `target` too.
+fn slot(field: &str, ctx: &Context) -> Result<usize, InvokeResult> {
+  match ctx
+    .module
+    .get_class(THREAD)
+    .and_then(|c| c.field_slot(field))
+  {
+    None => Err(ctx.error(RuntimeError::FieldOutOfBounds(0))),
+    Some(slot) => Ok(slot),
+  }
+}
+
+fn get_field(
+  this: &runtime::Value,
+  field: &str,
+  ctx: &Context,
+) -> Result<runtime::Value, InvokeResult> {
+  if this.is_null() {
+    return Err(ctx.throw("Ljava/lang/NullPointerException;", None));
+  }
+
+  let slot = slot(field, ctx)?;
+  let this = this.instance().map_err(|e| ctx.error(e))?.lock().unwrap();
+  Ok(
+    this
+      .get_field(slot)
+      .cloned()
+      .unwrap_or(runtime::Value::Void),
+  )
+}
+
+fn set_field(
+  this: &runtime::Value,
+  field: &str,
+  value: runtime::Value,
+  ctx: &Context,
+) -> Result<(), InvokeResult> {
+  if this.is_null() {
+    return Err(ctx.throw("Ljava/lang/NullPointerException;", None));
+  }
+
+  let slot = slot(field, ctx)?;
+  let mut this = this.instance().map_err(|e| ctx.error(e))?.lock().unwrap();
+  this.set_field(slot, value).map_err(|e| ctx.error(e))
+}
+
+/// The state of a thread, shared by the threads using it
+fn control(thread: &runtime::Value, ctx: &Context) -> Result<Arc<Control>, InvokeResult> {
+  let data = get_field(thread, "control", ctx)?;
+  let data = data
+    .native_data()
+    .map_err(|e| ctx.error(e))?
+    .lock()
+    .unwrap();
+  match data.get::<Arc<Control>>() {
+    None => Err(ctx.error(RuntimeError::CastError("native data as thread".to_owned()))),
+    Some(c) => Ok(c.clone()),
+  }
+}
+
+/// The constructors: a thread running `target`, or its own `run()`. Threads are named
+/// `Thread-{n}` by default, like on the JVM.
+fn init(
+  this: runtime::Value,
+  target: runtime::Value,
+  name: Option<runtime::JavaString>,
+  ctx: &Context,
+) -> Result<(), InvokeResult> {
+  let name = match name {
+    None => {
+      let n = NEXT_NUMBER.fetch_add(1, Ordering::Relaxed);
+      runtime::Value::new_string(&format!("Thread-{}", n))
+    }
+    Some(name) => runtime::Value::String(name),
+  };
+
+  set_field(&this, "name", name, ctx)?;
+  set_field(&this, "target", target, ctx)?;
+  let control = Arc::new(Control::default());
+  set_field(&this, "control", runtime::Value::native(control), ctx)
+}
+
+/// `Thread.currentThread()`. The main thread is named `main` like on the JVM, and its
+/// constructor isn't run, so only the fields set here have a value.
+fn current_thread(ctx: &Context) -> Result<runtime::Value, InvokeResult> {
+  if let Some(thread) = CURRENT.with(|c| c.borrow().clone()) {
+    return Ok(thread);
+  }
+  if let Some(thread) = ctx.module.get_static(MAIN).filter(|t| !t.is_null()) {
+    return Ok(thread);
+  }
+
+  let thread = runtime::Value::from_instance(ctx.module.new_instance(THREAD));
+  set_field(&thread, "name", runtime::Value::new_string("main"), ctx)?;
+  let control = Control::default();
+  control.state.lock().unwrap().started = true;
+  set_field(
+    &thread,
+    "control",
+    runtime::Value::native(Arc::new(control)),
+    ctx,
+  )?;
+
+  ctx.module.set_static(MAIN.to_owned(), thread.clone());
+  Ok(thread)
+}
+
+fn get_name(this: runtime::Value, ctx: &Context) -> Result<runtime::Value, InvokeResult> {
+  get_field(&this, "name", ctx)
+}
+
+/// `run()`, which runs the target of the thread, if it has one
+fn run(this: runtime::Value, ctx: &Context) -> Result<(), InvokeResult> {
+  let target = get_field(&this, "target", ctx)?;
+  if target.is_null() {
+    return Ok(());
+  }
+
+  match ctx
+    .module
+    .invoke_virtual("run()V", ctx.cs.clone(), vec![target])
+  {
+    InvokeResult::Ok(_) => Ok(()),
+    r => Err(r),
+  }
+}
+
+/// `start()`: run the thread on a thread of its own. Only a shared module has threads, so any
+/// other module throws an `UnsupportedOperationException`.
+fn start(this: runtime::Value, ctx: &Context) -> Result<(), InvokeResult> {
+  let control = control(&this, ctx)?;
+  let daemon = {
+    let mut state = control.state.lock().unwrap();
+    if state.started {
+      return Err(ctx.throw("Ljava/lang/IllegalThreadStateException;", None));
+    }
+    state.started = true;
+    state.daemon
+  };
+
+  let name = get_name(this.clone(), ctx)?.string().unwrap_or_default();
+  let running = control.clone();
+  let thread = move |module: &Module| run_thread(this, running, module);
+  if ctx.module.spawn(name, daemon, thread).is_err() {
+    control.state.lock().unwrap().started = false;
+    return Err(ctx.throw(
+      "Ljava/lang/UnsupportedOperationException;",
+      Some("threads need a shared module (Module::into_shared)"),
+    ));
+  }
+  Ok(())
+}
+
+/// Run a thread until it ends. Like the JVM, an exception it doesn't catch is written to
+/// `System.err`, and only ends this thread.
+fn run_thread(thread: runtime::Value, control: Arc<Control>, module: &Module) {
+  let previous = CURRENT.with(|c| c.replace(Some(thread.clone())));
+  let cs = Rc::new(CallStack::default());
+  let result = module.invoke_virtual("run()V", cs.clone(), vec![thread.clone()]);
+
+  let ctx = Context { module, cs: &cs };
+  let message = match result {
+    InvokeResult::Ok(_) => None,
+    InvokeResult::Exception(e, _) => {
+      let name = get_name(thread, &ctx)
+        .ok()
+        .and_then(|n| n.string())
+        .unwrap_or_default();
+      Some(match super::format_uncaught(&e, &name) {
+        Ok(s) => s,
+        Err(e) => format!(
+          "Exception in thread \"{}\" (could not be formatted: {:?})",
+          name, e
+        ),
+      })
+    }
+    InvokeResult::RuntimeError(e) => Some(e.finalize()),
+  };
+  if let Some(message) = message {
+    if let Err(e) = module.write_output(StandardStream::Err, format!("{}\n", message).as_bytes()) {
+      warn!("Could not write to {:?}: {:?}", StandardStream::Err, e);
+    }
+  }
+
+  CURRENT.with(|c| c.replace(previous));
+  control.state.lock().unwrap().finished = true;
+  control.changed.notify_all();
+}
+
+/// Throw an `InterruptedException` if the current thread is interrupted, clearing its status
+fn check_interrupted(control: &Control, ctx: &Context) -> Result<(), InvokeResult> {
+  let mut state = control.state.lock().unwrap();
+  match std::mem::replace(&mut state.interrupted, false) {
+    false => Ok(()),
+    true => Err(ctx.throw("Ljava/lang/InterruptedException;", None)),
+  }
+}
+
+/// The time a wait of some milliseconds ends, or `None` for a wait without end (0)
+fn deadline(millis: i64, ctx: &Context) -> Result<Option<Instant>, InvokeResult> {
+  match millis {
+    m if m < 0 => Err(ctx.throw(
+      "Ljava/lang/IllegalArgumentException;",
+      Some("timeout value is negative"),
+    )),
+    0 => Ok(None),
+    m => Ok(Some(Instant::now() + Duration::from_millis(m as u64))),
+  }
+}
+
+/// `join()` and `join(millis)`: wait for a thread to end, or until some milliseconds passed. It
+/// ends right away for a thread which isn't started.
+fn join(this: runtime::Value, millis: i64, ctx: &Context) -> Result<(), InvokeResult> {
+  let deadline = deadline(millis, ctx)?;
+  let current = control(&current_thread(ctx)?, ctx)?;
+  let control = control(&this, ctx)?;
+
+  loop {
+    check_interrupted(&current, ctx)?;
+
+    let mut timeout = JOIN_POLL;
+    if let Some(deadline) = deadline {
+      match deadline.checked_duration_since(Instant::now()) {
+        None => return Ok(()),
+        Some(left) => timeout = timeout.min(left),
+      }
+    }
+
+    let state = control.state.lock().unwrap();
+    if !state.started || state.finished {
+      return Ok(());
+    }
+    drop(control.changed.wait_timeout(state, timeout).unwrap());
+  }
+}
+
+/// `Object.wait(millis, nanos)`: wait on the monitor of an object until it is notified, or until
+/// some time passed. `interrupt()` wakes it up with an `InterruptedException`.
+pub(super) fn wait(
+  object: &runtime::Value,
+  millis: i64,
+  nanos: i32,
+  ctx: &Context,
+) -> Result<(), InvokeResult> {
+  if !(0..=999_999).contains(&nanos) {
+    return Err(ctx.throw(
+      "Ljava/lang/IllegalArgumentException;",
+      Some("nanosecond timeout value out of range"),
+    ));
+  }
+  // Like the JVM, the nanoseconds round up to a millisecond
+  let millis = match (millis, nanos) {
+    (m, n) if n > 0 && m < i64::MAX => m + 1,
+    (m, _) => m,
+  };
+  let timeout = deadline(millis, ctx)?.map(|d| d.saturating_duration_since(Instant::now()));
+  let control = control(&current_thread(ctx)?, ctx)?;
+
+  let interrupted = || control.state.lock().unwrap().interrupted;
+  match ctx.module.monitors().wait(object, timeout, interrupted) {
+    Wait::Done => Ok(()),
+    Wait::Interrupted => check_interrupted(&control, ctx),
+    Wait::NotOwner => Err(ctx.throw(
+      "Ljava/lang/IllegalMonitorStateException;",
+      Some("current thread is not owner"),
+    )),
+  }
+}
+
+/// `Thread.sleep(millis)`, which `interrupt()` wakes up with an `InterruptedException`
+fn sleep(millis: i64, ctx: &Context) -> Result<(), InvokeResult> {
+  let deadline = deadline(millis, ctx)?.unwrap_or_else(Instant::now);
+  let control = control(&current_thread(ctx)?, ctx)?;
+
+  let mut state = control.state.lock().unwrap();
+  loop {
+    if std::mem::replace(&mut state.interrupted, false) {
+      return Err(ctx.throw(
+        "Ljava/lang/InterruptedException;",
+        Some("sleep interrupted"),
+      ));
+    }
+
+    let left = match deadline.checked_duration_since(Instant::now()) {
+      None => return Ok(()),
+      Some(left) if left.is_zero() => return Ok(()),
+      Some(left) => left,
+    };
+    state = control.changed.wait_timeout(state, left).unwrap().0;
+  }
+}
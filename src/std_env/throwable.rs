@@ -0,0 +1,237 @@
+use std::rc::Rc;
+
+use log::warn;
+
+use super::print_stream;
+use super::stack_trace_element;
+use super::{get_field, runtime, CallStack, InvokeResult, Module, RuntimeError};
+use crate::codegen::{java_name, StackFrame, StandardStream};
+
+// Slots of the fields declared by `java.lang.Throwable`. Subclasses inherit them at the same slots.
+const DETAIL_MESSAGE: usize = 0;
+const CAUSE: usize = 1;
+const STACK_TRACE: usize = 2;
+
+pub fn add_functions(m: &mut Module) {
+  for parameters in &[
+    "",
+    "Ljava/lang/String;",
+    "Ljava/lang/Throwable;",
+    "Ljava/lang/String;Ljava/lang/Throwable;",
+  ] {
+    m.natives().untyped_method(
+      &format!("java/lang/Throwable.<init>({})V", parameters),
+      java_lang_throwable_init_,
+    );
+  }
+
+  m.natives()
+    .untyped_method(
+      "java/lang/Throwable.getMessage()Ljava/lang/String;",
+      java_lang_throwable_get_message,
+    )
+    .untyped_method(
+      "java/lang/Throwable.getLocalizedMessage()Ljava/lang/String;",
+      java_lang_throwable_get_message,
+    )
+    .untyped_method(
+      "java/lang/Throwable.getCause()Ljava/lang/Throwable;",
+      java_lang_throwable_get_cause,
+    )
+    .untyped_method(
+      "java/lang/Throwable.toString()Ljava/lang/String;",
+      java_lang_throwable_to_string,
+    )
+    .untyped_method(
+      "java/lang/Throwable.getStackTrace()[Ljava/lang/StackTraceElement;",
+      java_lang_throwable_get_stack_trace,
+    )
+    .untyped_method(
+      "java/lang/Throwable.printStackTrace()V",
+      java_lang_throwable_print_stack_trace,
+    )
+    .untyped_method(
+      "java/lang/Throwable.printStackTrace(Ljava/io/PrintStream;)V",
+      java_lang_throwable_print_stack_trace,
+    );
+}
+
+/// `Throwable.toString()`: the class name, followed by the message if there is one
+pub fn to_string(e: &runtime::Value) -> Result<String, RuntimeError> {
+  let class = match e.get_type() {
+    None => return Err(RuntimeError::CastError(format!("{:?} as throwable", e))),
+    Some(t) => java_name(&t),
+  };
+
+  match get_field(e, DETAIL_MESSAGE)?.string() {
+    None => Ok(class),
+    Some(message) => Ok(format!("{}: {}", class, message)),
+  }
+}
+
+/// The stack trace stored in a throwable, innermost frame first
+fn stack_trace(e: &runtime::Value) -> Result<Vec<StackFrame>, RuntimeError> {
+  match get_field(e, STACK_TRACE)?.array() {
+    // The stack trace was never filled in
+    Err(_) => Ok(Vec::new()),
+    Ok(frames) => frames
+      .lock()
+      .unwrap()
+      .data()
+      .iter()
+      .map(stack_trace_element::frame)
+      .collect(),
+  }
+}
+
+/// Format a throwable, its stack trace and its causes like `Throwable.printStackTrace()`
+pub fn format_stack_trace(e: &runtime::Value) -> Result<String, RuntimeError> {
+  let mut out = vec![];
+  write_stack_trace(e, &[], "", &mut out)?;
+
+  Ok(out.join("\n"))
+}
+
+/// Format an exception nobody caught, the way the JVM reports it before exiting
+pub fn format_uncaught(e: &runtime::Value, thread: &str) -> Result<String, RuntimeError> {
+  Ok(format!(
+    "Exception in thread \"{}\" {}",
+    thread,
+    format_stack_trace(e)?
+  ))
+}
+
+/// Write the stack trace of a throwable and its causes. Like java, the frames a cause has in
+/// common with the trace enclosing it are replaced by `... n more`.
+fn write_stack_trace(
+  e: &runtime::Value,
+  enclosing: &[StackFrame],
+  caption: &str,
+  out: &mut Vec<String>,
+) -> Result<(), RuntimeError> {
+  let trace = stack_trace(e)?;
+  let in_common = trace
+    .iter()
+    .rev()
+    .zip(enclosing.iter().rev())
+    .take_while(|(a, b)| a == b)
+    .count();
+
+  out.push(format!("{}{}", caption, to_string(e)?));
+  for f in &trace[..trace.len() - in_common] {
+    out.push(format!("\tat {}", f));
+  }
+  if in_common != 0 {
+    out.push(format!("\t... {} more", in_common));
+  }
+
+  let cause = get_field(e, CAUSE)?;
+  if !cause.is_null() {
+    write_stack_trace(&cause, &trace, "Caused by: ", out)?;
+  }
+
+  Ok(())
+}
+
+fn java_lang_throwable_init_(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  // Throwable(), Throwable(String), Throwable(Throwable) and Throwable(String, Throwable)
+  let (message, cause) = match params.len() {
+    1 => (runtime::Value::Void, runtime::Value::Void),
+    2 => match params[1].get_type() {
+      Some(t) if t != "Ljava/lang/String;" => match to_string(&params[1]) {
+        Err(e) => return InvokeResult::runtime(e, cs),
+        Ok(s) => (runtime::Value::new_string(&s), params[1].clone()),
+      },
+      _ => (params[1].clone(), runtime::Value::Void),
+    },
+    _ => (params[1].clone(), params[2].clone()),
+  };
+
+  let mut this = match params[0].instance() {
+    Err(e) => return InvokeResult::runtime(e, cs),
+    Ok(i) => i.lock().unwrap(),
+  };
+
+  if let Err(e) = this
+    .set_field(DETAIL_MESSAGE, message)
+    .and_then(|_| this.set_field(CAUSE, cause))
+  {
+    return InvokeResult::runtime(e, cs);
+  }
+  env.fill_in_stack_trace(&mut this, &cs);
+
+  InvokeResult::Ok(runtime::Value::Void)
+}
+
+fn java_lang_throwable_get_message(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  match get_field(&params[0], DETAIL_MESSAGE) {
+    Err(e) => InvokeResult::runtime(e, cs),
+    Ok(m) => InvokeResult::Ok(m),
+  }
+}
+
+fn java_lang_throwable_get_cause(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  match get_field(&params[0], CAUSE) {
+    Err(e) => InvokeResult::runtime(e, cs),
+    Ok(c) => InvokeResult::Ok(c),
+  }
+}
+
+fn java_lang_throwable_get_stack_trace(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  match get_field(&params[0], STACK_TRACE) {
+    Err(e) => InvokeResult::runtime(e, cs),
+    Ok(t) => InvokeResult::Ok(t),
+  }
+}
+
+fn java_lang_throwable_to_string(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  _env: &Module,
+) -> InvokeResult {
+  match to_string(&params[0]) {
+    Err(e) => InvokeResult::runtime(e, cs),
+    Ok(s) => InvokeResult::Ok(runtime::Value::new_string(&s)),
+  }
+}
+
+/// `printStackTrace()` to `System.err`, or `printStackTrace(PrintStream)`
+fn java_lang_throwable_print_stack_trace(
+  params: Vec<runtime::Value>,
+  cs: Rc<CallStack>,
+  env: &Module,
+) -> InvokeResult {
+  let stream = match params.get(1) {
+    None => StandardStream::Err,
+    Some(s) => match print_stream::stream(s) {
+      Err(e) => return InvokeResult::runtime(e, cs),
+      Ok(s) => s,
+    },
+  };
+
+  match format_stack_trace(&params[0]) {
+    Err(e) => InvokeResult::runtime(e, cs),
+    Ok(s) => {
+      if let Err(e) = env.write_output(stream, format!("{}\n", s).as_bytes()) {
+        warn!("Could not write to {:?}: {:?}", stream, e);
+      }
+      InvokeResult::Ok(runtime::Value::Void)
+    }
+  }
+}
@@ -0,0 +1,92 @@
+//! Runs the programs of resources/ which come with an expected.txt, like `make test-lambdas` and
+//! `make test-iteration`, and compares what they print with what java printed.
+//!
+//! The programs are compiled with javac and d8 into the target directory, so these tests are
+//! ignored unless asked for with `cargo test -- --ignored`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use aar::codegen::runtime::{InvokeResult, Value};
+use aar::codegen::{CapturedOutput, StandardStream};
+use aar::{format_uncaught_exception, process};
+
+fn run_fixture(name: &str) {
+  let sources = Path::new(env!("CARGO_MANIFEST_DIR"))
+    .join("resources")
+    .join(name);
+  let bytes = compile(&sources, &Path::new(env!("CARGO_TARGET_TMPDIR")).join(name));
+  let dex = match dexparser::parse(&bytes) {
+    Ok(dex) => dex,
+    Err(e) => panic!("Failed to parse the dex file of {}: {}", name, e),
+  };
+  let module = process(&dex, &bytes);
+  let output = CapturedOutput::new();
+  module.set_output(StandardStream::Out, Box::new(output.clone()));
+
+  let module = module.into_shared();
+  let main_fn = format!("CLASS_{}__main", name);
+  let args = Value::from_array("[Ljava/lang/String;".to_owned(), Vec::new());
+  match module.run(&main_fn, vec![args]) {
+    InvokeResult::Ok(_) => {}
+    InvokeResult::Exception(e, _) => panic!("{}", format_uncaught_exception(&e, "main")),
+    InvokeResult::RuntimeError(e) => panic!("{}", e.finalize()),
+  }
+  module.join_threads();
+
+  let expected = fs::read_to_string(sources.join("expected.txt")).unwrap();
+  assert_eq!(output.contents(), expected);
+}
+
+/// Compile the java files of `sources` into a classes.dex in `out`, like their Makefile
+fn compile(sources: &Path, out: &Path) -> Vec<u8> {
+  let classes = out.join("classes");
+  // Classes of an earlier version of the sources would end up in the dex file too
+  let _ = fs::remove_dir_all(&classes);
+  fs::create_dir_all(&classes).unwrap();
+
+  run(
+    Command::new("javac")
+      .arg("-d")
+      .arg(&classes)
+      .args(files(sources, "java")),
+  );
+  run(
+    Command::new("d8")
+      .arg("--output")
+      .arg(out)
+      .args(files(&classes, "class")),
+  );
+  fs::read(out.join("classes.dex")).unwrap()
+}
+
+fn files(dir: &Path, extension: &str) -> Vec<PathBuf> {
+  let mut files: Vec<PathBuf> = fs::read_dir(dir)
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.extension().and_then(|e| e.to_str()) == Some(extension))
+    .collect();
+  files.sort();
+  files
+}
+
+fn run(command: &mut Command) {
+  match command.status() {
+    Ok(status) if status.success() => {}
+    Ok(status) => panic!("{:?} failed: {}", command, status),
+    Err(e) => panic!("Could not run {:?}: {}", command, e),
+  }
+}
+
+#[test]
+#[ignore = "needs javac and d8 on the PATH"]
+fn lambdas() {
+  run_fixture("Lambdas");
+}
+
+#[test]
+#[ignore = "needs javac and d8 on the PATH"]
+fn iteration() {
+  run_fixture("Iteration");
+}